@@ -2,16 +2,28 @@ use crate::command::AppState;
 use mirrorx_core::{
     api::{
         config::{
-            entity::{domain::Domain, history::Record, kv::Theme},
+            bundle::{
+                export_bundle, import_bundle, ExportOptions, ImportConflictPolicy, ImportSummary,
+            },
+            entity::{
+                audit::{AuditEventRecord, AuditFilter, AuditPage},
+                domain::Domain,
+                history::{HistoryFilter, HistoryPage},
+                kv::Theme,
+                temporary_password::TemporaryPassword,
+            },
+            settings::{set_current_settings, Settings},
             LocalStorage,
         },
-        signaling::http_message::Response,
+        signaling::{http_message::Response, tls::TlsPin},
     },
+    component::fs::transfer::manager::TransferManager,
     core_error,
     error::CoreResult,
+    utility::proxy::{set_proxy_config, ProxyConfig},
 };
 use serde::{Deserialize, Serialize};
-use std::net::SocketAddr;
+use std::{net::SocketAddr, time::Duration};
 use tauri::{
     http::Uri, AppHandle, CustomMenuItem, Manager, State, SystemTrayMenu, SystemTrayMenuItem,
     Window,
@@ -34,6 +46,16 @@ pub async fn config_init(
     let storage = LocalStorage::new(config_dir.join("mirrorx.db"))?;
     let domain_count = storage.domain().get_domain_count()?;
 
+    // proxy must be applied before the first signaling request
+    if let Some(proxy) = storage.kv().get_proxy()? {
+        set_proxy_config(proxy);
+    }
+
+    set_current_settings(storage.kv().get_settings()?);
+    storage.audit().serve_retention();
+
+    *app_state.transfer_manager.lock().await = Some(TransferManager::new(storage.clone())?);
+
     let mut storage_guard = app_state.storage.lock().await;
     *storage_guard = Some(storage);
     drop(storage_guard);
@@ -106,12 +128,18 @@ pub async fn config_domain_create(
         })
         .unwrap_or_else(|_| Uri::try_from(addr).map_err(|_| core_error!("invalid uri format")))?;
 
-    let client = mirrorx_core::api::signaling::SignalingClient::new(uri.to_string())?;
+    let client = mirrorx_core::api::signaling::SignalingClient::new(uri.to_string(), None)?;
     let response = match client.identity().await? {
         Response::Message(resp) => resp,
         Response::Error(err) => return Err(core_error!("http error: {:?}", err)),
     };
 
+    // trust on first use, later connections must present the same server identity
+    let tls_pin = client
+        .server_tls_pin()
+        .map(|pin| pin.to_string())
+        .unwrap_or_default();
+
     let Some(ref storage) = *app_state.storage.lock().await else {
         return Err(core_error!("storage not initialize"));
     };
@@ -140,6 +168,8 @@ pub async fn config_domain_create(
         password: mirrorx_core::utility::rand::generate_random_password(),
         finger_print,
         remarks,
+        tls_pin,
+        expire: response.expire,
     })?;
 
     Ok(())
@@ -155,6 +185,8 @@ pub async fn config_domain_delete(id: i64, app_state: State<'_, AppState>) -> Co
     let domain = storage.domain().get_domain_by_id(id)?;
     storage.domain().delete_domain(id)?;
     storage.history().delete_domain_related(&domain.name)?;
+    storage.temporary_password().delete_domain_related(id)?;
+    storage.contact().delete_domain_related(&domain.name)?;
 
     Ok(())
 }
@@ -193,6 +225,8 @@ pub enum ConfigDomainUpdateType {
     SetPrimary,
     Password(String),
     Remarks(String),
+    TlsPin(String),
+    ClearTlsPin,
 }
 
 #[tauri::command]
@@ -222,6 +256,18 @@ pub async fn config_domain_update(
         ConfigDomainUpdateType::Remarks(new_remarks) => {
             storage.domain().set_domain_remarks(req.id, &new_remarks)?
         }
+        ConfigDomainUpdateType::TlsPin(new_tls_pin) => {
+            let tls_pin: TlsPin = new_tls_pin.parse()?;
+            let domain = storage.domain().get_domain_by_id(req.id)?;
+            if !domain.addr.starts_with("https://") {
+                return Err(core_error!("tls pin requires an https domain"));
+            }
+
+            storage
+                .domain()
+                .set_domain_tls_pin(req.id, &tls_pin.to_string())?
+        }
+        ConfigDomainUpdateType::ClearTlsPin => storage.domain().set_domain_tls_pin(req.id, "")?,
     }
 
     Ok(())
@@ -347,18 +393,233 @@ pub async fn config_theme_set(app_state: State<'_, AppState>, theme: Theme) -> C
     Ok(())
 }
 
+#[tauri::command]
+#[tracing::instrument(skip(app_state))]
+pub async fn config_proxy_get(app_state: State<'_, AppState>) -> CoreResult<ProxyConfig> {
+    let Some(ref storage) = *app_state.storage.lock().await else {
+        return Err(core_error!("storage not initialize"));
+    };
+
+    Ok(storage.kv().get_proxy()?.unwrap_or_default())
+}
+
+/// Stores and applies the proxy, the signaling connection should be re-established with
+/// `signaling_connect(force: true)` to take effect.
+#[tauri::command]
+#[tracing::instrument(skip(app_state, proxy))]
+pub async fn config_proxy_set(
+    app_state: State<'_, AppState>,
+    proxy: ProxyConfig,
+) -> CoreResult<()> {
+    let Some(ref storage) = *app_state.storage.lock().await else {
+        return Err(core_error!("storage not initialize"));
+    };
+
+    storage.kv().set_proxy(&proxy)?;
+    set_proxy_config(proxy);
+
+    Ok(())
+}
+
+#[tauri::command]
+#[tracing::instrument(skip(app_state))]
+pub async fn config_settings_get(app_state: State<'_, AppState>) -> CoreResult<Settings> {
+    let Some(ref storage) = *app_state.storage.lock().await else {
+        return Err(core_error!("storage not initialize"));
+    };
+
+    storage.kv().get_settings()
+}
+
+/// Stores and applies all settings, changed LAN ports take effect after
+/// `lan_init(force: true)`.
+#[tauri::command]
+#[tracing::instrument(skip(app_state))]
+pub async fn config_settings_set(
+    app_state: State<'_, AppState>,
+    settings: Settings,
+) -> CoreResult<()> {
+    let Some(ref storage) = *app_state.storage.lock().await else {
+        return Err(core_error!("storage not initialize"));
+    };
+
+    storage.kv().set_settings(&settings)?;
+    storage
+        .audit()
+        .delete_expired(settings.audit_retention_days)?;
+    set_current_settings(settings);
+
+    Ok(())
+}
+
+#[tauri::command]
+#[tracing::instrument(skip(app_state))]
+pub async fn config_setting_set(
+    app_state: State<'_, AppState>,
+    key: String,
+    value: serde_json::Value,
+) -> CoreResult<Settings> {
+    let Some(ref storage) = *app_state.storage.lock().await else {
+        return Err(core_error!("storage not initialize"));
+    };
+
+    let settings = storage.kv().set_setting(&key, value)?;
+    storage
+        .audit()
+        .delete_expired(settings.audit_retention_days)?;
+    set_current_settings(settings.clone());
+
+    Ok(settings)
+}
+
+#[tauri::command]
+#[tracing::instrument(skip(app_state, passphrase))]
+pub async fn config_export(
+    app_state: State<'_, AppState>,
+    path: String,
+    passphrase: Option<String>,
+    options: ExportOptions,
+) -> CoreResult<()> {
+    let Some(ref storage) = *app_state.storage.lock().await else {
+        return Err(core_error!("storage not initialize"));
+    };
+
+    let content = export_bundle(storage, passphrase.as_deref(), options)?;
+    std::fs::write(path, content)?;
+
+    Ok(())
+}
+
+#[tauri::command]
+#[tracing::instrument(skip(app_state, passphrase))]
+pub async fn config_import(
+    app_state: State<'_, AppState>,
+    path: String,
+    passphrase: Option<String>,
+    conflict_policy: ImportConflictPolicy,
+) -> CoreResult<ImportSummary> {
+    let Some(ref storage) = *app_state.storage.lock().await else {
+        return Err(core_error!("storage not initialize"));
+    };
+
+    let content = std::fs::read_to_string(path)?;
+    let summary = import_bundle(storage, &content, passphrase.as_deref(), conflict_policy)?;
+
+    // imported settings may contain a proxy
+    if let Some(proxy) = storage.kv().get_proxy()? {
+        set_proxy_config(proxy);
+    }
+
+    set_current_settings(storage.kv().get_settings()?);
+
+    Ok(summary)
+}
+
 #[tauri::command]
 #[tracing::instrument(skip(app_state))]
 pub async fn config_history_get(
     app_state: State<'_, AppState>,
-    time_range: Option<(i64, i64)>,
-) -> CoreResult<Vec<Record>> {
+    filter: HistoryFilter,
+) -> CoreResult<HistoryPage> {
+    let Some(ref storage) = *app_state.storage.lock().await else {
+        return Err(core_error!("storage not initialize"));
+    };
+
+    tracing::info!(?filter, "query");
+    storage.history().query(&filter)
+}
+
+#[tauri::command]
+#[tracing::instrument(skip(app_state))]
+pub async fn config_audit_query(
+    app_state: State<'_, AppState>,
+    filter: AuditFilter,
+) -> CoreResult<AuditPage> {
+    let Some(ref storage) = *app_state.storage.lock().await else {
+        return Err(core_error!("storage not initialize"));
+    };
+
+    storage.audit().query(&filter)
+}
+
+#[tauri::command]
+#[tracing::instrument(skip(app_state))]
+pub async fn config_audit_events(
+    app_state: State<'_, AppState>,
+    session_id: i64,
+) -> CoreResult<Vec<AuditEventRecord>> {
+    let Some(ref storage) = *app_state.storage.lock().await else {
+        return Err(core_error!("storage not initialize"));
+    };
+
+    storage.audit().query_events(session_id)
+}
+
+#[tauri::command]
+#[tracing::instrument(skip(app_state))]
+pub async fn config_audit_export(
+    app_state: State<'_, AppState>,
+    path: String,
+    filter: AuditFilter,
+) -> CoreResult<()> {
+    let Some(ref storage) = *app_state.storage.lock().await else {
+        return Err(core_error!("storage not initialize"));
+    };
+
+    let content = storage.audit().export_csv(&filter)?;
+    std::fs::write(path, content)?;
+
+    Ok(())
+}
+
+#[tauri::command]
+#[tracing::instrument(skip(app_state))]
+pub async fn config_temporary_password_generate(
+    app_state: State<'_, AppState>,
+    domain_id: i64,
+    one_time: bool,
+    expire_minutes: Option<u32>,
+) -> CoreResult<TemporaryPassword> {
     let Some(ref storage) = *app_state.storage.lock().await else {
         return Err(core_error!("storage not initialize"));
     };
 
-    tracing::info!(?time_range, "query");
-    let records = storage.history().query(time_range)?;
+    if !one_time && expire_minutes.is_none() {
+        return Err(core_error!(
+            "temporary password should be one-time or time-limited"
+        ));
+    }
+
+    storage.temporary_password().create(
+        domain_id,
+        &mirrorx_core::utility::rand::generate_random_password(),
+        one_time,
+        expire_minutes.map(|minutes| Duration::from_secs(u64::from(minutes) * 60)),
+    )
+}
+
+#[tauri::command]
+#[tracing::instrument(skip(app_state))]
+pub async fn config_temporary_password_list(
+    app_state: State<'_, AppState>,
+    domain_id: i64,
+) -> CoreResult<Vec<TemporaryPassword>> {
+    let Some(ref storage) = *app_state.storage.lock().await else {
+        return Err(core_error!("storage not initialize"));
+    };
+
+    storage.temporary_password().get_valid_passwords(domain_id)
+}
+
+#[tauri::command]
+#[tracing::instrument(skip(app_state))]
+pub async fn config_temporary_password_revoke(
+    app_state: State<'_, AppState>,
+    id: i64,
+) -> CoreResult<()> {
+    let Some(ref storage) = *app_state.storage.lock().await else {
+        return Err(core_error!("storage not initialize"));
+    };
 
-    Ok(records)
+    storage.temporary_password().delete(id)
 }
@@ -0,0 +1,126 @@
+use super::AppState;
+use mirrorx_core::{
+    api::config::entity::{
+        contact::{Contact, ContactFilter},
+        history::Record,
+    },
+    core_error,
+    error::CoreResult,
+};
+use tauri::State;
+
+#[tauri::command]
+#[tracing::instrument(skip(app_state))]
+pub async fn contact_create(
+    app_state: State<'_, AppState>,
+    contact: Contact,
+) -> CoreResult<Contact> {
+    let Some(ref storage) = *app_state.storage.lock().await else {
+        return Err(core_error!("storage not initialize"));
+    };
+
+    if storage
+        .contact()
+        .get_by_device(contact.device_id, &contact.domain)?
+        .is_some()
+    {
+        return Err(core_error!("contact already exists"));
+    }
+
+    storage.contact().create(contact)
+}
+
+#[tauri::command]
+#[tracing::instrument(skip(app_state))]
+pub async fn contact_update(app_state: State<'_, AppState>, contact: Contact) -> CoreResult<()> {
+    let Some(ref storage) = *app_state.storage.lock().await else {
+        return Err(core_error!("storage not initialize"));
+    };
+
+    storage.contact().update(&contact)
+}
+
+#[tauri::command]
+#[tracing::instrument(skip(app_state))]
+pub async fn contact_set_favorite(
+    app_state: State<'_, AppState>,
+    id: i64,
+    favorite: bool,
+) -> CoreResult<()> {
+    let Some(ref storage) = *app_state.storage.lock().await else {
+        return Err(core_error!("storage not initialize"));
+    };
+
+    storage.contact().set_favorite(id, favorite)
+}
+
+#[tauri::command]
+#[tracing::instrument(skip(app_state))]
+pub async fn contact_delete(app_state: State<'_, AppState>, id: i64) -> CoreResult<()> {
+    let Some(ref storage) = *app_state.storage.lock().await else {
+        return Err(core_error!("storage not initialize"));
+    };
+
+    storage.contact().delete(id)
+}
+
+#[tauri::command]
+#[tracing::instrument(skip(app_state))]
+pub async fn contact_get(app_state: State<'_, AppState>, id: i64) -> CoreResult<Contact> {
+    let Some(ref storage) = *app_state.storage.lock().await else {
+        return Err(core_error!("storage not initialize"));
+    };
+
+    storage.contact().get(id)
+}
+
+#[tauri::command]
+#[tracing::instrument(skip(app_state))]
+pub async fn contact_get_by_device(
+    app_state: State<'_, AppState>,
+    device_id: i64,
+    domain: String,
+) -> CoreResult<Option<Contact>> {
+    let Some(ref storage) = *app_state.storage.lock().await else {
+        return Err(core_error!("storage not initialize"));
+    };
+
+    storage.contact().get_by_device(device_id, &domain)
+}
+
+#[tauri::command]
+#[tracing::instrument(skip(app_state))]
+pub async fn contact_search(
+    app_state: State<'_, AppState>,
+    filter: ContactFilter,
+) -> CoreResult<Vec<Contact>> {
+    let Some(ref storage) = *app_state.storage.lock().await else {
+        return Err(core_error!("storage not initialize"));
+    };
+
+    storage.contact().search(&filter)
+}
+
+#[tauri::command]
+#[tracing::instrument(skip(app_state))]
+pub async fn contact_group_names(app_state: State<'_, AppState>) -> CoreResult<Vec<String>> {
+    let Some(ref storage) = *app_state.storage.lock().await else {
+        return Err(core_error!("storage not initialize"));
+    };
+
+    storage.contact().get_group_names()
+}
+
+#[tauri::command]
+#[tracing::instrument(skip(app_state))]
+pub async fn contact_history(app_state: State<'_, AppState>, id: i64) -> CoreResult<Vec<Record>> {
+    let Some(ref storage) = *app_state.storage.lock().await else {
+        return Err(core_error!("storage not initialize"));
+    };
+
+    let contact = storage.contact().get(id)?;
+
+    storage
+        .history()
+        .query_by_device(contact.device_id, &contact.domain)
+}
@@ -1,19 +1,47 @@
 use super::AppState;
 use mirrorx_core::{
-    api::endpoint::message::{
-        EndPointCallRequest, EndPointDownloadFileReply, EndPointDownloadFileRequest,
-        EndPointFileTransferError, EndPointMessage, EndPointSendFileReply, EndPointSendFileRequest,
-        EndPointVisitDirectoryRequest, EndPointVisitDirectoryResponse,
+    api::{
+        config::entity::transfer::{TransferKind, TransferRecord, TransferState},
+        endpoint::{
+            client::EndPointClient,
+            handlers::{
+                fs_copy::handle_copy_request, fs_create_directory::handle_create_directory_request,
+                fs_delete::handle_delete_request, fs_rename::handle_rename_request,
+            },
+            message::{
+                EndPointCallRequest, EndPointCopyRequest, EndPointCreateDirectoryRequest,
+                EndPointDeleteRequest, EndPointFileOperationReply, EndPointRenameRequest,
+                EndPointVisitDirectoryRequest, EndPointVisitDirectoryResponse,
+                FileTransferErrorReason,
+            },
+        },
     },
-    component::fs::transfer::{
-        create_file_append_session, query_transferred_bytes_count, send_file_to_remote,
+    component::fs::{
+        directory::{directory_transfer_id, DirectoryTransferStatus},
+        operation::FileOperationError,
+        transfer::{
+            progress::TransferEvent, query_transfer_error, query_transferred_bytes_count,
+            transfer_id,
+        },
+        tree::DirectoryTransferOptions,
     },
     core_error,
     error::CoreResult,
 };
 use rayon::prelude::*;
 use serde::Serialize;
-use std::path::PathBuf;
+use std::{collections::HashMap, path::PathBuf, sync::Arc, time::Duration};
+use tauri::Manager;
+use tokio::sync::broadcast::error::RecvError;
+
+/// Progress of a transfer is forwarded to the windows at most once per this interval.
+const TRANSFER_EVENT_INTERVAL: Duration = Duration::from_millis(250);
+
+#[derive(Serialize, Clone)]
+pub struct FileTransferEvent {
+    pub remote_device_id: String,
+    pub event: TransferEvent,
+}
 
 #[derive(Serialize)]
 pub struct DirectoryResult {
@@ -105,6 +133,7 @@ pub async fn file_manager_visit_local(path: Option<PathBuf>) -> CoreResult<Direc
     Ok(DirectoryResult { path, entries })
 }
 
+/// Queues the upload of `local_path` into the remote directory `remote_path`.
 #[tauri::command]
 #[tracing::instrument(skip(app_state))]
 pub async fn file_manager_send_file(
@@ -118,18 +147,14 @@ pub async fn file_manager_send_file(
     }
 
     let Some(filename) = local_path.file_name() else {
-         return Err(core_error!("local path get filename failed"));
+        return Err(core_error!("local path get filename failed"));
     };
 
-    let filename = filename
-        .to_str()
-        .ok_or_else(|| core_error!("convert filename failed"))?
-        .to_string();
-
     let meta = local_path.metadata()?;
     let size = meta.len();
 
-    let id = uuid::Uuid::new_v4().to_string();
+    // sending the same file to the same place again resumes an interrupted transfer
+    let id = transfer_id(&remote_device_id, &local_path, &remote_path.join(filename));
 
     let client = app_state
         .files_endpoints
@@ -138,22 +163,33 @@ pub async fn file_manager_send_file(
         .get(&remote_device_id)
         .ok_or_else(|| core_error!("remote file manager not exist"))?;
 
-    let _: EndPointSendFileReply = client
-        .call(EndPointCallRequest::SendFileRequest(
-            EndPointSendFileRequest {
-                id: id.clone(),
-                filename,
-                path: remote_path,
-                size,
-            },
-        ))
-        .await?;
+    let Some(ref transfer_manager) = *app_state.transfer_manager.lock().await else {
+        return Err(core_error!("transfer manager not initialize"));
+    };
 
-    send_file_to_remote(id.clone(), client, &local_path).await?;
+    let record = transfer_manager.enqueue(
+        client,
+        TransferRecord {
+            id,
+            remote_device_id,
+            kind: TransferKind::Upload,
+            local_path,
+            remote_path,
+            total_size: size,
+            transferred_size: 0,
+            state: TransferState::Queued,
+            error: None,
+            created_at: 0,
+            updated_at: 0,
+            options: None,
+        },
+    )?;
 
-    Ok((id, size))
+    Ok((record.id, record.total_size))
 }
 
+/// Queues the download of the remote file `remote_path` to `local_path`, `size` is the
+/// one listed by the remote directory and is corrected once the transfer starts.
 #[tauri::command]
 #[tracing::instrument(skip(app_state))]
 pub async fn file_manager_download_file(
@@ -161,12 +197,14 @@ pub async fn file_manager_download_file(
     remote_device_id: String,
     local_path: PathBuf,
     remote_path: PathBuf,
+    size: u64,
 ) -> CoreResult<(String, u64)> {
+    // existing files are never overwritten
     if local_path.exists() {
-        return Err(core_error!("local path is not a file"));
+        return Err(core_error!("file already exists"));
     }
 
-    let id = uuid::Uuid::new_v4().to_string();
+    let id = transfer_id(&remote_device_id, &remote_path, &local_path);
 
     let client = app_state
         .files_endpoints
@@ -175,29 +213,432 @@ pub async fn file_manager_download_file(
         .get(&remote_device_id)
         .ok_or_else(|| core_error!("remote file manager not exist"))?;
 
-    let reply: EndPointDownloadFileReply = client
-        .call(EndPointCallRequest::DownloadFileRequest(
-            EndPointDownloadFileRequest {
-                id: id.clone(),
-                path: remote_path,
-            },
-        ))
-        .await?;
+    let Some(ref transfer_manager) = *app_state.transfer_manager.lock().await else {
+        return Err(core_error!("transfer manager not initialize"));
+    };
+
+    let record = transfer_manager.enqueue(
+        client,
+        TransferRecord {
+            id,
+            remote_device_id,
+            kind: TransferKind::Download,
+            local_path,
+            remote_path,
+            total_size: size,
+            transferred_size: 0,
+            state: TransferState::Queued,
+            error: None,
+            created_at: 0,
+            updated_at: 0,
+            options: None,
+        },
+    )?;
+
+    Ok((record.id, record.total_size))
+}
+
+/// Queues the upload of the directory `local_path` into the remote directory
+/// `remote_path`, its size is known once it starts.
+#[tauri::command]
+#[tracing::instrument(skip(app_state))]
+pub async fn file_manager_send_directory(
+    app_state: tauri::State<'_, AppState>,
+    remote_device_id: String,
+    local_path: PathBuf,
+    remote_path: PathBuf,
+    options: Option<DirectoryTransferOptions>,
+) -> CoreResult<(String, u64)> {
+    if !local_path.is_dir() {
+        return Err(core_error!("local path is not a directory"));
+    }
+
+    enqueue_directory(
+        &app_state,
+        TransferKind::UploadDirectory,
+        remote_device_id,
+        local_path,
+        remote_path,
+        options,
+    )
+    .await
+}
+
+/// Queues the download of the remote directory `remote_path` into the directory
+/// `local_path`, its size is known once it starts.
+#[tauri::command]
+#[tracing::instrument(skip(app_state))]
+pub async fn file_manager_download_directory(
+    app_state: tauri::State<'_, AppState>,
+    remote_device_id: String,
+    local_path: PathBuf,
+    remote_path: PathBuf,
+    options: Option<DirectoryTransferOptions>,
+) -> CoreResult<(String, u64)> {
+    enqueue_directory(
+        &app_state,
+        TransferKind::DownloadDirectory,
+        remote_device_id,
+        local_path,
+        remote_path,
+        options,
+    )
+    .await
+}
+
+async fn enqueue_directory(
+    app_state: &AppState,
+    kind: TransferKind,
+    remote_device_id: String,
+    local_path: PathBuf,
+    remote_path: PathBuf,
+    options: Option<DirectoryTransferOptions>,
+) -> CoreResult<(String, u64)> {
+    let client = app_state
+        .files_endpoints
+        .lock()
+        .await
+        .get(&remote_device_id)
+        .ok_or_else(|| core_error!("remote file manager not exist"))?;
+
+    let Some(ref transfer_manager) = *app_state.transfer_manager.lock().await else {
+        return Err(core_error!("transfer manager not initialize"));
+    };
+
+    let mut record = TransferRecord {
+        id: String::default(),
+        remote_device_id,
+        kind,
+        local_path,
+        remote_path,
+        total_size: 0,
+        transferred_size: 0,
+        state: TransferState::Queued,
+        error: None,
+        created_at: 0,
+        updated_at: 0,
+        options: Some(options.unwrap_or_default()),
+    };
+
+    // queuing the same directory to the same place again resumes its files
+    record.id = directory_transfer_id(&record)?;
+
+    let record = transfer_manager.enqueue(client, record)?;
 
-    if let Err(err) = create_file_append_session(id.clone(), &local_path).await {
-        let _ = client
-            .send(&EndPointMessage::FileTransferError(
-                EndPointFileTransferError { id: id.clone() },
-            ))
-            .await;
+    Ok((record.id, record.total_size))
+}
 
-        return Err(err);
+#[tauri::command]
+#[tracing::instrument(skip(app_state))]
+pub async fn file_manager_query_transferred_bytes_count(
+    app_state: tauri::State<'_, AppState>,
+    remote_device_id: String,
+    id: String,
+) -> CoreResult<u64> {
+    if let Some(status) = directory_status(&app_state, &id).await? {
+        return Ok(status.transferred_size);
     }
 
-    Ok((id, reply.size))
+    let client = files_endpoint(&app_state, &remote_device_id).await?;
+
+    Ok(query_transferred_bytes_count(&client, &id))
+}
+
+/// Returns the aggregate progress and the failed entries of a directory transfer.
+#[tauri::command]
+#[tracing::instrument(skip(app_state))]
+pub async fn file_manager_query_directory_transfer(
+    app_state: tauri::State<'_, AppState>,
+    id: String,
+) -> CoreResult<Option<DirectoryTransferStatus>> {
+    directory_status(&app_state, &id).await
+}
+
+async fn directory_status(
+    app_state: &AppState,
+    id: &str,
+) -> CoreResult<Option<DirectoryTransferStatus>> {
+    let Some(ref transfer_manager) = *app_state.transfer_manager.lock().await else {
+        return Err(core_error!("transfer manager not initialize"));
+    };
+
+    transfer_manager.directory_status(id)
 }
 
+/// Returns why a transfer with `remote_device_id` failed, `None` while it's running or
+/// once it succeeded.
 #[tauri::command]
-pub async fn file_manager_query_transferred_bytes_count(id: String) -> u64 {
-    query_transferred_bytes_count(&id)
+#[tracing::instrument(skip(app_state))]
+pub async fn file_manager_query_transfer_error(
+    app_state: tauri::State<'_, AppState>,
+    remote_device_id: String,
+    id: String,
+) -> CoreResult<Option<FileTransferErrorReason>> {
+    let client = files_endpoint(&app_state, &remote_device_id).await?;
+
+    Ok(query_transfer_error(&client, &id))
+}
+
+async fn files_endpoint(
+    app_state: &AppState,
+    remote_device_id: &str,
+) -> CoreResult<Arc<EndPointClient>> {
+    app_state
+        .files_endpoints
+        .lock()
+        .await
+        .get(remote_device_id)
+        .ok_or_else(|| core_error!("remote file manager not exist"))
+}
+
+/// Returns the queued transfers with their states.
+#[tauri::command]
+#[tracing::instrument(skip(app_state))]
+pub async fn file_manager_transfers_list(
+    app_state: tauri::State<'_, AppState>,
+) -> CoreResult<Vec<TransferRecord>> {
+    let Some(ref transfer_manager) = *app_state.transfer_manager.lock().await else {
+        return Err(core_error!("transfer manager not initialize"));
+    };
+
+    transfer_manager.list()
+}
+
+#[tauri::command]
+#[tracing::instrument(skip(app_state))]
+pub async fn file_manager_transfer_pause(
+    app_state: tauri::State<'_, AppState>,
+    id: String,
+) -> CoreResult<()> {
+    let Some(ref transfer_manager) = *app_state.transfer_manager.lock().await else {
+        return Err(core_error!("transfer manager not initialize"));
+    };
+
+    transfer_manager.pause(&id).await
+}
+
+/// Queues a paused or failed transfer again, its remote file manager must be
+/// connected.
+#[tauri::command]
+#[tracing::instrument(skip(app_state))]
+pub async fn file_manager_transfer_resume(
+    app_state: tauri::State<'_, AppState>,
+    id: String,
+) -> CoreResult<()> {
+    let Some(ref transfer_manager) = *app_state.transfer_manager.lock().await else {
+        return Err(core_error!("transfer manager not initialize"));
+    };
+
+    let Some(record) = transfer_manager
+        .list()?
+        .into_iter()
+        .find(|record| record.id == id)
+    else {
+        return Err(core_error!("transfer not exists"));
+    };
+
+    let client = app_state
+        .files_endpoints
+        .lock()
+        .await
+        .get(&record.remote_device_id)
+        .ok_or_else(|| core_error!("remote file manager not exist"))?;
+
+    transfer_manager.resume(&id, client)
+}
+
+#[tauri::command]
+#[tracing::instrument(skip(app_state))]
+pub async fn file_manager_transfer_cancel(
+    app_state: tauri::State<'_, AppState>,
+    id: String,
+) -> CoreResult<()> {
+    let Some(ref transfer_manager) = *app_state.transfer_manager.lock().await else {
+        return Err(core_error!("transfer manager not initialize"));
+    };
+
+    transfer_manager.cancel(&id).await
+}
+
+#[tauri::command]
+#[tracing::instrument(skip(app_state))]
+pub async fn file_manager_transfers_clear_done(
+    app_state: tauri::State<'_, AppState>,
+) -> CoreResult<()> {
+    let Some(ref transfer_manager) = *app_state.transfer_manager.lock().await else {
+        return Err(core_error!("transfer manager not initialize"));
+    };
+
+    transfer_manager.clear_done()
+}
+
+/// Creates the directory `path` on the remote device, or locally when
+/// `remote_device_id` is `None`. Returns why it failed, empty once it succeeded.
+#[tauri::command]
+#[tracing::instrument(skip(app_state))]
+pub async fn file_manager_create_directory(
+    app_state: tauri::State<'_, AppState>,
+    remote_device_id: Option<String>,
+    path: PathBuf,
+) -> CoreResult<Vec<FileOperationError>> {
+    file_operation(
+        &app_state,
+        remote_device_id,
+        EndPointCallRequest::CreateDirectoryRequest(EndPointCreateDirectoryRequest { path }),
+    )
+    .await
+}
+
+/// Renames `from` to `to`, moving it when `to` is in another directory.
+#[tauri::command]
+#[tracing::instrument(skip(app_state))]
+pub async fn file_manager_rename(
+    app_state: tauri::State<'_, AppState>,
+    remote_device_id: Option<String>,
+    from: PathBuf,
+    to: PathBuf,
+) -> CoreResult<Vec<FileOperationError>> {
+    file_operation(
+        &app_state,
+        remote_device_id,
+        EndPointCallRequest::RenameRequest(EndPointRenameRequest { from, to }),
+    )
+    .await
+}
+
+/// Copies the file or the directory `from` to `to`, returns the entries which failed.
+#[tauri::command]
+#[tracing::instrument(skip(app_state))]
+pub async fn file_manager_copy(
+    app_state: tauri::State<'_, AppState>,
+    remote_device_id: Option<String>,
+    from: PathBuf,
+    to: PathBuf,
+) -> CoreResult<Vec<FileOperationError>> {
+    file_operation(
+        &app_state,
+        remote_device_id,
+        EndPointCallRequest::CopyRequest(EndPointCopyRequest { from, to }),
+    )
+    .await
+}
+
+/// Deletes `path`, a directory with content fails as `not_empty` until the deletion
+/// is confirmed with `recursive`.
+#[tauri::command]
+#[tracing::instrument(skip(app_state))]
+pub async fn file_manager_delete(
+    app_state: tauri::State<'_, AppState>,
+    remote_device_id: Option<String>,
+    path: PathBuf,
+    recursive: bool,
+) -> CoreResult<Vec<FileOperationError>> {
+    file_operation(
+        &app_state,
+        remote_device_id,
+        EndPointCallRequest::DeleteRequest(EndPointDeleteRequest { path, recursive }),
+    )
+    .await
+}
+
+/// Applies a file operation on the remote device, or through the same handlers the
+/// remote device serves it with when `remote_device_id` is `None`.
+async fn file_operation(
+    app_state: &AppState,
+    remote_device_id: Option<String>,
+    request: EndPointCallRequest,
+) -> CoreResult<Vec<FileOperationError>> {
+    let reply: EndPointFileOperationReply = match remote_device_id {
+        Some(remote_device_id) => {
+            let client = app_state
+                .files_endpoints
+                .lock()
+                .await
+                .get(&remote_device_id)
+                .ok_or_else(|| core_error!("remote file manager not exist"))?;
+
+            client.call(request).await?
+        }
+        None => match request {
+            EndPointCallRequest::CreateDirectoryRequest(req) => {
+                handle_create_directory_request(req).await?
+            }
+            EndPointCallRequest::RenameRequest(req) => handle_rename_request(req).await?,
+            EndPointCallRequest::CopyRequest(req) => handle_copy_request(req).await?,
+            EndPointCallRequest::DeleteRequest(req) => handle_delete_request(req).await?,
+            _ => return Err(core_error!("not a file operation")),
+        },
+    };
+
+    Ok(reply.errors)
+}
+
+/// Forwards the transfer events of the file manager endpoint of `remote_device_id` to
+/// the windows as `file_transfer_event` until it's closed. Progress is coalesced per
+/// transfer, state changes and errors are forwarded at once after the pending progress.
+pub fn forward_transfer_events(
+    app_handle: tauri::AppHandle,
+    remote_device_id: String,
+    client: Arc<EndPointClient>,
+) {
+    let mut events_rx = client.transfer_events().subscribe();
+
+    tokio::spawn(async move {
+        let emit = |event: TransferEvent| {
+            if let Err(err) = app_handle.emit_all(
+                "file_transfer_event",
+                FileTransferEvent {
+                    remote_device_id: remote_device_id.clone(),
+                    event,
+                },
+            ) {
+                tracing::error!(?err, "emit event 'file_transfer_event' failed");
+            }
+        };
+
+        let mut pending = HashMap::new();
+        let mut interval = tokio::time::interval(TRANSFER_EVENT_INTERVAL);
+
+        loop {
+            tokio::select! {
+                event = events_rx.recv() => match event {
+                    Ok(event) => coalesce_transfer_event(&mut pending, event)
+                        .into_iter()
+                        .for_each(emit),
+                    // skipped progress is superseded by the next one
+                    Err(RecvError::Lagged(skipped)) => {
+                        tracing::warn!(?skipped, "transfer events lagged");
+                    }
+                    Err(RecvError::Closed) => break,
+                },
+                _ = interval.tick() => pending.drain().for_each(|(_, progress)| emit(progress)),
+                _ = client.wait_closed() => break,
+            }
+        }
+
+        // transfers over the closed endpoint report how they ended shortly after
+        tokio::time::sleep(TRANSFER_EVENT_INTERVAL).await;
+        while let Ok(event) = events_rx.try_recv() {
+            coalesce_transfer_event(&mut pending, event)
+                .into_iter()
+                .for_each(emit);
+        }
+        pending.drain().for_each(|(_, progress)| emit(progress));
+    });
+}
+
+/// Keeps the latest progress of each transfer pending, returns the events to emit at
+/// once.
+fn coalesce_transfer_event(
+    pending: &mut HashMap<String, TransferEvent>,
+    event: TransferEvent,
+) -> Vec<TransferEvent> {
+    if let TransferEvent::Progress { .. } = event {
+        pending.insert(event.id().to_string(), event);
+        return Vec::new();
+    }
+
+    let mut events: Vec<TransferEvent> = pending.remove(event.id()).into_iter().collect();
+    events.push(event);
+    events
 }
@@ -1,33 +1,73 @@
-use crate::{command::AppState, window::create_desktop_window};
+use crate::{
+    command::{
+        file_manager::forward_transfer_events, session_transport_error, track_session, AppState,
+    },
+    window::create_desktop_window,
+};
 use mirrorx_core::{
-    api::endpoint::{
-        create_desktop_active_endpoint_client, create_file_manager_active_endpoint_client,
-        id::EndPointID, EndPointStream,
+    api::{
+        config::{
+            entity::{
+                history::{ConnectionType, SessionStart, SessionType},
+                lan_bookmark::LanBookmark,
+                lan_node::{LanPeer, PinnedNode},
+            },
+            settings::{current_settings, set_current_settings},
+        },
+        endpoint::{
+            create_desktop_active_endpoint_client, create_file_manager_active_endpoint_client,
+            id::EndPointID, EndPointStream,
+        },
     },
     component::lan::{
         discover::{Discover, Node},
+        handshake::{connect_node, probe_node, ProbeResult},
+        identity::NodeIdentity,
         server::Server,
+        wake::send_magic_packets,
     },
     core_error,
-    error::CoreResult,
-    utility::lan_ip::get_lan_ip,
+    error::{CoreError, CoreResult},
+    utility::lan_ip::{
+        get_route_ip, list_lan_interfaces, resolve_lan_host, select_lan_interfaces, LanInterface,
+    },
+};
+use std::{
+    net::IpAddr,
+    sync::Arc,
+    time::{Duration, Instant},
 };
-use std::net::{IpAddr, SocketAddr};
 use tauri_egui::EguiPluginHandle;
 
+/// How long [`lan_wake`] waits for a node to boot when no timeout is given.
+const WAKE_TIMEOUT: Duration = Duration::from_secs(60);
+/// Magic packets are sent again at this interval while waiting, a single UDP packet
+/// may get lost.
+const WAKE_RESEND_INTERVAL: Duration = Duration::from_secs(10);
+
 #[tauri::command]
 #[tracing::instrument(skip(app_state))]
 pub async fn lan_init(app_state: tauri::State<'_, AppState>, force: bool) -> CoreResult<()> {
     let mut lan_components = app_state.lan_components.lock().await;
 
     if force || lan_components.is_none() {
-        let lan_ip = get_lan_ip().await?;
-        let discover = Discover::new(lan_ip).await?;
+        let interfaces = select_lan_interfaces(current_settings().lan_interface.as_deref())?;
+        let lan_ips: Vec<IpAddr> = interfaces.iter().map(|interface| interface.ip).collect();
 
+        // release the ports before binding them again
         let old_components = lan_components.take();
         drop(old_components);
 
-        *lan_components = Some((discover, Server::new(lan_ip).await?));
+        // without storage the identity only lasts until the next initialization
+        let storage = app_state.storage.lock().await.clone();
+        let identity = Arc::new(match storage {
+            Some(ref storage) => storage.lan_node().identity()?,
+            None => NodeIdentity::generate()?.0,
+        });
+
+        let discover = Discover::new(&interfaces, identity.clone(), storage.clone()).await?;
+        let server = Server::new(&lan_ips, identity, storage).await?;
+        *lan_components = Some((discover, server));
     }
 
     Ok(())
@@ -40,12 +80,14 @@ pub async fn lan_connect(
     app_state: tauri::State<'_, AppState>,
     egui_plugin: tauri::State<'_, EguiPluginHandle>,
     addr: String,
+    port: Option<u16>,
     visit_desktop: bool,
 ) -> CoreResult<()> {
-    let local_ip = get_lan_ip().await?;
-    let remote_ip: IpAddr = addr
-        .parse()
-        .map_err(|_| core_error!("parse addr to IpAddr failed"))?;
+    // hosts on routed subnets are never discovered, they're given by name or address
+    let port = port.unwrap_or_else(|| current_settings().lan_server_port);
+    let remote_addr = resolve_lan_host(&addr, port).await?;
+    let remote_ip = remote_addr.ip();
+    let local_ip = get_route_ip(remote_ip).await?;
 
     let window_label = if visit_desktop {
         format!("Desktop:{}", remote_ip.to_string().replace('.', "_"))
@@ -59,21 +101,59 @@ pub async fn lan_connect(
         format!("MirrorX File Transfer {}", remote_ip)
     };
 
-    let remote_addr = SocketAddr::new(remote_ip, 48001);
-
     let endpoint_id = EndPointID::LANID {
         local_ip,
         remote_ip,
     };
 
+    let node = match *app_state.lan_components.lock().await {
+        Some((ref discover, _)) => discover.node(remote_ip),
+        None => None,
+    };
+
+    // LAN sessions are recorded only when the storage is ready, they don't depend on it
+    let storage = app_state.storage.lock().await.clone();
+    let session = SessionStart {
+        device_id: 0,
+        domain: String::default(),
+        remote_addr: remote_ip.to_string(),
+        session_type: if visit_desktop {
+            SessionType::Desktop
+        } else {
+            SessionType::FileManager
+        },
+        connection_type: ConnectionType::Lan,
+    };
+
+    let stream = connect_node(remote_addr, node.as_ref(), storage.as_ref())
+        .await
+        .map_err(|err| match storage {
+            Some(ref storage) => session_transport_error(storage, &session, err),
+            None => err,
+        })?;
+
+    if let Some(ref storage) = storage {
+        if let Err(err) = storage.lan_bookmark().touch(&addr, port) {
+            tracing::warn!(?err, "update lan bookmark last connected time failed");
+        }
+    }
+
     if visit_desktop {
         let (client, render_frame_rx) = create_desktop_active_endpoint_client(
             endpoint_id,
             None,
-            EndPointStream::ActiveTCP(remote_addr),
+            EndPointStream::ConnectedTCP(stream),
             None,
         )
-        .await?;
+        .await
+        .map_err(|err| match storage {
+            Some(ref storage) => session_transport_error(storage, &session, err),
+            None => err,
+        })?;
+
+        if let Some(ref storage) = storage {
+            track_session(storage, &session, client.clone());
+        }
 
         if let Err(err) = egui_plugin.create_window(
             window_label.clone(),
@@ -103,10 +183,20 @@ pub async fn lan_connect(
         let client = create_file_manager_active_endpoint_client(
             endpoint_id,
             None,
-            EndPointStream::ActiveTCP(remote_addr),
+            EndPointStream::ConnectedTCP(stream),
             None,
         )
-        .await?;
+        .await
+        .map_err(|err| match storage {
+            Some(ref storage) => session_transport_error(storage, &session, err),
+            None => err,
+        })?;
+
+        if let Some(ref storage) = storage {
+            track_session(storage, &session, client.clone());
+        }
+
+        forward_transfer_events(app_handle.clone(), remote_ip.to_string(), client.clone());
 
         app_state
             .files_endpoints
@@ -206,6 +296,14 @@ pub async fn lan_discoverable_set(
         };
 
     discover.set_discoverable(discoverable);
+
+    if let Some(ref storage) = *app_state.storage.lock().await {
+        let settings = storage
+            .kv()
+            .set_setting("lan_discoverable", serde_json::Value::Bool(discoverable))?;
+        set_current_settings(settings);
+    }
+
     Ok(())
 }
 
@@ -221,3 +319,182 @@ pub async fn lan_discoverable_get(app_state: tauri::State<'_, AppState>) -> Core
 
     Ok(discover.discoverable())
 }
+
+#[tauri::command]
+#[tracing::instrument]
+pub async fn lan_interfaces_list() -> CoreResult<Vec<LanInterface>> {
+    list_lan_interfaces()
+}
+
+#[tauri::command]
+#[tracing::instrument(skip(app_state))]
+pub async fn lan_pinned_nodes_list(
+    app_state: tauri::State<'_, AppState>,
+) -> CoreResult<Vec<PinnedNode>> {
+    let Some(ref storage) = *app_state.storage.lock().await else {
+        return Err(core_error!("storage not initialize"));
+    };
+
+    storage.lan_node().list()
+}
+
+/// Forgets the key pinned for a node, e.g. after it was reinstalled, the next
+/// connection pins the key it presents.
+#[tauri::command]
+#[tracing::instrument(skip(app_state))]
+pub async fn lan_pinned_node_delete(
+    app_state: tauri::State<'_, AppState>,
+    node_id: String,
+) -> CoreResult<()> {
+    let Some(ref storage) = *app_state.storage.lock().await else {
+        return Err(core_error!("storage not initialize"));
+    };
+
+    storage.lan_node().delete(&node_id)
+}
+
+/// Sends magic packets to the peer at `addr` through the interface of its subnet and
+/// waits until it's discovered again, its MAC addresses are the ones learned while it
+/// was online.
+#[tauri::command]
+#[tracing::instrument(skip(app_state))]
+pub async fn lan_wake(
+    app_state: tauri::State<'_, AppState>,
+    addr: String,
+    timeout_secs: Option<u64>,
+) -> CoreResult<Node> {
+    let remote_ip: IpAddr = addr
+        .parse()
+        .map_err(|_| core_error!("parse addr to IpAddr failed"))?;
+
+    let peer = match *app_state.storage.lock().await {
+        Some(ref storage) => storage.lan_node().get_peer(&remote_ip.to_string())?,
+        None => None,
+    };
+
+    let (node, interfaces) = match *app_state.lan_components.lock().await {
+        Some((ref discover, _)) => (discover.node(remote_ip), discover.interfaces().to_vec()),
+        None => (
+            None,
+            select_lan_interfaces(current_settings().lan_interface.as_deref())?,
+        ),
+    };
+
+    let mac_addresses = match peer {
+        Some(peer) => peer.mac_addresses,
+        None => node
+            .map(|node| node.capabilities.mac_addresses)
+            .unwrap_or_default(),
+    };
+
+    if mac_addresses.is_empty() {
+        return Err(core_error!(
+            "mac address of lan peer {} is unknown",
+            remote_ip
+        ));
+    }
+
+    let deadline = Instant::now() + timeout_secs.map_or(WAKE_TIMEOUT, Duration::from_secs);
+    let mut last_sent: Option<Instant> = None;
+
+    loop {
+        if last_sent.map_or(true, |sent| sent.elapsed() >= WAKE_RESEND_INTERVAL) {
+            send_magic_packets(&mac_addresses, remote_ip, &interfaces).await?;
+            last_sent = Some(Instant::now());
+        }
+
+        tokio::time::sleep(Duration::from_secs(1)).await;
+
+        // the lock isn't held across sleeps so other lan commands keep working
+        if let Some((ref discover, _)) = *app_state.lan_components.lock().await {
+            if let Some(node) = discover.node(remote_ip) {
+                return Ok(node);
+            }
+        }
+
+        if Instant::now() >= deadline {
+            return Err(CoreError::Timeout);
+        }
+    }
+}
+
+#[tauri::command]
+#[tracing::instrument(skip(app_state))]
+pub async fn lan_peers_list(app_state: tauri::State<'_, AppState>) -> CoreResult<Vec<LanPeer>> {
+    let Some(ref storage) = *app_state.storage.lock().await else {
+        return Err(core_error!("storage not initialize"));
+    };
+
+    storage.lan_node().list_peers()
+}
+
+#[tauri::command]
+#[tracing::instrument(skip(app_state))]
+pub async fn lan_peer_delete(
+    app_state: tauri::State<'_, AppState>,
+    addr: String,
+) -> CoreResult<()> {
+    let Some(ref storage) = *app_state.storage.lock().await else {
+        return Err(core_error!("storage not initialize"));
+    };
+
+    storage.lan_node().delete_peer(&addr)
+}
+
+#[tauri::command]
+#[tracing::instrument(skip(app_state))]
+pub async fn lan_bookmarks_list(
+    app_state: tauri::State<'_, AppState>,
+) -> CoreResult<Vec<LanBookmark>> {
+    let Some(ref storage) = *app_state.storage.lock().await else {
+        return Err(core_error!("storage not initialize"));
+    };
+
+    storage.lan_bookmark().list()
+}
+
+#[tauri::command]
+#[tracing::instrument(skip(app_state))]
+pub async fn lan_bookmark_create(
+    app_state: tauri::State<'_, AppState>,
+    bookmark: LanBookmark,
+) -> CoreResult<LanBookmark> {
+    let Some(ref storage) = *app_state.storage.lock().await else {
+        return Err(core_error!("storage not initialize"));
+    };
+
+    storage.lan_bookmark().create(bookmark)
+}
+
+#[tauri::command]
+#[tracing::instrument(skip(app_state))]
+pub async fn lan_bookmark_update(
+    app_state: tauri::State<'_, AppState>,
+    bookmark: LanBookmark,
+) -> CoreResult<()> {
+    let Some(ref storage) = *app_state.storage.lock().await else {
+        return Err(core_error!("storage not initialize"));
+    };
+
+    storage.lan_bookmark().update(&bookmark)
+}
+
+#[tauri::command]
+#[tracing::instrument(skip(app_state))]
+pub async fn lan_bookmark_delete(app_state: tauri::State<'_, AppState>, id: i64) -> CoreResult<()> {
+    let Some(ref storage) = *app_state.storage.lock().await else {
+        return Err(core_error!("storage not initialize"));
+    };
+
+    storage.lan_bookmark().delete(id)
+}
+
+/// Resolves `host` and checks that a node accepts LAN connections on it.
+#[tauri::command]
+#[tracing::instrument]
+pub async fn lan_probe(host: String, port: Option<u16>) -> CoreResult<ProbeResult> {
+    let port = port.unwrap_or_else(|| current_settings().lan_server_port);
+    let addr = resolve_lan_host(&host, port).await?;
+
+    probe_node(addr).await
+}
@@ -1,12 +1,24 @@
 pub mod config;
+pub mod contact;
 pub mod file_manager;
 pub mod lan;
 pub mod signaling;
 pub mod utility;
 
 use mirrorx_core::{
-    api::{config::LocalStorage, endpoint::client::EndPointClient, signaling::SignalingClient},
-    component::lan::{discover::Discover, server::Server},
+    api::{
+        config::{
+            entity::history::{SessionResult, SessionStart},
+            LocalStorage,
+        },
+        endpoint::client::EndPointClient,
+        signaling::SignalingClient,
+    },
+    component::{
+        fs::transfer::manager::TransferManager,
+        lan::{discover::Discover, server::Server},
+    },
+    error::CoreError,
 };
 use moka::future::{Cache, CacheBuilder};
 use std::sync::Arc;
@@ -14,9 +26,10 @@ use tauri::async_runtime::Mutex;
 
 pub struct AppState {
     storage: Mutex<Option<LocalStorage>>,
-    signaling_client: Mutex<Option<(i64, SignalingClient)>>,
+    signaling_client: Mutex<Option<(i64, Arc<SignalingClient>)>>,
     lan_components: Mutex<Option<(Discover, Server)>>,
     files_endpoints: Mutex<Cache<String, Arc<EndPointClient>>>,
+    transfer_manager: Mutex<Option<TransferManager>>,
 }
 
 impl AppState {
@@ -26,6 +39,53 @@ impl AppState {
             signaling_client: Mutex::new(None),
             lan_components: Mutex::new(None),
             files_endpoints: Mutex::new(CacheBuilder::new(64).build()),
+            transfer_manager: Mutex::new(None),
         }
     }
 }
+
+/// Records an established session in history, the record is finished with the
+/// traffic and peer os once the connection closes.
+fn track_session(storage: &LocalStorage, session: &SessionStart, client: Arc<EndPointClient>) {
+    let id = match storage.history().start_session(session) {
+        Ok(id) => id,
+        Err(err) => {
+            tracing::error!(?err, "record session start failed");
+            return;
+        }
+    };
+
+    let storage = storage.clone();
+    tokio::spawn(async move {
+        client.wait_closed().await;
+
+        let (bytes_sent, bytes_received) = client.traffic();
+        if let Err(err) =
+            storage
+                .history()
+                .finish_session(id, bytes_sent, bytes_received, client.peer_os())
+        {
+            tracing::error!(?err, "record session finish failed");
+        }
+    });
+}
+
+fn record_session_failure(storage: &LocalStorage, session: &SessionStart, result: SessionResult) {
+    if let Err(err) = storage.history().record_failure(session, &result) {
+        tracing::error!(?err, "record session failure failed");
+    }
+}
+
+/// Records a transport error and passes it through, used with `map_err`.
+fn session_transport_error(
+    storage: &LocalStorage,
+    session: &SessionStart,
+    err: CoreError,
+) -> CoreError {
+    record_session_failure(
+        storage,
+        session,
+        SessionResult::TransportError(err.to_string()),
+    );
+    err
+}
@@ -1,17 +1,33 @@
-use super::AppState;
+use super::{
+    file_manager::forward_transfer_events, record_session_failure, session_transport_error,
+    track_session, AppState,
+};
 use crate::window::create_desktop_window;
 use mirrorx_core::{
     api::{
+        config::{
+            entity::{
+                contact::{Contact, ContactFilter},
+                history::{
+                    ConnectionType, HistoryFilter, Record, SessionResult, SessionStart, SessionType,
+                },
+            },
+            LocalStorage,
+        },
         endpoint::{
             create_desktop_active_endpoint_client, create_file_manager_active_endpoint_client,
             id::EndPointID, EndPointStream,
         },
-        signaling::{http_message::Response, SignalingClient},
+        signaling::{http_message::Response, tls::TlsPin, SignalingClient},
     },
     core_error,
     error::CoreResult,
 };
-use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr, ToSocketAddrs};
+use serde::Serialize;
+use std::{
+    net::{Ipv4Addr, Ipv6Addr, SocketAddr, ToSocketAddrs},
+    sync::Arc,
+};
 use tauri::http::Uri;
 use tauri_egui::EguiPluginHandle;
 
@@ -27,7 +43,7 @@ pub async fn signaling_connect(
         return Err(core_error!("storage not initialize"));
     };
 
-    let primary_domain = storage.domain().get_primary_domain()?;
+    let mut primary_domain = storage.domain().get_primary_domain()?;
 
     if let Some((current_domain_id, _)) = *current_signaling {
         if current_domain_id == primary_domain.id && !force {
@@ -75,7 +91,31 @@ pub async fn signaling_connect(
         return Err(core_error!("invalid domain addr"));
     };
 
-    let mut client = SignalingClient::new(primary_domain.addr)?;
+    let tls_pin = if primary_domain.tls_pin.is_empty() {
+        None
+    } else {
+        Some(primary_domain.tls_pin.parse::<TlsPin>()?)
+    };
+
+    let mut client = SignalingClient::new(primary_domain.addr, tls_pin)?;
+
+    // domains imported without their identity register as a new device first
+    if primary_domain.device_id == 0 {
+        let response = match client
+            .domain_register(0, &primary_domain.finger_print)
+            .await?
+        {
+            Response::Message(resp) => resp,
+            Response::Error(err) => return Err(core_error!("http error: {:?}", err)),
+        };
+
+        storage.domain().set_domain_registration(
+            primary_domain.id,
+            response.device_id,
+            response.expire,
+        )?;
+        primary_domain.device_id = response.device_id;
+    }
 
     client
         .subscribe(
@@ -86,7 +126,17 @@ pub async fn signaling_connect(
         )
         .await?;
 
-    *current_signaling = Some((primary_domain.id, client));
+    if primary_domain.tls_pin.is_empty() {
+        if let Some(tls_pin) = client.server_tls_pin() {
+            storage
+                .domain()
+                .set_domain_tls_pin(primary_domain.id, &tls_pin.to_string())?;
+        }
+    }
+
+    client.serve_registration_renewal(primary_domain.id, storage.clone());
+
+    *current_signaling = Some((primary_domain.id, Arc::new(client)));
 
     Ok(())
 }
@@ -113,17 +163,31 @@ pub async fn signaling_visit(
         format!("MirrorX File Transfer {}", remote_device_id)
     };
 
-    let Some(ref storage) = *app_state.storage.lock().await else {
+    // clone out of the guards, the visit below waits on the network
+    let Some(storage) = app_state.storage.lock().await.clone() else {
         return Err(core_error!("storage not initialize"));
     };
 
-    let Some((_,ref signaling_client)) = *app_state.signaling_client.lock().await else {
-        return Err(core_error!("storage not initialize"));
+    let Some((_, signaling_client)) = app_state.signaling_client.lock().await.clone() else {
+        return Err(core_error!("signaling client not initialize"));
     };
 
     let remote_device_id_num = remote_device_id.replace('-', "").parse()?;
     let primary_domain = storage.domain().get_primary_domain()?;
     let local_device_id = primary_domain.device_id;
+
+    let session = SessionStart {
+        device_id: remote_device_id_num,
+        domain: primary_domain.name.clone(),
+        remote_addr: String::default(),
+        session_type: if visit_desktop {
+            SessionType::Desktop
+        } else {
+            SessionType::FileManager
+        },
+        connection_type: ConnectionType::Domain,
+    };
+
     let resp = signaling_client
         .visit(
             primary_domain.device_id,
@@ -131,14 +195,29 @@ pub async fn signaling_visit(
             password,
             visit_desktop,
         )
-        .await?;
+        .await
+        .map_err(|err| session_transport_error(&storage, &session, err))?;
 
     let (endpoint_addr, visit_credentials, opening_key, sealing_key) = match resp {
         Response::Message(result) => match result {
             Ok(v) => v,
-            Err(reason) => return Err(core_error!("Visit Failed ({:?})", reason)),
+            Err(reason) => {
+                record_session_failure(
+                    &storage,
+                    &session,
+                    SessionResult::VisitFailed(format!("{:?}", reason)),
+                );
+                return Err(core_error!("Visit Failed ({:?})", reason));
+            }
         },
-        Response::Error(err) => return Err(core_error!("Visit Failed ({:?})", err)),
+        Response::Error(err) => {
+            record_session_failure(
+                &storage,
+                &session,
+                SessionResult::TransportError(format!("{:?}", err)),
+            );
+            return Err(core_error!("Visit Failed ({:?})", err));
+        }
     };
 
     let endpoint_addr: SocketAddr = endpoint_addr
@@ -159,7 +238,10 @@ pub async fn signaling_visit(
             EndPointStream::ActiveTCP(endpoint_addr),
             Some(visit_credentials),
         )
-        .await?;
+        .await
+        .map_err(|err| session_transport_error(&storage, &session, err))?;
+
+        track_session(&storage, &session, client.clone());
 
         if let Err(err) = egui_plugin.create_window(
             window_label,
@@ -192,7 +274,11 @@ pub async fn signaling_visit(
             EndPointStream::ActiveTCP(endpoint_addr),
             Some(visit_credentials),
         )
-        .await?;
+        .await
+        .map_err(|err| session_transport_error(&storage, &session, err))?;
+
+        track_session(&storage, &session, client.clone());
+        forward_transfer_events(app_handle.clone(), remote_device_id.clone(), client.clone());
 
         app_state
             .files_endpoints
@@ -236,9 +322,134 @@ pub async fn signaling_visit(
         }
     }
 
-    let _ = storage
-        .history()
-        .create(remote_device_id_num, &primary_domain.name);
-
     Ok(())
 }
+
+#[derive(Serialize)]
+pub struct HistoryRecordPresence {
+    #[serde(flatten)]
+    pub record: Record,
+    /// None when the record belongs to another domain than the connected one or the
+    /// presence query failed.
+    pub online: Option<bool>,
+}
+
+#[derive(Serialize)]
+pub struct HistoryPresencePage {
+    /// Count of all records matching the filter.
+    pub total: u32,
+    pub records: Vec<HistoryRecordPresence>,
+}
+
+#[derive(Serialize)]
+pub struct ContactPresence {
+    #[serde(flatten)]
+    pub contact: Contact,
+    /// None when the contact belongs to another domain than the connected one or the
+    /// presence query failed.
+    pub online: Option<bool>,
+}
+
+/// Queries the page of history records `filter` selects together with the presence
+/// of their devices, like `config_history_get`.
+#[tauri::command]
+#[tracing::instrument(skip(app_state))]
+pub async fn signaling_history_presence(
+    app_state: tauri::State<'_, AppState>,
+    filter: HistoryFilter,
+) -> CoreResult<HistoryPresencePage> {
+    // clone out of the guards, the presence query below waits on the network
+    let current_signaling = app_state.signaling_client.lock().await.clone();
+
+    let Some(storage) = app_state.storage.lock().await.clone() else {
+        return Err(core_error!("storage not initialize"));
+    };
+
+    let page = storage.history().query(&filter)?;
+
+    let devices: Vec<(i64, &str)> = page
+        .records
+        .iter()
+        .map(|record| (record.device_id, record.domain.as_str()))
+        .collect();
+    let presence = query_presence(&storage, current_signaling, &devices).await?;
+
+    Ok(HistoryPresencePage {
+        total: page.total,
+        records: page
+            .records
+            .into_iter()
+            .zip(presence)
+            .map(|(record, online)| HistoryRecordPresence { record, online })
+            .collect(),
+    })
+}
+
+/// Searches contacts like `contact_search` together with the presence of their devices.
+#[tauri::command]
+#[tracing::instrument(skip(app_state))]
+pub async fn signaling_contact_presence(
+    app_state: tauri::State<'_, AppState>,
+    filter: ContactFilter,
+) -> CoreResult<Vec<ContactPresence>> {
+    let current_signaling = app_state.signaling_client.lock().await.clone();
+
+    let Some(storage) = app_state.storage.lock().await.clone() else {
+        return Err(core_error!("storage not initialize"));
+    };
+
+    let contacts = storage.contact().search(&filter)?;
+
+    let devices: Vec<(i64, &str)> = contacts
+        .iter()
+        .map(|contact| (contact.device_id, contact.domain.as_str()))
+        .collect();
+    let presence = query_presence(&storage, current_signaling, &devices).await?;
+
+    Ok(contacts
+        .into_iter()
+        .zip(presence)
+        .map(|(contact, online)| ContactPresence { contact, online })
+        .collect())
+}
+
+/// Returns whether each of the `(device id, domain)` devices is online, `None` for
+/// devices of other domains than the connected one or when the query failed.
+async fn query_presence(
+    storage: &LocalStorage,
+    current_signaling: Option<(i64, Arc<SignalingClient>)>,
+    devices: &[(i64, &str)],
+) -> CoreResult<Vec<Option<bool>>> {
+    let Some((current_domain_id, signaling_client)) = current_signaling else {
+        return Ok(vec![None; devices.len()]);
+    };
+
+    let current_domain = storage.domain().get_domain_by_id(current_domain_id)?;
+
+    let mut device_ids: Vec<i64> = devices
+        .iter()
+        .filter(|(_, domain)| *domain == current_domain.name)
+        .map(|(device_id, _)| *device_id)
+        .collect();
+    device_ids.sort_unstable();
+    device_ids.dedup();
+
+    let presence = match signaling_client.presence(&device_ids).await {
+        Ok(presence) => presence,
+        Err(err) => {
+            tracing::warn!(?err, "query device presence failed");
+            Default::default()
+        }
+    };
+
+    Ok(devices
+        .iter()
+        .map(|(device_id, domain)| {
+            if *domain == current_domain.name {
+                presence.get(device_id).copied()
+            } else {
+                None
+            }
+        })
+        .collect())
+}
@@ -23,6 +23,8 @@ async fn main() {
 
     let app = build_app();
 
+    mirrorx_core::utility::version::set_client_version(&app.package_info().version.to_string());
+
     let log_dir = app
         .path_resolver()
         .app_log_dir()
@@ -172,20 +174,68 @@ fn build_app() -> App {
             command::config::config_language_set,
             command::config::config_theme_get,
             command::config::config_theme_set,
+            command::config::config_proxy_get,
+            command::config::config_proxy_set,
+            command::config::config_settings_get,
+            command::config::config_settings_set,
+            command::config::config_setting_set,
+            command::config::config_export,
+            command::config::config_import,
             command::config::config_history_get,
+            command::config::config_audit_query,
+            command::config::config_audit_events,
+            command::config::config_audit_export,
+            command::config::config_temporary_password_generate,
+            command::config::config_temporary_password_list,
+            command::config::config_temporary_password_revoke,
+            command::contact::contact_create,
+            command::contact::contact_update,
+            command::contact::contact_set_favorite,
+            command::contact::contact_delete,
+            command::contact::contact_get,
+            command::contact::contact_get_by_device,
+            command::contact::contact_search,
+            command::contact::contact_group_names,
+            command::contact::contact_history,
             command::lan::lan_init,
             command::lan::lan_connect,
             command::lan::lan_nodes_list,
             command::lan::lan_nodes_search,
             command::lan::lan_discoverable_get,
             command::lan::lan_discoverable_set,
+            command::lan::lan_interfaces_list,
+            command::lan::lan_pinned_nodes_list,
+            command::lan::lan_pinned_node_delete,
+            command::lan::lan_wake,
+            command::lan::lan_peers_list,
+            command::lan::lan_peer_delete,
+            command::lan::lan_bookmarks_list,
+            command::lan::lan_bookmark_create,
+            command::lan::lan_bookmark_update,
+            command::lan::lan_bookmark_delete,
+            command::lan::lan_probe,
             command::signaling::signaling_connect,
             command::signaling::signaling_visit,
+            command::signaling::signaling_history_presence,
+            command::signaling::signaling_contact_presence,
             command::file_manager::file_manager_visit_remote,
             command::file_manager::file_manager_visit_local,
             command::file_manager::file_manager_send_file,
             command::file_manager::file_manager_download_file,
+            command::file_manager::file_manager_send_directory,
+            command::file_manager::file_manager_download_directory,
             command::file_manager::file_manager_query_transferred_bytes_count,
+            command::file_manager::file_manager_query_transfer_error,
+            command::file_manager::file_manager_query_directory_transfer,
+            command::file_manager::file_manager_transfers_list,
+            command::file_manager::file_manager_transfer_pause,
+            command::file_manager::file_manager_transfer_resume,
+            command::file_manager::file_manager_transfer_cancel,
+            command::file_manager::file_manager_transfers_clear_done,
+            command::file_manager::file_manager_create_directory,
+            command::file_manager::file_manager_rename,
+            command::file_manager::file_manager_copy,
+            command::file_manager::file_manager_delete,
             command::utility::utility_generate_random_password,
             command::utility::utility_detect_os_platform,
             command::utility::utility_enum_graphics_cards,
@@ -1,5 +1,34 @@
 import { invoke } from '@tauri-apps/api';
-import type { Directory, Domain, HistoryRecord, LanDiscoverNode } from '$lib/components/types';
+import type {
+	AuditEventRecord,
+	AuditFilter,
+	AuditPage,
+	Contact,
+	ContactFilter,
+	ContactPresence,
+	Directory,
+	DirectoryTransferOptions,
+	DirectoryTransferStatus,
+	Domain,
+	ExportOptions,
+	FileOperationError,
+	FileTransferErrorReason,
+	HistoryFilter,
+	HistoryPage,
+	HistoryPresencePage,
+	HistoryRecord,
+	ImportConflictPolicy,
+	ImportSummary,
+	LanBookmark,
+	LanDiscoverNode,
+	LanInterface,
+	LanPeer,
+	LanProbe,
+	PinnedNode,
+	ProxyConfig,
+	Settings,
+	TransferRecord
+} from '$lib/components/types';
 
 export function invoke_config_init(): Promise<void> {
 	return invoke('config_init');
@@ -55,18 +84,110 @@ export function invoke_config_theme_set(theme: 'light' | 'dark' | 'auto'): Promi
 	return invoke('config_theme_set', { theme });
 }
 
-export function invoke_config_history_get(
-	time_range: [number, number] | null
-): Promise<Array<HistoryRecord>> {
-	return invoke('config_history_get', { timeRange: time_range });
+export function invoke_config_proxy_get(): Promise<ProxyConfig> {
+	return invoke('config_proxy_get');
+}
+
+export function invoke_config_proxy_set(proxy: ProxyConfig): Promise<void> {
+	return invoke('config_proxy_set', { proxy });
+}
+
+export function invoke_config_settings_get(): Promise<Settings> {
+	return invoke('config_settings_get');
+}
+
+export function invoke_config_settings_set(settings: Settings): Promise<void> {
+	return invoke('config_settings_set', { settings });
+}
+
+export function invoke_config_setting_set<K extends keyof Settings>(
+	key: K,
+	value: Settings[K]
+): Promise<Settings> {
+	return invoke('config_setting_set', { key, value });
+}
+
+export function invoke_config_export(
+	path: string,
+	passphrase: string | null,
+	options: ExportOptions
+): Promise<void> {
+	return invoke('config_export', { path, passphrase, options });
+}
+
+export function invoke_config_import(
+	path: string,
+	passphrase: string | null,
+	conflictPolicy: ImportConflictPolicy
+): Promise<ImportSummary> {
+	return invoke('config_import', { path, passphrase, conflictPolicy });
+}
+
+export function invoke_config_history_get(filter: HistoryFilter): Promise<HistoryPage> {
+	return invoke('config_history_get', { filter });
+}
+
+export function invoke_config_audit_query(filter: AuditFilter): Promise<AuditPage> {
+	return invoke('config_audit_query', { filter });
+}
+
+export function invoke_config_audit_events(session_id: number): Promise<Array<AuditEventRecord>> {
+	return invoke('config_audit_events', { sessionId: session_id });
+}
+
+export function invoke_config_audit_export(path: string, filter: AuditFilter): Promise<void> {
+	return invoke('config_audit_export', { path, filter });
+}
+
+export function invoke_contact_create(contact: Contact): Promise<Contact> {
+	return invoke('contact_create', { contact });
+}
+
+export function invoke_contact_update(contact: Contact): Promise<void> {
+	return invoke('contact_update', { contact });
+}
+
+export function invoke_contact_set_favorite(id: number, favorite: boolean): Promise<void> {
+	return invoke('contact_set_favorite', { id, favorite });
+}
+
+export function invoke_contact_delete(id: number): Promise<void> {
+	return invoke('contact_delete', { id });
+}
+
+export function invoke_contact_get(id: number): Promise<Contact> {
+	return invoke('contact_get', { id });
+}
+
+export function invoke_contact_get_by_device(
+	deviceId: number,
+	domain: string
+): Promise<Contact | null> {
+	return invoke('contact_get_by_device', { deviceId, domain });
+}
+
+export function invoke_contact_search(filter: ContactFilter): Promise<Array<Contact>> {
+	return invoke('contact_search', { filter });
+}
+
+export function invoke_contact_group_names(): Promise<Array<string>> {
+	return invoke('contact_group_names');
+}
+
+export function invoke_contact_history(id: number): Promise<Array<HistoryRecord>> {
+	return invoke('contact_history', { id });
 }
 
 export function invoke_lan_init(force: boolean): Promise<void> {
 	return invoke('lan_init', { force });
 }
 
-export function invoke_lan_connect(addr: string, visitDesktop: boolean): Promise<void> {
-	return invoke('lan_connect', { addr, visitDesktop });
+export function invoke_lan_connect(
+	addr: string,
+	port: number | null,
+	visitDesktop: boolean
+): Promise<void> {
+	return invoke('lan_connect', { addr, port, visitDesktop });
 }
 
 export function invoke_lan_nodes_list(): Promise<Array<LanDiscoverNode>> {
@@ -85,6 +206,50 @@ export function invoke_lan_discoverable_set(discoverable: boolean): Promise<void
 	return invoke('lan_discoverable_set', { discoverable });
 }
 
+export function invoke_lan_interfaces_list(): Promise<Array<LanInterface>> {
+	return invoke('lan_interfaces_list');
+}
+
+export function invoke_lan_pinned_nodes_list(): Promise<Array<PinnedNode>> {
+	return invoke('lan_pinned_nodes_list');
+}
+
+export function invoke_lan_pinned_node_delete(nodeId: string): Promise<void> {
+	return invoke('lan_pinned_node_delete', { nodeId });
+}
+
+export function invoke_lan_wake(addr: string, timeoutSecs?: number): Promise<LanDiscoverNode> {
+	return invoke('lan_wake', { addr, timeoutSecs });
+}
+
+export function invoke_lan_peers_list(): Promise<Array<LanPeer>> {
+	return invoke('lan_peers_list');
+}
+
+export function invoke_lan_peer_delete(addr: string): Promise<void> {
+	return invoke('lan_peer_delete', { addr });
+}
+
+export function invoke_lan_bookmarks_list(): Promise<Array<LanBookmark>> {
+	return invoke('lan_bookmarks_list');
+}
+
+export function invoke_lan_bookmark_create(bookmark: LanBookmark): Promise<LanBookmark> {
+	return invoke('lan_bookmark_create', { bookmark });
+}
+
+export function invoke_lan_bookmark_update(bookmark: LanBookmark): Promise<void> {
+	return invoke('lan_bookmark_update', { bookmark });
+}
+
+export function invoke_lan_bookmark_delete(id: number): Promise<void> {
+	return invoke('lan_bookmark_delete', { id });
+}
+
+export function invoke_lan_probe(host: string, port: number | null): Promise<LanProbe> {
+	return invoke('lan_probe', { host, port });
+}
+
 export function invoke_signaling_connect(force: boolean): Promise<void> {
 	return invoke('signaling_connect', { force });
 }
@@ -97,6 +262,18 @@ export function invoke_signaling_visit(
 	return invoke('signaling_visit', { remoteDeviceId, password, visitDesktop });
 }
 
+export function invoke_signaling_history_presence(
+	filter: HistoryFilter
+): Promise<HistoryPresencePage> {
+	return invoke('signaling_history_presence', { filter });
+}
+
+export function invoke_signaling_contact_presence(
+	filter: ContactFilter
+): Promise<Array<ContactPresence>> {
+	return invoke('signaling_contact_presence', { filter });
+}
+
 export function invoke_file_manager_visit_remote(
 	remoteDeviceId: string,
 	path: string | null
@@ -119,13 +296,109 @@ export function invoke_file_manager_send_file(
 export function invoke_file_manager_download_file(
 	remoteDeviceId: string,
 	localPath: string,
-	remotePath: string
+	remotePath: string,
+	size: number
 ): Promise<[string, number]> {
-	return invoke('file_manager_download_file', { remoteDeviceId, localPath, remotePath });
+	return invoke('file_manager_download_file', { remoteDeviceId, localPath, remotePath, size });
+}
+
+export function invoke_file_manager_send_directory(
+	remoteDeviceId: string,
+	localPath: string,
+	remotePath: string,
+	options: DirectoryTransferOptions | null
+): Promise<[string, number]> {
+	return invoke('file_manager_send_directory', {
+		remoteDeviceId,
+		localPath,
+		remotePath,
+		options
+	});
+}
+
+export function invoke_file_manager_download_directory(
+	remoteDeviceId: string,
+	localPath: string,
+	remotePath: string,
+	options: DirectoryTransferOptions | null
+): Promise<[string, number]> {
+	return invoke('file_manager_download_directory', {
+		remoteDeviceId,
+		localPath,
+		remotePath,
+		options
+	});
+}
+
+export function invoke_file_manager_query_transferred_bytes_count(
+	remoteDeviceId: string,
+	id: string
+): Promise<number> {
+	return invoke('file_manager_query_transferred_bytes_count', { remoteDeviceId, id });
+}
+
+export function invoke_file_manager_query_transfer_error(
+	remoteDeviceId: string,
+	id: string
+): Promise<FileTransferErrorReason | null> {
+	return invoke('file_manager_query_transfer_error', { remoteDeviceId, id });
+}
+
+export function invoke_file_manager_query_directory_transfer(
+	id: string
+): Promise<DirectoryTransferStatus | null> {
+	return invoke('file_manager_query_directory_transfer', { id });
+}
+
+export function invoke_file_manager_transfers_list(): Promise<Array<TransferRecord>> {
+	return invoke('file_manager_transfers_list');
+}
+
+export function invoke_file_manager_transfer_pause(id: string): Promise<void> {
+	return invoke('file_manager_transfer_pause', { id });
+}
+
+export function invoke_file_manager_transfer_resume(id: string): Promise<void> {
+	return invoke('file_manager_transfer_resume', { id });
+}
+
+export function invoke_file_manager_transfer_cancel(id: string): Promise<void> {
+	return invoke('file_manager_transfer_cancel', { id });
+}
+
+export function invoke_file_manager_transfers_clear_done(): Promise<void> {
+	return invoke('file_manager_transfers_clear_done');
+}
+
+export function invoke_file_manager_create_directory(
+	remoteDeviceId: string | null,
+	path: string
+): Promise<Array<FileOperationError>> {
+	return invoke('file_manager_create_directory', { remoteDeviceId, path });
+}
+
+export function invoke_file_manager_rename(
+	remoteDeviceId: string | null,
+	from: string,
+	to: string
+): Promise<Array<FileOperationError>> {
+	return invoke('file_manager_rename', { remoteDeviceId, from, to });
+}
+
+export function invoke_file_manager_copy(
+	remoteDeviceId: string | null,
+	from: string,
+	to: string
+): Promise<Array<FileOperationError>> {
+	return invoke('file_manager_copy', { remoteDeviceId, from, to });
 }
 
-export function invoke_file_manager_query_transferred_bytes_count(id: string): Promise<number> {
-	return invoke('file_manager_query_transferred_bytes_count', { id });
+export function invoke_file_manager_delete(
+	remoteDeviceId: string | null,
+	path: string,
+	recursive: boolean
+): Promise<Array<FileOperationError>> {
+	return invoke('file_manager_delete', { remoteDeviceId, path, recursive });
 }
 
 export function invoke_utility_generate_random_password(): Promise<string> {
@@ -13,18 +13,123 @@ export interface Domain {
 	remarks: string;
 }
 
+export type VideoCodec = 'H264' | 'Hevc' | 'VP8' | 'VP9';
+
+export type NodeTrust = 'unsigned' | 'unknown' | 'trusted' | 'key_changed';
+
 export interface LanDiscoverNode {
 	host_name: string;
 	addr: string;
+	port: number;
 	os: string;
 	os_version: string;
+	node_id: string | null;
+	fingerprint: string | null;
+	trust: NodeTrust;
+	capabilities: NodeCapabilities;
+}
+
+export interface NodeCapabilities {
+	protocol_version: number;
+	client_version: string;
+	session_types: Array<SessionType>;
+	video_codecs: Array<VideoCodec>;
+	password_required: boolean;
+	device_id: number | null;
+	session_count: number;
+	mac_addresses: Array<string>;
+}
+
+export interface PinnedNode {
+	node_id: string;
+	fingerprint: string;
+	host_name: string;
+	first_seen: number;
+	last_seen: number;
+}
+
+export interface LanInterface {
+	name: string;
+	ip: string;
+	broadcast: string | null;
+	netmask: string | null;
+	mac: string | null;
 }
 
+export interface LanBookmark {
+	id: number;
+	alias: string;
+	host: string;
+	port: number;
+	created_at: number;
+	updated_at: number;
+	last_connected_at: number | null;
+}
+
+export interface LanProbe {
+	addr: string;
+	latency_ms: number;
+	node_id: string | null;
+	fingerprint: string | null;
+}
+
+export interface LanPeer {
+	addr: string;
+	mac_addresses: Array<string>;
+	host_name: string;
+	node_id: string | null;
+	updated_at: number;
+}
+
+export type SessionType = 'desktop' | 'file_manager' | 'unknown';
+
+export type ConnectionType = 'lan' | 'domain';
+
+export type SessionResult =
+	| { kind: 'success' }
+	| { kind: 'visit_failed'; detail: string }
+	| { kind: 'transport_error'; detail: string };
+
 export interface HistoryRecord {
 	id: number;
 	device_id: number;
 	domain: string;
 	timestamp: number;
+	remote_addr: string;
+	end_time: number | null;
+	session_type: SessionType;
+	connection_type: ConnectionType;
+	result: SessionResult;
+	bytes_sent: number;
+	bytes_received: number;
+	peer_os: string | null;
+	contact_id: number | null;
+	contact_alias: string | null;
+}
+
+export interface HistoryFilter {
+	time_range: [number, number] | null;
+	device_id: number | null;
+	domain: string | null;
+	session_type: SessionType | null;
+	connection_type: ConnectionType | null;
+	result: 'success' | 'failed' | null;
+	page: number | null;
+	limit: number | null;
+}
+
+export interface HistoryPage {
+	total: number;
+	records: Array<HistoryRecord>;
+}
+
+export interface HistoryRecordPresence extends HistoryRecord {
+	online: boolean | null;
+}
+
+export interface HistoryPresencePage {
+	total: number;
+	records: Array<HistoryRecordPresence>;
 }
 
 export interface Directory {
@@ -51,4 +156,201 @@ export interface FileTransferItem {
 	launch_at: number;
 	succeed_at: number;
 	failed_at: number;
+	failed_reason?: FileTransferErrorReason;
+	is_directory?: boolean;
+	errors?: Array<TreeEntryError>;
+	eta_secs?: number | null;
+}
+
+export interface DirectoryTransferOptions {
+	symlinks: 'skip' | 'follow';
+	include: Array<string>;
+	exclude: Array<string>;
+}
+
+export interface TreeEntryError {
+	path: string;
+	message: string;
+}
+
+export interface DirectoryTransferStatus {
+	total_files: number;
+	transferred_files: number;
+	total_size: number;
+	transferred_size: number;
+	finished: boolean;
+	errors: Array<TreeEntryError>;
+}
+
+export type ProxyConfig =
+	| { mode: 'system' }
+	| { mode: 'none' }
+	| {
+			mode: 'manual';
+			kind: 'http' | 'socks5';
+			host: string;
+			port: number;
+			username: string | null;
+			password: string | null;
+	  };
+
+export interface Settings {
+	frame_rate: number;
+	max_bitrate_kbps: number;
+	preferred_codec: VideoCodec;
+	audio_enabled: boolean;
+	lan_discoverable: boolean;
+	lan_legacy_broadcast: boolean;
+	lan_discover_port: number;
+	lan_server_port: number;
+	lan_interface: string | null;
+	audit_retention_days: number;
+	transfer_concurrency: number;
+}
+
+export type AuditCredential = 'password' | 'temporary_password' | 'one_time_password' | 'lan';
+
+export interface AuditSessionRecord {
+	id: number;
+	device_id: number | null;
+	remote_ip: string;
+	domain: string;
+	credential: AuditCredential;
+	permissions: Array<string>;
+	start_time: number;
+	end_time: number | null;
+}
+
+export interface AuditEventRecord {
+	id: number;
+	session_id: number;
+	timestamp: number;
+	operation:
+		| 'visit_directory'
+		| 'send_file'
+		| 'download_file'
+		| 'send_directory'
+		| 'download_directory'
+		| 'create_directory'
+		| 'rename'
+		| 'copy'
+		| 'delete';
+	path: string;
+	destination: string | null;
+	size: number | null;
+	error: string;
+}
+
+export interface AuditFilter {
+	time_range: [number, number] | null;
+	keyword: string | null;
+	page: number | null;
+	limit: number | null;
+}
+
+export interface AuditPage {
+	total: number;
+	sessions: Array<AuditSessionRecord>;
+}
+
+export interface ExportOptions {
+	include_secrets: boolean;
+	include_identities: boolean;
+}
+
+export type ImportConflictPolicy = 'skip' | 'overwrite' | 'rename';
+
+export interface ImportSummary {
+	domains_imported: number;
+	domains_overwritten: number;
+	domains_renamed: number;
+	domains_skipped: number;
+	settings_imported: number;
+	history_imported: number;
+}
+
+export type ConnectionPreference = 'desktop' | 'file_manager';
+
+export interface Contact {
+	id: number;
+	device_id: number;
+	domain: string;
+	alias: string;
+	group_name: string;
+	tags: Array<string>;
+	notes: string;
+	connection_preference: ConnectionPreference;
+	favorite: boolean;
+	created_at: number;
+	updated_at: number;
+}
+
+export interface ContactPresence extends Contact {
+	online: boolean | null;
+}
+
+export interface ContactFilter {
+	keyword: string | null;
+	group_name: string | null;
+	favorite_only: boolean;
+}
+
+export type FileTransferErrorReason =
+	| 'session_not_found'
+	| 'read_failed'
+	| 'write_failed'
+	| 'digest_mismatch'
+	| 'already_exists'
+	| 'disconnected'
+	| 'paused'
+	| 'cancelled';
+
+export type TransferKind = 'upload' | 'download' | 'upload_directory' | 'download_directory';
+
+export type TransferState = 'queued' | 'running' | 'paused' | 'done' | 'failed';
+
+export interface TransferRecord {
+	id: string;
+	remote_device_id: string;
+	kind: TransferKind;
+	local_path: string;
+	remote_path: string;
+	total_size: number;
+	transferred_size: number;
+	state: TransferState;
+	error: string | null;
+	created_at: number;
+	updated_at: number;
+	options: DirectoryTransferOptions | null;
+}
+
+export type TransferEvent =
+	| {
+			type: 'progress';
+			id: string;
+			transferred_size: number;
+			total_size: number;
+			rate: number;
+			eta_secs: number | null;
+	  }
+	| { type: 'state'; id: string; state: TransferState }
+	| { type: 'error'; id: string; reason: FileTransferErrorReason };
+
+export interface FileTransferEvent {
+	remote_device_id: string;
+	event: TransferEvent;
+}
+
+export type FileOperationErrorKind =
+	| 'not_found'
+	| 'already_exists'
+	| 'permission_denied'
+	| 'not_empty'
+	| 'invalid_path'
+	| 'other';
+
+export interface FileOperationError {
+	path: string;
+	kind: FileOperationErrorKind;
+	message: string;
 }
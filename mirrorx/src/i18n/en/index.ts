@@ -64,7 +64,10 @@ const en: BaseTranslation = {
 
 			ContextMenu: {
 				SendToRemote: 'Send to Remote',
-				DownloadToLocal: 'Download to Local'
+				DownloadToLocal: 'Download to Local',
+				Delete: 'Delete',
+				DeleteConfirm: 'Delete the selected entry?',
+				DeleteRecursiveConfirm: "The directory isn't empty, delete it with all its content?"
 			}
 		},
 		Transfer: {
@@ -215,6 +215,18 @@ type RootTranslation = {
 				 * D​o​w​n​l​o​a​d​ ​t​o​ ​L​o​c​a​l
 				 */
 				DownloadToLocal: string
+				/**
+				 * D​e​l​e​t​e
+				 */
+				Delete: string
+				/**
+				 * D​e​l​e​t​e​ ​t​h​e​ ​s​e​l​e​c​t​e​d​ ​e​n​t​r​y​?
+				 */
+				DeleteConfirm: string
+				/**
+				 * T​h​e​ ​d​i​r​e​c​t​o​r​y​ ​i​s​n​'​t​ ​e​m​p​t​y​,​ ​d​e​l​e​t​e​ ​i​t​ ​w​i​t​h​ ​a​l​l​ ​i​t​s​ ​c​o​n​t​e​n​t​?
+				 */
+				DeleteRecursiveConfirm: string
 			}
 		}
 		Transfer: {
@@ -620,6 +632,18 @@ export type TranslationFunctions = {
 				 * Download to Local
 				 */
 				DownloadToLocal: () => LocalizedString
+				/**
+				 * Delete
+				 */
+				Delete: () => LocalizedString
+				/**
+				 * Delete the selected entry?
+				 */
+				DeleteConfirm: () => LocalizedString
+				/**
+				 * The directory isn't empty, delete it with all its content?
+				 */
+				DeleteRecursiveConfirm: () => LocalizedString
 			}
 		}
 		Transfer: {
@@ -55,7 +55,10 @@ const zh_Hans: Translation = {
 
 			ContextMenu: {
 				SendToRemote: '发送到远端',
-				DownloadToLocal: '下载到本地'
+				DownloadToLocal: '下载到本地',
+				Delete: '删除',
+				DeleteConfirm: '确定删除所选项目？',
+				DeleteRecursiveConfirm: '目录不为空，是否删除其中的全部内容？'
 			}
 		},
 		Transfer: {
@@ -0,0 +1,367 @@
+use super::{
+    cipher::{is_storage_key_entry, StorageCipher},
+    entity::{
+        contact::{Contact, ContactFilter},
+        domain::Domain,
+        history::{HistoryFilter, Record},
+    },
+    settings::SETTINGS_KEY_PREFIX,
+    LocalStorage,
+};
+use crate::{
+    core_error,
+    error::CoreResult,
+    utility::{
+        proxy::ProxyConfig,
+        rand::{generate_device_finger_print, generate_random_password},
+    },
+};
+use rand::RngCore;
+use rsa::rand_core::OsRng;
+use rusqlite::Connection;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// Version of [`ConfigBundle`], bumped on incompatible changes of the bundle layout.
+pub const BUNDLE_VERSION: u32 = 1;
+
+/// Portable copy of the local storage. Domains carry their trusted server identity
+/// (`tls_pin`), device secrets and identities only when [`ExportOptions`] asked for
+/// them, which requires a passphrase.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ConfigBundle {
+    pub version: u32,
+    pub exported_at: i64,
+    pub domains: Vec<Domain>,
+    pub settings: Vec<(String, String)>,
+    pub history: Vec<Record>,
+    #[serde(default)]
+    pub contacts: Vec<Contact>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "format", rename_all = "snake_case")]
+enum BundleFile {
+    Plain { bundle: ConfigBundle },
+    Encrypted { salt: String, bundle: String },
+}
+
+/// What [`export_bundle`] includes next to domains, settings, history and contacts.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+pub struct ExportOptions {
+    /// Device passwords of domains and the proxy password.
+    pub include_secrets: bool,
+    /// Device id and finger print of domains. Importing them on another machine makes
+    /// it the same device in those domains, only meant to move an installation.
+    pub include_identities: bool,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ImportConflictPolicy {
+    /// Keep the local domain and ignore the imported one.
+    Skip,
+    /// Replace the local domain with the imported one.
+    Overwrite,
+    /// Import the domain under a new name like `name (2)`.
+    Rename,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct ImportSummary {
+    pub domains_imported: u32,
+    pub domains_overwritten: u32,
+    pub domains_renamed: u32,
+    pub domains_skipped: u32,
+    pub settings_imported: u32,
+    pub history_imported: u32,
+    pub contacts_imported: u32,
+}
+
+/// Exports the storage, secrets and identities are left out unless `options` asks for
+/// them, which is only allowed with a `passphrase` encrypting the bundle.
+pub fn export_bundle(
+    storage: &LocalStorage,
+    passphrase: Option<&str>,
+    options: ExportOptions,
+) -> CoreResult<String> {
+    if passphrase.is_none() && (options.include_secrets || options.include_identities) {
+        return Err(core_error!(
+            "exporting secrets or identities requires a passphrase"
+        ));
+    }
+
+    let (_, mut domains) = storage
+        .domain()
+        .get_domains(1, storage.domain().get_domain_count()?.max(1))?;
+
+    for domain in domains.iter_mut() {
+        if !options.include_secrets {
+            domain.password = String::default();
+        }
+
+        if !options.include_identities {
+            domain.device_id = 0;
+            domain.finger_print = String::default();
+            domain.expire = 0;
+        }
+    }
+
+    let mut settings = Vec::new();
+    for (key, value) in storage.kv().get_all()? {
+        if is_storage_key_entry(&key) {
+            continue;
+        }
+
+        if key == "proxy" && !options.include_secrets {
+            let mut proxy: ProxyConfig = serde_json::from_str(&value)?;
+            if let ProxyConfig::Manual(ref mut proxy) = proxy {
+                proxy.password = None;
+            }
+
+            settings.push((key, serde_json::to_string(&proxy)?));
+        } else {
+            settings.push((key, value));
+        }
+    }
+
+    let bundle = ConfigBundle {
+        version: BUNDLE_VERSION,
+        exported_at: chrono::Utc::now().timestamp(),
+        domains,
+        settings,
+        history: storage.history().query(&HistoryFilter::default())?.records,
+        contacts: storage.contact().search(&ContactFilter::default())?,
+    };
+
+    let file = match passphrase {
+        Some(passphrase) => {
+            let mut salt = [0u8; 16];
+            OsRng.fill_bytes(&mut salt);
+
+            let cipher = StorageCipher::from_passphrase(passphrase, &salt)?;
+
+            BundleFile::Encrypted {
+                salt: base64::encode(salt),
+                bundle: cipher.encrypt(&serde_json::to_string(&bundle)?)?,
+            }
+        }
+        None => BundleFile::Plain { bundle },
+    };
+
+    Ok(serde_json::to_string_pretty(&file)?)
+}
+
+pub fn import_bundle(
+    storage: &LocalStorage,
+    content: &str,
+    passphrase: Option<&str>,
+    policy: ImportConflictPolicy,
+) -> CoreResult<ImportSummary> {
+    let bundle = match serde_json::from_str::<BundleFile>(content)? {
+        BundleFile::Plain { bundle } => bundle,
+        BundleFile::Encrypted { salt, bundle } => {
+            let Some(passphrase) = passphrase else {
+                return Err(core_error!("bundle is encrypted, passphrase required"));
+            };
+
+            let cipher = StorageCipher::from_passphrase(passphrase, &base64::decode(salt)?)?;
+            let bundle = cipher
+                .decrypt(&bundle)
+                .map_err(|_| core_error!("decrypt bundle failed, wrong passphrase"))?;
+
+            serde_json::from_str::<ConfigBundle>(&bundle)?
+        }
+    };
+
+    if bundle.version > BUNDLE_VERSION {
+        return Err(core_error!(
+            "bundle version {} is newer than supported version {}",
+            bundle.version,
+            BUNDLE_VERSION
+        ));
+    }
+
+    let has_domains = storage.domain().get_domain_count()? > 0;
+
+    // nothing is kept when any part fails
+    let summary =
+        storage.transaction(|conn| import_entries(storage, conn, bundle, policy, has_domains))?;
+
+    tracing::info!(?summary, "import config bundle");
+
+    Ok(summary)
+}
+
+fn import_entries(
+    storage: &LocalStorage,
+    conn: &Connection,
+    bundle: ConfigBundle,
+    policy: ImportConflictPolicy,
+    has_domains: bool,
+) -> CoreResult<ImportSummary> {
+    let mut summary = ImportSummary::default();
+    let mut renamed_domains = HashMap::new();
+
+    for mut domain in bundle.domains {
+        // the primary domain of this device stays unless the storage is empty
+        let is_primary = domain.is_primary && !has_domains;
+        domain.is_primary = false;
+
+        if storage.domain().domain_exist_in(conn, &domain.name)? {
+            match policy {
+                ImportConflictPolicy::Skip => {
+                    summary.domains_skipped += 1;
+                    continue;
+                }
+                ImportConflictPolicy::Overwrite => {
+                    let existing = storage
+                        .domain()
+                        .get_domain_by_name_in(conn, domain.name.clone())?;
+
+                    // secrets and identities left out of the bundle stay as they are
+                    if domain.password.is_empty() {
+                        domain.password = existing.password;
+                    }
+                    if domain.finger_print.is_empty() {
+                        domain.device_id = existing.device_id;
+                        domain.finger_print = existing.finger_print;
+                        domain.expire = existing.expire;
+                    }
+
+                    storage
+                        .domain()
+                        .update_domain_in(conn, existing.id, &domain)?;
+                    summary.domains_overwritten += 1;
+                    continue;
+                }
+                ImportConflictPolicy::Rename => {
+                    let name = available_domain_name(storage, conn, &domain.name)?;
+                    renamed_domains.insert(domain.name.clone(), name.clone());
+                    domain.name = name;
+                    summary.domains_renamed += 1;
+                }
+            }
+        } else {
+            summary.domains_imported += 1;
+        }
+
+        // a domain without identity registers as a new device when connecting
+        if domain.finger_print.is_empty() {
+            domain.device_id = 0;
+            domain.finger_print = generate_device_finger_print();
+            domain.expire = 0;
+        }
+        if domain.password.is_empty() {
+            domain.password = generate_random_password();
+        }
+
+        let domain = storage.domain().add_domain_in(conn, domain)?;
+        if is_primary {
+            storage.domain().set_domain_is_primary_in(conn, domain.id)?;
+        }
+    }
+
+    let mut setting_entries = Vec::new();
+    for (key, value) in bundle.settings {
+        if is_storage_key_entry(&key) {
+            continue;
+        }
+
+        if key.starts_with(SETTINGS_KEY_PREFIX) {
+            setting_entries.push((key, value));
+            continue;
+        }
+
+        let value = if key == "proxy" {
+            let proxy = keep_proxy_password(
+                storage.kv().get_proxy_in(conn)?,
+                serde_json::from_str(&value)?,
+            );
+            serde_json::to_string(&proxy)?
+        } else {
+            value
+        };
+
+        storage.kv().import_in(conn, &key, &value)?;
+        summary.settings_imported += 1;
+    }
+
+    // invalid settings fail the import like they fail being set one by one
+    if !setting_entries.is_empty() {
+        let settings = storage
+            .kv()
+            .get_settings()?
+            .with_entries(&setting_entries)?;
+        storage.kv().set_settings_in(conn, &settings)?;
+        summary.settings_imported += setting_entries.len() as u32;
+    }
+
+    for mut record in bundle.history {
+        if let Some(domain) = renamed_domains.get(&record.domain) {
+            record.domain = domain.clone();
+        }
+
+        if storage.history().import_in(conn, &record)? {
+            summary.history_imported += 1;
+        }
+    }
+
+    for mut contact in bundle.contacts {
+        if let Some(domain) = renamed_domains.get(&contact.domain) {
+            contact.domain = domain.clone();
+        }
+
+        match storage
+            .contact()
+            .get_by_device_in(conn, contact.device_id, &contact.domain)?
+        {
+            Some(existing) => {
+                if let ImportConflictPolicy::Overwrite = policy {
+                    contact.id = existing.id;
+                    storage.contact().update_in(conn, &contact)?;
+                    summary.contacts_imported += 1;
+                }
+            }
+            None => {
+                storage.contact().create_in(conn, contact)?;
+                summary.contacts_imported += 1;
+            }
+        }
+    }
+
+    Ok(summary)
+}
+
+/// Bundles exported without secrets carry a manual proxy without its password, the
+/// local password is kept when the imported proxy is the same server and user.
+fn keep_proxy_password(local: Option<ProxyConfig>, imported: ProxyConfig) -> ProxyConfig {
+    match (local, imported) {
+        (Some(ProxyConfig::Manual(local)), ProxyConfig::Manual(mut imported))
+            if imported.password.is_none()
+                && imported.kind == local.kind
+                && imported.host == local.host
+                && imported.port == local.port
+                && imported.username == local.username =>
+        {
+            imported.password = local.password;
+            ProxyConfig::Manual(imported)
+        }
+        (_, imported) => imported,
+    }
+}
+
+fn available_domain_name(
+    storage: &LocalStorage,
+    conn: &Connection,
+    name: &str,
+) -> CoreResult<String> {
+    let mut index = 2;
+    loop {
+        let candidate = format!("{name} ({index})");
+        if !storage.domain().domain_exist_in(conn, &candidate)? {
+            return Ok(candidate);
+        }
+        index += 1;
+    }
+}
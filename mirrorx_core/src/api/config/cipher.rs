@@ -0,0 +1,410 @@
+use crate::{core_error, error::CoreResult};
+use hmac::Hmac;
+use ring::{
+    aead::{Aad, LessSafeKey, Nonce, UnboundKey, AES_256_GCM, NONCE_LEN},
+    rand::{SecureRandom, SystemRandom},
+};
+use rusqlite::{Connection, OptionalExtension};
+use sha2::Sha256;
+use std::{
+    io::{ErrorKind, Write},
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+/// Prefix of encrypted column values, values without it are legacy plaintext.
+const ENCRYPTED_PREFIX: &str = "enc:v1:";
+
+/// Setting this variable derives the storage key from a passphrase instead of the OS
+/// keyring, useful on headless machines without a secret service.
+const PASSPHRASE_ENV: &str = "MIRRORX_STORAGE_PASSPHRASE";
+
+const KEYRING_SERVICE: &str = "MirrorX";
+const KEYRING_USER: &str = "storage-key";
+
+const PASSPHRASE_SALT_KEY: &str = "storage_key_salt";
+const PASSPHRASE_ROUNDS: u32 = 100_000;
+
+/// Key of the kv entry recording the [`KeySource`] the storage is encrypted with.
+const KEY_SOURCE_KEY: &str = "storage_key_source";
+
+const KEY_FILE_NAME: &str = "mirrorx.key";
+
+/// Whether the kv entry `key` describes the storage key of this storage, such entries
+/// never leave it.
+pub(crate) fn is_storage_key_entry(key: &str) -> bool {
+    key == PASSPHRASE_SALT_KEY || key == KEY_SOURCE_KEY
+}
+
+/// Where the storage key comes from. The source a storage was first encrypted with is
+/// recorded in it and the only one used afterwards, so a key of another source never
+/// replaces it silently.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeySource {
+    Passphrase,
+    Keyring,
+    File,
+}
+
+impl KeySource {
+    fn as_str(&self) -> &'static str {
+        match self {
+            KeySource::Passphrase => "passphrase",
+            KeySource::Keyring => "keyring",
+            KeySource::File => "file",
+        }
+    }
+
+    fn parse(value: &str) -> CoreResult<Self> {
+        match value {
+            "passphrase" => Ok(KeySource::Passphrase),
+            "keyring" => Ok(KeySource::Keyring),
+            "file" => Ok(KeySource::File),
+            _ => Err(core_error!("unknown storage key source '{}'", value)),
+        }
+    }
+}
+
+/// Keeps the storage key outside of the database.
+pub trait KeyStore: Send + Sync {
+    /// Reads the key, `None` when it was never stored. Fails when the store can't be
+    /// reached, e.g. the keychain is locked.
+    fn read(&self) -> CoreResult<Option<Vec<u8>>>;
+
+    fn write(&self, raw_key: &[u8]) -> CoreResult<()>;
+}
+
+/// Keeps the key in the OS keyring.
+pub struct KeyringKeyStore;
+
+impl KeyStore for KeyringKeyStore {
+    fn read(&self) -> CoreResult<Option<Vec<u8>>> {
+        match keyring::Entry::new(KEYRING_SERVICE, KEYRING_USER).get_password() {
+            Ok(encoded) => Ok(Some(base64::decode(encoded)?)),
+            Err(keyring::Error::NoEntry) => Ok(None),
+            Err(err) => Err(core_error!("read key from os keyring failed ({})", err)),
+        }
+    }
+
+    fn write(&self, raw_key: &[u8]) -> CoreResult<()> {
+        keyring::Entry::new(KEYRING_SERVICE, KEYRING_USER)
+            .set_password(&base64::encode(raw_key))
+            .map_err(|err| core_error!("store key to os keyring failed ({})", err))
+    }
+}
+
+/// Keeps the key in a file only readable by the current user.
+pub struct FileKeyStore {
+    path: PathBuf,
+}
+
+impl FileKeyStore {
+    pub fn new(path: PathBuf) -> Self {
+        Self { path }
+    }
+}
+
+impl KeyStore for FileKeyStore {
+    fn read(&self) -> CoreResult<Option<Vec<u8>>> {
+        match std::fs::read_to_string(&self.path) {
+            Ok(encoded) => Ok(Some(base64::decode(encoded.trim())?)),
+            Err(err) if err.kind() == ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    fn write(&self, raw_key: &[u8]) -> CoreResult<()> {
+        let mut options = std::fs::OpenOptions::new();
+        options.write(true).create_new(true);
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.mode(0o600);
+        }
+
+        options
+            .open(&self.path)?
+            .write_all(base64::encode(raw_key).as_bytes())?;
+
+        Ok(())
+    }
+}
+
+/// Sources the storage key can be loaded from.
+pub struct KeyStores {
+    pub passphrase: Option<String>,
+    pub keyring: Arc<dyn KeyStore>,
+    pub file: Arc<dyn KeyStore>,
+}
+
+impl KeyStores {
+    /// Sources of this machine, the passphrase is given by `MIRRORX_STORAGE_PASSPHRASE`
+    /// and the key file lies next to the database.
+    pub fn system(db_path: &Path) -> Self {
+        Self {
+            passphrase: std::env::var(PASSPHRASE_ENV).ok(),
+            keyring: Arc::new(KeyringKeyStore),
+            file: Arc::new(FileKeyStore::new(db_path.with_file_name(KEY_FILE_NAME))),
+        }
+    }
+}
+
+/// Encrypts sensitive columns of the local storage with AES-256-GCM.
+pub struct StorageCipher {
+    key: LessSafeKey,
+    random: SystemRandom,
+}
+
+impl StorageCipher {
+    /// Loads the storage key from the source recorded in the storage. A new storage
+    /// prefers a passphrase, then the OS keyring, then a key file. Fails rather than
+    /// replacing a key which can't be reached while encrypted values exist.
+    pub fn load(conn: &Connection, key_stores: &KeyStores) -> CoreResult<Self> {
+        let random = SystemRandom::new();
+        let sample = encrypted_sample(conn)?;
+
+        let recorded: Option<String> = conn
+            .query_row(
+                "SELECT value FROM kv WHERE key = ? LIMIT 1",
+                [KEY_SOURCE_KEY],
+                |row| row.get(0),
+            )
+            .optional()?;
+
+        let (source, raw_key) = match recorded {
+            Some(source) => {
+                let source = KeySource::parse(&source)?;
+                let raw_key = load_key(conn, &random, key_stores, source, sample.is_some())?;
+                (source, raw_key)
+            }
+            None => match sample {
+                Some(ref sample) => find_legacy_key(conn, key_stores, sample)?,
+                None => {
+                    let source = if key_stores.passphrase.is_some() {
+                        KeySource::Passphrase
+                    } else if key_stores.keyring.read().is_ok() {
+                        KeySource::Keyring
+                    } else {
+                        tracing::warn!("os keyring unavailable, use key file");
+                        KeySource::File
+                    };
+
+                    (source, load_key(conn, &random, key_stores, source, false)?)
+                }
+            },
+        };
+
+        let cipher = Self::new(&raw_key)?;
+
+        if let Some(sample) = sample {
+            cipher.decrypt(&sample).map_err(|_| {
+                core_error!(
+                    "storage key from {} doesn't match the encrypted values",
+                    source.as_str()
+                )
+            })?;
+        }
+
+        conn.execute(
+            "INSERT INTO kv(key, value) VALUES(?, ?) ON CONFLICT DO UPDATE SET value = ?",
+            [KEY_SOURCE_KEY, source.as_str(), source.as_str()],
+        )?;
+
+        Ok(cipher)
+    }
+
+    pub fn new(raw_key: &[u8]) -> CoreResult<Self> {
+        let key = UnboundKey::new(&AES_256_GCM, raw_key)?;
+
+        Ok(Self {
+            key: LessSafeKey::new(key),
+            random: SystemRandom::new(),
+        })
+    }
+
+    pub fn from_passphrase(passphrase: &str, salt: &[u8]) -> CoreResult<Self> {
+        Self::new(&derive_raw_key(passphrase, salt))
+    }
+
+    pub fn encrypt(&self, plaintext: &str) -> CoreResult<String> {
+        let mut nonce = [0u8; NONCE_LEN];
+        self.random.fill(&mut nonce)?;
+
+        let mut buffer = plaintext.as_bytes().to_vec();
+        self.key.seal_in_place_append_tag(
+            Nonce::assume_unique_for_key(nonce),
+            Aad::empty(),
+            &mut buffer,
+        )?;
+
+        let mut sealed = nonce.to_vec();
+        sealed.append(&mut buffer);
+
+        Ok(format!("{}{}", ENCRYPTED_PREFIX, base64::encode(sealed)))
+    }
+
+    /// Decrypts a column value, legacy plaintext values are returned unchanged.
+    pub fn decrypt(&self, value: &str) -> CoreResult<String> {
+        let Some(encoded) = value.strip_prefix(ENCRYPTED_PREFIX) else {
+            return Ok(value.to_string());
+        };
+
+        let mut sealed = base64::decode(encoded)?;
+        if sealed.len() < NONCE_LEN {
+            return Err(core_error!("encrypted value too short"));
+        }
+
+        let mut buffer = sealed.split_off(NONCE_LEN);
+        let nonce = Nonce::try_assume_unique_for_key(&sealed)?;
+
+        let plaintext = self
+            .key
+            .open_in_place(nonce, Aad::empty(), &mut buffer)
+            .map_err(|_| {
+                core_error!("decrypt storage value failed, the storage key may be changed")
+            })?;
+
+        Ok(String::from_utf8(plaintext.to_vec())?)
+    }
+
+    pub fn is_encrypted(value: &str) -> bool {
+        value.starts_with(ENCRYPTED_PREFIX)
+    }
+}
+
+fn generate_raw_key(random: &SystemRandom) -> CoreResult<Vec<u8>> {
+    let mut raw_key = vec![0u8; AES_256_GCM.key_len()];
+    random.fill(&mut raw_key)?;
+    Ok(raw_key)
+}
+
+fn derive_passphrase_key(
+    conn: &Connection,
+    random: &SystemRandom,
+    passphrase: &str,
+) -> CoreResult<Vec<u8>> {
+    let salt: Option<String> = conn
+        .query_row(
+            "SELECT value FROM kv WHERE key = ? LIMIT 1",
+            [PASSPHRASE_SALT_KEY],
+            |row| row.get(0),
+        )
+        .optional()?;
+
+    let salt = match salt {
+        Some(salt) => base64::decode(salt)?,
+        None => {
+            let mut salt = vec![0u8; 16];
+            random.fill(&mut salt)?;
+            conn.execute(
+                "INSERT INTO kv(key, value) VALUES(?, ?)",
+                [PASSPHRASE_SALT_KEY, &base64::encode(&salt)],
+            )?;
+            salt
+        }
+    };
+
+    Ok(derive_raw_key(passphrase, &salt))
+}
+
+fn derive_raw_key(passphrase: &str, salt: &[u8]) -> Vec<u8> {
+    let mut raw_key = vec![0u8; AES_256_GCM.key_len()];
+    pbkdf2::pbkdf2::<Hmac<Sha256>>(passphrase.as_bytes(), salt, PASSPHRASE_ROUNDS, &mut raw_key);
+    raw_key
+}
+
+/// Returns an encrypted value of the storage, the loaded key must decrypt it.
+fn encrypted_sample(conn: &Connection) -> CoreResult<Option<String>> {
+    const COMMAND: &str = r"
+        SELECT password FROM domains WHERE password LIKE 'enc:v1:%'
+        UNION ALL SELECT private_key FROM lan_identity WHERE private_key LIKE 'enc:v1:%'
+        UNION ALL SELECT password FROM temporary_passwords WHERE password LIKE 'enc:v1:%'
+        UNION ALL SELECT value FROM kv WHERE key = 'proxy' AND value LIKE 'enc:v1:%'
+        LIMIT 1";
+
+    Ok(conn.query_row(COMMAND, [], |row| row.get(0)).optional()?)
+}
+
+fn load_key(
+    conn: &Connection,
+    random: &SystemRandom,
+    key_stores: &KeyStores,
+    source: KeySource,
+    has_encrypted: bool,
+) -> CoreResult<Vec<u8>> {
+    let store = match source {
+        KeySource::Passphrase => {
+            let Some(ref passphrase) = key_stores.passphrase else {
+                return Err(core_error!(
+                    "storage is encrypted with a passphrase, set {}",
+                    PASSPHRASE_ENV
+                ));
+            };
+
+            return derive_passphrase_key(conn, random, passphrase);
+        }
+        KeySource::Keyring => key_stores.keyring.as_ref(),
+        KeySource::File => key_stores.file.as_ref(),
+    };
+
+    match store.read()? {
+        Some(raw_key) => Ok(raw_key),
+        None if has_encrypted => Err(core_error!(
+            "storage key not found in {}, encrypted values can't be read",
+            source.as_str()
+        )),
+        None => {
+            let raw_key = generate_raw_key(random)?;
+            match store.write(&raw_key) {
+                Ok(()) => Ok(raw_key),
+                // another storage may have created the key at the same time
+                Err(err) => store.read()?.ok_or(err),
+            }
+        }
+    }
+}
+
+/// Finds the key of a storage encrypted before its key source was recorded, by trying
+/// every source whose key exists against an encrypted value.
+fn find_legacy_key(
+    conn: &Connection,
+    key_stores: &KeyStores,
+    sample: &str,
+) -> CoreResult<(KeySource, Vec<u8>)> {
+    let has_salt: bool = conn.query_row(
+        "SELECT COUNT(*) > 0 FROM kv WHERE key = ?",
+        [PASSPHRASE_SALT_KEY],
+        |row| row.get(0),
+    )?;
+
+    if has_salt {
+        if let Some(ref passphrase) = key_stores.passphrase {
+            let raw_key = derive_passphrase_key(conn, &SystemRandom::new(), passphrase)?;
+            if StorageCipher::new(&raw_key)?.decrypt(sample).is_ok() {
+                return Ok((KeySource::Passphrase, raw_key));
+            }
+        }
+    }
+
+    let stores = [
+        (KeySource::Keyring, &key_stores.keyring),
+        (KeySource::File, &key_stores.file),
+    ];
+
+    for (source, store) in stores {
+        match store.read() {
+            Ok(Some(raw_key)) => {
+                if StorageCipher::new(&raw_key)?.decrypt(sample).is_ok() {
+                    return Ok((source, raw_key));
+                }
+            }
+            Ok(None) => {}
+            Err(err) => tracing::warn!(?err, source = source.as_str(), "read storage key failed"),
+        }
+    }
+
+    Err(core_error!(
+        "no storage key decrypts the stored values, the key may be unavailable or lost"
+    ))
+}
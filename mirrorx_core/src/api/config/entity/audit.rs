@@ -0,0 +1,550 @@
+use crate::{api::config::settings::current_settings, core_error, error::CoreResult};
+use r2d2::Pool;
+use r2d2_sqlite::SqliteConnectionManager;
+use rusqlite::{params, Row};
+use serde::{Deserialize, Serialize};
+use std::{path::Path, str::FromStr, sync::Arc, time::Duration};
+
+const DEFAULT_PAGE_LIMIT: u32 = 50;
+
+/// Columns of `audit_sessions` read by name in [`parse_session_record`].
+const SESSION_COLUMNS: &str =
+    r"id, device_id, remote_ip, domain, credential, permissions, start_time, end_time";
+
+/// Columns of `audit_events` read by name in [`parse_event_record`].
+const EVENT_COLUMNS: &str = r"id, session_id, timestamp, operation, path, destination, size, error";
+
+/// How often long running instances apply the retention policy.
+const RETENTION_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// How the remote side was authorized to open the session.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AuditCredential {
+    Password,
+    TemporaryPassword,
+    OneTimePassword,
+    /// LAN connections are not authorized by a password.
+    Lan,
+}
+
+impl<'a> Into<&'a str> for AuditCredential {
+    fn into(self) -> &'a str {
+        match self {
+            AuditCredential::Password => "password",
+            AuditCredential::TemporaryPassword => "temporary_password",
+            AuditCredential::OneTimePassword => "one_time_password",
+            AuditCredential::Lan => "lan",
+        }
+    }
+}
+
+impl FromStr for AuditCredential {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "password" => Ok(AuditCredential::Password),
+            "temporary_password" => Ok(AuditCredential::TemporaryPassword),
+            "one_time_password" => Ok(AuditCredential::OneTimePassword),
+            "lan" => Ok(AuditCredential::Lan),
+            _ => Err(String::from("Unknown audit credential")),
+        }
+    }
+}
+
+/// File operations served to the remote side, named after the endpoint requests.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FileOperation {
+    VisitDirectory,
+    /// The remote side uploaded a file to this device.
+    SendFile,
+    /// The remote side downloaded a file from this device.
+    DownloadFile,
+    /// The remote side created a directory tree on this device to upload it.
+    SendDirectory,
+    /// The remote side listed a directory tree of this device to download it.
+    DownloadDirectory,
+    CreateDirectory,
+    /// Renamed or moved, recorded with the destination.
+    Rename,
+    /// Copied, recorded with the destination.
+    Copy,
+    Delete,
+}
+
+impl<'a> Into<&'a str> for FileOperation {
+    fn into(self) -> &'a str {
+        match self {
+            FileOperation::VisitDirectory => "visit_directory",
+            FileOperation::SendFile => "send_file",
+            FileOperation::DownloadFile => "download_file",
+            FileOperation::SendDirectory => "send_directory",
+            FileOperation::DownloadDirectory => "download_directory",
+            FileOperation::CreateDirectory => "create_directory",
+            FileOperation::Rename => "rename",
+            FileOperation::Copy => "copy",
+            FileOperation::Delete => "delete",
+        }
+    }
+}
+
+impl FromStr for FileOperation {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "visit_directory" => Ok(FileOperation::VisitDirectory),
+            "send_file" => Ok(FileOperation::SendFile),
+            "download_file" => Ok(FileOperation::DownloadFile),
+            "send_directory" => Ok(FileOperation::SendDirectory),
+            "download_directory" => Ok(FileOperation::DownloadDirectory),
+            "create_directory" => Ok(FileOperation::CreateDirectory),
+            "rename" => Ok(FileOperation::Rename),
+            "copy" => Ok(FileOperation::Copy),
+            "delete" => Ok(FileOperation::Delete),
+            _ => Err(String::from("Unknown file operation")),
+        }
+    }
+}
+
+/// An incoming session, identified by `device_id` for domain sessions and by
+/// `remote_ip` for LAN sessions. Domain sessions are relayed, their `remote_ip` is the
+/// address of the endpoint server carrying the session.
+#[derive(Debug, Clone, Serialize)]
+pub struct AuditSessionRecord {
+    pub id: i64,
+    pub device_id: Option<i64>,
+    pub remote_ip: String,
+    pub domain: String,
+    pub credential: AuditCredential,
+    pub permissions: Vec<String>,
+    pub start_time: i64,
+    pub end_time: Option<i64>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct AuditEventRecord {
+    pub id: i64,
+    pub session_id: i64,
+    pub timestamp: i64,
+    pub operation: FileOperation,
+    pub path: String,
+    /// Where a renamed or copied entry went, `None` for other operations.
+    pub destination: Option<String>,
+    pub size: Option<u64>,
+    /// Error message of a failed operation, empty on success.
+    pub error: String,
+}
+
+/// Describes the remote side of a new incoming session.
+#[derive(Debug, Clone)]
+pub struct AuditSessionStart {
+    pub device_id: Option<i64>,
+    pub remote_ip: String,
+    pub domain: String,
+    pub credential: AuditCredential,
+    pub permissions: Vec<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct AuditFilter {
+    pub time_range: Option<(i64, i64)>,
+    /// Matched against the device id and the remote ip.
+    pub keyword: Option<String>,
+    /// Starts from 1, all matched sessions are returned when empty.
+    pub page: Option<u32>,
+    pub limit: Option<u32>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct AuditPage {
+    pub total: u32,
+    pub sessions: Vec<AuditSessionRecord>,
+}
+
+/// Append-only log of incoming sessions and the file operations served in them, rows
+/// are only removed by the retention policy.
+pub struct AuditRepository {
+    pool: Pool<SqliteConnectionManager>,
+}
+
+impl AuditRepository {
+    pub fn new(pool: Pool<SqliteConnectionManager>) -> Self {
+        Self { pool }
+    }
+
+    pub fn start_session(
+        self: &Arc<Self>,
+        session: &AuditSessionStart,
+    ) -> CoreResult<AuditSession> {
+        const COMMAND: &str = r"
+        INSERT INTO audit_sessions(
+            device_id,
+            remote_ip,
+            domain,
+            credential,
+            permissions,
+            start_time,
+            end_time
+        )
+        VALUES(?, ?, ?, ?, ?, ?, NULL)";
+
+        let credential: &str = session.credential.into();
+
+        let conn = self.pool.get()?;
+        conn.execute(
+            COMMAND,
+            params![
+                session.device_id,
+                session.remote_ip,
+                session.domain,
+                credential,
+                serde_json::to_string(&session.permissions)?,
+                chrono::Utc::now().timestamp(),
+            ],
+        )?;
+
+        Ok(AuditSession {
+            repository: self.clone(),
+            id: conn.last_insert_rowid(),
+        })
+    }
+
+    fn finish_session(&self, id: i64) -> CoreResult<()> {
+        const COMMAND: &str =
+            r"UPDATE audit_sessions SET end_time = ? WHERE id = ? AND end_time IS NULL";
+
+        self.pool
+            .get()?
+            .execute(COMMAND, params![chrono::Utc::now().timestamp(), id])?;
+
+        Ok(())
+    }
+
+    fn append_event(
+        &self,
+        session_id: i64,
+        operation: FileOperation,
+        path: &str,
+        destination: Option<&str>,
+        size: Option<u64>,
+        error: &str,
+    ) -> CoreResult<()> {
+        const COMMAND: &str = r"
+        INSERT INTO audit_events(session_id, timestamp, operation, path, destination, size, error)
+        VALUES(?, ?, ?, ?, ?, ?, ?)";
+
+        let operation: &str = operation.into();
+
+        self.pool.get()?.execute(
+            COMMAND,
+            params![
+                session_id,
+                chrono::Utc::now().timestamp(),
+                operation,
+                path,
+                destination,
+                size.map(|size| size as i64),
+                error
+            ],
+        )?;
+
+        Ok(())
+    }
+
+    /// Returns matched sessions of the requested page, latest first.
+    pub fn query(&self, filter: &AuditFilter) -> CoreResult<AuditPage> {
+        const CONDITION: &str = r"
+        WHERE start_time BETWEEN ?1 AND ?2
+        AND (?3 IS NULL
+            OR CAST(device_id AS TEXT) LIKE ?3 ESCAPE '\'
+            OR remote_ip LIKE ?3 ESCAPE '\')";
+
+        let (start, end) = filter
+            .time_range
+            .unwrap_or_else(|| (0, chrono::Utc::now().timestamp()));
+
+        // device ids are displayed with dashes, like 12-3456-7890
+        let keyword = filter
+            .keyword
+            .as_deref()
+            .map(str::trim)
+            .filter(|keyword| !keyword.is_empty())
+            .map(|keyword| {
+                let keyword = keyword
+                    .replace('-', "")
+                    .replace('\\', "\\\\")
+                    .replace('%', "\\%")
+                    .replace('_', "\\_");
+                format!("%{keyword}%")
+            });
+
+        let (limit, offset) = match filter.page {
+            Some(page) => {
+                let limit = filter.limit.unwrap_or(DEFAULT_PAGE_LIMIT).max(1);
+                (limit as i64, (page.max(1) - 1) as i64 * limit as i64)
+            }
+            None => (-1, 0),
+        };
+
+        let conn = self.pool.get()?;
+
+        let total: u32 = conn.query_row(
+            &format!("SELECT COUNT(*) FROM audit_sessions {CONDITION}"),
+            params![start, end, keyword],
+            |row| row.get(0),
+        )?;
+
+        let mut stmt = conn.prepare(&format!(
+            r"
+            SELECT {SESSION_COLUMNS} FROM audit_sessions
+            {CONDITION}
+            ORDER BY start_time DESC, id DESC
+            LIMIT ?4 OFFSET ?5"
+        ))?;
+
+        let rows = stmt.query_and_then(
+            params![start, end, keyword, limit, offset],
+            parse_session_record,
+        )?;
+
+        let mut sessions = Vec::new();
+        for row in rows {
+            sessions.push(row?);
+        }
+
+        Ok(AuditPage { total, sessions })
+    }
+
+    pub fn query_events(&self, session_id: i64) -> CoreResult<Vec<AuditEventRecord>> {
+        let conn = self.pool.get()?;
+        let mut stmt = conn.prepare(&format!(
+            r"
+            SELECT {EVENT_COLUMNS} FROM audit_events
+            WHERE session_id = ?
+            ORDER BY timestamp ASC, id ASC"
+        ))?;
+        let rows = stmt.query_and_then([session_id], parse_event_record)?;
+
+        let mut events = Vec::new();
+        for row in rows {
+            events.push(row?);
+        }
+
+        Ok(events)
+    }
+
+    /// Exports matched sessions as CSV, one line per session followed by one line per
+    /// file operation of the session.
+    pub fn export_csv(&self, filter: &AuditFilter) -> CoreResult<String> {
+        let page = self.query(&AuditFilter {
+            time_range: filter.time_range,
+            keyword: filter.keyword.clone(),
+            page: None,
+            limit: None,
+        })?;
+
+        let mut csv = String::from(
+            "session_id,device_id,remote_ip,domain,credential,permissions,start_time,end_time,event_time,operation,path,destination,size,error\n",
+        );
+
+        for session in page.sessions {
+            let credential: &str = session.credential.into();
+            let session_columns = [
+                session.id.to_string(),
+                session
+                    .device_id
+                    .map(|device_id| device_id.to_string())
+                    .unwrap_or_default(),
+                session.remote_ip.clone(),
+                session.domain.clone(),
+                credential.to_string(),
+                session.permissions.join(" "),
+                format_time(session.start_time),
+                session.end_time.map(format_time).unwrap_or_default(),
+            ];
+
+            push_csv_line(&mut csv, &session_columns, &[]);
+
+            for event in self.query_events(session.id)? {
+                let operation: &str = event.operation.into();
+                push_csv_line(
+                    &mut csv,
+                    &session_columns,
+                    &[
+                        format_time(event.timestamp),
+                        operation.to_string(),
+                        event.path,
+                        event.destination.unwrap_or_default(),
+                        event.size.map(|size| size.to_string()).unwrap_or_default(),
+                        event.error,
+                    ],
+                );
+            }
+        }
+
+        Ok(csv)
+    }
+
+    /// Removes sessions started more than `retention_days` ago together with their
+    /// events, `0` keeps the log forever.
+    pub fn delete_expired(&self, retention_days: u32) -> CoreResult<()> {
+        if retention_days == 0 {
+            return Ok(());
+        }
+
+        let deadline = chrono::Utc::now().timestamp() - retention_days as i64 * 24 * 60 * 60;
+
+        let mut conn = self.pool.get()?;
+        let tx = conn.transaction()?;
+        tx.execute(
+            r"DELETE FROM audit_events WHERE session_id IN (SELECT id FROM audit_sessions WHERE start_time < ?)",
+            [deadline],
+        )?;
+        tx.execute(
+            r"DELETE FROM audit_sessions WHERE start_time < ?",
+            [deadline],
+        )?;
+        tx.commit()?;
+
+        Ok(())
+    }
+
+    /// Applies the retention policy of the current settings every hour until the
+    /// repository is dropped, startup and settings changes apply it right away.
+    pub fn serve_retention(self: &Arc<Self>) {
+        let repository = Arc::downgrade(self);
+
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(RETENTION_INTERVAL);
+            interval.tick().await;
+
+            loop {
+                interval.tick().await;
+
+                let Some(repository) = repository.upgrade() else {
+                    return;
+                };
+
+                let retention_days = current_settings().audit_retention_days;
+                if let Err(err) = repository.delete_expired(retention_days) {
+                    tracing::error!(?err, "delete expired audit sessions failed");
+                }
+            }
+        });
+    }
+}
+
+/// Handle of an incoming session held by the passive endpoint.
+#[derive(Clone)]
+pub struct AuditSession {
+    repository: Arc<AuditRepository>,
+    id: i64,
+}
+
+impl std::fmt::Debug for AuditSession {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("AuditSession")
+            .field("id", &self.id)
+            .finish()
+    }
+}
+
+impl AuditSession {
+    pub fn id(&self) -> i64 {
+        self.id
+    }
+
+    /// Appends a served file operation on `path`, with the `destination` of a rename or
+    /// a copy. Failures of the audit log itself are only logged so they never break the
+    /// operation.
+    pub fn record_file_operation(
+        &self,
+        operation: FileOperation,
+        path: &Path,
+        destination: Option<&Path>,
+        size: Option<u64>,
+        error: Option<String>,
+    ) {
+        let destination = destination.map(|destination| destination.to_string_lossy());
+
+        if let Err(err) = self.repository.append_event(
+            self.id,
+            operation,
+            &path.to_string_lossy(),
+            destination.as_deref(),
+            size,
+            error.as_deref().unwrap_or_default(),
+        ) {
+            tracing::error!(?err, "append audit event failed");
+        }
+    }
+
+    pub fn finish(&self) {
+        if let Err(err) = self.repository.finish_session(self.id) {
+            tracing::error!(?err, "finish audit session failed");
+        }
+    }
+}
+
+fn format_time(timestamp: i64) -> String {
+    chrono::NaiveDateTime::from_timestamp_opt(timestamp, 0)
+        .map(|time| time.format("%Y-%m-%dT%H:%M:%SZ").to_string())
+        .unwrap_or_default()
+}
+
+fn push_csv_line(csv: &mut String, session_columns: &[String], event_columns: &[String]) {
+    const EVENT_COLUMN_COUNT: usize = 6;
+
+    let empty = String::default();
+    let columns = session_columns
+        .iter()
+        .chain(event_columns.iter())
+        .chain(std::iter::repeat(&empty).take(EVENT_COLUMN_COUNT - event_columns.len()));
+
+    let line: Vec<String> = columns.map(|column| escape_csv(column)).collect();
+    csv.push_str(&line.join(","));
+    csv.push('\n');
+}
+
+fn escape_csv(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+fn parse_session_record(row: &Row) -> CoreResult<AuditSessionRecord> {
+    let credential: String = row.get("credential")?;
+    let permissions: String = row.get("permissions")?;
+
+    Ok(AuditSessionRecord {
+        id: row.get("id")?,
+        device_id: row.get("device_id")?,
+        remote_ip: row.get("remote_ip")?,
+        domain: row.get("domain")?,
+        credential: AuditCredential::from_str(&credential).map_err(|err| core_error!("{}", err))?,
+        permissions: serde_json::from_str(&permissions)?,
+        start_time: row.get("start_time")?,
+        end_time: row.get("end_time")?,
+    })
+}
+
+fn parse_event_record(row: &Row) -> CoreResult<AuditEventRecord> {
+    let operation: String = row.get("operation")?;
+    let size: Option<i64> = row.get("size")?;
+
+    Ok(AuditEventRecord {
+        id: row.get("id")?,
+        session_id: row.get("session_id")?,
+        timestamp: row.get("timestamp")?,
+        operation: FileOperation::from_str(&operation).map_err(|err| core_error!("{}", err))?,
+        path: row.get("path")?,
+        destination: row.get("destination")?,
+        size: size.map(|size| size as u64),
+        error: row.get("error")?,
+    })
+}
@@ -0,0 +1,300 @@
+use crate::{core_error, error::CoreResult};
+use r2d2::Pool;
+use r2d2_sqlite::SqliteConnectionManager;
+use rusqlite::{params, Connection, Row};
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+
+/// Columns of `contacts` read by name in [`parse_contact`].
+const CONTACT_COLUMNS: &str = r"id, device_id, domain, alias, group_name, tags, notes,
+connection_preference, favorite, created_at, updated_at";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ConnectionPreference {
+    Desktop,
+    FileManager,
+}
+
+impl<'a> Into<&'a str> for ConnectionPreference {
+    fn into(self) -> &'a str {
+        match self {
+            ConnectionPreference::Desktop => "desktop",
+            ConnectionPreference::FileManager => "file_manager",
+        }
+    }
+}
+
+impl FromStr for ConnectionPreference {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "desktop" => Ok(ConnectionPreference::Desktop),
+            "file_manager" => Ok(ConnectionPreference::FileManager),
+            _ => Err(String::from("Unknown connection preference")),
+        }
+    }
+}
+
+/// A remote device saved by the user, identified by `(device_id, domain)` like history
+/// records.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Contact {
+    pub id: i64,
+    pub device_id: i64,
+    pub domain: String,
+    pub alias: String,
+    pub group_name: String,
+    pub tags: Vec<String>,
+    pub notes: String,
+    pub connection_preference: ConnectionPreference,
+    pub favorite: bool,
+    pub created_at: i64,
+    pub updated_at: i64,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct ContactFilter {
+    /// Matched against alias, notes, tags and device id.
+    pub keyword: Option<String>,
+    pub group_name: Option<String>,
+    #[serde(default)]
+    pub favorite_only: bool,
+}
+
+pub struct ContactRepository {
+    pool: Pool<SqliteConnectionManager>,
+}
+
+impl ContactRepository {
+    pub fn new(pool: Pool<SqliteConnectionManager>) -> Self {
+        Self { pool }
+    }
+
+    pub fn create(&self, contact: Contact) -> CoreResult<Contact> {
+        self.create_in(&self.pool.get()?, contact)
+    }
+
+    /// Like [`Self::create`], on the connection of a running transaction.
+    pub fn create_in(&self, conn: &Connection, mut contact: Contact) -> CoreResult<Contact> {
+        const COMMAND: &str = r"
+        INSERT INTO contacts(
+            device_id,
+            domain,
+            alias,
+            group_name,
+            tags,
+            notes,
+            connection_preference,
+            favorite,
+            created_at,
+            updated_at
+        )
+        VALUES(?, ?, ?, ?, ?, ?, ?, ?, ?, ?)";
+
+        let now = chrono::Utc::now().timestamp();
+        contact.created_at = now;
+        contact.updated_at = now;
+
+        let connection_preference: &str = contact.connection_preference.into();
+
+        conn.execute(
+            COMMAND,
+            params![
+                contact.device_id,
+                contact.domain,
+                contact.alias,
+                contact.group_name,
+                serde_json::to_string(&contact.tags)?,
+                contact.notes,
+                connection_preference,
+                contact.favorite,
+                contact.created_at,
+                contact.updated_at,
+            ],
+        )?;
+
+        contact.id = conn.last_insert_rowid();
+
+        Ok(contact)
+    }
+
+    /// Updates the editable fields, the device a contact refers to never changes.
+    pub fn update(&self, contact: &Contact) -> CoreResult<()> {
+        self.update_in(&self.pool.get()?, contact)
+    }
+
+    pub fn update_in(&self, conn: &Connection, contact: &Contact) -> CoreResult<()> {
+        const COMMAND: &str = r"
+        UPDATE contacts SET
+            alias = ?,
+            group_name = ?,
+            tags = ?,
+            notes = ?,
+            connection_preference = ?,
+            favorite = ?,
+            updated_at = ?
+        WHERE id = ?";
+
+        let connection_preference: &str = contact.connection_preference.into();
+
+        conn.execute(
+            COMMAND,
+            params![
+                contact.alias,
+                contact.group_name,
+                serde_json::to_string(&contact.tags)?,
+                contact.notes,
+                connection_preference,
+                contact.favorite,
+                chrono::Utc::now().timestamp(),
+                contact.id,
+            ],
+        )?;
+
+        Ok(())
+    }
+
+    pub fn set_favorite(&self, id: i64, favorite: bool) -> CoreResult<()> {
+        const COMMAND: &str = r"UPDATE contacts SET favorite = ?, updated_at = ? WHERE id = ?";
+
+        self.pool.get()?.execute(
+            COMMAND,
+            params![favorite, chrono::Utc::now().timestamp(), id],
+        )?;
+
+        Ok(())
+    }
+
+    pub fn get(&self, id: i64) -> CoreResult<Contact> {
+        self.pool.get()?.query_row_and_then(
+            &format!("SELECT {CONTACT_COLUMNS} FROM contacts WHERE id = ?"),
+            [id],
+            parse_contact,
+        )
+    }
+
+    pub fn get_by_device(&self, device_id: i64, domain: &str) -> CoreResult<Option<Contact>> {
+        self.get_by_device_in(&self.pool.get()?, device_id, domain)
+    }
+
+    pub fn get_by_device_in(
+        &self,
+        conn: &Connection,
+        device_id: i64,
+        domain: &str,
+    ) -> CoreResult<Option<Contact>> {
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {CONTACT_COLUMNS} FROM contacts WHERE device_id = ? AND domain = ?"
+        ))?;
+        let mut rows = stmt.query_and_then(params![device_id, domain], parse_contact)?;
+
+        rows.next().transpose()
+    }
+
+    /// Returns contacts matching the filter, favorites first and then by alias.
+    pub fn search(&self, filter: &ContactFilter) -> CoreResult<Vec<Contact>> {
+        let keyword = filter
+            .keyword
+            .as_deref()
+            .map(str::trim)
+            .filter(|keyword| !keyword.is_empty())
+            .map(|keyword| {
+                keyword
+                    .replace('\\', "\\\\")
+                    .replace('%', "\\%")
+                    .replace('_', "\\_")
+            });
+
+        // device ids are displayed with dashes, like 12-3456-7890
+        let device_id_keyword = keyword
+            .as_ref()
+            .map(|keyword| format!("%{}%", keyword.replace('-', "")));
+        let keyword = keyword.map(|keyword| format!("%{keyword}%"));
+
+        let conn = self.pool.get()?;
+        let mut stmt = conn.prepare(&format!(
+            r"
+            SELECT {CONTACT_COLUMNS} FROM contacts
+            WHERE (?1 IS NULL
+                OR alias LIKE ?1 ESCAPE '\'
+                OR notes LIKE ?1 ESCAPE '\'
+                OR tags LIKE ?1 ESCAPE '\'
+                OR CAST(device_id AS TEXT) LIKE ?2 ESCAPE '\')
+            AND (?3 IS NULL OR group_name = ?3)
+            AND (?4 = 0 OR favorite = 1)
+            ORDER BY favorite DESC, alias COLLATE NOCASE ASC"
+        ))?;
+        let rows = stmt.query_and_then(
+            params![
+                keyword,
+                device_id_keyword,
+                filter.group_name,
+                filter.favorite_only
+            ],
+            parse_contact,
+        )?;
+
+        let mut contacts = Vec::new();
+        for row in rows {
+            contacts.push(row?);
+        }
+
+        Ok(contacts)
+    }
+
+    pub fn get_group_names(&self) -> CoreResult<Vec<String>> {
+        const COMMAND: &str = r"
+        SELECT DISTINCT group_name FROM contacts
+        WHERE group_name != ''
+        ORDER BY group_name COLLATE NOCASE ASC";
+
+        let conn = self.pool.get()?;
+        let mut stmt = conn.prepare(COMMAND)?;
+        let rows = stmt.query_and_then([], |row| -> CoreResult<String> { Ok(row.get(0)?) })?;
+
+        let mut group_names = Vec::new();
+        for row in rows {
+            group_names.push(row?);
+        }
+
+        Ok(group_names)
+    }
+
+    pub fn delete(&self, id: i64) -> CoreResult<()> {
+        const COMMAND: &str = r"DELETE FROM contacts WHERE id = ?";
+
+        self.pool.get()?.execute(COMMAND, [id])?;
+
+        Ok(())
+    }
+
+    pub fn delete_domain_related(&self, domain: &str) -> CoreResult<()> {
+        const COMMAND: &str = r"DELETE FROM contacts WHERE domain = ?";
+
+        self.pool.get()?.execute(COMMAND, [domain])?;
+
+        Ok(())
+    }
+}
+
+fn parse_contact(row: &Row) -> CoreResult<Contact> {
+    let tags: String = row.get("tags")?;
+    let connection_preference: String = row.get("connection_preference")?;
+
+    Ok(Contact {
+        id: row.get("id")?,
+        device_id: row.get("device_id")?,
+        domain: row.get("domain")?,
+        alias: row.get("alias")?,
+        group_name: row.get("group_name")?,
+        tags: serde_json::from_str(&tags)?,
+        notes: row.get("notes")?,
+        connection_preference: ConnectionPreference::from_str(&connection_preference)
+            .map_err(|err| core_error!("{}", err))?,
+        favorite: row.get("favorite")?,
+        created_at: row.get("created_at")?,
+        updated_at: row.get("updated_at")?,
+    })
+}
@@ -1,10 +1,16 @@
-use crate::error::{CoreError, CoreResult};
+use crate::{api::config::cipher::StorageCipher, error::CoreResult};
 use r2d2::Pool;
 use r2d2_sqlite::SqliteConnectionManager;
-use rusqlite::{params, OptionalExtension, Row};
-use serde::Serialize;
+use rusqlite::{params, Connection, OptionalExtension, Row};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
 
-#[derive(Debug, Clone, Serialize)]
+/// Columns read by [`DomainRepository::parse_domain`], which reads them by name so
+/// columns added by migrations can't shift the fields.
+const DOMAIN_COLUMNS: &str = r"id, name, addr, signaling_port, subscribe_port, is_primary,
+device_id, password, finger_print, remarks, tls_pin, expire";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Domain {
     pub id: i64,
     pub name: String,
@@ -16,40 +22,67 @@ pub struct Domain {
     pub password: String,
     pub finger_print: String,
     pub remarks: String,
+    pub tls_pin: String,
+    pub expire: i64,
 }
 
+/// Device `password` and `finger_print` are stored encrypted with [`StorageCipher`].
 pub struct DomainRepository {
     pool: Pool<SqliteConnectionManager>,
+    cipher: Arc<StorageCipher>,
 }
 
 impl DomainRepository {
-    pub fn new(pool: Pool<SqliteConnectionManager>) -> Self {
-        Self { pool }
+    pub fn new(pool: Pool<SqliteConnectionManager>, cipher: Arc<StorageCipher>) -> Self {
+        Self { pool, cipher }
     }
 
-    pub fn ensure_table(&self) -> CoreResult<()> {
-        let conn = self.pool.get()?;
+    /// Encrypts secrets of rows written by versions before encryption at rest.
+    pub fn encrypt_plaintext_secrets(&self) -> CoreResult<()> {
+        const SELECT_COMMAND: &str = r"SELECT id, password, finger_print FROM domains";
+        const UPDATE_COMMAND: &str =
+            r"UPDATE domains SET password = ?, finger_print = ? WHERE id = ?";
 
-        const COMMAND: &str = r"
-        CREATE TABLE IF NOT EXISTS domains(
-            id INTEGER PRIMARY KEY,
-            name TEXT NOT NULL UNIQUE,
-            addr TEXT NOT NULL,
-            signaling_port INTEGER NOT NULL,
-            subscribe_port INTEGER NOT NULL,
-            is_primary BOOLEAN NOT NULL,
-            device_id INTEGER NOT NULL,
-            password TEXT NOT NULL,
-            finger_print TEXT NOT NULL,
-            remarks TEXT NOT NULL
-        )";
-
-        conn.execute(COMMAND, [])?;
+        let mut conn = self.pool.get()?;
+        let tx = conn.transaction()?;
+
+        let rows = {
+            let mut stmt = tx.prepare(SELECT_COMMAND)?;
+            let rows = stmt.query_and_then([], |row| -> CoreResult<(i64, String, String)> {
+                Ok((row.get(0)?, row.get(1)?, row.get(2)?))
+            })?;
+            rows.collect::<CoreResult<Vec<_>>>()?
+        };
+
+        for (id, password, finger_print) in rows {
+            if StorageCipher::is_encrypted(&password) && StorageCipher::is_encrypted(&finger_print)
+            {
+                continue;
+            }
+
+            tracing::info!(domain_id = id, "encrypt domain secrets");
+
+            tx.execute(
+                UPDATE_COMMAND,
+                params![
+                    self.encrypt_if_plaintext(&password)?,
+                    self.encrypt_if_plaintext(&finger_print)?,
+                    id
+                ],
+            )?;
+        }
+
+        tx.commit()?;
 
         Ok(())
     }
 
-    pub fn add_domain(&self, mut domain: Domain) -> CoreResult<Domain> {
+    pub fn add_domain(&self, domain: Domain) -> CoreResult<Domain> {
+        self.add_domain_in(&self.pool.get()?, domain)
+    }
+
+    /// Like [`Self::add_domain`], on the connection of a running transaction.
+    pub fn add_domain_in(&self, conn: &Connection, mut domain: Domain) -> CoreResult<Domain> {
         const COMMAND: &str = r#"
         INSERT INTO domains(
             name,
@@ -60,11 +93,12 @@ impl DomainRepository {
             device_id,
             password,
             finger_print,
-            remarks
+            remarks,
+            tls_pin,
+            expire
         )
-        VALUES(?, ?, ?, ?, ?, ?, ?, ?, ?)"#;
+        VALUES(?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)"#;
 
-        let conn = self.pool.get()?;
         conn.execute(
             COMMAND,
             params![
@@ -74,9 +108,11 @@ impl DomainRepository {
                 domain.subscribe_port,
                 domain.is_primary,
                 domain.device_id,
-                domain.password,
-                domain.finger_print,
+                self.cipher.encrypt(&domain.password)?,
+                self.cipher.encrypt(&domain.finger_print)?,
                 domain.remarks,
+                domain.tls_pin,
+                domain.expire,
             ],
         )?;
 
@@ -86,19 +122,21 @@ impl DomainRepository {
     }
 
     pub fn get_primary_domain(&self) -> CoreResult<Domain> {
-        const COMMAND: &str = r"SELECT * FROM domains WHERE is_primary = 1 LIMIT 1";
-
-        self.pool
-            .get()?
-            .query_row_and_then(COMMAND, [], parse_domain)
+        self.pool.get()?.query_row_and_then(
+            &format!("SELECT {DOMAIN_COLUMNS} FROM domains WHERE is_primary = 1 LIMIT 1"),
+            [],
+            |row| self.parse_domain(row),
+        )
     }
 
     pub fn domain_exist(&self, name: &str) -> CoreResult<bool> {
+        self.domain_exist_in(&self.pool.get()?, name)
+    }
+
+    pub fn domain_exist_in(&self, conn: &Connection, name: &str) -> CoreResult<bool> {
         const COMMAND: &str = r"SELECT 1 FROM domains WHERE name = ?";
 
-        let res = self
-            .pool
-            .get()?
+        let res = conn
             .query_row(COMMAND, [name], |row| row.get::<_, u32>(0))
             .optional()?;
 
@@ -123,30 +161,31 @@ impl DomainRepository {
     }
 
     pub fn get_domain_by_name(&self, name: String) -> CoreResult<Domain> {
-        const COMMAND: &str = r"SELECT * FROM domains WHERE name = ? LIMIT 1";
+        self.get_domain_by_name_in(&self.pool.get()?, name)
+    }
 
-        let domain = self
-            .pool
-            .get()?
-            .query_row_and_then(COMMAND, [name], parse_domain)?;
+    pub fn get_domain_by_name_in(&self, conn: &Connection, name: String) -> CoreResult<Domain> {
+        let domain = conn.query_row_and_then(
+            &format!("SELECT {DOMAIN_COLUMNS} FROM domains WHERE name = ? LIMIT 1"),
+            [name],
+            |row| self.parse_domain(row),
+        )?;
 
         Ok(domain)
     }
 
     pub fn get_domain_by_id(&self, domain_id: i64) -> CoreResult<Domain> {
-        const COMMAND: &str = r"SELECT * FROM domains WHERE id = ?";
-
-        let domain = self
-            .pool
-            .get()?
-            .query_row_and_then(COMMAND, [domain_id], parse_domain)?;
+        let domain = self.pool.get()?.query_row_and_then(
+            &format!("SELECT {DOMAIN_COLUMNS} FROM domains WHERE id = ?"),
+            [domain_id],
+            |row| self.parse_domain(row),
+        )?;
 
         Ok(domain)
     }
 
     pub fn get_domains(&self, page: u32, limit: u32) -> CoreResult<(u32, Vec<Domain>)> {
         const COUNT_COMMAND: &str = r"SELECT COUNT(*) FROM domains";
-        const PAGINATION_COMMAND: &str = r"SELECT * FROM domains LIMIT ? OFFSET ?";
 
         let conn = self.pool.get()?;
 
@@ -154,8 +193,11 @@ impl DomainRepository {
             Ok(row.get(0)?)
         })?;
 
-        let mut stmt = conn.prepare(PAGINATION_COMMAND)?;
-        let rows = stmt.query_and_then([limit, (page - 1) * limit], parse_domain)?;
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {DOMAIN_COLUMNS} FROM domains LIMIT ? OFFSET ?"
+        ))?;
+        let rows =
+            stmt.query_and_then([limit, (page - 1) * limit], |row| self.parse_domain(row))?;
 
         let mut domains = Vec::new();
         for row in rows {
@@ -173,23 +215,21 @@ impl DomainRepository {
     }
 
     pub fn set_domain_is_primary(&self, domain_id: i64) -> CoreResult<()> {
-        const UNSET_PRIMARY_COMMAND: &str =
-            r"UPDATE domains SET is_primary = 0 WHERE is_primary = 1";
-        const SET_PRIMARY_COMMAND: &str = r"UPDATE domains SET is_primary = 1 WHERE id = ?";
-
         let mut conn = self.pool.get()?;
         let tx = conn.transaction()?;
-        if let Err(err) = tx.execute(UNSET_PRIMARY_COMMAND, []) {
-            tx.rollback()?;
-            return Err(CoreError::SQLiteError(err));
-        }
+        self.set_domain_is_primary_in(&tx, domain_id)?;
+        tx.commit()?;
 
-        if let Err(err) = tx.execute(SET_PRIMARY_COMMAND, [domain_id]) {
-            tx.rollback()?;
-            return Err(CoreError::SQLiteError(err));
-        }
+        Ok(())
+    }
 
-        tx.commit()?;
+    pub fn set_domain_is_primary_in(&self, conn: &Connection, domain_id: i64) -> CoreResult<()> {
+        const UNSET_PRIMARY_COMMAND: &str =
+            r"UPDATE domains SET is_primary = 0 WHERE is_primary = 1";
+        const SET_PRIMARY_COMMAND: &str = r"UPDATE domains SET is_primary = 1 WHERE id = ?";
+
+        conn.execute(UNSET_PRIMARY_COMMAND, [])?;
+        conn.execute(SET_PRIMARY_COMMAND, [domain_id])?;
 
         Ok(())
     }
@@ -204,12 +244,27 @@ impl DomainRepository {
         Ok(())
     }
 
+    pub fn set_domain_registration(
+        &self,
+        domain_id: i64,
+        device_id: i64,
+        expire: i64,
+    ) -> CoreResult<()> {
+        const COMMAND: &str = r"UPDATE domains SET device_id = ?, expire = ? WHERE id =?";
+
+        self.pool
+            .get()?
+            .execute(COMMAND, params![device_id, expire, domain_id])?;
+
+        Ok(())
+    }
+
     pub fn set_domain_device_password(&self, domain_id: i64, password: &str) -> CoreResult<()> {
         const COMMAND: &str = r"UPDATE domains SET password = ? WHERE id =?";
 
         self.pool
             .get()?
-            .execute(COMMAND, params![password, domain_id])?;
+            .execute(COMMAND, params![self.cipher.encrypt(password)?, domain_id])?;
 
         Ok(())
     }
@@ -224,6 +279,61 @@ impl DomainRepository {
         Ok(())
     }
 
+    pub fn set_domain_tls_pin(&self, domain_id: i64, tls_pin: &str) -> CoreResult<()> {
+        const COMMAND: &str = r"UPDATE domains SET tls_pin = ? WHERE id =?";
+
+        self.pool
+            .get()?
+            .execute(COMMAND, params![tls_pin, domain_id])?;
+
+        Ok(())
+    }
+
+    /// Replaces all fields except `id` and `is_primary` of the domain.
+    pub fn update_domain(&self, domain_id: i64, domain: &Domain) -> CoreResult<()> {
+        self.update_domain_in(&self.pool.get()?, domain_id, domain)
+    }
+
+    pub fn update_domain_in(
+        &self,
+        conn: &Connection,
+        domain_id: i64,
+        domain: &Domain,
+    ) -> CoreResult<()> {
+        const COMMAND: &str = r"
+        UPDATE domains SET
+            name = ?,
+            addr = ?,
+            signaling_port = ?,
+            subscribe_port = ?,
+            device_id = ?,
+            password = ?,
+            finger_print = ?,
+            remarks = ?,
+            tls_pin = ?,
+            expire = ?
+        WHERE id = ?";
+
+        conn.execute(
+            COMMAND,
+            params![
+                domain.name,
+                domain.addr,
+                domain.signaling_port,
+                domain.subscribe_port,
+                domain.device_id,
+                self.cipher.encrypt(&domain.password)?,
+                self.cipher.encrypt(&domain.finger_print)?,
+                domain.remarks,
+                domain.tls_pin,
+                domain.expire,
+                domain_id,
+            ],
+        )?;
+
+        Ok(())
+    }
+
     pub fn delete_domain(&self, domain_id: i64) -> CoreResult<()> {
         const COMMAND: &str = r"DELETE FROM domains WHERE id = ?";
 
@@ -231,19 +341,31 @@ impl DomainRepository {
 
         Ok(())
     }
-}
 
-fn parse_domain(row: &Row) -> CoreResult<Domain> {
-    Ok(Domain {
-        id: row.get(0)?,
-        name: row.get(1)?,
-        addr: row.get(2)?,
-        signaling_port: row.get(3)?,
-        subscribe_port: row.get(4)?,
-        is_primary: row.get(5)?,
-        device_id: row.get(6)?,
-        password: row.get(7)?,
-        finger_print: row.get(8)?,
-        remarks: row.get(9)?,
-    })
+    fn encrypt_if_plaintext(&self, value: &str) -> CoreResult<String> {
+        if StorageCipher::is_encrypted(value) {
+            Ok(value.to_string())
+        } else {
+            self.cipher.encrypt(value)
+        }
+    }
+
+    fn parse_domain(&self, row: &Row) -> CoreResult<Domain> {
+        Ok(Domain {
+            id: row.get("id")?,
+            name: row.get("name")?,
+            addr: row.get("addr")?,
+            signaling_port: row.get("signaling_port")?,
+            subscribe_port: row.get("subscribe_port")?,
+            is_primary: row.get("is_primary")?,
+            device_id: row.get("device_id")?,
+            password: self.cipher.decrypt(&row.get::<_, String>("password")?)?,
+            finger_print: self
+                .cipher
+                .decrypt(&row.get::<_, String>("finger_print")?)?,
+            remarks: row.get("remarks")?,
+            tls_pin: row.get("tls_pin")?,
+            expire: row.get("expire")?,
+        })
+    }
 }
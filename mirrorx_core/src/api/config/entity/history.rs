@@ -1,15 +1,182 @@
-use crate::error::CoreResult;
+use crate::{core_error, error::CoreResult};
 use r2d2::Pool;
 use r2d2_sqlite::SqliteConnectionManager;
-use rusqlite::{params, Row};
-use serde::Serialize;
+use rusqlite::{params, Connection, Row};
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
 
-#[derive(Debug, Clone, Serialize)]
+const DEFAULT_PAGE_LIMIT: u32 = 50;
+
+/// Columns of `history` read by name in [`parse_record`], next to the `contact_id` and
+/// `contact_alias` of the joined contact.
+const HISTORY_COLUMNS: &str = r"id, device_id, domain, remote_addr, timestamp, end_time,
+session_type, connection_type, result, result_detail, bytes_sent, bytes_received, peer_os";
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SessionType {
+    Desktop,
+    FileManager,
+    /// Recorded before session details were stored.
+    #[default]
+    Unknown,
+}
+
+impl<'a> Into<&'a str> for SessionType {
+    fn into(self) -> &'a str {
+        match self {
+            SessionType::Desktop => "desktop",
+            SessionType::FileManager => "file_manager",
+            SessionType::Unknown => "unknown",
+        }
+    }
+}
+
+impl FromStr for SessionType {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "desktop" => Ok(SessionType::Desktop),
+            "file_manager" => Ok(SessionType::FileManager),
+            "unknown" => Ok(SessionType::Unknown),
+            _ => Err(String::from("Unknown session type")),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ConnectionType {
+    Lan,
+    #[default]
+    Domain,
+}
+
+impl<'a> Into<&'a str> for ConnectionType {
+    fn into(self) -> &'a str {
+        match self {
+            ConnectionType::Lan => "lan",
+            ConnectionType::Domain => "domain",
+        }
+    }
+}
+
+impl FromStr for ConnectionType {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "lan" => Ok(ConnectionType::Lan),
+            "domain" => Ok(ConnectionType::Domain),
+            _ => Err(String::from("Unknown connection type")),
+        }
+    }
+}
+
+/// Outcome of a session, failures carry the visit failure reason or the transport
+/// error message.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", content = "detail", rename_all = "snake_case")]
+pub enum SessionResult {
+    #[default]
+    Success,
+    VisitFailed(String),
+    TransportError(String),
+}
+
+impl SessionResult {
+    fn kind(&self) -> &'static str {
+        match self {
+            SessionResult::Success => "success",
+            SessionResult::VisitFailed(_) => "visit_failed",
+            SessionResult::TransportError(_) => "transport_error",
+        }
+    }
+
+    fn detail(&self) -> &str {
+        match self {
+            SessionResult::Success => "",
+            SessionResult::VisitFailed(detail) | SessionResult::TransportError(detail) => detail,
+        }
+    }
+
+    fn parse(kind: &str, detail: String) -> CoreResult<Self> {
+        match kind {
+            "success" => Ok(SessionResult::Success),
+            "visit_failed" => Ok(SessionResult::VisitFailed(detail)),
+            "transport_error" => Ok(SessionResult::TransportError(detail)),
+            _ => Err(core_error!("Unknown session result")),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SessionResultKind {
+    Success,
+    Failed,
+}
+
+/// One connection attempt, `timestamp` is the start time and `end_time` stays empty
+/// while the session is alive.
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Record {
     pub id: i64,
     pub device_id: i64,
     pub domain: String,
     pub timestamp: i64,
+    /// Address of the peer for LAN sessions, empty for domain sessions.
+    #[serde(default)]
+    pub remote_addr: String,
+    #[serde(default)]
+    pub end_time: Option<i64>,
+    #[serde(default)]
+    pub session_type: SessionType,
+    #[serde(default)]
+    pub connection_type: ConnectionType,
+    #[serde(default)]
+    pub result: SessionResult,
+    #[serde(default)]
+    pub bytes_sent: u64,
+    #[serde(default)]
+    pub bytes_received: u64,
+    #[serde(default)]
+    pub peer_os: Option<String>,
+    /// Contact saved for the same `(device_id, domain)`, if any.
+    #[serde(default)]
+    pub contact_id: Option<i64>,
+    #[serde(default)]
+    pub contact_alias: Option<String>,
+}
+
+/// Describes the peer of a new session.
+#[derive(Debug, Clone)]
+pub struct SessionStart {
+    pub device_id: i64,
+    pub domain: String,
+    pub remote_addr: String,
+    pub session_type: SessionType,
+    pub connection_type: ConnectionType,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct HistoryFilter {
+    pub time_range: Option<(i64, i64)>,
+    pub device_id: Option<i64>,
+    pub domain: Option<String>,
+    pub session_type: Option<SessionType>,
+    pub connection_type: Option<ConnectionType>,
+    pub result: Option<SessionResultKind>,
+    /// Starts from 1, all matched records are returned when empty.
+    pub page: Option<u32>,
+    pub limit: Option<u32>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct HistoryPage {
+    pub total: u32,
+    pub records: Vec<Record>,
 }
 
 pub struct HistoryRepository {
@@ -21,57 +188,245 @@ impl HistoryRepository {
         Self { pool }
     }
 
-    pub fn ensure_table(&self) -> CoreResult<()> {
-        let conn = self.pool.get()?;
+    /// Inserts a live session and returns its id for [`HistoryRepository::finish_session`].
+    pub fn start_session(&self, session: &SessionStart) -> CoreResult<i64> {
+        self.insert(
+            session,
+            chrono::Utc::now().timestamp(),
+            None,
+            &SessionResult::Success,
+        )
+    }
 
-        const CREATE_TABLE_COMMAND: &str = r"
-        CREATE TABLE IF NOT EXISTS history(
-            id INTEGER PRIMARY KEY,
-            device_id INTEGER NOT NULL,
-            domain TEXT NOT NULL,
-            timestamp INTEGER NOT NULL
-        )";
+    pub fn finish_session(
+        &self,
+        id: i64,
+        bytes_sent: u64,
+        bytes_received: u64,
+        peer_os: Option<&str>,
+    ) -> CoreResult<()> {
+        const COMMAND: &str = r"
+        UPDATE history SET
+            end_time = ?,
+            bytes_sent = ?,
+            bytes_received = ?,
+            peer_os = COALESCE(?, peer_os)
+        WHERE id = ?";
+
+        self.pool.get()?.execute(
+            COMMAND,
+            params![
+                chrono::Utc::now().timestamp(),
+                bytes_sent as i64,
+                bytes_received as i64,
+                peer_os,
+                id
+            ],
+        )?;
+
+        Ok(())
+    }
 
-        conn.execute(CREATE_TABLE_COMMAND, [])?;
+    /// Records a session which failed before it was established.
+    pub fn record_failure(
+        &self,
+        session: &SessionStart,
+        result: &SessionResult,
+    ) -> CoreResult<i64> {
+        let now = chrono::Utc::now().timestamp();
+        self.insert(session, now, Some(now), result)
+    }
 
-        const CREATE_UNIQUE_INDEX_COMMAND: &str = r"
-        CREATE UNIQUE INDEX IF NOT EXISTS uq_device_id_domain ON history(device_id, domain)";
+    fn insert(
+        &self,
+        session: &SessionStart,
+        timestamp: i64,
+        end_time: Option<i64>,
+        result: &SessionResult,
+    ) -> CoreResult<i64> {
+        const COMMAND: &str = r"
+        INSERT INTO history(
+            device_id,
+            domain,
+            remote_addr,
+            timestamp,
+            end_time,
+            session_type,
+            connection_type,
+            result,
+            result_detail,
+            bytes_sent,
+            bytes_received,
+            peer_os
+        )
+        VALUES(?, ?, ?, ?, ?, ?, ?, ?, ?, 0, 0, NULL)";
 
-        conn.execute(CREATE_UNIQUE_INDEX_COMMAND, [])?;
+        let session_type: &str = session.session_type.into();
+        let connection_type: &str = session.connection_type.into();
 
-        Ok(())
+        let conn = self.pool.get()?;
+        conn.execute(
+            COMMAND,
+            params![
+                session.device_id,
+                session.domain,
+                session.remote_addr,
+                timestamp,
+                end_time,
+                session_type,
+                connection_type,
+                result.kind(),
+                result.detail(),
+            ],
+        )?;
+
+        Ok(conn.last_insert_rowid())
     }
 
-    pub fn create(&self, device_id: i64, domain: &str) -> CoreResult<()> {
-        const COMMAND: &str = r"INSERT INTO history(device_id, domain, timestamp) VALUES(?, ?, ?) ON CONFLICT DO UPDATE SET timestamp = ?";
+    /// Inserts a record from another storage, records already present are skipped.
+    pub fn import(&self, record: &Record) -> CoreResult<bool> {
+        self.import_in(&self.pool.get()?, record)
+    }
 
-        let timestamp = chrono::Utc::now().timestamp();
+    /// Like [`Self::import`], on the connection of a running transaction.
+    pub fn import_in(&self, conn: &Connection, record: &Record) -> CoreResult<bool> {
+        const COMMAND: &str = r"
+        INSERT INTO history(
+            device_id,
+            domain,
+            remote_addr,
+            timestamp,
+            end_time,
+            session_type,
+            connection_type,
+            result,
+            result_detail,
+            bytes_sent,
+            bytes_received,
+            peer_os
+        )
+        SELECT ?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12
+        WHERE NOT EXISTS (
+            SELECT 1 FROM history
+            WHERE device_id = ?1 AND domain = ?2 AND timestamp = ?4 AND session_type = ?6
+        )";
 
-        let _ = self
-            .pool
-            .get()?
-            .execute(COMMAND, params![device_id, domain, timestamp, timestamp])?;
+        let session_type: &str = record.session_type.into();
+        let connection_type: &str = record.connection_type.into();
 
-        Ok(())
+        let inserted = conn.execute(
+            COMMAND,
+            params![
+                record.device_id,
+                record.domain,
+                record.remote_addr,
+                record.timestamp,
+                record.end_time,
+                session_type,
+                connection_type,
+                record.result.kind(),
+                record.result.detail(),
+                record.bytes_sent as i64,
+                record.bytes_received as i64,
+                record.peer_os,
+            ],
+        )?;
+
+        Ok(inserted > 0)
     }
 
-    pub fn query(&self, time_range: Option<(i64, i64)>) -> CoreResult<Vec<Record>> {
-        const COMMAND: &str =
-            r"SELECT * FROM history WHERE timestamp BETWEEN ? AND ? ORDER BY timestamp DESC";
+    /// Returns the matched records of the requested page, latest first, together with the
+    /// count of all matched records.
+    pub fn query(&self, filter: &HistoryFilter) -> CoreResult<HistoryPage> {
+        const CONDITION: &str = r"
+        WHERE history.timestamp BETWEEN ?1 AND ?2
+        AND (?3 IS NULL OR history.device_id = ?3)
+        AND (?4 IS NULL OR history.domain = ?4)
+        AND (?5 IS NULL OR history.session_type = ?5)
+        AND (?6 IS NULL OR history.connection_type = ?6)
+        AND (?7 IS NULL OR (history.result = 'success') = (?7 = 'success'))";
+
+        let (start, end) = filter
+            .time_range
+            .unwrap_or_else(|| (0, chrono::Utc::now().timestamp()));
+        let session_type: Option<&str> = filter.session_type.map(Into::into);
+        let connection_type: Option<&str> = filter.connection_type.map(Into::into);
+        let result = filter.result.map(|result| match result {
+            SessionResultKind::Success => "success",
+            SessionResultKind::Failed => "failed",
+        });
 
-        let (start, end) = time_range.unwrap_or_else(|| (0, chrono::Utc::now().timestamp()));
+        let (limit, offset) = match filter.page {
+            Some(page) => {
+                let limit = filter.limit.unwrap_or(DEFAULT_PAGE_LIMIT).max(1);
+                (limit as i64, (page.max(1) - 1) as i64 * limit as i64)
+            }
+            None => (-1, 0),
+        };
 
         let conn = self.pool.get()?;
 
-        let mut stmt = conn.prepare(COMMAND)?;
-        let rows = stmt.query_and_then([start, end], parse_record)?;
+        let total: u32 = conn.query_row(
+            &format!("SELECT COUNT(*) FROM history {CONDITION}"),
+            params![
+                start,
+                end,
+                filter.device_id,
+                filter.domain,
+                session_type,
+                connection_type,
+                result
+            ],
+            |row| row.get(0),
+        )?;
+
+        let mut stmt = conn.prepare(&format!(
+            r"
+            SELECT {HISTORY_COLUMNS}, contact_id, contact_alias FROM history
+            LEFT JOIN (
+                SELECT
+                    id AS contact_id,
+                    alias AS contact_alias,
+                    device_id AS contact_device_id,
+                    domain AS contact_domain
+                FROM contacts
+            ) ON contact_device_id = history.device_id AND contact_domain = history.domain
+            {CONDITION}
+            ORDER BY history.timestamp DESC, history.id DESC
+            LIMIT ?8 OFFSET ?9"
+        ))?;
+
+        let rows = stmt.query_and_then(
+            params![
+                start,
+                end,
+                filter.device_id,
+                filter.domain,
+                session_type,
+                connection_type,
+                result,
+                limit,
+                offset
+            ],
+            parse_record,
+        )?;
 
         let mut records = Vec::new();
         for row in rows {
             records.push(row?);
         }
 
-        Ok(records)
+        Ok(HistoryPage { total, records })
+    }
+
+    pub fn query_by_device(&self, device_id: i64, domain: &str) -> CoreResult<Vec<Record>> {
+        let page = self.query(&HistoryFilter {
+            device_id: Some(device_id),
+            domain: Some(domain.to_string()),
+            ..Default::default()
+        })?;
+
+        Ok(page.records)
     }
 
     pub fn delete_domain_related(&self, domain: &str) -> CoreResult<()> {
@@ -84,10 +439,27 @@ impl HistoryRepository {
 }
 
 fn parse_record(row: &Row) -> CoreResult<Record> {
+    let session_type: String = row.get("session_type")?;
+    let connection_type: String = row.get("connection_type")?;
+    let result: String = row.get("result")?;
+    let bytes_sent: i64 = row.get("bytes_sent")?;
+    let bytes_received: i64 = row.get("bytes_received")?;
+
     Ok(Record {
-        id: row.get(0)?,
-        device_id: row.get(1)?,
-        domain: row.get(2)?,
-        timestamp: row.get(3)?,
+        id: row.get("id")?,
+        device_id: row.get("device_id")?,
+        domain: row.get("domain")?,
+        remote_addr: row.get("remote_addr")?,
+        timestamp: row.get("timestamp")?,
+        end_time: row.get("end_time")?,
+        session_type: SessionType::from_str(&session_type).map_err(|err| core_error!("{}", err))?,
+        connection_type: ConnectionType::from_str(&connection_type)
+            .map_err(|err| core_error!("{}", err))?,
+        result: SessionResult::parse(&result, row.get("result_detail")?)?,
+        bytes_sent: bytes_sent as u64,
+        bytes_received: bytes_received as u64,
+        peer_os: row.get("peer_os")?,
+        contact_id: row.get("contact_id")?,
+        contact_alias: row.get("contact_alias")?,
     })
 }
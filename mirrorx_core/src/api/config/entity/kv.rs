@@ -1,9 +1,20 @@
-use crate::{core_error, error::CoreResult};
+use crate::{
+    api::config::{
+        cipher::StorageCipher,
+        settings::{Settings, SETTINGS_KEY_PREFIX},
+    },
+    core_error,
+    error::CoreResult,
+    utility::proxy::ProxyConfig,
+};
 use r2d2::Pool;
 use r2d2_sqlite::SqliteConnectionManager;
-use rusqlite::OptionalExtension;
+use rusqlite::{Connection, OptionalExtension};
 use serde::{Deserialize, Serialize};
-use std::str::FromStr;
+use std::{str::FromStr, sync::Arc};
+
+/// Keys whose values hold secrets, stored encrypted with [`StorageCipher`].
+const SECRET_KEYS: [&str; 1] = ["proxy"];
 
 #[derive(Debug, Serialize, Deserialize)]
 #[serde(rename_all = "snake_case")]
@@ -38,24 +49,23 @@ impl FromStr for Theme {
 
 pub struct KVRepository {
     pool: Pool<SqliteConnectionManager>,
+    cipher: Arc<StorageCipher>,
 }
 
 impl KVRepository {
-    pub fn new(pool: Pool<SqliteConnectionManager>) -> Self {
-        Self { pool }
+    pub fn new(pool: Pool<SqliteConnectionManager>, cipher: Arc<StorageCipher>) -> Self {
+        Self { pool, cipher }
     }
 
-    pub fn ensure_table(&self) -> CoreResult<()> {
-        let conn = self.pool.get()?;
-
-        const COMMAND: &str = r"
-        CREATE TABLE IF NOT EXISTS kv(
-            id INTEGER PRIMARY KEY,
-            key TEXT NOT NULL UNIQUE,
-            value TEXT NOT NULL
-        )";
-
-        conn.execute(COMMAND, [])?;
+    /// Encrypts secret values written by versions before encryption at rest.
+    pub fn encrypt_plaintext_secrets(&self) -> CoreResult<()> {
+        for key in SECRET_KEYS {
+            if let Some(value) = self.get_stored(key)? {
+                if !StorageCipher::is_encrypted(&value) {
+                    self.set(key, &value)?;
+                }
+            }
+        }
 
         Ok(())
     }
@@ -82,21 +92,146 @@ impl KVRepository {
         }
     }
 
+    pub fn set_proxy(&self, value: &ProxyConfig) -> CoreResult<()> {
+        self.set("proxy", &serde_json::to_string(value)?)
+    }
+
+    pub fn get_proxy(&self) -> CoreResult<Option<ProxyConfig>> {
+        self.get_proxy_in(&self.pool.get()?)
+    }
+
+    /// Like [`Self::get_proxy`], on the connection of a running transaction.
+    pub fn get_proxy_in(&self, conn: &Connection) -> CoreResult<Option<ProxyConfig>> {
+        match self.get_in(conn, "proxy")? {
+            Some(proxy_str) => Ok(Some(serde_json::from_str(&proxy_str)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Loads settings, values which are missing or invalid fall back to their defaults.
+    pub fn get_settings(&self) -> CoreResult<Settings> {
+        let mut settings = Settings::default();
+
+        for (key, value) in self.get_all()? {
+            let Some(field) = key.strip_prefix(SETTINGS_KEY_PREFIX) else {
+                continue;
+            };
+
+            let value = match serde_json::from_str(&value) {
+                Ok(value) => value,
+                Err(err) => {
+                    tracing::warn!(?key, ?err, "stored setting is not valid json");
+                    continue;
+                }
+            };
+
+            match settings.with_value(field, value) {
+                Ok(updated) => settings = updated,
+                Err(err) => tracing::warn!(?key, ?err, "ignore invalid stored setting"),
+            }
+        }
+
+        Ok(settings)
+    }
+
+    pub fn set_settings(&self, settings: &Settings) -> CoreResult<()> {
+        self.set_settings_in(&self.pool.get()?, settings)
+    }
+
+    /// Like [`Self::set_settings`], on the connection of a running transaction.
+    pub fn set_settings_in(&self, conn: &Connection, settings: &Settings) -> CoreResult<()> {
+        settings.validate()?;
+
+        for (key, value) in settings.to_entries()? {
+            self.set_in(conn, &key, &value)?;
+        }
+
+        Ok(())
+    }
+
+    /// Updates a single setting by field name and returns the settings after the update.
+    pub fn set_setting(&self, key: &str, value: serde_json::Value) -> CoreResult<Settings> {
+        let settings = self.get_settings()?.with_value(key, value)?;
+        self.set_settings(&settings)?;
+        Ok(settings)
+    }
+
+    /// Returns all entries, secret values decrypted.
+    pub fn get_all(&self) -> CoreResult<Vec<(String, String)>> {
+        const COMMAND: &str = r"SELECT key, value FROM kv";
+
+        let conn = self.pool.get()?;
+        let mut stmt = conn.prepare(COMMAND)?;
+        let rows = stmt.query_and_then([], |row| -> CoreResult<(String, String)> {
+            let key: String = row.get(0)?;
+            let value: String = row.get(1)?;
+
+            let value = if SECRET_KEYS.contains(&key.as_str()) {
+                self.cipher.decrypt(&value)?
+            } else {
+                value
+            };
+
+            Ok((key, value))
+        })?;
+
+        let mut values = Vec::new();
+        for row in rows {
+            values.push(row?);
+        }
+
+        Ok(values)
+    }
+
+    /// Writes a raw value, used by importing settings from another storage.
+    pub fn import(&self, key: &str, value: &str) -> CoreResult<()> {
+        self.set(key, value)
+    }
+
+    /// Like [`Self::import`], on the connection of a running transaction.
+    pub fn import_in(&self, conn: &Connection, key: &str, value: &str) -> CoreResult<()> {
+        self.set_in(conn, key, value)
+    }
+
     fn set(&self, key: &str, value: &str) -> CoreResult<()> {
+        self.set_in(&self.pool.get()?, key, value)
+    }
+
+    fn set_in(&self, conn: &Connection, key: &str, value: &str) -> CoreResult<()> {
         const COMMAND: &str =
             r"INSERT INTO kv(key, value) VALUES(?, ?) ON CONFLICT DO UPDATE SET value = ?";
 
-        let _ = self.pool.get()?.execute(COMMAND, [key, value, value])?;
+        let value = if SECRET_KEYS.contains(&key) {
+            self.cipher.encrypt(value)?
+        } else {
+            value.to_string()
+        };
+
+        let _ = conn.execute(COMMAND, [key, &value, &value])?;
 
         Ok(())
     }
 
     fn get(&self, key: &str) -> CoreResult<Option<String>> {
+        self.get_in(&self.pool.get()?, key)
+    }
+
+    fn get_in(&self, conn: &Connection, key: &str) -> CoreResult<Option<String>> {
+        match self.get_stored_in(conn, key)? {
+            Some(value) if SECRET_KEYS.contains(&key) => Ok(Some(self.cipher.decrypt(&value)?)),
+            value => Ok(value),
+        }
+    }
+
+    /// Reads the value as stored, secrets are left encrypted.
+    fn get_stored(&self, key: &str) -> CoreResult<Option<String>> {
+        self.get_stored_in(&self.pool.get()?, key)
+    }
+
+    fn get_stored_in(&self, conn: &Connection, key: &str) -> CoreResult<Option<String>> {
         const COMMAND: &str = r"SELECT value FROM kv WHERE key = ? LIMIT 1";
 
-        let value = self
-            .pool
-            .get()?
+        let value = conn
             .query_row(COMMAND, [key], |row| row.get(0))
             .optional()?;
 
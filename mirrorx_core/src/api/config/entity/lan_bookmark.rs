@@ -0,0 +1,141 @@
+use crate::{core_error, error::CoreResult};
+use r2d2::Pool;
+use r2d2_sqlite::SqliteConnectionManager;
+use rusqlite::{params, Row};
+use serde::{Deserialize, Serialize};
+
+/// Columns of `lan_bookmarks` read by name in [`parse_lan_bookmark`].
+const BOOKMARK_COLUMNS: &str = r"id, alias, host, port, created_at, updated_at, last_connected_at";
+
+/// A LAN host saved by the user, reachable directly even when it's on a routed subnet
+/// which discovery doesn't cover.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LanBookmark {
+    pub id: i64,
+    pub alias: String,
+    /// Hostname or IP address.
+    pub host: String,
+    pub port: u16,
+    pub created_at: i64,
+    pub updated_at: i64,
+    pub last_connected_at: Option<i64>,
+}
+
+pub struct LanBookmarkRepository {
+    pool: Pool<SqliteConnectionManager>,
+}
+
+impl LanBookmarkRepository {
+    pub fn new(pool: Pool<SqliteConnectionManager>) -> Self {
+        Self { pool }
+    }
+
+    pub fn create(&self, mut bookmark: LanBookmark) -> CoreResult<LanBookmark> {
+        const COMMAND: &str = r"
+        INSERT INTO lan_bookmarks(alias, host, port, created_at, updated_at)
+        VALUES(?1, ?2, ?3, ?4, ?4)";
+
+        validate(&bookmark)?;
+
+        let now = chrono::Utc::now().timestamp();
+        bookmark.host = bookmark.host.trim().to_string();
+        bookmark.created_at = now;
+        bookmark.updated_at = now;
+        bookmark.last_connected_at = None;
+
+        let conn = self.pool.get()?;
+        conn.execute(
+            COMMAND,
+            params![bookmark.alias, bookmark.host, bookmark.port, now],
+        )?;
+
+        bookmark.id = conn.last_insert_rowid();
+
+        Ok(bookmark)
+    }
+
+    pub fn update(&self, bookmark: &LanBookmark) -> CoreResult<()> {
+        const COMMAND: &str = r"
+        UPDATE lan_bookmarks SET alias = ?, host = ?, port = ?, updated_at = ? WHERE id = ?";
+
+        validate(bookmark)?;
+
+        self.pool.get()?.execute(
+            COMMAND,
+            params![
+                bookmark.alias,
+                bookmark.host.trim(),
+                bookmark.port,
+                chrono::Utc::now().timestamp(),
+                bookmark.id,
+            ],
+        )?;
+
+        Ok(())
+    }
+
+    pub fn get(&self, id: i64) -> CoreResult<LanBookmark> {
+        self.pool.get()?.query_row_and_then(
+            &format!("SELECT {BOOKMARK_COLUMNS} FROM lan_bookmarks WHERE id = ?"),
+            [id],
+            parse_lan_bookmark,
+        )
+    }
+
+    /// Returns bookmarks ordered by alias.
+    pub fn list(&self) -> CoreResult<Vec<LanBookmark>> {
+        let conn = self.pool.get()?;
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {BOOKMARK_COLUMNS} FROM lan_bookmarks ORDER BY alias COLLATE NOCASE ASC"
+        ))?;
+        let rows = stmt.query_and_then([], parse_lan_bookmark)?;
+
+        rows.collect()
+    }
+
+    /// Records a connection to every bookmark of `host` and `port`.
+    pub fn touch(&self, host: &str, port: u16) -> CoreResult<()> {
+        const COMMAND: &str = r"
+        UPDATE lan_bookmarks SET last_connected_at = ?
+        WHERE host = ? COLLATE NOCASE AND port = ?";
+
+        self.pool.get()?.execute(
+            COMMAND,
+            params![chrono::Utc::now().timestamp(), host.trim(), port],
+        )?;
+
+        Ok(())
+    }
+
+    pub fn delete(&self, id: i64) -> CoreResult<()> {
+        const COMMAND: &str = r"DELETE FROM lan_bookmarks WHERE id = ?";
+
+        self.pool.get()?.execute(COMMAND, [id])?;
+
+        Ok(())
+    }
+}
+
+fn validate(bookmark: &LanBookmark) -> CoreResult<()> {
+    if bookmark.host.trim().is_empty() {
+        return Err(core_error!("lan bookmark host is empty"));
+    }
+
+    if bookmark.port == 0 {
+        return Err(core_error!("lan bookmark port is invalid"));
+    }
+
+    Ok(())
+}
+
+fn parse_lan_bookmark(row: &Row) -> CoreResult<LanBookmark> {
+    Ok(LanBookmark {
+        id: row.get("id")?,
+        alias: row.get("alias")?,
+        host: row.get("host")?,
+        port: row.get("port")?,
+        created_at: row.get("created_at")?,
+        updated_at: row.get("updated_at")?,
+        last_connected_at: row.get("last_connected_at")?,
+    })
+}
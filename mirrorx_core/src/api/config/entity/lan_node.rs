@@ -0,0 +1,238 @@
+use crate::{
+    api::config::cipher::StorageCipher, component::lan::identity::NodeIdentity, core_error,
+    error::CoreResult,
+};
+use r2d2::Pool;
+use r2d2_sqlite::SqliteConnectionManager;
+use rusqlite::{params, OptionalExtension, Row};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+/// Columns of `lan_nodes` read by name in [`parse_pinned_node`].
+const NODE_COLUMNS: &str = r"node_id, fingerprint, host_name, first_seen, last_seen";
+
+/// Columns of `lan_peers` read by name in [`parse_lan_peer`].
+const PEER_COLUMNS: &str = r"addr, mac_addresses, host_name, node_id, updated_at";
+
+/// How far the identity a LAN node announces can be trusted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NodeTrust {
+    /// Announced by a version without node identities.
+    Unsigned,
+    /// Signed but never verified by a connection.
+    Unknown,
+    /// Signed with the key pinned on the first connection.
+    Trusted,
+    /// Signed with another key than the pinned one.
+    KeyChanged,
+}
+
+/// A LAN peer whose key was pinned on the first connection to it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PinnedNode {
+    pub node_id: String,
+    pub fingerprint: String,
+    pub host_name: String,
+    pub first_seen: i64,
+    pub last_seen: i64,
+}
+
+/// A LAN peer whose MAC addresses were learned while it was online, used to wake it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LanPeer {
+    pub addr: String,
+    pub mac_addresses: Vec<String>,
+    pub host_name: String,
+    pub node_id: Option<String>,
+    pub updated_at: i64,
+}
+
+/// Stores the identity of this installation, its private key encrypted with
+/// [`StorageCipher`], the keys pinned for LAN peers and their MAC addresses.
+pub struct LanNodeRepository {
+    pool: Pool<SqliteConnectionManager>,
+    cipher: Arc<StorageCipher>,
+}
+
+impl LanNodeRepository {
+    pub fn new(pool: Pool<SqliteConnectionManager>, cipher: Arc<StorageCipher>) -> Self {
+        Self { pool, cipher }
+    }
+
+    /// Loads the identity of this installation, it's generated on the first call.
+    pub fn identity(&self) -> CoreResult<NodeIdentity> {
+        const SELECT_COMMAND: &str = r"SELECT node_id, private_key FROM lan_identity WHERE id = 1";
+        const INSERT_COMMAND: &str =
+            r"INSERT INTO lan_identity(id, node_id, private_key) VALUES(1, ?, ?)";
+
+        let conn = self.pool.get()?;
+
+        let stored = conn
+            .query_row(SELECT_COMMAND, [], |row| {
+                Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
+            })
+            .optional()?;
+
+        if let Some((node_id, private_key)) = stored {
+            let pkcs8 = base64::decode(self.cipher.decrypt(&private_key)?)?;
+            return NodeIdentity::from_pkcs8(node_id, &pkcs8);
+        }
+
+        let (identity, pkcs8) = NodeIdentity::generate()?;
+        conn.execute(
+            INSERT_COMMAND,
+            params![
+                identity.node_id(),
+                self.cipher.encrypt(&base64::encode(pkcs8))?
+            ],
+        )?;
+
+        tracing::info!(?identity, "lan identity generated");
+
+        Ok(identity)
+    }
+
+    pub fn get(&self, node_id: &str) -> CoreResult<Option<PinnedNode>> {
+        let conn = self.pool.get()?;
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {NODE_COLUMNS} FROM lan_nodes WHERE node_id = ?"
+        ))?;
+        let mut rows = stmt.query_and_then([node_id], parse_pinned_node)?;
+
+        rows.next().transpose()
+    }
+
+    pub fn trust(&self, node_id: &str, fingerprint: &str) -> CoreResult<NodeTrust> {
+        Ok(match self.get(node_id)? {
+            Some(pinned) if pinned.fingerprint == fingerprint => NodeTrust::Trusted,
+            Some(_) => NodeTrust::KeyChanged,
+            None => NodeTrust::Unknown,
+        })
+    }
+
+    /// Pins `fingerprint` to `node_id` on first use, later calls fail when the
+    /// fingerprint differs from the pinned one until the pin is deleted.
+    pub fn pin(&self, node_id: &str, fingerprint: &str, host_name: &str) -> CoreResult<()> {
+        const INSERT_COMMAND: &str = r"
+        INSERT INTO lan_nodes(node_id, fingerprint, host_name, first_seen, last_seen)
+        VALUES(?1, ?2, ?3, ?4, ?4)";
+        const UPDATE_COMMAND: &str =
+            r"UPDATE lan_nodes SET host_name = ?, last_seen = ? WHERE node_id = ?";
+
+        let now = chrono::Utc::now().timestamp();
+
+        match self.get(node_id)? {
+            None => {
+                self.pool.get()?.execute(
+                    INSERT_COMMAND,
+                    params![node_id, fingerprint, host_name, now],
+                )?;
+
+                tracing::info!(?node_id, ?fingerprint, "lan node pinned");
+            }
+            Some(pinned) if pinned.fingerprint == fingerprint => {
+                self.pool
+                    .get()?
+                    .execute(UPDATE_COMMAND, params![host_name, now, node_id])?;
+            }
+            Some(pinned) => {
+                return Err(core_error!(
+                    "key of lan node '{}' changed from {} to {}",
+                    node_id,
+                    pinned.fingerprint,
+                    fingerprint
+                ));
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn list(&self) -> CoreResult<Vec<PinnedNode>> {
+        let conn = self.pool.get()?;
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {NODE_COLUMNS} FROM lan_nodes ORDER BY last_seen DESC"
+        ))?;
+        let rows = stmt.query_and_then([], parse_pinned_node)?;
+
+        rows.collect()
+    }
+
+    /// Forgets the pinned key, the next connection pins the key the node presents.
+    pub fn delete(&self, node_id: &str) -> CoreResult<()> {
+        const COMMAND: &str = r"DELETE FROM lan_nodes WHERE node_id = ?";
+
+        self.pool.get()?.execute(COMMAND, [node_id])?;
+
+        Ok(())
+    }
+
+    /// Inserts the peer or replaces the one stored for the same address.
+    pub fn save_peer(&self, peer: &LanPeer) -> CoreResult<()> {
+        const COMMAND: &str = r"
+        INSERT OR REPLACE INTO lan_peers(addr, mac_addresses, host_name, node_id, updated_at)
+        VALUES(?, ?, ?, ?, ?)";
+
+        self.pool.get()?.execute(
+            COMMAND,
+            params![
+                peer.addr,
+                serde_json::to_string(&peer.mac_addresses)?,
+                peer.host_name,
+                peer.node_id,
+                peer.updated_at
+            ],
+        )?;
+
+        Ok(())
+    }
+
+    pub fn get_peer(&self, addr: &str) -> CoreResult<Option<LanPeer>> {
+        let conn = self.pool.get()?;
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {PEER_COLUMNS} FROM lan_peers WHERE addr = ?"
+        ))?;
+        let mut rows = stmt.query_and_then([addr], parse_lan_peer)?;
+
+        rows.next().transpose()
+    }
+
+    pub fn list_peers(&self) -> CoreResult<Vec<LanPeer>> {
+        let conn = self.pool.get()?;
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {PEER_COLUMNS} FROM lan_peers ORDER BY updated_at DESC"
+        ))?;
+        let rows = stmt.query_and_then([], parse_lan_peer)?;
+
+        rows.collect()
+    }
+
+    pub fn delete_peer(&self, addr: &str) -> CoreResult<()> {
+        const COMMAND: &str = r"DELETE FROM lan_peers WHERE addr = ?";
+
+        self.pool.get()?.execute(COMMAND, [addr])?;
+
+        Ok(())
+    }
+}
+
+fn parse_pinned_node(row: &Row) -> CoreResult<PinnedNode> {
+    Ok(PinnedNode {
+        node_id: row.get("node_id")?,
+        fingerprint: row.get("fingerprint")?,
+        host_name: row.get("host_name")?,
+        first_seen: row.get("first_seen")?,
+        last_seen: row.get("last_seen")?,
+    })
+}
+
+fn parse_lan_peer(row: &Row) -> CoreResult<LanPeer> {
+    Ok(LanPeer {
+        addr: row.get("addr")?,
+        mac_addresses: serde_json::from_str(&row.get::<_, String>("mac_addresses")?)?,
+        host_name: row.get("host_name")?,
+        node_id: row.get("node_id")?,
+        updated_at: row.get("updated_at")?,
+    })
+}
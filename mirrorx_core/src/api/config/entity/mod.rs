@@ -1,3 +1,9 @@
+pub mod audit;
+pub mod contact;
 pub mod domain;
 pub mod history;
 pub mod kv;
+pub mod lan_bookmark;
+pub mod lan_node;
+pub mod temporary_password;
+pub mod transfer;
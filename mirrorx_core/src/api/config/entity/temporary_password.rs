@@ -0,0 +1,168 @@
+use crate::{api::config::cipher::StorageCipher, error::CoreResult};
+use r2d2::Pool;
+use r2d2_sqlite::SqliteConnectionManager;
+use rusqlite::{params, Row};
+use serde::Serialize;
+use std::{sync::Arc, time::Duration};
+
+/// Columns of `temporary_passwords` read by name in
+/// [`TemporaryPasswordRepository::parse_temporary_password`].
+const PASSWORD_COLUMNS: &str = r"id, domain_id, password, one_time, expire_at, created_at";
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TemporaryPassword {
+    pub id: i64,
+    pub domain_id: i64,
+    pub password: String,
+    pub one_time: bool,
+    pub expire_at: Option<i64>,
+    pub created_at: i64,
+}
+
+/// Passwords are stored encrypted with [`StorageCipher`].
+pub struct TemporaryPasswordRepository {
+    pool: Pool<SqliteConnectionManager>,
+    cipher: Arc<StorageCipher>,
+}
+
+impl TemporaryPasswordRepository {
+    pub fn new(pool: Pool<SqliteConnectionManager>, cipher: Arc<StorageCipher>) -> Self {
+        Self { pool, cipher }
+    }
+
+    /// Encrypts passwords of rows written by versions before encryption at rest.
+    pub fn encrypt_plaintext_secrets(&self) -> CoreResult<()> {
+        const SELECT_COMMAND: &str = r"SELECT id, password FROM temporary_passwords";
+        const UPDATE_COMMAND: &str = r"UPDATE temporary_passwords SET password = ? WHERE id = ?";
+
+        let mut conn = self.pool.get()?;
+        let tx = conn.transaction()?;
+
+        let rows = {
+            let mut stmt = tx.prepare(SELECT_COMMAND)?;
+            let rows = stmt.query_and_then([], |row| -> CoreResult<(i64, String)> {
+                Ok((row.get(0)?, row.get(1)?))
+            })?;
+            rows.collect::<CoreResult<Vec<_>>>()?
+        };
+
+        for (id, password) in rows {
+            if StorageCipher::is_encrypted(&password) {
+                continue;
+            }
+
+            tx.execute(UPDATE_COMMAND, params![self.cipher.encrypt(&password)?, id])?;
+        }
+
+        tx.commit()?;
+
+        Ok(())
+    }
+
+    pub fn create(
+        &self,
+        domain_id: i64,
+        password: &str,
+        one_time: bool,
+        valid_duration: Option<Duration>,
+    ) -> CoreResult<TemporaryPassword> {
+        const COMMAND: &str = r"
+        INSERT INTO temporary_passwords(domain_id, password, one_time, expire_at, created_at)
+        VALUES(?, ?, ?, ?, ?)";
+
+        let created_at = chrono::Utc::now().timestamp();
+        let expire_at = valid_duration.map(|duration| created_at + duration.as_secs() as i64);
+
+        let conn = self.pool.get()?;
+        conn.execute(
+            COMMAND,
+            params![
+                domain_id,
+                self.cipher.encrypt(password)?,
+                one_time,
+                expire_at,
+                created_at
+            ],
+        )?;
+
+        Ok(TemporaryPassword {
+            id: conn.last_insert_rowid(),
+            domain_id,
+            password: password.to_string(),
+            one_time,
+            expire_at,
+            created_at,
+        })
+    }
+
+    /// Returns passwords of the domain which are not expired yet, ordered by creation time.
+    pub fn get_valid_passwords(&self, domain_id: i64) -> CoreResult<Vec<TemporaryPassword>> {
+        let now = chrono::Utc::now().timestamp();
+
+        let conn = self.pool.get()?;
+        let mut stmt = conn.prepare(&format!(
+            r"
+            SELECT {PASSWORD_COLUMNS} FROM temporary_passwords
+            WHERE domain_id = ? AND (expire_at IS NULL OR expire_at > ?)
+            ORDER BY created_at ASC"
+        ))?;
+        let rows = stmt.query_and_then(params![domain_id, now], |row| {
+            self.parse_temporary_password(row)
+        })?;
+
+        let mut passwords = Vec::new();
+        for row in rows {
+            passwords.push(row?);
+        }
+
+        Ok(passwords)
+    }
+
+    /// Deletes a one-time password after it was used successfully. Returns false when the
+    /// password has already been consumed or revoked, the caller should treat it as invalid.
+    pub fn consume(&self, id: i64) -> CoreResult<bool> {
+        const COMMAND: &str = r"DELETE FROM temporary_passwords WHERE id = ? AND one_time = 1";
+
+        let affected = self.pool.get()?.execute(COMMAND, [id])?;
+
+        Ok(affected > 0)
+    }
+
+    pub fn delete(&self, id: i64) -> CoreResult<()> {
+        const COMMAND: &str = r"DELETE FROM temporary_passwords WHERE id = ?";
+
+        self.pool.get()?.execute(COMMAND, [id])?;
+
+        Ok(())
+    }
+
+    pub fn delete_expired(&self) -> CoreResult<()> {
+        const COMMAND: &str =
+            r"DELETE FROM temporary_passwords WHERE expire_at IS NOT NULL AND expire_at <= ?";
+
+        let now = chrono::Utc::now().timestamp();
+
+        self.pool.get()?.execute(COMMAND, [now])?;
+
+        Ok(())
+    }
+
+    pub fn delete_domain_related(&self, domain_id: i64) -> CoreResult<()> {
+        const COMMAND: &str = r"DELETE FROM temporary_passwords WHERE domain_id = ?";
+
+        self.pool.get()?.execute(COMMAND, [domain_id])?;
+
+        Ok(())
+    }
+
+    fn parse_temporary_password(&self, row: &Row) -> CoreResult<TemporaryPassword> {
+        Ok(TemporaryPassword {
+            id: row.get("id")?,
+            domain_id: row.get("domain_id")?,
+            password: self.cipher.decrypt(&row.get::<_, String>("password")?)?,
+            one_time: row.get("one_time")?,
+            expire_at: row.get("expire_at")?,
+            created_at: row.get("created_at")?,
+        })
+    }
+}
@@ -0,0 +1,386 @@
+use crate::{component::fs::tree::DirectoryTransferOptions, core_error, error::CoreResult};
+use r2d2::Pool;
+use r2d2_sqlite::SqliteConnectionManager;
+use rusqlite::{params, Row};
+use serde::{Deserialize, Serialize};
+use std::{path::PathBuf, str::FromStr};
+
+/// Columns of `transfers` read by name in [`parse_transfer`].
+const TRANSFER_COLUMNS: &str = r"id, remote_device_id, kind, local_path, remote_path, total_size,
+transferred_size, state, error, created_at, updated_at, options";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TransferKind {
+    Upload,
+    Download,
+    UploadDirectory,
+    DownloadDirectory,
+}
+
+impl TransferKind {
+    pub fn is_directory(self) -> bool {
+        matches!(
+            self,
+            TransferKind::UploadDirectory | TransferKind::DownloadDirectory
+        )
+    }
+}
+
+impl<'a> Into<&'a str> for TransferKind {
+    fn into(self) -> &'a str {
+        match self {
+            TransferKind::Upload => "upload",
+            TransferKind::Download => "download",
+            TransferKind::UploadDirectory => "upload_directory",
+            TransferKind::DownloadDirectory => "download_directory",
+        }
+    }
+}
+
+impl FromStr for TransferKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "upload" => Ok(TransferKind::Upload),
+            "download" => Ok(TransferKind::Download),
+            "upload_directory" => Ok(TransferKind::UploadDirectory),
+            "download_directory" => Ok(TransferKind::DownloadDirectory),
+            _ => Err(String::from("Unknown transfer kind")),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TransferState {
+    /// Waits for a free slot of the concurrency limit.
+    Queued,
+    Running,
+    /// Stopped by the user, the partial file is kept and resumed from.
+    Paused,
+    Done,
+    Failed,
+}
+
+impl<'a> Into<&'a str> for TransferState {
+    fn into(self) -> &'a str {
+        match self {
+            TransferState::Queued => "queued",
+            TransferState::Running => "running",
+            TransferState::Paused => "paused",
+            TransferState::Done => "done",
+            TransferState::Failed => "failed",
+        }
+    }
+}
+
+impl FromStr for TransferState {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "queued" => Ok(TransferState::Queued),
+            "running" => Ok(TransferState::Running),
+            "paused" => Ok(TransferState::Paused),
+            "done" => Ok(TransferState::Done),
+            "failed" => Ok(TransferState::Failed),
+            _ => Err(String::from("Unknown transfer state")),
+        }
+    }
+}
+
+/// A transfer of the queue. `remote_path` is the directory an upload is sent into or
+/// the file or directory a download reads from, `local_path` is the directory a
+/// directory download is received into.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TransferRecord {
+    pub id: String,
+    pub remote_device_id: String,
+    pub kind: TransferKind,
+    pub local_path: PathBuf,
+    pub remote_path: PathBuf,
+    pub total_size: u64,
+    pub transferred_size: u64,
+    pub state: TransferState,
+    /// Why the transfer failed.
+    pub error: Option<String>,
+    pub created_at: i64,
+    pub updated_at: i64,
+    /// Entries selected by a directory transfer.
+    pub options: Option<DirectoryTransferOptions>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TransferEntryState {
+    Pending,
+    Done,
+    /// The file transfer failed, it's retried when the directory transfer resumes.
+    Failed,
+    /// Left out of the transfer, e.g. it can't be read.
+    Skipped,
+}
+
+impl<'a> Into<&'a str> for TransferEntryState {
+    fn into(self) -> &'a str {
+        match self {
+            TransferEntryState::Pending => "pending",
+            TransferEntryState::Done => "done",
+            TransferEntryState::Failed => "failed",
+            TransferEntryState::Skipped => "skipped",
+        }
+    }
+}
+
+impl FromStr for TransferEntryState {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "pending" => Ok(TransferEntryState::Pending),
+            "done" => Ok(TransferEntryState::Done),
+            "failed" => Ok(TransferEntryState::Failed),
+            "skipped" => Ok(TransferEntryState::Skipped),
+            _ => Err(String::from("Unknown transfer entry state")),
+        }
+    }
+}
+
+/// An entry of a directory transfer, `path` is relative to the transferred directory
+/// like [`crate::component::fs::tree::TreeEntry::path`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TransferEntry {
+    pub path: String,
+    pub size: u64,
+    pub state: TransferEntryState,
+    /// Why the entry failed or was left out.
+    pub error: Option<String>,
+}
+
+pub struct TransferRepository {
+    pool: Pool<SqliteConnectionManager>,
+}
+
+impl TransferRepository {
+    pub fn new(pool: Pool<SqliteConnectionManager>) -> Self {
+        Self { pool }
+    }
+
+    /// Inserts the transfer or replaces the one with the same id.
+    pub fn save(&self, record: &TransferRecord) -> CoreResult<()> {
+        const COMMAND: &str = r"
+        INSERT OR REPLACE INTO transfers(
+            id,
+            remote_device_id,
+            kind,
+            local_path,
+            remote_path,
+            total_size,
+            transferred_size,
+            state,
+            error,
+            created_at,
+            updated_at,
+            options
+        ) VALUES(?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)";
+
+        let kind: &str = record.kind.into();
+        let state: &str = record.state.into();
+        let options = match record.options {
+            Some(ref options) => Some(serde_json::to_string(options)?),
+            None => None,
+        };
+
+        self.pool.get()?.execute(
+            COMMAND,
+            params![
+                record.id,
+                record.remote_device_id,
+                kind,
+                record.local_path.to_string_lossy(),
+                record.remote_path.to_string_lossy(),
+                record.total_size as i64,
+                record.transferred_size as i64,
+                state,
+                record.error,
+                record.created_at,
+                record.updated_at,
+                options,
+            ],
+        )?;
+
+        Ok(())
+    }
+
+    pub fn update_state(
+        &self,
+        id: &str,
+        state: TransferState,
+        transferred_size: u64,
+        error: Option<&str>,
+    ) -> CoreResult<()> {
+        const COMMAND: &str = r"
+        UPDATE transfers SET state = ?, transferred_size = ?, error = ?, updated_at = ?
+        WHERE id = ?";
+
+        let state: &str = state.into();
+
+        self.pool.get()?.execute(
+            COMMAND,
+            params![
+                state,
+                transferred_size as i64,
+                error,
+                chrono::Utc::now().timestamp(),
+                id,
+            ],
+        )?;
+
+        Ok(())
+    }
+
+    /// Returns transfers in the order they were queued.
+    pub fn list(&self) -> CoreResult<Vec<TransferRecord>> {
+        let conn = self.pool.get()?;
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {TRANSFER_COLUMNS} FROM transfers ORDER BY created_at ASC, rowid ASC"
+        ))?;
+        let rows = stmt.query_and_then([], parse_transfer)?;
+
+        rows.collect()
+    }
+
+    /// Removes the transfer with its entries.
+    pub fn delete(&self, id: &str) -> CoreResult<()> {
+        let mut conn = self.pool.get()?;
+        let tx = conn.transaction()?;
+        tx.execute(r"DELETE FROM transfer_entries WHERE transfer_id = ?", [id])?;
+        tx.execute(r"DELETE FROM transfers WHERE id = ?", [id])?;
+        tx.commit()?;
+
+        Ok(())
+    }
+
+    /// Removes the transfers which are done from the queue.
+    pub fn delete_done(&self) -> CoreResult<()> {
+        let state: &str = TransferState::Done.into();
+
+        let mut conn = self.pool.get()?;
+        let tx = conn.transaction()?;
+        tx.execute(
+            r"DELETE FROM transfer_entries WHERE transfer_id IN (SELECT id FROM transfers WHERE state = ?)",
+            [state],
+        )?;
+        tx.execute(r"DELETE FROM transfers WHERE state = ?", [state])?;
+        tx.commit()?;
+
+        Ok(())
+    }
+
+    /// Replaces the entries of the directory transfer `id`.
+    pub fn save_entries(&self, id: &str, entries: &[TransferEntry]) -> CoreResult<()> {
+        const COMMAND: &str = r"
+        INSERT INTO transfer_entries(transfer_id, path, size, state, error)
+        VALUES(?, ?, ?, ?, ?)";
+
+        let mut conn = self.pool.get()?;
+        let tx = conn.transaction()?;
+        tx.execute(r"DELETE FROM transfer_entries WHERE transfer_id = ?", [id])?;
+
+        {
+            let mut stmt = tx.prepare(COMMAND)?;
+            for entry in entries {
+                let state: &str = entry.state.into();
+                stmt.execute(params![
+                    id,
+                    entry.path,
+                    entry.size as i64,
+                    state,
+                    entry.error
+                ])?;
+            }
+        }
+
+        tx.commit()?;
+
+        Ok(())
+    }
+
+    /// Updates the file `path` of the directory transfer `id`, the entries left out
+    /// keep their states.
+    pub fn update_entry(
+        &self,
+        id: &str,
+        path: &str,
+        state: TransferEntryState,
+        error: Option<&str>,
+    ) -> CoreResult<()> {
+        const COMMAND: &str = r"
+        UPDATE transfer_entries SET state = ?, error = ?
+        WHERE transfer_id = ? AND path = ? AND state != ?";
+
+        let state: &str = state.into();
+        let skipped: &str = TransferEntryState::Skipped.into();
+        self.pool
+            .get()?
+            .execute(COMMAND, params![state, error, id, path, skipped])?;
+
+        Ok(())
+    }
+
+    /// Returns the entries of the directory transfer `id` in the order they're
+    /// transferred.
+    pub fn list_entries(&self, id: &str) -> CoreResult<Vec<TransferEntry>> {
+        const COMMAND: &str = r"
+        SELECT path, size, state, error FROM transfer_entries WHERE transfer_id = ?
+        ORDER BY rowid ASC";
+
+        let conn = self.pool.get()?;
+        let mut stmt = conn.prepare(COMMAND)?;
+        let rows = stmt.query_and_then([id], parse_transfer_entry)?;
+
+        rows.collect()
+    }
+}
+
+fn parse_transfer(row: &Row) -> CoreResult<TransferRecord> {
+    let kind: String = row.get("kind")?;
+    let local_path: String = row.get("local_path")?;
+    let remote_path: String = row.get("remote_path")?;
+    let total_size: i64 = row.get("total_size")?;
+    let transferred_size: i64 = row.get("transferred_size")?;
+    let state: String = row.get("state")?;
+    let options: Option<String> = row.get("options")?;
+
+    Ok(TransferRecord {
+        id: row.get("id")?,
+        remote_device_id: row.get("remote_device_id")?,
+        kind: TransferKind::from_str(&kind).map_err(|err| core_error!("{}", err))?,
+        local_path: PathBuf::from(local_path),
+        remote_path: PathBuf::from(remote_path),
+        total_size: total_size as u64,
+        transferred_size: transferred_size as u64,
+        state: TransferState::from_str(&state).map_err(|err| core_error!("{}", err))?,
+        error: row.get("error")?,
+        created_at: row.get("created_at")?,
+        updated_at: row.get("updated_at")?,
+        options: match options {
+            Some(options) => Some(serde_json::from_str(&options)?),
+            None => None,
+        },
+    })
+}
+
+fn parse_transfer_entry(row: &Row) -> CoreResult<TransferEntry> {
+    let size: i64 = row.get("size")?;
+    let state: String = row.get("state")?;
+
+    Ok(TransferEntry {
+        path: row.get("path")?,
+        size: size as u64,
+        state: TransferEntryState::from_str(&state).map_err(|err| core_error!("{}", err))?,
+        error: row.get("error")?,
+    })
+}
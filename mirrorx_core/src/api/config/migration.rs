@@ -0,0 +1,487 @@
+use crate::{core_error, error::CoreResult};
+use rusqlite::{Connection, Transaction};
+use std::path::{Path, PathBuf};
+
+struct Migration {
+    version: u32,
+    description: &'static str,
+    up: fn(&Transaction) -> CoreResult<()>,
+}
+
+/// Ordered schema migrations, the schema version of a database is stored in
+/// `PRAGMA user_version`. Append new steps to the end and never modify a released one.
+const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        description: "create domains, kv and history tables",
+        up: create_initial_tables,
+    },
+    Migration {
+        version: 2,
+        description: "create temporary_passwords table",
+        up: create_temporary_passwords_table,
+    },
+    Migration {
+        version: 3,
+        description: "add tls_pin and expire columns to domains",
+        up: add_domain_tls_pin_and_expire,
+    },
+    Migration {
+        version: 4,
+        description: "create contacts table",
+        up: create_contacts_table,
+    },
+    Migration {
+        version: 5,
+        description: "record every session in history with type, outcome and traffic",
+        up: rebuild_history_table,
+    },
+    Migration {
+        version: 6,
+        description: "create audit_sessions and audit_events tables",
+        up: create_audit_tables,
+    },
+    Migration {
+        version: 7,
+        description: "create lan_identity and lan_nodes tables",
+        up: create_lan_node_tables,
+    },
+    Migration {
+        version: 8,
+        description: "create lan_peers table",
+        up: create_lan_peers_table,
+    },
+    Migration {
+        version: 9,
+        description: "create lan_bookmarks table",
+        up: create_lan_bookmarks_table,
+    },
+    Migration {
+        version: 10,
+        description: "create transfers table",
+        up: create_transfers_table,
+    },
+    Migration {
+        version: 11,
+        description: "end sessions migrated from the legacy history table",
+        up: end_legacy_history_sessions,
+    },
+    Migration {
+        version: 12,
+        description: "add options column to transfers and create transfer_entries table",
+        up: add_directory_transfers,
+    },
+    Migration {
+        version: 13,
+        description: "add destination column to audit_events",
+        up: add_audit_event_destination,
+    },
+];
+
+pub const LATEST_SCHEMA_VERSION: u32 = MIGRATIONS[MIGRATIONS.len() - 1].version;
+
+/// Upgrades the database to [`LATEST_SCHEMA_VERSION`]. Every step runs in its own
+/// transaction. Before an existing database is upgraded a copy only readable by the
+/// current user is saved as [`backup_path`], it holds secrets which may still be in
+/// plaintext and is removed once all steps succeeded. When a step failed the copy is
+/// kept, the database is restored by replacing it with the copy while the app isn't
+/// running.
+pub fn migrate(conn: &mut Connection, db_path: &Path) -> CoreResult<()> {
+    let current_version = schema_version(conn)?;
+
+    if current_version > LATEST_SCHEMA_VERSION {
+        return Err(core_error!(
+            "database schema version {} is newer than supported version {}",
+            current_version,
+            LATEST_SCHEMA_VERSION
+        ));
+    }
+
+    if current_version == LATEST_SCHEMA_VERSION {
+        return Ok(());
+    }
+
+    let backup_path = if current_version > 0 || has_tables(conn)? {
+        let backup_path = backup_path(db_path, current_version);
+        tracing::info!(
+            ?backup_path,
+            current_version,
+            "backup database before migration"
+        );
+
+        if backup_path.exists() {
+            std::fs::remove_file(&backup_path)?;
+        }
+
+        create_private_file(&backup_path)?;
+        conn.execute("VACUUM INTO ?", [backup_path.to_string_lossy().as_ref()])?;
+
+        Some(backup_path)
+    } else {
+        None
+    };
+
+    for migration in MIGRATIONS
+        .iter()
+        .filter(|migration| migration.version > current_version)
+    {
+        tracing::info!(
+            version = migration.version,
+            description = migration.description,
+            "migrate database"
+        );
+
+        let tx = conn.transaction()?;
+        (migration.up)(&tx)?;
+        tx.pragma_update(None, "user_version", migration.version)?;
+        tx.commit()?;
+    }
+
+    if let Some(backup_path) = backup_path {
+        std::fs::remove_file(backup_path)?;
+    }
+
+    Ok(())
+}
+
+pub fn schema_version(conn: &Connection) -> CoreResult<u32> {
+    Ok(conn.pragma_query_value(None, "user_version", |row| row.get(0))?)
+}
+
+pub fn backup_path(db_path: &Path, version: u32) -> PathBuf {
+    let mut file_name = db_path.file_name().unwrap_or_default().to_os_string();
+    file_name.push(format!(".v{version}.bak"));
+    db_path.with_file_name(file_name)
+}
+
+/// Creates the empty file `VACUUM INTO` writes to, so the copy is never readable by
+/// other users.
+fn create_private_file(path: &Path) -> CoreResult<()> {
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create_new(true);
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+
+    options.open(path)?;
+
+    Ok(())
+}
+
+fn has_tables(conn: &Connection) -> CoreResult<bool> {
+    let count: u32 = conn.query_row(
+        "SELECT COUNT(*) FROM sqlite_master WHERE type = 'table'",
+        [],
+        |row| row.get(0),
+    )?;
+
+    Ok(count > 0)
+}
+
+fn has_column(tx: &Transaction, table: &str, column: &str) -> bool {
+    tx.prepare(&format!("SELECT {column} FROM {table} LIMIT 0"))
+        .is_ok()
+}
+
+// databases created before migrations were introduced have user_version 0 but may
+// already contain the tables, so the first steps are idempotent
+
+fn create_initial_tables(tx: &Transaction) -> CoreResult<()> {
+    tx.execute_batch(
+        r"
+        CREATE TABLE IF NOT EXISTS domains(
+            id INTEGER PRIMARY KEY,
+            name TEXT NOT NULL UNIQUE,
+            addr TEXT NOT NULL,
+            signaling_port INTEGER NOT NULL,
+            subscribe_port INTEGER NOT NULL,
+            is_primary BOOLEAN NOT NULL,
+            device_id INTEGER NOT NULL,
+            password TEXT NOT NULL,
+            finger_print TEXT NOT NULL,
+            remarks TEXT NOT NULL
+        );
+
+        CREATE TABLE IF NOT EXISTS kv(
+            id INTEGER PRIMARY KEY,
+            key TEXT NOT NULL UNIQUE,
+            value TEXT NOT NULL
+        );
+
+        CREATE TABLE IF NOT EXISTS history(
+            id INTEGER PRIMARY KEY,
+            device_id INTEGER NOT NULL,
+            domain TEXT NOT NULL,
+            timestamp INTEGER NOT NULL
+        );
+
+        CREATE UNIQUE INDEX IF NOT EXISTS uq_device_id_domain ON history(device_id, domain);
+        ",
+    )?;
+
+    Ok(())
+}
+
+fn create_temporary_passwords_table(tx: &Transaction) -> CoreResult<()> {
+    tx.execute_batch(
+        r"
+        CREATE TABLE IF NOT EXISTS temporary_passwords(
+            id INTEGER PRIMARY KEY,
+            domain_id INTEGER NOT NULL,
+            password TEXT NOT NULL,
+            one_time BOOLEAN NOT NULL,
+            expire_at INTEGER,
+            created_at INTEGER NOT NULL
+        );
+        ",
+    )?;
+
+    Ok(())
+}
+
+fn add_domain_tls_pin_and_expire(tx: &Transaction) -> CoreResult<()> {
+    if !has_column(tx, "domains", "tls_pin") {
+        tx.execute(
+            "ALTER TABLE domains ADD COLUMN tls_pin TEXT NOT NULL DEFAULT ''",
+            [],
+        )?;
+    }
+
+    if !has_column(tx, "domains", "expire") {
+        tx.execute(
+            "ALTER TABLE domains ADD COLUMN expire INTEGER NOT NULL DEFAULT 0",
+            [],
+        )?;
+    }
+
+    Ok(())
+}
+
+fn create_contacts_table(tx: &Transaction) -> CoreResult<()> {
+    tx.execute_batch(
+        r"
+        CREATE TABLE contacts(
+            id INTEGER PRIMARY KEY,
+            device_id INTEGER NOT NULL,
+            domain TEXT NOT NULL,
+            alias TEXT NOT NULL,
+            group_name TEXT NOT NULL,
+            tags TEXT NOT NULL,
+            notes TEXT NOT NULL,
+            connection_preference TEXT NOT NULL,
+            favorite BOOLEAN NOT NULL,
+            created_at INTEGER NOT NULL,
+            updated_at INTEGER NOT NULL
+        );
+
+        CREATE UNIQUE INDEX uq_contacts_device_id_domain ON contacts(device_id, domain);
+        ",
+    )?;
+
+    Ok(())
+}
+
+fn rebuild_history_table(tx: &Transaction) -> CoreResult<()> {
+    // the old table kept one row per device, rows of the new table are sessions so the
+    // unique index is dropped together with the old table
+    tx.execute_batch(
+        r"
+        CREATE TABLE history_sessions(
+            id INTEGER PRIMARY KEY,
+            device_id INTEGER NOT NULL,
+            domain TEXT NOT NULL,
+            remote_addr TEXT NOT NULL,
+            timestamp INTEGER NOT NULL,
+            end_time INTEGER,
+            session_type TEXT NOT NULL,
+            connection_type TEXT NOT NULL,
+            result TEXT NOT NULL,
+            result_detail TEXT NOT NULL,
+            bytes_sent INTEGER NOT NULL,
+            bytes_received INTEGER NOT NULL,
+            peer_os TEXT
+        );
+
+        INSERT INTO history_sessions(
+            id,
+            device_id,
+            domain,
+            remote_addr,
+            timestamp,
+            end_time,
+            session_type,
+            connection_type,
+            result,
+            result_detail,
+            bytes_sent,
+            bytes_received,
+            peer_os
+        )
+        SELECT id, device_id, domain, '', timestamp, NULL, 'unknown', 'domain', 'success', '', 0, 0, NULL
+        FROM history;
+
+        DROP TABLE history;
+        ALTER TABLE history_sessions RENAME TO history;
+
+        CREATE INDEX idx_history_timestamp ON history(timestamp);
+        CREATE INDEX idx_history_device_id_domain ON history(device_id, domain);
+        ",
+    )?;
+
+    Ok(())
+}
+
+fn create_audit_tables(tx: &Transaction) -> CoreResult<()> {
+    tx.execute_batch(
+        r"
+        CREATE TABLE audit_sessions(
+            id INTEGER PRIMARY KEY,
+            device_id INTEGER,
+            remote_ip TEXT NOT NULL,
+            domain TEXT NOT NULL,
+            credential TEXT NOT NULL,
+            permissions TEXT NOT NULL,
+            start_time INTEGER NOT NULL,
+            end_time INTEGER
+        );
+
+        CREATE TABLE audit_events(
+            id INTEGER PRIMARY KEY,
+            session_id INTEGER NOT NULL,
+            timestamp INTEGER NOT NULL,
+            operation TEXT NOT NULL,
+            path TEXT NOT NULL,
+            size INTEGER,
+            error TEXT NOT NULL
+        );
+
+        CREATE INDEX idx_audit_sessions_start_time ON audit_sessions(start_time);
+        CREATE INDEX idx_audit_events_session_id ON audit_events(session_id);
+        ",
+    )?;
+
+    Ok(())
+}
+
+fn create_lan_node_tables(tx: &Transaction) -> CoreResult<()> {
+    tx.execute_batch(
+        r"
+        CREATE TABLE lan_identity(
+            id INTEGER PRIMARY KEY CHECK (id = 1),
+            node_id TEXT NOT NULL,
+            private_key TEXT NOT NULL
+        );
+
+        CREATE TABLE lan_nodes(
+            node_id TEXT PRIMARY KEY,
+            fingerprint TEXT NOT NULL,
+            host_name TEXT NOT NULL,
+            first_seen INTEGER NOT NULL,
+            last_seen INTEGER NOT NULL
+        );
+        ",
+    )?;
+
+    Ok(())
+}
+
+fn create_lan_peers_table(tx: &Transaction) -> CoreResult<()> {
+    tx.execute_batch(
+        r"
+        CREATE TABLE lan_peers(
+            addr TEXT PRIMARY KEY,
+            mac_addresses TEXT NOT NULL,
+            host_name TEXT NOT NULL,
+            node_id TEXT,
+            updated_at INTEGER NOT NULL
+        );
+        ",
+    )?;
+
+    Ok(())
+}
+
+fn create_lan_bookmarks_table(tx: &Transaction) -> CoreResult<()> {
+    tx.execute_batch(
+        r"
+        CREATE TABLE lan_bookmarks(
+            id INTEGER PRIMARY KEY,
+            alias TEXT NOT NULL,
+            host TEXT NOT NULL,
+            port INTEGER NOT NULL,
+            created_at INTEGER NOT NULL,
+            updated_at INTEGER NOT NULL,
+            last_connected_at INTEGER
+        );
+        ",
+    )?;
+
+    Ok(())
+}
+
+fn create_transfers_table(tx: &Transaction) -> CoreResult<()> {
+    tx.execute_batch(
+        r"
+        CREATE TABLE transfers(
+            id TEXT PRIMARY KEY,
+            remote_device_id TEXT NOT NULL,
+            kind TEXT NOT NULL,
+            local_path TEXT NOT NULL,
+            remote_path TEXT NOT NULL,
+            total_size INTEGER NOT NULL,
+            transferred_size INTEGER NOT NULL,
+            state TEXT NOT NULL,
+            error TEXT,
+            created_at INTEGER NOT NULL,
+            updated_at INTEGER NOT NULL
+        );
+        ",
+    )?;
+
+    Ok(())
+}
+
+/// Sessions copied from the legacy history table by [`rebuild_history_table`] got no
+/// end time, which reads as alive, they ended at an unknown time after their start.
+fn end_legacy_history_sessions(tx: &Transaction) -> CoreResult<()> {
+    tx.execute_batch(
+        r"
+        UPDATE history SET end_time = timestamp
+        WHERE end_time IS NULL AND session_type = 'unknown';
+        ",
+    )?;
+
+    Ok(())
+}
+
+fn add_directory_transfers(tx: &Transaction) -> CoreResult<()> {
+    if !has_column(tx, "transfers", "options") {
+        tx.execute("ALTER TABLE transfers ADD COLUMN options TEXT", [])?;
+    }
+
+    tx.execute_batch(
+        r"
+        CREATE TABLE transfer_entries(
+            transfer_id TEXT NOT NULL,
+            path TEXT NOT NULL,
+            size INTEGER NOT NULL,
+            state TEXT NOT NULL,
+            error TEXT
+        );
+        CREATE INDEX transfer_entries_transfer_id ON transfer_entries(transfer_id);
+        ",
+    )?;
+
+    Ok(())
+}
+
+fn add_audit_event_destination(tx: &Transaction) -> CoreResult<()> {
+    if !has_column(tx, "audit_events", "destination") {
+        tx.execute("ALTER TABLE audit_events ADD COLUMN destination TEXT", [])?;
+    }
+
+    Ok(())
+}
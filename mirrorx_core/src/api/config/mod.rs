@@ -1,15 +1,36 @@
+pub mod bundle;
+pub mod cipher;
 pub mod entity;
+pub mod migration;
+pub mod settings;
 
-use self::entity::{domain::DomainRepository, history::HistoryRepository, kv::KVRepository};
+use self::{
+    cipher::{KeyStores, StorageCipher},
+    entity::{
+        audit::AuditRepository, contact::ContactRepository, domain::DomainRepository,
+        history::HistoryRepository, kv::KVRepository, lan_bookmark::LanBookmarkRepository,
+        lan_node::LanNodeRepository, temporary_password::TemporaryPasswordRepository,
+        transfer::TransferRepository,
+    },
+};
 use crate::error::CoreResult;
+use r2d2::Pool;
 use r2d2_sqlite::SqliteConnectionManager;
+use rusqlite::Connection;
 use std::{path::Path, sync::Arc};
 
 #[derive(Clone)]
 pub struct LocalStorage {
+    pool: Pool<SqliteConnectionManager>,
     domain: Arc<DomainRepository>,
     kv: Arc<KVRepository>,
     history: Arc<HistoryRepository>,
+    temporary_password: Arc<TemporaryPasswordRepository>,
+    contact: Arc<ContactRepository>,
+    audit: Arc<AuditRepository>,
+    lan_node: Arc<LanNodeRepository>,
+    lan_bookmark: Arc<LanBookmarkRepository>,
+    transfer: Arc<TransferRepository>,
 }
 
 impl LocalStorage {
@@ -17,25 +38,66 @@ impl LocalStorage {
     where
         P: AsRef<Path>,
     {
-        let manager = SqliteConnectionManager::file(db_path);
+        let key_stores = KeyStores::system(db_path.as_ref());
+        Self::with_key_stores(db_path, &key_stores)
+    }
+
+    /// Opens the storage with its key loaded from `key_stores`.
+    pub fn with_key_stores<P>(db_path: P, key_stores: &KeyStores) -> CoreResult<LocalStorage>
+    where
+        P: AsRef<Path>,
+    {
+        let manager = SqliteConnectionManager::file(db_path.as_ref());
         let pool = r2d2::Pool::new(manager)?;
 
-        let domain_repository = DomainRepository::new(pool.clone());
-        domain_repository.ensure_table()?;
+        let cipher = {
+            let mut conn = pool.get()?;
+            migration::migrate(&mut conn, db_path.as_ref())?;
+            Arc::new(StorageCipher::load(&conn, key_stores)?)
+        };
+
+        let domain_repository = DomainRepository::new(pool.clone(), cipher.clone());
+        domain_repository.encrypt_plaintext_secrets()?;
 
-        let kv_repository = KVRepository::new(pool.clone());
-        kv_repository.ensure_table()?;
+        let temporary_password_repository =
+            TemporaryPasswordRepository::new(pool.clone(), cipher.clone());
+        temporary_password_repository.delete_expired()?;
+        temporary_password_repository.encrypt_plaintext_secrets()?;
 
-        let history_repository = HistoryRepository::new(pool);
-        history_repository.ensure_table()?;
+        let kv_repository = KVRepository::new(pool.clone(), cipher.clone());
+        kv_repository.encrypt_plaintext_secrets()?;
+
+        let audit_repository = AuditRepository::new(pool.clone());
+        audit_repository.delete_expired(kv_repository.get_settings()?.audit_retention_days)?;
 
         Ok(Self {
             domain: Arc::new(domain_repository),
             kv: Arc::new(kv_repository),
-            history: Arc::new(history_repository),
+            history: Arc::new(HistoryRepository::new(pool.clone())),
+            temporary_password: Arc::new(temporary_password_repository),
+            contact: Arc::new(ContactRepository::new(pool.clone())),
+            audit: Arc::new(audit_repository),
+            lan_bookmark: Arc::new(LanBookmarkRepository::new(pool.clone())),
+            transfer: Arc::new(TransferRepository::new(pool.clone())),
+            lan_node: Arc::new(LanNodeRepository::new(pool.clone(), cipher)),
+            pool,
         })
     }
 
+    /// Runs `f` in one transaction, nothing it wrote is kept when it fails. Writes
+    /// within `f` must go through the `_in` methods of the repositories.
+    pub fn transaction<T, F>(&self, f: F) -> CoreResult<T>
+    where
+        F: FnOnce(&Connection) -> CoreResult<T>,
+    {
+        let mut conn = self.pool.get()?;
+        let tx = conn.transaction()?;
+        let value = f(&tx)?;
+        tx.commit()?;
+
+        Ok(value)
+    }
+
     pub fn domain(&self) -> &DomainRepository {
         &self.domain
     }
@@ -47,4 +109,28 @@ impl LocalStorage {
     pub fn history(&self) -> &HistoryRepository {
         &self.history
     }
+
+    pub fn temporary_password(&self) -> &TemporaryPasswordRepository {
+        &self.temporary_password
+    }
+
+    pub fn contact(&self) -> &ContactRepository {
+        &self.contact
+    }
+
+    pub fn audit(&self) -> &Arc<AuditRepository> {
+        &self.audit
+    }
+
+    pub fn lan_node(&self) -> &LanNodeRepository {
+        &self.lan_node
+    }
+
+    pub fn lan_bookmark(&self) -> &LanBookmarkRepository {
+        &self.lan_bookmark
+    }
+
+    pub fn transfer(&self) -> &TransferRepository {
+        &self.transfer
+    }
 }
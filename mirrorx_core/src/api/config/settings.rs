@@ -0,0 +1,184 @@
+use crate::{api::endpoint::message::VideoCodec, core_error, error::CoreResult};
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use tokio::sync::watch;
+
+/// Prefix of setting keys in the kv table, every field is stored as its own JSON value
+/// under `settings.<field>`.
+pub(crate) const SETTINGS_KEY_PREFIX: &str = "settings.";
+
+static SETTINGS: Lazy<watch::Sender<Settings>> =
+    Lazy::new(|| watch::channel(Settings::default()).0);
+
+/// Tunables read by the core components, missing values fall back to their defaults.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    /// Frame rate requested from the remote desktop and used by the local encoder.
+    pub frame_rate: u8,
+    /// Upper bound of the encoder bitrate.
+    pub max_bitrate_kbps: u32,
+    /// Codec offered first when negotiating with the remote desktop.
+    pub preferred_codec: VideoCodec,
+    /// Whether audio of this device is captured for remote desktop sessions.
+    pub audio_enabled: bool,
+    pub lan_discoverable: bool,
+    /// Whether the legacy UDP broadcast is sent next to the mDNS announcement, for
+    /// peers running older versions.
+    pub lan_legacy_broadcast: bool,
+    /// UDP port of LAN discovery broadcasts.
+    pub lan_discover_port: u16,
+    /// TCP port LAN connections are accepted on.
+    pub lan_server_port: u16,
+    /// Name of the only network interface LAN components bind to, all interfaces are
+    /// used when `None`.
+    pub lan_interface: Option<String>,
+    /// Days incoming sessions are kept in the audit log, `0` keeps them forever.
+    pub audit_retention_days: u32,
+    /// File transfers running at the same time, the others wait in the queue.
+    pub transfer_concurrency: u8,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            frame_rate: 60,
+            max_bitrate_kbps: 4000,
+            preferred_codec: VideoCodec::H264,
+            audio_enabled: true,
+            lan_discoverable: true,
+            lan_legacy_broadcast: true,
+            lan_discover_port: 48000,
+            lan_server_port: 48001,
+            lan_interface: None,
+            audit_retention_days: 90,
+            transfer_concurrency: 3,
+        }
+    }
+}
+
+impl Settings {
+    pub fn validate(&self) -> CoreResult<()> {
+        if !(1..=120).contains(&self.frame_rate) {
+            return Err(core_error!("frame rate must be between 1 and 120"));
+        }
+
+        if !(500..=100_000).contains(&self.max_bitrate_kbps) {
+            return Err(core_error!(
+                "max bitrate must be between 500 and 100000 kbps"
+            ));
+        }
+
+        if self.lan_discover_port == 0 || self.lan_server_port == 0 {
+            return Err(core_error!("lan ports can't be 0"));
+        }
+
+        if self.lan_discover_port == self.lan_server_port {
+            return Err(core_error!("lan discover port and server port must differ"));
+        }
+
+        if self.lan_interface.as_deref() == Some("") {
+            return Err(core_error!("lan interface name can't be empty"));
+        }
+
+        if self.audit_retention_days > 3650 {
+            return Err(core_error!(
+                "audit retention can't be longer than 3650 days"
+            ));
+        }
+
+        if !(1..=16).contains(&self.transfer_concurrency) {
+            return Err(core_error!("transfer concurrency must be between 1 and 16"));
+        }
+
+        Ok(())
+    }
+
+    /// Returns a copy with the field `key` replaced by `value`, the result is validated.
+    pub fn with_value(&self, key: &str, value: serde_json::Value) -> CoreResult<Settings> {
+        let serde_json::Value::Object(mut fields) = serde_json::to_value(self)? else {
+            return Err(core_error!("settings should be serialized as object"));
+        };
+
+        if !fields.contains_key(key) {
+            return Err(core_error!("unknown setting '{}'", key));
+        }
+
+        fields.insert(key.to_string(), value);
+
+        let settings: Settings = serde_json::from_value(serde_json::Value::Object(fields))
+            .map_err(|err| core_error!("invalid value of setting '{}' ({})", key, err))?;
+        settings.validate()?;
+
+        Ok(settings)
+    }
+
+    /// Returns a copy with `entries`, `(key, JSON value)` pairs as stored in the kv table,
+    /// applied at once so fields checked against each other can change together. The
+    /// result is validated, keys of settings this version doesn't know are skipped.
+    pub(crate) fn with_entries(&self, entries: &[(String, String)]) -> CoreResult<Settings> {
+        let serde_json::Value::Object(mut fields) = serde_json::to_value(self)? else {
+            return Err(core_error!("settings should be serialized as object"));
+        };
+
+        for (key, value) in entries {
+            let Some(field) = key.strip_prefix(SETTINGS_KEY_PREFIX) else {
+                continue;
+            };
+
+            if !fields.contains_key(field) {
+                tracing::warn!(?key, "skip unknown setting");
+                continue;
+            }
+
+            let value = serde_json::from_str(value)
+                .map_err(|err| core_error!("invalid value of setting '{}' ({})", field, err))?;
+            fields.insert(field.to_string(), value);
+        }
+
+        let settings: Settings = serde_json::from_value(serde_json::Value::Object(fields))
+            .map_err(|err| core_error!("invalid settings ({})", err))?;
+        settings.validate()?;
+
+        Ok(settings)
+    }
+
+    /// Splits settings into `(key, JSON value)` pairs as stored in the kv table.
+    pub(crate) fn to_entries(&self) -> CoreResult<Vec<(String, String)>> {
+        let serde_json::Value::Object(fields) = serde_json::to_value(self)? else {
+            return Err(core_error!("settings should be serialized as object"));
+        };
+
+        fields
+            .into_iter()
+            .map(|(key, value)| {
+                Ok((
+                    format!("{SETTINGS_KEY_PREFIX}{key}"),
+                    serde_json::to_string(&value)?,
+                ))
+            })
+            .collect()
+    }
+}
+
+/// Returns the settings currently in effect.
+pub fn current_settings() -> Settings {
+    SETTINGS.borrow().clone()
+}
+
+/// Subscribes to settings changes, the receiver sees the current value first.
+pub fn subscribe_settings() -> watch::Receiver<Settings> {
+    SETTINGS.subscribe()
+}
+
+/// Replaces the settings in effect and notifies all subscribers when they changed.
+pub fn set_current_settings(settings: Settings) {
+    SETTINGS.send_if_modified(|current| {
+        if *current == settings {
+            false
+        } else {
+            *current = settings;
+            true
+        }
+    });
+}
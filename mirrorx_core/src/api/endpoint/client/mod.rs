@@ -7,21 +7,36 @@ use super::{
     message::*, EndPointStream,
 };
 use crate::{
-    api::endpoint::handlers::{
-        fs_download_file::handle_download_file_request, fs_send_file::handle_send_file_request,
-        fs_visit_directory::handle_visit_directory_request, input::handle_input,
-        negotiate_finished::handle_negotiate_finished_request,
+    api::{
+        config::{
+            entity::audit::{AuditSession, FileOperation},
+            settings::current_settings,
+        },
+        endpoint::handlers::{
+            fs_copy::handle_copy_request, fs_create_directory::handle_create_directory_request,
+            fs_delete::handle_delete_request,
+            fs_download_directory::handle_download_directory_request,
+            fs_download_file::handle_download_file_request, fs_rename::handle_rename_request,
+            fs_send_directory::handle_send_directory_request,
+            fs_send_file::handle_send_file_request,
+            fs_visit_directory::handle_visit_directory_request, input::handle_input,
+            negotiate_finished::handle_negotiate_finished_request,
+        },
     },
     call,
     component::{
         desktop::monitor::Monitor,
-        fs::transfer::{append_file_block, delete_file_append_session},
+        fs::transfer::{
+            append_file_block, handle_file_transfer_complete, handle_file_transfer_control,
+            handle_file_transfer_error, progress::TransferEvents, TransferSessions,
+        },
     },
     core_error,
     error::{CoreError, CoreResult},
     utility::{
         bincode::{bincode_deserialize, bincode_serialize},
         nonce_value::NonceValue,
+        proxy::connect_tcp,
     },
 };
 use bytes::Bytes;
@@ -31,13 +46,25 @@ use serde::de::DeserializeOwned;
 use std::{
     fmt::Display,
     ops::Deref,
-    sync::{atomic::AtomicU16, Arc},
+    path::Path,
+    sync::{
+        atomic::{AtomicU16, AtomicU32, AtomicU64, Ordering},
+        Arc,
+    },
     time::Duration,
 };
-use tokio::sync::{mpsc::Sender, RwLock};
+use tokio::sync::{mpsc::Sender, watch, RwLock};
 
 const RECV_MESSAGE_TIMEOUT: Duration = Duration::from_secs(30);
 
+/// Sessions this device currently serves for remote endpoints.
+static PASSIVE_SESSION_COUNT: AtomicU32 = AtomicU32::new(0);
+
+/// Returns how many sessions this device currently serves for remote endpoints.
+pub fn passive_session_count() -> u32 {
+    PASSIVE_SESSION_COUNT.load(Ordering::SeqCst)
+}
+
 #[derive(Debug, Clone)]
 pub struct EndPointClient {
     endpoint_id: EndPointID,
@@ -45,6 +72,13 @@ pub struct EndPointClient {
     tx: Sender<Vec<u8>>,
     call_id: Arc<AtomicU16>,
     call_store: Arc<moka::sync::Cache<u16, Sender<Vec<u8>>>>,
+    bytes_sent: Arc<AtomicU64>,
+    bytes_received: Arc<AtomicU64>,
+    peer_os: Option<String>,
+    closed_rx: watch::Receiver<()>,
+    audit: Option<AuditSession>,
+    transfer_events: TransferEvents,
+    transfer_sessions: TransferSessions,
 }
 
 impl EndPointClient {
@@ -64,6 +98,7 @@ impl EndPointClient {
             Some(video_frame_tx),
             Some(audio_frame_tx),
             visit_credentials,
+            None,
         )
         .await
     }
@@ -82,17 +117,21 @@ impl EndPointClient {
             None,
             None,
             visit_credentials,
+            None,
         )
         .await
     }
 
+    /// Creates the endpoint serving an incoming session, operations served to the
+    /// remote side are recorded to `audit` when present.
     pub async fn new_passive(
         endpoint_id: EndPointID,
         key_pair: Option<(OpeningKey<NonceValue>, SealingKey<NonceValue>)>,
         stream: EndPointStream,
         visit_credentials: Option<Vec<u8>>,
-    ) -> CoreResult<()> {
-        let _ = EndPointClient::create(
+        audit: Option<AuditSession>,
+    ) -> CoreResult<Arc<EndPointClient>> {
+        let client = EndPointClient::create(
             false,
             endpoint_id,
             key_pair,
@@ -100,9 +139,18 @@ impl EndPointClient {
             None,
             None,
             visit_credentials,
+            audit,
         )
         .await?;
-        Ok(())
+
+        PASSIVE_SESSION_COUNT.fetch_add(1, Ordering::SeqCst);
+        let closing_client = client.clone();
+        tokio::spawn(async move {
+            closing_client.wait_closed().await;
+            PASSIVE_SESSION_COUNT.fetch_sub(1, Ordering::SeqCst);
+        });
+
+        Ok(client)
     }
 
     #[allow(clippy::too_many_arguments)]
@@ -114,6 +162,7 @@ impl EndPointClient {
         video_frame_tx: Option<Sender<EndPointVideoFrame>>,
         audio_frame_tx: Option<Sender<EndPointAudioFrame>>,
         visit_credentials: Option<Vec<u8>>,
+        audit: Option<AuditSession>,
     ) -> CoreResult<Arc<EndPointClient>> {
         let (opening_key, sealing_key) = match key_pair {
             Some((opening_key, sealing_key)) => (Some(opening_key), Some(sealing_key)),
@@ -122,12 +171,7 @@ impl EndPointClient {
 
         let (tx, mut rx) = match stream {
             EndPointStream::ActiveTCP(addr) => {
-                let stream = tokio::time::timeout(
-                    Duration::from_secs(10),
-                    tokio::net::TcpStream::connect(addr),
-                )
-                .await
-                .map_err(|_| CoreError::Timeout)??;
+                let stream = connect_tcp(addr).await?;
 
                 serve_tcp(
                     stream,
@@ -139,7 +183,7 @@ impl EndPointClient {
                 .await?
             }
             EndPointStream::ActiveUDP(_) => panic!("not support yet"),
-            EndPointStream::PassiveTCP(stream) => {
+            EndPointStream::PassiveTCP(stream) | EndPointStream::ConnectedTCP(stream) => {
                 serve_tcp(
                     stream,
                     endpoint_id,
@@ -162,12 +206,22 @@ impl EndPointClient {
         };
 
         // active endpoint should start negotiate with passive endpoint
-        let primary_monitor = if active && video_frame_tx.is_some() && audio_frame_tx.is_some() {
-            let params = serve_active_negotiate(&tx, &mut rx).await?;
-            Some(Arc::new(params.primary_monitor))
-        } else {
-            None
-        };
+        let (primary_monitor, peer_os) =
+            if active && video_frame_tx.is_some() && audio_frame_tx.is_some() {
+                let params = serve_active_negotiate(&tx, &mut rx).await?;
+                let peer_os = format!("{} {}", params.os_type, params.os_version)
+                    .trim()
+                    .to_string();
+
+                (
+                    Some(Arc::new(params.primary_monitor)),
+                    Some(peer_os).filter(|peer_os| !peer_os.is_empty()),
+                )
+            } else {
+                (None, None)
+            };
+
+        let (closed_tx, closed_rx) = watch::channel(());
 
         let call_store = moka::sync::CacheBuilder::new(32)
             .time_to_live(Duration::from_secs(60))
@@ -179,9 +233,22 @@ impl EndPointClient {
             tx,
             call_id: Arc::new(AtomicU16::new(0)),
             call_store: Arc::new(call_store),
+            bytes_sent: Arc::new(AtomicU64::new(0)),
+            bytes_received: Arc::new(AtomicU64::new(0)),
+            peer_os,
+            closed_rx,
+            audit,
+            transfer_events: TransferEvents::new(),
+            transfer_sessions: TransferSessions::new(),
         });
 
-        handle_message(client.clone(), rx, video_frame_tx, audio_frame_tx);
+        handle_message(
+            client.clone(),
+            rx,
+            video_frame_tx,
+            audio_frame_tx,
+            closed_tx,
+        );
 
         Ok(client)
     }
@@ -195,11 +262,73 @@ impl EndPointClient {
     pub async fn set_monitor(&self, monitor: Monitor) {
         (*self.monitor.write().await) = Some(Arc::new(monitor))
     }
+
+    /// Returns bytes `(sent, received)` through this endpoint so far.
+    pub fn traffic(&self) -> (u64, u64) {
+        (
+            self.bytes_sent.load(Ordering::Relaxed),
+            self.bytes_received.load(Ordering::Relaxed),
+        )
+    }
+
+    /// Operating system reported by the passive endpoint of a desktop session.
+    pub fn peer_os(&self) -> Option<&str> {
+        self.peer_os.as_deref()
+    }
+
+    /// Events of the file transfers over this endpoint.
+    pub fn transfer_events(&self) -> &TransferEvents {
+        &self.transfer_events
+    }
+
+    /// Sessions of the file transfers over this endpoint.
+    pub fn transfer_sessions(&self) -> &TransferSessions {
+        &self.transfer_sessions
+    }
+
+    fn audit_file_operation<T>(
+        &self,
+        operation: FileOperation,
+        path: &Path,
+        size: Option<u64>,
+        result: &CoreResult<T>,
+    ) {
+        if let Some(ref audit) = self.audit {
+            let error = result.as_ref().err().map(|err| err.to_string());
+            audit.record_file_operation(operation, path, None, size, error);
+        }
+    }
+
+    /// Records a file operation on `path` with the `destination` of a rename or a copy,
+    /// it failed when its reply carries errors.
+    fn audit_file_operation_reply(
+        &self,
+        operation: FileOperation,
+        path: &Path,
+        destination: Option<&Path>,
+        result: &CoreResult<EndPointFileOperationReply>,
+    ) {
+        if let Some(ref audit) = self.audit {
+            let error = match result {
+                Ok(reply) => reply.errors.first().map(|err| err.to_string()),
+                Err(err) => Some(err.to_string()),
+            };
+
+            audit.record_file_operation(operation, path, destination, None, error);
+        }
+    }
+
+    /// Waits until the connection is closed and the message loop exits.
+    pub async fn wait_closed(&self) {
+        let mut closed_rx = self.closed_rx.clone();
+        while closed_rx.changed().await.is_ok() {}
+    }
 }
 
 impl EndPointClient {
     pub fn try_send(&self, message: &EndPointMessage) -> CoreResult<()> {
         let buffer = bincode_serialize(message)?;
+        self.count_sent(&buffer);
         self.tx
             .try_send(buffer)
             .map_err(|_| CoreError::OutgoingMessageChannelDisconnect)
@@ -207,6 +336,7 @@ impl EndPointClient {
 
     pub fn blocking_send(&self, message: &EndPointMessage) -> CoreResult<()> {
         let buffer = bincode_serialize(message)?;
+        self.count_sent(&buffer);
         self.tx
             .blocking_send(buffer)
             .map_err(|_| CoreError::OutgoingMessageChannelDisconnect)
@@ -214,19 +344,23 @@ impl EndPointClient {
 
     pub async fn send(&self, message: &EndPointMessage) -> CoreResult<()> {
         let buffer = bincode_serialize(message)?;
+        self.count_sent(&buffer);
         self.tx
             .send(buffer)
             .await
             .map_err(|_| CoreError::OutgoingMessageChannelDisconnect)
     }
 
+    fn count_sent(&self, buffer: &[u8]) {
+        self.bytes_sent
+            .fetch_add(buffer.len() as u64, Ordering::Relaxed);
+    }
+
     pub async fn call<TReply>(&self, message: EndPointCallRequest) -> CoreResult<TReply>
     where
         TReply: DeserializeOwned,
     {
-        let call_id = self
-            .call_id
-            .fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+        let call_id = self.call_id.fetch_add(1, Ordering::SeqCst);
 
         let (tx, mut rx) = tokio::sync::mpsc::channel(1);
 
@@ -255,11 +389,18 @@ async fn serve_active_negotiate(
     tx: &Sender<Vec<u8>>,
     rx: &mut tokio::sync::mpsc::Receiver<Bytes>,
 ) -> CoreResult<EndPointNegotiateVisitDesktopParams> {
-    let negotiate_request_buffer = bincode_serialize(
-        &EndPointMessage::NegotiateDesktopParamsRequest(EndPointNegotiateDesktopParamsRequest {
-            video_codecs: vec![VideoCodec::H264],
-        }),
-    )?;
+    let settings = current_settings();
+
+    // the preferred codec is offered first, H264 is always supported as fallback
+    let mut video_codecs = vec![settings.preferred_codec.clone()];
+    if settings.preferred_codec != VideoCodec::H264 {
+        video_codecs.push(VideoCodec::H264);
+    }
+
+    let negotiate_request_buffer =
+        bincode_serialize(&EndPointMessage::NegotiateDesktopParamsRequest(
+            EndPointNegotiateDesktopParamsRequest { video_codecs },
+        ))?;
 
     tx.send(negotiate_request_buffer)
         .await
@@ -292,7 +433,7 @@ async fn serve_active_negotiate(
 
     let negotiate_request_buffer = bincode_serialize(&EndPointMessage::NegotiateFinishedRequest(
         EndPointNegotiateFinishedRequest {
-            expected_frame_rate: 60,
+            expected_frame_rate: settings.frame_rate,
         },
     ))?;
 
@@ -308,8 +449,19 @@ fn handle_message(
     mut rx: tokio::sync::mpsc::Receiver<Bytes>,
     video_frame_tx: Option<Sender<EndPointVideoFrame>>,
     audio_frame_tx: Option<Sender<EndPointAudioFrame>>,
+    closed_tx: watch::Sender<()>,
 ) {
     tokio::spawn(async move {
+        // dropped when the loop exits, which wakes up EndPointClient::wait_closed
+        let _closed_tx = closed_tx;
+
+        let audit = client.audit.clone();
+        defer! {
+            if let Some(ref audit) = audit {
+                audit.finish();
+            }
+        }
+
         loop {
             let buffer = match rx.recv().await {
                 Some(buffer) => buffer,
@@ -319,6 +471,10 @@ fn handle_message(
                 }
             };
 
+            client
+                .bytes_received
+                .fetch_add(buffer.len() as u64, Ordering::Relaxed);
+
             let message = match bincode_deserialize(&buffer) {
                 Ok(message) => message,
                 Err(err) => {
@@ -369,13 +525,108 @@ fn handle_message(
                     tokio::spawn(async move {
                         let reply = match message {
                             EndPointCallRequest::VisitDirectoryRequest(req) => {
-                                call!(handle_visit_directory_request(req).await)
+                                let path = req.path.clone().unwrap_or_default();
+                                let result = handle_visit_directory_request(req).await;
+                                client.audit_file_operation(
+                                    FileOperation::VisitDirectory,
+                                    &path,
+                                    None,
+                                    &result,
+                                );
+                                call!(result)
                             }
                             EndPointCallRequest::SendFileRequest(req) => {
-                                call!(handle_send_file_request(req).await)
+                                let path = req.path.join(&req.filename);
+                                let size = req.size;
+                                let result = handle_send_file_request(client.clone(), req).await;
+                                client.audit_file_operation(
+                                    FileOperation::SendFile,
+                                    &path,
+                                    Some(size),
+                                    &result,
+                                );
+                                call!(result)
                             }
                             EndPointCallRequest::DownloadFileRequest(req) => {
-                                call!(handle_download_file_request(client.clone(), req).await)
+                                let path = req.path.clone();
+                                let result =
+                                    handle_download_file_request(client.clone(), req).await;
+                                let size = result.as_ref().ok().map(|reply| reply.size);
+                                client.audit_file_operation(
+                                    FileOperation::DownloadFile,
+                                    &path,
+                                    size,
+                                    &result,
+                                );
+                                call!(result)
+                            }
+                            EndPointCallRequest::SendDirectoryRequest(req) => {
+                                let path = req.path.clone();
+                                let result = handle_send_directory_request(req).await;
+                                client.audit_file_operation(
+                                    FileOperation::SendDirectory,
+                                    &path,
+                                    None,
+                                    &result,
+                                );
+                                call!(result)
+                            }
+                            EndPointCallRequest::DownloadDirectoryRequest(req) => {
+                                let path = req.path.clone();
+                                let result = handle_download_directory_request(req).await;
+                                let size =
+                                    result.as_ref().ok().map(|reply| reply.tree.total_size());
+                                client.audit_file_operation(
+                                    FileOperation::DownloadDirectory,
+                                    &path,
+                                    size,
+                                    &result,
+                                );
+                                call!(result)
+                            }
+                            EndPointCallRequest::CreateDirectoryRequest(req) => {
+                                let path = req.path.clone();
+                                let result = handle_create_directory_request(req).await;
+                                client.audit_file_operation_reply(
+                                    FileOperation::CreateDirectory,
+                                    &path,
+                                    None,
+                                    &result,
+                                );
+                                call!(result)
+                            }
+                            EndPointCallRequest::RenameRequest(req) => {
+                                let (from, to) = (req.from.clone(), req.to.clone());
+                                let result = handle_rename_request(req).await;
+                                client.audit_file_operation_reply(
+                                    FileOperation::Rename,
+                                    &from,
+                                    Some(&to),
+                                    &result,
+                                );
+                                call!(result)
+                            }
+                            EndPointCallRequest::CopyRequest(req) => {
+                                let (from, to) = (req.from.clone(), req.to.clone());
+                                let result = handle_copy_request(req).await;
+                                client.audit_file_operation_reply(
+                                    FileOperation::Copy,
+                                    &from,
+                                    Some(&to),
+                                    &result,
+                                );
+                                call!(result)
+                            }
+                            EndPointCallRequest::DeleteRequest(req) => {
+                                let path = req.path.clone();
+                                let result = handle_delete_request(req).await;
+                                client.audit_file_operation_reply(
+                                    FileOperation::Delete,
+                                    &path,
+                                    None,
+                                    &result,
+                                );
+                                call!(result)
                             }
                         };
 
@@ -406,7 +657,13 @@ fn handle_message(
                     append_file_block(client.clone(), block).await
                 }
                 EndPointMessage::FileTransferError(message) => {
-                    delete_file_append_session(&message.id).await
+                    handle_file_transfer_error(&client, message).await
+                }
+                EndPointMessage::FileTransferControl(message) => {
+                    handle_file_transfer_control(&client, message).await
+                }
+                EndPointMessage::FileTransferComplete(message) => {
+                    handle_file_transfer_complete(&client, message)
                 }
             }
         }
@@ -0,0 +1,16 @@
+use crate::{
+    api::endpoint::message::{EndPointCopyRequest, EndPointFileOperationReply},
+    component::fs::operation::copy,
+    core_error,
+    error::CoreResult,
+};
+
+pub async fn handle_copy_request(
+    req: EndPointCopyRequest,
+) -> CoreResult<EndPointFileOperationReply> {
+    let errors = tokio::task::spawn_blocking(move || copy(&req.from, &req.to))
+        .await
+        .map_err(|err| core_error!("copy failed ({})", err))?;
+
+    Ok(EndPointFileOperationReply { errors })
+}
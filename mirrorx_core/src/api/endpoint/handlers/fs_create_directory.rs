@@ -0,0 +1,18 @@
+use crate::{
+    api::endpoint::message::{EndPointCreateDirectoryRequest, EndPointFileOperationReply},
+    component::fs::operation::create_directory,
+    core_error,
+    error::CoreResult,
+};
+
+pub async fn handle_create_directory_request(
+    req: EndPointCreateDirectoryRequest,
+) -> CoreResult<EndPointFileOperationReply> {
+    let errors = tokio::task::spawn_blocking(move || create_directory(&req.path).err())
+        .await
+        .map_err(|err| core_error!("create directory failed ({})", err))?
+        .into_iter()
+        .collect();
+
+    Ok(EndPointFileOperationReply { errors })
+}
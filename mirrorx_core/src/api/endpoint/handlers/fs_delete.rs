@@ -0,0 +1,16 @@
+use crate::{
+    api::endpoint::message::{EndPointDeleteRequest, EndPointFileOperationReply},
+    component::fs::operation::delete,
+    core_error,
+    error::CoreResult,
+};
+
+pub async fn handle_delete_request(
+    req: EndPointDeleteRequest,
+) -> CoreResult<EndPointFileOperationReply> {
+    let errors = tokio::task::spawn_blocking(move || delete(&req.path, req.recursive))
+        .await
+        .map_err(|err| core_error!("delete failed ({})", err))?;
+
+    Ok(EndPointFileOperationReply { errors })
+}
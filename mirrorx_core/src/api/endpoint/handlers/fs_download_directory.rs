@@ -0,0 +1,16 @@
+use crate::{
+    api::endpoint::message::{EndPointDownloadDirectoryReply, EndPointDownloadDirectoryRequest},
+    component::fs::tree::walk_tree,
+    core_error,
+    error::CoreResult,
+};
+
+pub async fn handle_download_directory_request(
+    req: EndPointDownloadDirectoryRequest,
+) -> CoreResult<EndPointDownloadDirectoryReply> {
+    let tree = tokio::task::spawn_blocking(move || walk_tree(&req.path, &req.options))
+        .await
+        .map_err(|err| core_error!("walk directory failed ({})", err))??;
+
+    Ok(EndPointDownloadDirectoryReply { tree })
+}
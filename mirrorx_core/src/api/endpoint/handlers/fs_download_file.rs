@@ -3,10 +3,10 @@ use crate::{
         client::EndPointClient,
         message::{
             EndPointDownloadFileReply, EndPointDownloadFileRequest, EndPointFileTransferError,
-            EndPointMessage,
+            EndPointMessage, FileTransferErrorReason,
         },
     },
-    component::fs::transfer::send_file_to_remote,
+    component::fs::transfer::{confirm_resume_offset, send_file_to_remote},
     core_error,
     error::CoreResult,
 };
@@ -23,18 +23,22 @@ pub async fn handle_download_file_request(
     let id = req.id.clone();
     let meta = req.path.metadata()?;
     let size = meta.len();
+    let offset = confirm_resume_offset(&req.path, req.offset, req.checksums).await?;
 
     tokio::spawn(async move {
         tokio::time::sleep(Duration::from_secs(1)).await;
-        if let Err(err) = send_file_to_remote(id.clone(), client.clone(), &req.path).await {
+        if let Err(err) = send_file_to_remote(id.clone(), client.clone(), &req.path, offset).await {
             tracing::error!(?err, "read file block failed");
             let _ = client
                 .send(&EndPointMessage::FileTransferError(
-                    EndPointFileTransferError { id: id.clone() },
+                    EndPointFileTransferError {
+                        id: id.clone(),
+                        reason: FileTransferErrorReason::ReadFailed,
+                    },
                 ))
                 .await;
         }
     });
 
-    Ok(EndPointDownloadFileReply { size })
+    Ok(EndPointDownloadFileReply { size, offset })
 }
@@ -0,0 +1,18 @@
+use crate::{
+    api::endpoint::message::{EndPointFileOperationReply, EndPointRenameRequest},
+    component::fs::operation::rename,
+    core_error,
+    error::CoreResult,
+};
+
+pub async fn handle_rename_request(
+    req: EndPointRenameRequest,
+) -> CoreResult<EndPointFileOperationReply> {
+    let errors = tokio::task::spawn_blocking(move || rename(&req.from, &req.to).err())
+        .await
+        .map_err(|err| core_error!("rename failed ({})", err))?
+        .into_iter()
+        .collect();
+
+    Ok(EndPointFileOperationReply { errors })
+}
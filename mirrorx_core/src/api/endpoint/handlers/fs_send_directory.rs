@@ -0,0 +1,31 @@
+use crate::{
+    api::endpoint::message::{EndPointSendDirectoryReply, EndPointSendDirectoryRequest},
+    component::fs::tree::{join_tree_path, TreeEntryError},
+    error::CoreResult,
+};
+
+pub async fn handle_send_directory_request(
+    req: EndPointSendDirectoryRequest,
+) -> CoreResult<EndPointSendDirectoryReply> {
+    tokio::fs::create_dir_all(&req.path).await?;
+
+    let mut errors = Vec::new();
+    for directory in req.directories {
+        let result = match join_tree_path(&req.path, &directory) {
+            Ok(path) => tokio::fs::create_dir_all(path)
+                .await
+                .map_err(|err| err.to_string()),
+            Err(err) => Err(err.to_string()),
+        };
+
+        if let Err(message) = result {
+            tracing::warn!(?directory, ?message, "create directory failed");
+            errors.push(TreeEntryError {
+                path: directory,
+                message,
+            });
+        }
+    }
+
+    Ok(EndPointSendDirectoryReply { errors })
+}
@@ -1,20 +1,23 @@
 use crate::{
-    api::endpoint::message::{EndPointSendFileReply, EndPointSendFileRequest},
-    component::fs::transfer::create_file_append_session,
-    core_error,
+    api::endpoint::{
+        client::EndPointClient,
+        message::{EndPointSendFileReply, EndPointSendFileRequest},
+    },
+    component::fs::transfer::{create_file_append_session, prepare_receive_file},
     error::CoreResult,
 };
+use std::sync::Arc;
 
 pub async fn handle_send_file_request(
+    client: Arc<EndPointClient>,
     req: EndPointSendFileRequest,
 ) -> CoreResult<EndPointSendFileReply> {
     let path = req.path.join(req.filename);
 
-    if path.exists() {
-        return Err(core_error!("file already exists"));
-    }
+    // existing files are never overwritten, the partial file of this transfer resumes
+    let (offset, checksums) = prepare_receive_file(&req.id, &path).await?;
 
-    create_file_append_session(req.id, &path).await?;
+    create_file_append_session(req.id, client, &path, req.size).await?;
 
-    Ok(EndPointSendFileReply {})
+    Ok(EndPointSendFileReply { offset, checksums })
 }
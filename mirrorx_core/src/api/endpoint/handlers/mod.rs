@@ -1,6 +1,12 @@
 pub mod audio_frame;
 pub mod error;
+pub mod fs_copy;
+pub mod fs_create_directory;
+pub mod fs_delete;
+pub mod fs_download_directory;
 pub mod fs_download_file;
+pub mod fs_rename;
+pub mod fs_send_directory;
 pub mod fs_send_file;
 pub mod fs_visit_directory;
 pub mod input;
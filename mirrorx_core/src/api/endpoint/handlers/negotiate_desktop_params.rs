@@ -44,10 +44,12 @@ async fn negotiate_media_params(
 
     client.set_monitor(primary_monitor.clone()).await;
 
+    let os_info = os_info::get();
+
     let params = EndPointNegotiateVisitDesktopParams {
         video_codec: VideoCodec::H264,
-        os_type: String::from(""),
-        os_version: String::from(""),
+        os_type: os_info.os_type().to_string(),
+        os_version: os_info.version().to_string(),
         primary_monitor,
     };
 
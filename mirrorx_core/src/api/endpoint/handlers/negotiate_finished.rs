@@ -1,5 +1,8 @@
 use crate::{
-    api::endpoint::{client::EndPointClient, message::EndPointMessage},
+    api::{
+        config::settings::current_settings,
+        endpoint::{client::EndPointClient, message::EndPointMessage},
+    },
     component::{
         audio::{duplicator::new_record_stream_and_rx, encoder::AudioEncoder},
         desktop::{monitor::get_active_monitors, Duplicator},
@@ -20,7 +23,12 @@ pub struct NegotiateFinishedRequest {
 
 pub fn handle_negotiate_finished_request(client: Arc<EndPointClient>) {
     spawn_desktop_capture_and_encode_process(client.clone());
-    spawn_audio_capture_and_encode_process(client);
+
+    if current_settings().audio_enabled {
+        spawn_audio_capture_and_encode_process(client);
+    } else {
+        tracing::info!("audio capture is disabled by settings");
+    }
 }
 
 #[cfg(target_os = "macos")]
@@ -1,4 +1,13 @@
-use crate::component::{desktop::monitor::Monitor, fs::Directory, input::key::MouseKey};
+use crate::component::{
+    desktop::monitor::Monitor,
+    fs::{
+        operation::FileOperationError,
+        transfer::BlockChecksum,
+        tree::{DirectoryTransferOptions, Tree, TreeEntryError},
+        Directory,
+    },
+    input::key::MouseKey,
+};
 use cpal::SampleFormat;
 use serde::{Deserialize, Serialize};
 use std::path::PathBuf;
@@ -28,6 +37,8 @@ pub enum EndPointMessage {
     InputCommand(EndPointInput),
     FileTransferBlock(EndPointFileTransferBlock),
     FileTransferError(EndPointFileTransferError),
+    FileTransferControl(EndPointFileTransferControl),
+    FileTransferComplete(EndPointFileTransferComplete),
 }
 
 #[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
@@ -35,6 +46,12 @@ pub enum EndPointCallRequest {
     VisitDirectoryRequest(EndPointVisitDirectoryRequest),
     SendFileRequest(EndPointSendFileRequest),
     DownloadFileRequest(EndPointDownloadFileRequest),
+    SendDirectoryRequest(EndPointSendDirectoryRequest),
+    DownloadDirectoryRequest(EndPointDownloadDirectoryRequest),
+    CreateDirectoryRequest(EndPointCreateDirectoryRequest),
+    RenameRequest(EndPointRenameRequest),
+    CopyRequest(EndPointCopyRequest),
+    DeleteRequest(EndPointDeleteRequest),
 }
 
 #[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
@@ -173,28 +190,146 @@ pub struct EndPointSendFileRequest {
     pub size: u64,
 }
 
+/// The receiver reports the partial file it holds of the transfer, the sender resumes
+/// at the end of the prefix matching `checksums`.
 #[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
-pub struct EndPointSendFileReply {}
+pub struct EndPointSendFileReply {
+    pub offset: u64,
+    pub checksums: Vec<BlockChecksum>,
+}
 
+/// `offset` and `checksums` describe the partial file the receiver holds, like in
+/// [`EndPointSendFileReply`].
 #[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
 pub struct EndPointDownloadFileRequest {
     pub id: String,
     pub path: PathBuf,
+    pub offset: u64,
+    pub checksums: Vec<BlockChecksum>,
 }
 
 #[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
 pub struct EndPointDownloadFileReply {
     pub size: u64,
+    /// Offset the sender resumes at.
+    pub offset: u64,
+}
+
+/// Creates the directory `path` and its `directories` on the receiver before the
+/// files of a directory transfer are sent one by one.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
+pub struct EndPointSendDirectoryRequest {
+    pub path: PathBuf,
+    /// Paths relative to `path`, see [`crate::component::fs::tree::TreeEntry`].
+    pub directories: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
+pub struct EndPointSendDirectoryReply {
+    /// Directories the receiver failed to create.
+    pub errors: Vec<TreeEntryError>,
+}
+
+/// Walks the directory `path` on the sender, its files are then downloaded one by one.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
+pub struct EndPointDownloadDirectoryRequest {
+    pub path: PathBuf,
+    pub options: DirectoryTransferOptions,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
+pub struct EndPointDownloadDirectoryReply {
+    pub tree: Tree,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
+pub struct EndPointCreateDirectoryRequest {
+    pub path: PathBuf,
+}
+
+/// Renames `from` to `to`, moving it when `to` is in another directory.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
+pub struct EndPointRenameRequest {
+    pub from: PathBuf,
+    pub to: PathBuf,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
+pub struct EndPointCopyRequest {
+    pub from: PathBuf,
+    pub to: PathBuf,
+}
+
+/// Deletes `path`, a directory with content only when `recursive` confirms it.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
+pub struct EndPointDeleteRequest {
+    pub path: PathBuf,
+    pub recursive: bool,
+}
+
+/// Reply of the file operations, the operation succeeded when `errors` is empty.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
+pub struct EndPointFileOperationReply {
+    pub errors: Vec<FileOperationError>,
 }
 
 #[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
 pub struct EndPointFileTransferBlock {
     pub id: String,
+    /// Position of `data` in the file, or the file size on the last block.
+    pub offset: u64,
     #[serde(with = "serde_bytes")]
     pub data: Option<Vec<u8>>,
+    /// SHA-256 digest of the whole file, sent with the last block.
+    pub digest: Option<BlockChecksum>,
 }
 
 #[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
 pub struct EndPointFileTransferError {
     pub id: String,
+    pub reason: FileTransferErrorReason,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum FileTransferErrorReason {
+    /// The receiver has no session for the transfer, e.g. it expired.
+    SessionNotFound,
+    ReadFailed,
+    WriteFailed,
+    /// The received file doesn't match the digest computed by the sender.
+    DigestMismatch,
+    /// A file was created at the destination while the transfer was running.
+    AlreadyExists,
+    /// The connection to the remote side was lost.
+    Disconnected,
+    /// Paused by the side which queued the transfer.
+    Paused,
+    /// Cancelled by the side which queued the transfer.
+    Cancelled,
+}
+
+/// Sent by the receiver once the file of the transfer `id` is verified and moved to
+/// its destination, the sender only reports the transfer done then.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
+pub struct EndPointFileTransferComplete {
+    pub id: String,
+}
+
+/// Sent by the side which queued the transfer `id` to control it on the other side.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
+pub struct EndPointFileTransferControl {
+    pub id: String,
+    pub action: FileTransferAction,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum FileTransferAction {
+    /// Stops the transfer and keeps the partial file.
+    Pause,
+    /// Announces the transfer is requested again.
+    Resume,
+    /// Stops the transfer and removes the partial file.
+    Cancel,
 }
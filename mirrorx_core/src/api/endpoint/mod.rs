@@ -8,7 +8,10 @@ use self::{
     handlers::{audio_frame::serve_audio_decode, video_frame::serve_video_decode},
     id::EndPointID,
 };
-use crate::{error::CoreResult, utility::nonce_value::NonceValue, DesktopDecodeFrame};
+use crate::{
+    api::config::entity::audit::AuditSession, error::CoreResult, utility::nonce_value::NonceValue,
+    DesktopDecodeFrame,
+};
 use ring::aead::{OpeningKey, SealingKey};
 use std::{net::SocketAddr, sync::Arc};
 use tokio::net::{TcpStream, UdpSocket};
@@ -17,6 +20,8 @@ pub enum EndPointStream {
     ActiveTCP(SocketAddr),
     ActiveUDP(SocketAddr),
     PassiveTCP(TcpStream),
+    /// Stream the active side already connected, e.g. to verify the peer identity first.
+    ConnectedTCP(TcpStream),
     PassiveUDP {
         remote_addr: SocketAddr,
         socket: UdpSocket,
@@ -69,7 +74,8 @@ pub async fn create_passive_endpoint_client(
     key_pair: Option<(OpeningKey<NonceValue>, SealingKey<NonceValue>)>,
     stream: EndPointStream,
     visit_credentials: Option<Vec<u8>>,
+    audit: Option<AuditSession>,
 ) -> CoreResult<()> {
-    EndPointClient::new_passive(endpoint_id, key_pair, stream, visit_credentials).await?;
+    EndPointClient::new_passive(endpoint_id, key_pair, stream, visit_credentials, audit).await?;
     Ok(())
 }
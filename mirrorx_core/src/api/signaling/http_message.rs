@@ -53,3 +53,13 @@ pub struct VisitResponse {
     pub visit_credentials: String,
     pub result: Result<String, VisitFailureReason>,
 }
+
+#[derive(Debug, Serialize)]
+pub struct PresenceRequest {
+    pub device_ids: Vec<i64>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PresenceResponse {
+    pub online_device_ids: Vec<i64>,
+}
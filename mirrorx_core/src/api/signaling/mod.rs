@@ -1,26 +1,34 @@
 pub mod http_message;
 pub mod subscribe_message;
+pub mod tls;
 
 use self::{
     http_message::{
-        IdentityResponse, RegisterRequest, RegisterResponse, Response, VisitRequest, VisitResponse,
+        IdentityResponse, PresenceRequest, PresenceResponse, RegisterRequest, RegisterResponse,
+        Response, VisitRequest, VisitResponse,
     },
     subscribe_message::{
         ActiveEndpointKeyExchangeSecret, ClientMessage, PassiveEndpointKeyExchangeSecret,
         ServerMessage, Subscription, VisitFailureReason,
     },
+    tls::{create_client_config, PinnedServerVerifier, TlsPin, TlsPinKind},
 };
 use super::{
-    config::LocalStorage,
+    config::{
+        entity::audit::{AuditCredential, AuditSessionStart},
+        LocalStorage,
+    },
     endpoint::{create_passive_endpoint_client, id::EndPointID},
 };
 use crate::{
     core_error,
-    error::CoreResult,
+    error::{CoreError, CoreResult},
     utility::{
         bincode::{bincode_deserialize, bincode_serialize},
         nonce_value::NonceValue,
+        proxy::{connect_tcp, resolve_proxy_for_url},
         rand::generate_random_ping_value,
+        version::{client_version, compare_version},
     },
 };
 use bytes::Bytes;
@@ -33,34 +41,99 @@ use rand::RngCore;
 use reqwest::IntoUrl;
 use ring::aead::{BoundKey, OpeningKey, SealingKey, UnboundKey};
 use rsa::{rand_core::OsRng, BigUint, PublicKey, PublicKeyParts};
+use rustls::{ClientConfig, ServerName};
 use sha2::Sha256;
-use std::{net::SocketAddr, time::Duration};
-use tokio::net::TcpStream;
+use std::{cmp::Ordering, collections::HashMap, net::SocketAddr, sync::Arc, time::Duration};
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio_rustls::TlsConnector;
 use tokio_util::codec::{Framed, LengthDelimitedCodec};
 use url::Url;
 
+/// Re-register the device this long before the registration expires.
+const REGISTRATION_RENEW_AHEAD: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Wait this long before retrying a failed registration renewal.
+const REGISTRATION_RETRY_INTERVAL: Duration = Duration::from_secs(5 * 60);
+
+/// How long a presence answer is reused before querying the server again.
+const PRESENCE_CACHE_TTL: Duration = Duration::from_secs(30);
+
+/// Max device count of a single presence query.
+const PRESENCE_BATCH_SIZE: usize = 128;
+
 pub struct SignalingClient {
     url: Url,
     http_client: reqwest::Client,
+    tls: Option<(Arc<PinnedServerVerifier>, Arc<ClientConfig>)>,
     subscribe_tx: Option<tokio::sync::mpsc::Sender<Bytes>>,
+    renewal_exit_tx: Option<tokio::sync::oneshot::Sender<()>>,
+    presence_cache: moka::sync::Cache<i64, bool>,
 }
 
 impl SignalingClient {
-    pub fn new<U: IntoUrl>(domain: U) -> CoreResult<Self> {
+    /// Creates a client for the domain, `https` domains use TLS for both the http api and
+    /// the subscribe connection. Without `tls_pin` the server certificate is verified with
+    /// web pki roots and its public key pin is captured, see [`SignalingClient::server_tls_pin`].
+    pub fn new<U: IntoUrl>(domain: U, tls_pin: Option<TlsPin>) -> CoreResult<Self> {
         let url = domain.into_url()?;
 
-        let http_client = reqwest::Client::builder()
+        // a pin over plain http would be silently ignored and give a false sense of trust
+        if tls_pin.is_some() && url.scheme() != "https" {
+            return Err(core_error!("tls pin requires an https domain"));
+        }
+
+        let mut http_client_builder = reqwest::Client::builder()
             .connect_timeout(Duration::from_secs(10))
-            .timeout(Duration::from_secs(10))
-            .build()?;
+            .timeout(Duration::from_secs(10));
+
+        http_client_builder = match resolve_proxy_for_url(&url) {
+            Some(proxy) => http_client_builder.proxy(proxy.to_reqwest_proxy()?),
+            None => http_client_builder.no_proxy(),
+        };
+
+        let tls = if url.scheme() == "https" {
+            let verifier = Arc::new(PinnedServerVerifier::new(tls_pin, TlsPinKind::PublicKey));
+            let config = create_client_config(verifier.clone());
+            http_client_builder = http_client_builder.use_preconfigured_tls(config.clone());
+            Some((verifier, Arc::new(config)))
+        } else {
+            None
+        };
+
+        let http_client = http_client_builder.build()?;
 
         Ok(Self {
             url,
             http_client,
+            tls,
             subscribe_tx: None,
+            renewal_exit_tx: None,
+            presence_cache: moka::sync::CacheBuilder::new(1024)
+                .time_to_live(PRESENCE_CACHE_TTL)
+                .build(),
         })
     }
 
+    /// The pin observed from the last TLS handshake with the server.
+    pub fn server_tls_pin(&self) -> Option<TlsPin> {
+        self.tls
+            .as_ref()
+            .and_then(|(verifier, _)| verifier.observed_pin())
+    }
+
+    fn map_tls_error<E>(&self, err: E) -> CoreError
+    where
+        E: Into<CoreError>,
+    {
+        if let Some((ref verifier, _)) = self.tls {
+            if let Err(mismatch_err) = verifier.check_mismatch() {
+                return mismatch_err;
+            }
+        }
+
+        err.into()
+    }
+
     #[tracing::instrument(skip(self))]
     pub async fn identity(&self) -> CoreResult<Response<IdentityResponse>> {
         let url = self.url.join("/api/identity")?;
@@ -68,33 +141,107 @@ impl SignalingClient {
             .http_client
             .get(url)
             .send()
-            .await?
+            .await
+            .map_err(|err| self.map_tls_error(err))?
             .json::<Response<IdentityResponse>>()
             .await?;
 
         Ok(resp)
     }
 
+    /// Fetches the server identity and fails with [`CoreError::ClientVersionTooLow`] when
+    /// the server requires a newer client.
+    #[tracing::instrument(skip(self))]
+    pub async fn check_client_version(&self) -> CoreResult<()> {
+        let identity = match self.identity().await? {
+            Response::Message(identity) => identity,
+            Response::Error(err) => return Err(core_error!("http error: {:?}", err)),
+        };
+
+        if compare_version(client_version(), &identity.min_client_version) == Ordering::Less {
+            return Err(CoreError::ClientVersionTooLow {
+                current: client_version().to_string(),
+                required: identity.min_client_version,
+            });
+        }
+
+        Ok(())
+    }
+
     #[tracing::instrument(skip(self))]
     pub async fn domain_register(
         &self,
         device_id: i64,
         device_finger_print: &str,
     ) -> CoreResult<Response<RegisterResponse>> {
-        let url = self.url.join("/api/domain/register")?;
-        let resp = self
-            .http_client
-            .post(url)
-            .json(&RegisterRequest {
-                device_id,
-                device_finger_print: device_finger_print.to_string(),
-            })
-            .send()
-            .await?
-            .json::<Response<RegisterResponse>>()
-            .await?;
+        domain_register(&self.http_client, &self.url, device_id, device_finger_print)
+            .await
+            .map_err(|err| self.map_tls_error(err))
+    }
 
-        Ok(resp)
+    /// Keeps the device registration of the domain alive by re-registering in the
+    /// background before it expires. The task exits when the client is dropped.
+    pub fn serve_registration_renewal(&mut self, domain_id: i64, storage: LocalStorage) {
+        let (exit_tx, exit_rx) = tokio::sync::oneshot::channel();
+
+        tokio::spawn(serve_registration_renewal(
+            self.http_client.clone(),
+            self.url.clone(),
+            domain_id,
+            storage,
+            exit_rx,
+        ));
+
+        self.renewal_exit_tx = Some(exit_tx);
+    }
+
+    /// Queries whether devices are online. Answers are cached for [`PRESENCE_CACHE_TTL`],
+    /// only the uncached devices are queried in batches of [`PRESENCE_BATCH_SIZE`].
+    #[tracing::instrument(skip(self))]
+    pub async fn presence(&self, device_ids: &[i64]) -> CoreResult<HashMap<i64, bool>> {
+        let mut presence = HashMap::with_capacity(device_ids.len());
+        let mut uncached_device_ids = Vec::new();
+
+        for device_id in device_ids {
+            match self.presence_cache.get(device_id) {
+                Some(online) => {
+                    presence.insert(*device_id, online);
+                }
+                None => uncached_device_ids.push(*device_id),
+            }
+        }
+
+        uncached_device_ids.sort_unstable();
+        uncached_device_ids.dedup();
+
+        let url = self.url.join("/api/device/presence")?;
+
+        for device_ids in uncached_device_ids.chunks(PRESENCE_BATCH_SIZE) {
+            let resp = self
+                .http_client
+                .post(url.clone())
+                .json(&PresenceRequest {
+                    device_ids: device_ids.to_vec(),
+                })
+                .send()
+                .await
+                .map_err(|err| self.map_tls_error(err))?
+                .json::<Response<PresenceResponse>>()
+                .await?;
+
+            let online_device_ids = match resp {
+                Response::Message(resp) => resp.online_device_ids,
+                Response::Error(err) => return Err(core_error!("http error: {:?}", err)),
+            };
+
+            for device_id in device_ids {
+                let online = online_device_ids.contains(device_id);
+                self.presence_cache.insert(*device_id, online);
+                presence.insert(*device_id, online);
+            }
+        }
+
+        Ok(presence)
     }
 
     #[allow(clippy::type_complexity)]
@@ -192,7 +339,8 @@ impl SignalingClient {
             })
             .timeout(Duration::from_secs(60))
             .send()
-            .await?
+            .await
+            .map_err(|err| self.map_tls_error(err))?
             .json::<Response<VisitResponse>>()
             .await?;
 
@@ -285,50 +433,196 @@ impl SignalingClient {
         device_finger_print: &str,
         storage: LocalStorage,
     ) -> CoreResult<()> {
+        self.check_client_version().await?;
+
         let subscription_bytes = Bytes::from(bincode_serialize(&Subscription {
             device_id,
             device_finger_print: device_finger_print.to_string(),
         })?);
 
         for addr in addrs {
-            let Ok(Ok(stream)) = tokio::time::timeout(
-                Duration::from_secs(10),
-                tokio::net::TcpStream::connect(addr),
-            )
-            .await else {
+            let Ok(stream) = connect_tcp(addr).await else {
                 continue;
             };
 
-            let mut framed_stream = Framed::new(
-                stream,
-                LengthDelimitedCodec::builder()
-                    .length_field_length(2)
-                    .little_endian()
-                    .new_codec(),
-            );
+            if let Some((_, ref config)) = self.tls {
+                let host = self
+                    .url
+                    .host_str()
+                    .ok_or_else(|| core_error!("domain url has no host"))?;
+
+                let server_name = ServerName::try_from(host)
+                    .map_err(|_| core_error!("invalid domain server name"))?;
+
+                let stream = TlsConnector::from(config.clone())
+                    .connect(server_name, stream)
+                    .await
+                    .map_err(|err| self.map_tls_error(err))?;
+
+                self.serve_subscribe(stream, subscription_bytes, storage)
+                    .await?;
+            } else {
+                self.serve_subscribe(stream, subscription_bytes, storage)
+                    .await?;
+            }
 
-            framed_stream.send(subscription_bytes.clone()).await?;
+            return Ok(());
+        }
 
-            let (sink, stream) = framed_stream.split();
-            let (tx, rx) = tokio::sync::mpsc::channel(1);
+        Err(core_error!("non addr usable"))
+    }
 
-            tokio::spawn(serve_connection(rx, sink, stream, storage.clone()));
+    async fn serve_subscribe<S>(
+        &mut self,
+        stream: S,
+        subscription_bytes: Bytes,
+        storage: LocalStorage,
+    ) -> CoreResult<()>
+    where
+        S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+    {
+        let mut framed_stream = Framed::new(
+            stream,
+            LengthDelimitedCodec::builder()
+                .length_field_length(2)
+                .little_endian()
+                .new_codec(),
+        );
 
-            self.subscribe_tx = Some(tx);
+        framed_stream.send(subscription_bytes).await?;
 
-            return Ok(());
+        let (sink, stream) = framed_stream.split();
+        let (tx, rx) = tokio::sync::mpsc::channel(1);
+
+        tokio::spawn(serve_connection(rx, sink, stream, storage));
+
+        self.subscribe_tx = Some(tx);
+
+        Ok(())
+    }
+}
+
+impl Drop for SignalingClient {
+    fn drop(&mut self) {
+        if let Some(tx) = self.renewal_exit_tx.take() {
+            let _ = tx.send(());
         }
+    }
+}
 
-        Err(core_error!("non addr usable"))
+async fn domain_register(
+    http_client: &reqwest::Client,
+    url: &Url,
+    device_id: i64,
+    device_finger_print: &str,
+) -> CoreResult<Response<RegisterResponse>> {
+    let url = url.join("/api/domain/register")?;
+    let resp = http_client
+        .post(url)
+        .json(&RegisterRequest {
+            device_id,
+            device_finger_print: device_finger_print.to_string(),
+        })
+        .send()
+        .await?
+        .json::<Response<RegisterResponse>>()
+        .await?;
+
+    Ok(resp)
+}
+
+async fn serve_registration_renewal(
+    http_client: reqwest::Client,
+    url: Url,
+    domain_id: i64,
+    storage: LocalStorage,
+    mut exit_rx: tokio::sync::oneshot::Receiver<()>,
+) {
+    loop {
+        let domain = match storage.domain().get_domain_by_id(domain_id) {
+            Ok(domain) => domain,
+            Err(err) => {
+                tracing::error!(?err, domain_id, "registration renewal read domain failed");
+                return;
+            }
+        };
+
+        // renew a day ahead, or at half of the remaining time for short registrations
+        let remaining_secs = domain.expire - chrono::Utc::now().timestamp();
+        let wait_secs = if remaining_secs > 0 {
+            (remaining_secs - REGISTRATION_RENEW_AHEAD.as_secs() as i64).max(remaining_secs / 2)
+                as u64
+        } else {
+            0
+        };
+
+        tokio::select! {
+            _ = tokio::time::sleep(Duration::from_secs(wait_secs)) => {},
+            _ = &mut exit_rx => {
+                tracing::info!(domain_id, "registration renewal loop exit");
+                return;
+            }
+        };
+
+        let result =
+            domain_register(&http_client, &url, domain.device_id, &domain.finger_print).await;
+
+        let retry = match result {
+            Ok(Response::Message(resp)) => {
+                tracing::info!(
+                    domain = domain.name,
+                    device_id = resp.device_id,
+                    expire = resp.expire,
+                    "device registration renewed"
+                );
+
+                let saved = storage.domain().set_domain_registration(
+                    domain.id,
+                    resp.device_id,
+                    resp.expire,
+                );
+
+                match saved {
+                    Ok(_) => {
+                        // a server without expiry would keep the loop spinning
+                        resp.expire <= chrono::Utc::now().timestamp()
+                    }
+                    Err(err) => {
+                        tracing::error!(?err, "save renewed device registration failed");
+                        true
+                    }
+                }
+            }
+            Ok(Response::Error(err)) => {
+                tracing::error!(?err, "renew device registration failed");
+                true
+            }
+            Err(err) => {
+                tracing::error!(?err, "renew device registration failed");
+                true
+            }
+        };
+
+        if retry {
+            tokio::select! {
+                _ = tokio::time::sleep(REGISTRATION_RETRY_INTERVAL) => {},
+                _ = &mut exit_rx => {
+                    tracing::info!(domain_id, "registration renewal loop exit");
+                    return;
+                }
+            };
+        }
     }
 }
 
-async fn serve_connection(
+async fn serve_connection<S>(
     mut rx: tokio::sync::mpsc::Receiver<Bytes>,
-    mut sink: SplitSink<Framed<TcpStream, LengthDelimitedCodec>, Bytes>,
-    mut stream: SplitStream<Framed<TcpStream, LengthDelimitedCodec>>,
+    mut sink: SplitSink<Framed<S, LengthDelimitedCodec>, Bytes>,
+    mut stream: SplitStream<Framed<S, LengthDelimitedCodec>>,
     storage: LocalStorage,
-) {
+) where
+    S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
     let mut ticker = tokio::time::interval(Duration::from_secs(60));
     let mut last_ping = None;
     let mut last_ping_value = 0;
@@ -464,18 +758,76 @@ async fn serve_visit_request(
         return Err(VisitFailureReason::InternalError);
     };
 
-    let (secret, sealing_key, opening_key) = match key_agreement(
-        &domain.password,
-        active_device_id,
-        password_salt,
-        secret,
-        secret_nonce,
-    )
-    .await
-    {
-        Ok(v) => v,
+    // the permanent password is always tried first, then temporary passwords which are
+    // still valid, one-time passwords are consumed once the key agreement succeeded
+    let temporary_passwords = match storage.temporary_password().get_valid_passwords(domain.id) {
+        Ok(passwords) => passwords,
+        Err(err) => {
+            tracing::error!(?err, "query temporary passwords failed");
+            Vec::new()
+        }
+    };
+
+    let mut credentials = vec![(None, domain.password.clone())];
+    for temporary_password in temporary_passwords {
+        let password = temporary_password.password.clone();
+        credentials.push((Some(temporary_password), password));
+    }
+
+    let mut agreement = Err(VisitFailureReason::InvalidPassword);
+    let mut used_temporary_password = None;
+
+    for (temporary_password, password) in credentials {
+        agreement = key_agreement(
+            &password,
+            active_device_id,
+            password_salt.clone(),
+            secret.clone(),
+            secret_nonce.clone(),
+        )
+        .await;
+
+        if !matches!(agreement, Err(VisitFailureReason::InvalidPassword)) {
+            used_temporary_password = temporary_password;
+            break;
+        }
+    }
+
+    let (secret, sealing_key, opening_key) = agreement?;
+
+    let credential = match used_temporary_password {
+        Some(ref temporary_password) if temporary_password.one_time => {
+            AuditCredential::OneTimePassword
+        }
+        Some(_) => AuditCredential::TemporaryPassword,
+        None => AuditCredential::Password,
+    };
+
+    if let Some(temporary_password) = used_temporary_password {
+        if temporary_password.one_time {
+            match storage.temporary_password().consume(temporary_password.id) {
+                Ok(true) => {}
+                Ok(false) => return Err(VisitFailureReason::InvalidPassword),
+                Err(err) => {
+                    tracing::error!(?err, "consume one-time password failed");
+                    return Err(VisitFailureReason::InternalError);
+                }
+            }
+        }
+    }
+
+    // the remote side may open desktop and file manager once authorized
+    let audit = match storage.audit().start_session(&AuditSessionStart {
+        device_id: Some(active_device_id),
+        remote_ip: endpoint_addr.ip().to_string(),
+        domain: domain.name.clone(),
+        credential,
+        permissions: vec![String::from("desktop"), String::from("file_manager")],
+    }) {
+        Ok(audit) => Some(audit),
         Err(err) => {
-            return Err(err);
+            tracing::error!(?err, "start audit session failed");
+            None
         }
     };
 
@@ -488,10 +840,15 @@ async fn serve_visit_request(
             Some((opening_key, sealing_key)),
             crate::api::endpoint::EndPointStream::ActiveTCP(endpoint_addr),
             Some(passive_visit_credentials),
+            audit.clone(),
         )
         .await
         {
             tracing::error!(?err, "create passive endpoint client failed");
+
+            if let Some(ref audit) = audit {
+                audit.finish();
+            }
         }
     });
 
@@ -0,0 +1,231 @@
+use crate::{core_error, error::CoreError};
+use rustls::{
+    client::{ServerCertVerified, ServerCertVerifier, WebPkiVerifier},
+    Certificate, ClientConfig, OwnedTrustAnchor, RootCertStore, ServerName,
+};
+use sha2::{Digest, Sha256};
+use std::{
+    fmt::Display,
+    str::FromStr,
+    sync::{Arc, Mutex},
+    time::SystemTime,
+};
+
+const CERTIFICATE_PIN_PREFIX: &str = "cert-sha256:";
+const PUBLIC_KEY_PIN_PREFIX: &str = "spki-sha256:";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TlsPinKind {
+    Certificate,
+    PublicKey,
+}
+
+/// SHA-256 digest of the server leaf certificate or its SubjectPublicKeyInfo.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TlsPin {
+    Certificate(Vec<u8>),
+    PublicKey(Vec<u8>),
+}
+
+impl TlsPin {
+    pub fn kind(&self) -> TlsPinKind {
+        match self {
+            TlsPin::Certificate(_) => TlsPinKind::Certificate,
+            TlsPin::PublicKey(_) => TlsPinKind::PublicKey,
+        }
+    }
+
+    pub fn compute(kind: TlsPinKind, certificate: &Certificate) -> Result<Self, CoreError> {
+        match kind {
+            TlsPinKind::Certificate => {
+                Ok(TlsPin::Certificate(Sha256::digest(&certificate.0).to_vec()))
+            }
+            TlsPinKind::PublicKey => {
+                let spki = extract_subject_public_key_info(&certificate.0)
+                    .ok_or_else(|| core_error!("parse server certificate public key failed"))?;
+                Ok(TlsPin::PublicKey(Sha256::digest(spki).to_vec()))
+            }
+        }
+    }
+}
+
+impl Display for TlsPin {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TlsPin::Certificate(digest) => {
+                write!(f, "{}{}", CERTIFICATE_PIN_PREFIX, hex::encode(digest))
+            }
+            TlsPin::PublicKey(digest) => {
+                write!(f, "{}{}", PUBLIC_KEY_PIN_PREFIX, hex::encode(digest))
+            }
+        }
+    }
+}
+
+impl FromStr for TlsPin {
+    type Err = CoreError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Some(digest) = s.strip_prefix(CERTIFICATE_PIN_PREFIX) {
+            Ok(TlsPin::Certificate(decode_digest(digest)?))
+        } else if let Some(digest) = s.strip_prefix(PUBLIC_KEY_PIN_PREFIX) {
+            Ok(TlsPin::PublicKey(decode_digest(digest)?))
+        } else {
+            Err(core_error!("unknown tls pin format"))
+        }
+    }
+}
+
+fn decode_digest(digest: &str) -> Result<Vec<u8>, CoreError> {
+    let digest = hex::decode(digest).map_err(|_| core_error!("invalid tls pin digest"))?;
+    if digest.len() != 32 {
+        return Err(core_error!("invalid tls pin digest length"));
+    }
+    Ok(digest)
+}
+
+/// Verifies the server certificate against a pin when the domain has one, otherwise
+/// falls back to the web pki roots. The observed pin is recorded either way so the
+/// caller can store it on first use (trust on first use).
+pub struct PinnedServerVerifier {
+    pin: Option<TlsPin>,
+    pin_kind: TlsPinKind,
+    webpki: WebPkiVerifier,
+    observed: Mutex<Option<TlsPin>>,
+}
+
+impl PinnedServerVerifier {
+    pub fn new(pin: Option<TlsPin>, pin_kind: TlsPinKind) -> Self {
+        let mut root_store = RootCertStore::empty();
+        root_store.add_server_trust_anchors(webpki_roots::TLS_SERVER_ROOTS.0.iter().map(|ta| {
+            OwnedTrustAnchor::from_subject_spki_name_constraints(
+                ta.subject,
+                ta.spki,
+                ta.name_constraints,
+            )
+        }));
+
+        let pin_kind = pin.as_ref().map(TlsPin::kind).unwrap_or(pin_kind);
+
+        Self {
+            pin,
+            pin_kind,
+            webpki: WebPkiVerifier::new(root_store, None),
+            observed: Mutex::new(None),
+        }
+    }
+
+    pub fn observed_pin(&self) -> Option<TlsPin> {
+        self.observed
+            .lock()
+            .ok()
+            .and_then(|observed| observed.clone())
+    }
+
+    /// Converts a failed connection into [`CoreError::ServerIdentityMismatch`] when it
+    /// was caused by a changed server identity.
+    pub fn check_mismatch(&self) -> Result<(), CoreError> {
+        let (Some(expected), Some(actual)) = (self.pin.as_ref(), self.observed_pin()) else {
+            return Ok(());
+        };
+
+        if *expected != actual {
+            return Err(CoreError::ServerIdentityMismatch {
+                expected: expected.to_string(),
+                actual: actual.to_string(),
+            });
+        }
+
+        Ok(())
+    }
+}
+
+impl ServerCertVerifier for PinnedServerVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &Certificate,
+        intermediates: &[Certificate],
+        server_name: &ServerName,
+        scts: &mut dyn Iterator<Item = &[u8]>,
+        ocsp_response: &[u8],
+        now: SystemTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        let observed = TlsPin::compute(self.pin_kind, end_entity)
+            .map_err(|err| rustls::Error::General(err.to_string()))?;
+
+        if let Ok(mut observed_guard) = self.observed.lock() {
+            *observed_guard = Some(observed.clone());
+        }
+
+        match self.pin {
+            // a pinned server may use a self-signed certificate, the pin is the trust anchor
+            Some(ref pin) if *pin == observed => Ok(ServerCertVerified::assertion()),
+            Some(_) => Err(rustls::Error::General(String::from(
+                "server identity mismatch",
+            ))),
+            None => self.webpki.verify_server_cert(
+                end_entity,
+                intermediates,
+                server_name,
+                scts,
+                ocsp_response,
+                now,
+            ),
+        }
+    }
+}
+
+pub fn create_client_config(verifier: Arc<PinnedServerVerifier>) -> ClientConfig {
+    ClientConfig::builder()
+        .with_safe_defaults()
+        .with_custom_certificate_verifier(verifier)
+        .with_no_client_auth()
+}
+
+/// Walks the DER encoded certificate down to `tbsCertificate.subjectPublicKeyInfo`.
+pub(crate) fn extract_subject_public_key_info(certificate: &[u8]) -> Option<&[u8]> {
+    let (_, certificate, _) = read_der_element(certificate, 0x30)?;
+    let (_, mut tbs_certificate, _) = read_der_element(certificate, 0x30)?;
+
+    // version is an optional explicit tagged field
+    if tbs_certificate.first() == Some(&0xA0) {
+        (_, _, tbs_certificate) = read_der_element(tbs_certificate, 0xA0)?;
+    }
+
+    // serialNumber, signature, issuer, validity, subject
+    for tag in [0x02, 0x30, 0x30, 0x30, 0x30] {
+        (_, _, tbs_certificate) = read_der_element(tbs_certificate, tag)?;
+    }
+
+    let (element, _, _) = read_der_element(tbs_certificate, 0x30)?;
+    Some(element)
+}
+
+/// Returns the whole element, its content and the remaining input.
+pub(crate) fn read_der_element(input: &[u8], expected_tag: u8) -> Option<(&[u8], &[u8], &[u8])> {
+    if *input.first()? != expected_tag {
+        return None;
+    }
+
+    let length_byte = *input.get(1)?;
+    let (length, header_length) = if length_byte < 0x80 {
+        (length_byte as usize, 2)
+    } else {
+        let length_bytes_count = (length_byte & 0x7F) as usize;
+        if length_bytes_count == 0 || length_bytes_count > 4 {
+            return None;
+        }
+
+        let mut length = 0usize;
+        for byte in input.get(2..2 + length_bytes_count)? {
+            length = (length << 8) | *byte as usize;
+        }
+
+        (length, 2 + length_bytes_count)
+    };
+
+    let end = header_length.checked_add(length)?;
+    let element = input.get(..end)?;
+    let content = input.get(header_length..end)?;
+    Some((element, content, &input[end..]))
+}
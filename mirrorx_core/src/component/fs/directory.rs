@@ -0,0 +1,230 @@
+use super::{
+    transfer::transfer_id,
+    tree::{join_tree_path, walk_tree, DirectoryTransferOptions, Tree, TreeEntryError},
+};
+use crate::{
+    api::{
+        config::entity::transfer::{
+            TransferEntry, TransferEntryState, TransferKind, TransferRecord, TransferState,
+        },
+        endpoint::{
+            client::EndPointClient,
+            message::{
+                EndPointCallRequest, EndPointDownloadDirectoryReply,
+                EndPointDownloadDirectoryRequest, EndPointSendDirectoryReply,
+                EndPointSendDirectoryRequest,
+            },
+        },
+    },
+    core_error,
+    error::CoreResult,
+};
+use serde::Serialize;
+use std::path::PathBuf;
+
+/// Aggregate progress of a directory transfer, its files are transferred one after
+/// another as file transfers.
+#[derive(Serialize, Debug, PartialEq, Eq, Clone, Default)]
+pub struct DirectoryTransferStatus {
+    pub total_files: usize,
+    pub transferred_files: usize,
+    pub total_size: u64,
+    /// Failed files count as transferred so the progress reaches `total_size`.
+    pub transferred_size: u64,
+    pub finished: bool,
+    pub errors: Vec<TreeEntryError>,
+}
+
+impl DirectoryTransferStatus {
+    pub fn new(record: &TransferRecord, entries: &[TransferEntry]) -> Self {
+        let files = entries
+            .iter()
+            .filter(|entry| entry.state != TransferEntryState::Skipped);
+
+        DirectoryTransferStatus {
+            total_files: files.clone().count(),
+            transferred_files: files
+                .filter(|entry| entry.state == TransferEntryState::Done)
+                .count(),
+            total_size: record.total_size,
+            transferred_size: record.transferred_size,
+            finished: matches!(record.state, TransferState::Done | TransferState::Failed),
+            errors: entries
+                .iter()
+                .filter_map(|entry| {
+                    entry.error.as_ref().map(|message| TreeEntryError {
+                        path: entry.path.clone(),
+                        message: message.clone(),
+                    })
+                })
+                .collect(),
+        }
+    }
+}
+
+/// Returns the roots of the directory transfer `record` on this side and on the
+/// remote side.
+pub fn directory_roots(record: &TransferRecord) -> CoreResult<(PathBuf, PathBuf)> {
+    match record.kind {
+        TransferKind::UploadDirectory => {
+            let Some(name) = record.local_path.file_name() else {
+                return Err(core_error!("local path get directory name failed"));
+            };
+
+            Ok((record.local_path.clone(), record.remote_path.join(name)))
+        }
+        TransferKind::DownloadDirectory => {
+            let Some(name) = record.remote_path.file_name() else {
+                return Err(core_error!("remote path get directory name failed"));
+            };
+
+            Ok((record.local_path.join(name), record.remote_path.clone()))
+        }
+        _ => Err(core_error!("transfer isn't a directory transfer")),
+    }
+}
+
+/// Identifies the directory transfer `record`, like [`transfer_id`] for its roots.
+pub fn directory_transfer_id(record: &TransferRecord) -> CoreResult<String> {
+    let (local_root, remote_root) = directory_roots(record)?;
+
+    Ok(match record.kind {
+        TransferKind::UploadDirectory => {
+            transfer_id(&record.remote_device_id, &local_root, &remote_root)
+        }
+        _ => transfer_id(&record.remote_device_id, &remote_root, &local_root),
+    })
+}
+
+/// Recreates the structure of the directory transfer `record` on the receiving side
+/// and returns its entries, the files to transfer and the entries left out.
+pub async fn prepare_directory_transfer(
+    record: &TransferRecord,
+    client: &EndPointClient,
+) -> CoreResult<Vec<TransferEntry>> {
+    let (local_root, remote_root) = directory_roots(record)?;
+    let options = record.options.clone().unwrap_or_default();
+
+    match record.kind {
+        TransferKind::UploadDirectory => {
+            let tree = walk_local_tree(local_root, options).await?;
+
+            let reply: EndPointSendDirectoryReply = client
+                .call(EndPointCallRequest::SendDirectoryRequest(
+                    EndPointSendDirectoryRequest {
+                        path: remote_root,
+                        directories: tree.directories().map(|entry| entry.path.clone()).collect(),
+                    },
+                ))
+                .await?;
+
+            Ok(transfer_entries(&tree, reply.errors))
+        }
+        _ => {
+            let reply: EndPointDownloadDirectoryReply = client
+                .call(EndPointCallRequest::DownloadDirectoryRequest(
+                    EndPointDownloadDirectoryRequest {
+                        path: remote_root,
+                        options,
+                    },
+                ))
+                .await?;
+
+            let tree = reply.tree;
+            let mut errors = Vec::new();
+
+            tokio::fs::create_dir_all(&local_root).await?;
+            for entry in tree.directories() {
+                let result = match join_tree_path(&local_root, &entry.path) {
+                    Ok(path) => tokio::fs::create_dir_all(path)
+                        .await
+                        .map_err(|err| err.to_string()),
+                    Err(err) => Err(err.to_string()),
+                };
+
+                if let Err(message) = result {
+                    errors.push(TreeEntryError {
+                        path: entry.path.clone(),
+                        message,
+                    });
+                }
+            }
+
+            Ok(transfer_entries(&tree, errors))
+        }
+    }
+}
+
+/// Returns the file transfer of `entry` of the directory transfer `record`.
+pub fn directory_file_record(
+    record: &TransferRecord,
+    entry: &TransferEntry,
+) -> CoreResult<TransferRecord> {
+    let (local_root, remote_root) = directory_roots(record)?;
+    let local_path = join_tree_path(&local_root, &entry.path)?;
+    let remote_path = join_tree_path(&remote_root, &entry.path)?;
+
+    let (id, kind, remote_path) = match record.kind {
+        TransferKind::UploadDirectory => {
+            let Some(parent) = remote_path.parent() else {
+                return Err(core_error!("invalid entry path ({})", entry.path));
+            };
+
+            (
+                transfer_id(&record.remote_device_id, &local_path, &remote_path),
+                TransferKind::Upload,
+                parent.to_path_buf(),
+            )
+        }
+        _ => (
+            transfer_id(&record.remote_device_id, &remote_path, &local_path),
+            TransferKind::Download,
+            remote_path,
+        ),
+    };
+
+    Ok(TransferRecord {
+        id,
+        remote_device_id: record.remote_device_id.clone(),
+        kind,
+        local_path,
+        remote_path,
+        total_size: entry.size,
+        transferred_size: 0,
+        state: TransferState::Running,
+        error: None,
+        created_at: record.created_at,
+        updated_at: record.updated_at,
+        options: None,
+    })
+}
+
+async fn walk_local_tree(root: PathBuf, options: DirectoryTransferOptions) -> CoreResult<Tree> {
+    tokio::task::spawn_blocking(move || walk_tree(&root, &options))
+        .await
+        .map_err(|err| core_error!("walk directory failed ({})", err))?
+}
+
+/// The files of `tree` to transfer, followed by the entries left out of it.
+fn transfer_entries(tree: &Tree, errors: Vec<TreeEntryError>) -> Vec<TransferEntry> {
+    let files = tree.files().map(|entry| TransferEntry {
+        path: entry.path.clone(),
+        size: entry.size,
+        state: TransferEntryState::Pending,
+        error: None,
+    });
+
+    let skipped = tree
+        .errors
+        .iter()
+        .cloned()
+        .chain(errors)
+        .map(|error| TransferEntry {
+            path: error.path,
+            size: 0,
+            state: TransferEntryState::Skipped,
+            error: Some(error.message),
+        });
+
+    files.chain(skipped).collect()
+}
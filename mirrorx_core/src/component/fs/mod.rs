@@ -4,7 +4,10 @@ mod macos;
 #[cfg(target_os = "windows")]
 mod windows;
 
+pub mod directory;
+pub mod operation;
 pub mod transfer;
+pub mod tree;
 
 use crate::error::CoreResult;
 use rayon::prelude::*;
@@ -0,0 +1,378 @@
+use serde::{Deserialize, Serialize};
+use std::{
+    fmt::Display,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+};
+use walkdir::WalkDir;
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum FileOperationErrorKind {
+    NotFound,
+    AlreadyExists,
+    PermissionDenied,
+    /// The directory has content and its deletion wasn't confirmed as recursive.
+    NotEmpty,
+    /// The path is relative, a root or doesn't end with a name, or the destination
+    /// is inside the source.
+    InvalidPath,
+    Other,
+}
+
+/// Why a file operation failed on `path`.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
+pub struct FileOperationError {
+    pub path: PathBuf,
+    pub kind: FileOperationErrorKind,
+    pub message: String,
+}
+
+impl FileOperationError {
+    fn new(path: &Path, kind: FileOperationErrorKind, message: &str) -> Self {
+        Self {
+            path: path.to_path_buf(),
+            kind,
+            message: message.to_string(),
+        }
+    }
+
+    fn io(path: &Path, err: std::io::Error) -> Self {
+        let kind = match err.kind() {
+            ErrorKind::NotFound => FileOperationErrorKind::NotFound,
+            ErrorKind::AlreadyExists => FileOperationErrorKind::AlreadyExists,
+            ErrorKind::PermissionDenied => FileOperationErrorKind::PermissionDenied,
+            _ => FileOperationErrorKind::Other,
+        };
+
+        Self::new(path, kind, &err.to_string())
+    }
+
+    fn walk(root: &Path, err: walkdir::Error) -> Self {
+        let path = err.path().unwrap_or(root).to_path_buf();
+        match err.into_io_error() {
+            Some(err) => Self::io(&path, err),
+            None => Self::new(
+                &path,
+                FileOperationErrorKind::Other,
+                "file system loop found",
+            ),
+        }
+    }
+}
+
+impl Display for FileOperationError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} ({})", self.message, self.path.display())
+    }
+}
+
+impl std::error::Error for FileOperationError {}
+
+/// Creates the directory `path`, its parent must exist.
+pub fn create_directory(path: &Path) -> Result<(), FileOperationError> {
+    check_path(path)?;
+    std::fs::create_dir(path).map_err(|err| FileOperationError::io(path, err))
+}
+
+/// Renames or moves `from` to `to`, an existing `to` is never overwritten. A move
+/// to another volume falls back to [`move_across_devices`].
+pub fn rename(from: &Path, to: &Path) -> Result<(), FileOperationError> {
+    check_path(from)?;
+    check_path(to)?;
+
+    if to.symlink_metadata().is_ok() {
+        return Err(FileOperationError::new(
+            to,
+            FileOperationErrorKind::AlreadyExists,
+            "destination already exists",
+        ));
+    }
+
+    match std::fs::rename(from, to) {
+        Ok(_) => Ok(()),
+        Err(err) if is_cross_device(&err) => move_across_devices(from, to),
+        Err(err) => Err(FileOperationError::io(from, err)),
+    }
+}
+
+/// Moves `from` to `to` as a [`copy`] followed by a [`delete`] of `from`. A failed
+/// copy is removed again and leaves `from` untouched, once the copy succeeded the
+/// first entry of `from` which failed to be deleted is reported.
+pub fn move_across_devices(from: &Path, to: &Path) -> Result<(), FileOperationError> {
+    let (created, errors) = copy_tree(from, to);
+    if let Some(err) = errors.into_iter().next() {
+        // a destination this copy didn't create belongs to someone else
+        if created {
+            delete(to, true);
+        }
+
+        return Err(err);
+    }
+
+    match delete(from, true).into_iter().next() {
+        Some(err) => Err(err),
+        None => Ok(()),
+    }
+}
+
+/// Copies the file or the directory `from` to `to` and returns the entries which
+/// failed, an existing `to` is never overwritten. The content of a directory which
+/// failed to be created is left out. Like [`delete`], symbolic links are never
+/// followed, they are copied as links to the same target.
+pub fn copy(from: &Path, to: &Path) -> Vec<FileOperationError> {
+    copy_tree(from, to).1
+}
+
+/// Copies like [`copy`] and also returns whether this call created `to`. A `to` which
+/// appears after it was checked fails the copy instead of being copied into.
+fn copy_tree(from: &Path, to: &Path) -> (bool, Vec<FileOperationError>) {
+    if let Err(err) = check_path(from).and_then(|_| check_path(to)) {
+        return (false, vec![err]);
+    }
+
+    let is_inside = match (resolve_path(from), resolve_path(to)) {
+        (Ok(from), Ok(to)) => to.starts_with(from),
+        (Err(err), _) | (_, Err(err)) => return (false, vec![err]),
+    };
+
+    if is_inside {
+        return (
+            false,
+            vec![FileOperationError::new(
+                to,
+                FileOperationErrorKind::InvalidPath,
+                "destination is inside the source",
+            )],
+        );
+    }
+
+    if to.symlink_metadata().is_ok() {
+        return (
+            false,
+            vec![FileOperationError::new(
+                to,
+                FileOperationErrorKind::AlreadyExists,
+                "destination already exists",
+            )],
+        );
+    }
+
+    // a root link would still be walked into, as walkdir always follows the root
+    if from
+        .symlink_metadata()
+        .map(|meta| meta.file_type().is_symlink())
+        .unwrap_or(false)
+    {
+        return match copy_symlink(from, to) {
+            Ok(_) => (true, Vec::new()),
+            Err(err) => (false, vec![root_error(from, to, err)]),
+        };
+    }
+
+    let mut created = false;
+    let mut errors = Vec::new();
+    let mut entries = WalkDir::new(from).into_iter();
+
+    while let Some(entry) = entries.next() {
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(err) => {
+                errors.push(FileOperationError::walk(from, err));
+                continue;
+            }
+        };
+
+        let Ok(relative) = entry.path().strip_prefix(from) else {
+            continue;
+        };
+
+        // joining the empty path of the root would append a separator
+        let is_root = relative.as_os_str().is_empty();
+        let target = if is_root {
+            to.to_path_buf()
+        } else {
+            to.join(relative)
+        };
+        let result = if entry.file_type().is_symlink() {
+            copy_symlink(entry.path(), &target)
+        } else if entry.file_type().is_dir() {
+            std::fs::create_dir(&target)
+        } else {
+            copy_file(entry.path(), &target)
+        };
+
+        match result {
+            Ok(_) if is_root => created = true,
+            Ok(_) => {}
+            Err(err) if is_root => return (false, vec![root_error(from, to, err)]),
+            Err(err) => {
+                if entry.file_type().is_dir() {
+                    entries.skip_current_dir();
+                }
+
+                errors.push(FileOperationError::io(entry.path(), err));
+            }
+        }
+    }
+
+    (created, errors)
+}
+
+/// A root which failed to be created is reported at `to` when it exists meanwhile.
+fn root_error(from: &Path, to: &Path, err: std::io::Error) -> FileOperationError {
+    if err.kind() == ErrorKind::AlreadyExists {
+        FileOperationError::io(to, err)
+    } else {
+        FileOperationError::io(from, err)
+    }
+}
+
+/// Copies the content and the permissions of the file `from` to `to`. Unlike
+/// `std::fs::copy` an existing `to` fails instead of being overwritten, and a `to`
+/// which fails to be filled is removed again.
+fn copy_file(from: &Path, to: &Path) -> std::io::Result<()> {
+    let mut source = std::fs::File::open(from)?;
+    let permissions = source.metadata()?.permissions();
+    let mut target = std::fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(to)?;
+
+    let result =
+        std::io::copy(&mut source, &mut target).and_then(|_| target.set_permissions(permissions));
+    if result.is_err() {
+        drop(target);
+        let _ = std::fs::remove_file(to);
+    }
+
+    result
+}
+
+/// Deletes the file or the directory `path` and returns the entries which failed. A
+/// directory with content is only deleted when `recursive` confirms it, otherwise it
+/// fails as [`FileOperationErrorKind::NotEmpty`]. Symbolic links are deleted, never
+/// followed.
+pub fn delete(path: &Path, recursive: bool) -> Vec<FileOperationError> {
+    if let Err(err) = check_path(path) {
+        return vec![err];
+    }
+
+    let meta = match path.symlink_metadata() {
+        Ok(meta) => meta,
+        Err(err) => return vec![FileOperationError::io(path, err)],
+    };
+
+    if !meta.is_dir() {
+        return std::fs::remove_file(path)
+            .map_err(|err| FileOperationError::io(path, err))
+            .err()
+            .into_iter()
+            .collect();
+    }
+
+    if !recursive {
+        let is_empty = match std::fs::read_dir(path) {
+            Ok(mut entries) => entries.next().is_none(),
+            Err(err) => return vec![FileOperationError::io(path, err)],
+        };
+
+        if !is_empty {
+            return vec![FileOperationError::new(
+                path,
+                FileOperationErrorKind::NotEmpty,
+                "directory isn't empty",
+            )];
+        }
+    }
+
+    let mut errors: Vec<FileOperationError> = Vec::new();
+
+    for entry in WalkDir::new(path).contents_first(true) {
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(err) => {
+                errors.push(FileOperationError::walk(path, err));
+                continue;
+            }
+        };
+
+        let result = if entry.file_type().is_dir() {
+            // a directory whose content failed is kept, it's reported by that content
+            if errors.iter().any(|err| err.path.starts_with(entry.path())) {
+                continue;
+            }
+
+            std::fs::remove_dir(entry.path())
+        } else {
+            std::fs::remove_file(entry.path())
+        };
+
+        if let Err(err) = result {
+            errors.push(FileOperationError::io(entry.path(), err));
+        }
+    }
+
+    errors
+}
+
+/// Resolves the links and the `..` of the parents of `path`, its last component
+/// stays as is since operations never follow it. The parent must exist.
+fn resolve_path(path: &Path) -> Result<PathBuf, FileOperationError> {
+    let (Some(parent), Some(name)) = (path.parent(), path.file_name()) else {
+        return Err(FileOperationError::new(
+            path,
+            FileOperationErrorKind::InvalidPath,
+            "path must end with a file name",
+        ));
+    };
+
+    parent
+        .canonicalize()
+        .map(|parent| parent.join(name))
+        .map_err(|err| FileOperationError::io(parent, err))
+}
+
+/// Creates the link `to` pointing to the target of the link `from`.
+#[cfg(unix)]
+fn copy_symlink(from: &Path, to: &Path) -> std::io::Result<()> {
+    std::os::unix::fs::symlink(std::fs::read_link(from)?, to)
+}
+
+/// Creates the link `to` pointing to the target of the link `from`, Windows tells
+/// links to directories apart.
+#[cfg(windows)]
+fn copy_symlink(from: &Path, to: &Path) -> std::io::Result<()> {
+    let target = std::fs::read_link(from)?;
+
+    if from.metadata().map(|meta| meta.is_dir()).unwrap_or(false) {
+        std::os::windows::fs::symlink_dir(target, to)
+    } else {
+        std::os::windows::fs::symlink_file(target, to)
+    }
+}
+
+/// `std::fs::rename` can't move entries between file systems.
+fn is_cross_device(err: &std::io::Error) -> bool {
+    #[cfg(unix)]
+    const CROSS_DEVICE: i32 = libc::EXDEV;
+    // ERROR_NOT_SAME_DEVICE
+    #[cfg(windows)]
+    const CROSS_DEVICE: i32 = 17;
+
+    err.raw_os_error() == Some(CROSS_DEVICE)
+}
+
+/// Operations only apply to absolute paths below a root, a relative path would
+/// resolve against the working directory of the serving side.
+fn check_path(path: &Path) -> Result<(), FileOperationError> {
+    if !path.is_absolute() || path.parent().is_none() {
+        return Err(FileOperationError::new(
+            path,
+            FileOperationErrorKind::InvalidPath,
+            "path must be absolute and not a root",
+        ));
+    }
+
+    Ok(())
+}
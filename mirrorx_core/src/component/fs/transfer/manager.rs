@@ -0,0 +1,814 @@
+use super::{
+    confirm_resume_offset, control_transfer, create_file_append_session, partial_file_path,
+    prepare_receive_file, query_transferred_bytes_count, send_file_to_remote, wait_transfer_end,
+};
+use crate::{
+    api::{
+        config::{
+            entity::transfer::{
+                TransferEntry, TransferEntryState, TransferKind, TransferRecord, TransferState,
+            },
+            settings::{current_settings, subscribe_settings},
+            LocalStorage,
+        },
+        endpoint::{
+            client::EndPointClient,
+            message::{
+                EndPointCallRequest, EndPointDownloadFileReply, EndPointDownloadFileRequest,
+                EndPointSendFileReply, EndPointSendFileRequest, FileTransferAction,
+                FileTransferErrorReason,
+            },
+        },
+    },
+    component::fs::directory::{
+        directory_file_record, prepare_directory_transfer, DirectoryTransferStatus,
+    },
+    core_error,
+    error::CoreResult,
+};
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{Arc, Mutex},
+};
+use tokio::sync::oneshot;
+
+/// Queue of file and directory transfers, at most [`Settings::transfer_concurrency`]
+/// of them run at the same time. The queue is saved in storage, transfers interrupted
+/// by an exit are paused on the next start and resumed by the user once the remote
+/// side is connected. The files of a directory transfer run one after another and
+/// their states are saved with it, so it resumes at the files which aren't done.
+///
+/// [`Settings::transfer_concurrency`]: crate::api::config::settings::Settings::transfer_concurrency
+#[derive(Clone)]
+pub struct TransferManager {
+    storage: LocalStorage,
+    state: Arc<Mutex<ManagerState>>,
+}
+
+#[derive(Default)]
+struct ManagerState {
+    transfers: Vec<TransferRecord>,
+    /// Connections of the queued and running transfers.
+    clients: HashMap<String, Arc<EndPointClient>>,
+    /// Transfers whose tasks haven't exited yet, they can't start again before.
+    active: HashSet<String>,
+    /// Transfers resumed by the user, the remote side is told before they start.
+    resumed: HashSet<String>,
+    /// Ids of the files running for the directory transfers, pausing or cancelling a
+    /// directory transfer stops its file.
+    files: HashMap<String, String>,
+}
+
+impl TransferManager {
+    pub fn new(storage: LocalStorage) -> CoreResult<Self> {
+        let mut transfers = storage.transfer().list()?;
+        for record in transfers.iter_mut() {
+            if matches!(record.state, TransferState::Queued | TransferState::Running) {
+                record.state = TransferState::Paused;
+                storage.transfer().update_state(
+                    &record.id,
+                    record.state,
+                    record.transferred_size,
+                    None,
+                )?;
+            }
+        }
+
+        let manager = Self {
+            storage,
+            state: Arc::new(Mutex::new(ManagerState {
+                transfers,
+                ..Default::default()
+            })),
+        };
+
+        // queued transfers start as soon as the limit is raised
+        let mut settings_rx = subscribe_settings();
+        let weak_state = Arc::downgrade(&manager.state);
+        let storage = manager.storage.clone();
+        tokio::spawn(async move {
+            while settings_rx.changed().await.is_ok() {
+                let Some(state) = weak_state.upgrade() else {
+                    break;
+                };
+
+                TransferManager {
+                    storage: storage.clone(),
+                    state,
+                }
+                .schedule();
+            }
+        });
+
+        Ok(manager)
+    }
+
+    /// Queues the transfer `record` and returns it as saved. A failed or paused
+    /// transfer with the same id is replaced, it resumes at its partial file.
+    pub fn enqueue(
+        &self,
+        client: Arc<EndPointClient>,
+        mut record: TransferRecord,
+    ) -> CoreResult<TransferRecord> {
+        let now = chrono::Utc::now().timestamp();
+        record.state = TransferState::Queued;
+        record.error = None;
+        record.created_at = now;
+        record.updated_at = now;
+
+        {
+            let mut state = self.lock()?;
+            if let Some(existing) = state.find(&record.id) {
+                if matches!(
+                    existing.state,
+                    TransferState::Queued | TransferState::Running
+                ) {
+                    return Err(core_error!("transfer is already queued"));
+                }
+            }
+
+            self.storage.transfer().save(&record)?;
+            if record.kind.is_directory() {
+                // a directory queued again is walked again
+                self.storage.transfer().save_entries(&record.id, &[])?;
+            }
+
+            state.transfers.retain(|transfer| transfer.id != record.id);
+            state.transfers.push(record.clone());
+            client.transfer_events().state(&record.id, record.state);
+            state.clients.insert(record.id.clone(), client);
+        }
+
+        self.schedule();
+
+        Ok(record)
+    }
+
+    /// Returns the transfers in the order they were queued, with the progress of the
+    /// running ones.
+    pub fn list(&self) -> CoreResult<Vec<TransferRecord>> {
+        let state = self.lock()?;
+
+        Ok(state
+            .transfers
+            .iter()
+            .cloned()
+            .map(|mut record| {
+                let client = state
+                    .clients
+                    .get(&record.id)
+                    .filter(|_| record.state == TransferState::Running);
+
+                if let Some(client) = client {
+                    // a directory transfer counts the size of its completed files
+                    record.transferred_size = match state.files.get(&record.id) {
+                        Some(file) => {
+                            record.transferred_size + query_transferred_bytes_count(client, file)
+                        }
+                        None if record.kind.is_directory() => record.transferred_size,
+                        None => query_transferred_bytes_count(client, &record.id),
+                    };
+                }
+                record
+            })
+            .collect())
+    }
+
+    /// Returns the progress and the failed entries of the directory transfer `id`,
+    /// `None` when it isn't a directory transfer of the queue.
+    pub fn directory_status(&self, id: &str) -> CoreResult<Option<DirectoryTransferStatus>> {
+        let Some(record) = self
+            .list()?
+            .into_iter()
+            .find(|record| record.id == id && record.kind.is_directory())
+        else {
+            return Ok(None);
+        };
+
+        let entries = self.storage.transfer().list_entries(id)?;
+
+        Ok(Some(DirectoryTransferStatus::new(&record, &entries)))
+    }
+
+    pub async fn pause(&self, id: &str) -> CoreResult<()> {
+        let client = {
+            let mut state = self.lock()?;
+            let transferred = state
+                .clients
+                .get(id)
+                .map_or(0, |client| query_transferred_bytes_count(client, id));
+            let Some(record) = state.find_mut(id) else {
+                return Err(core_error!("transfer not exists"));
+            };
+
+            let running = match record.state {
+                TransferState::Queued => false,
+                TransferState::Running => true,
+                _ => return Err(core_error!("transfer isn't queued or running")),
+            };
+
+            record.state = TransferState::Paused;
+            record.transferred_size = record.transferred_size.max(transferred);
+            self.storage.transfer().update_state(
+                id,
+                record.state,
+                record.transferred_size,
+                None,
+            )?;
+
+            let is_directory = record.kind.is_directory();
+            let client = state.clients.remove(id);
+            match client {
+                Some(client) if running => state
+                    .running_file(id, is_directory)
+                    .map(|file| (client, file)),
+                Some(client) => {
+                    client.transfer_events().state(id, TransferState::Paused);
+                    None
+                }
+                None => None,
+            }
+        };
+
+        // the task of the transfer exits once both sides stopped and emits the state, a
+        // directory transfer between two files stops before the next one
+        if let Some((client, file)) = client {
+            control_transfer(&client, &file, FileTransferAction::Pause).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Queues a paused or failed transfer again over `client`.
+    pub fn resume(&self, id: &str, client: Arc<EndPointClient>) -> CoreResult<()> {
+        {
+            let mut state = self.lock()?;
+            let Some(record) = state.find_mut(id) else {
+                return Err(core_error!("transfer not exists"));
+            };
+
+            if !matches!(record.state, TransferState::Paused | TransferState::Failed) {
+                return Err(core_error!("transfer isn't paused or failed"));
+            }
+
+            record.state = TransferState::Queued;
+            record.error = None;
+            self.storage.transfer().update_state(
+                id,
+                record.state,
+                record.transferred_size,
+                None,
+            )?;
+
+            client.transfer_events().state(id, record.state);
+            state.clients.insert(id.to_string(), client);
+            state.resumed.insert(id.to_string());
+        }
+
+        self.schedule();
+
+        Ok(())
+    }
+
+    /// Stops the transfer `id` and removes it from the queue. Its partial files are
+    /// discarded, except the ones a paused upload left on the remote side.
+    pub async fn cancel(&self, id: &str) -> CoreResult<()> {
+        let (record, entries, client) = {
+            let mut state = self.lock()?;
+            let Some(index) = state.transfers.iter().position(|record| record.id == id) else {
+                return Err(core_error!("transfer not exists"));
+            };
+
+            let entries = self.storage.transfer().list_entries(id)?;
+            self.storage.transfer().delete(id)?;
+            let record = state.transfers.remove(index);
+            let client = state
+                .clients
+                .remove(id)
+                .map(|client| (client, state.running_file(id, record.kind.is_directory())));
+
+            (record, entries, client)
+        };
+
+        if let Some((client, file)) = client {
+            // a running transfer reports it once its task stops
+            if record.state != TransferState::Running {
+                client
+                    .transfer_events()
+                    .finish(id, Some(FileTransferErrorReason::Cancelled));
+            }
+
+            if let Some(file) = file {
+                control_transfer(&client, &file, FileTransferAction::Cancel).await?;
+            }
+        }
+
+        // a running receiver removes the partial file itself when it stops
+        if record.state != TransferState::Running {
+            match record.kind {
+                TransferKind::Download => {
+                    let _ = tokio::fs::remove_file(partial_file_path(&record.local_path, id)).await;
+                }
+                TransferKind::DownloadDirectory => {
+                    for entry in entries.iter().filter(|entry| {
+                        matches!(
+                            entry.state,
+                            TransferEntryState::Pending | TransferEntryState::Failed
+                        )
+                    }) {
+                        if let Ok(file) = directory_file_record(&record, entry) {
+                            let _ = tokio::fs::remove_file(partial_file_path(
+                                &file.local_path,
+                                &file.id,
+                            ))
+                            .await;
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Removes the transfers which are done from the queue.
+    pub fn clear_done(&self) -> CoreResult<()> {
+        let mut state = self.lock()?;
+        self.storage.transfer().delete_done()?;
+        state
+            .transfers
+            .retain(|record| record.state != TransferState::Done);
+
+        Ok(())
+    }
+
+    /// Starts queued transfers while the concurrency limit allows.
+    fn schedule(&self) {
+        let Ok(mut state) = self.lock() else {
+            return;
+        };
+
+        let limit = current_settings().transfer_concurrency as usize;
+
+        loop {
+            let running = state
+                .transfers
+                .iter()
+                .filter(|record| record.state == TransferState::Running)
+                .count();
+
+            if running >= limit {
+                break;
+            }
+
+            let ManagerState {
+                transfers,
+                clients,
+                active,
+                resumed,
+                ..
+            } = &mut *state;
+
+            let Some(record) = transfers.iter_mut().find(|record| {
+                record.state == TransferState::Queued
+                    && !active.contains(&record.id)
+                    && clients.contains_key(&record.id)
+            }) else {
+                break;
+            };
+
+            let Some(client) = clients.get(&record.id).cloned() else {
+                break;
+            };
+
+            record.state = TransferState::Running;
+            if let Err(err) = self.storage.transfer().update_state(
+                &record.id,
+                record.state,
+                record.transferred_size,
+                None,
+            ) {
+                tracing::error!(?err, "save transfer state failed");
+            }
+
+            active.insert(record.id.clone());
+            let resume = resumed.remove(&record.id);
+            tokio::spawn(self.clone().run(record.clone(), client, resume));
+        }
+    }
+
+    async fn run(self, record: TransferRecord, client: Arc<EndPointClient>, resume: bool) {
+        // the files of a directory transfer are requested again one by one
+        if resume && !record.kind.is_directory() {
+            if let Err(err) =
+                control_transfer(&client, &record.id, FileTransferAction::Resume).await
+            {
+                tracing::warn!(id = record.id, ?err, "send resume control failed");
+            }
+        }
+
+        let result = if record.kind.is_directory() {
+            self.run_directory(&record, client).await
+        } else {
+            self.run_file(&record, client).await
+        };
+
+        self.finish(&record.id, result);
+        self.schedule();
+    }
+
+    async fn run_file(
+        &self,
+        record: &TransferRecord,
+        client: Arc<EndPointClient>,
+    ) -> Result<(), String> {
+        match start_transfer(record, client.clone()).await {
+            Ok((ended, size)) => {
+                if size != record.total_size {
+                    self.update_total_size(&record.id, size);
+                }
+
+                match ended.await {
+                    Ok(None) => Ok(()),
+                    Ok(Some(reason)) => Err(format!("file transfer failed ({:?})", reason)),
+                    Err(err) => Err(err.to_string()),
+                }
+            }
+            Err(err) => {
+                tracing::error!(id = record.id, ?err, "start transfer failed");
+                client
+                    .transfer_events()
+                    .state(&record.id, TransferState::Failed);
+                Err(err.to_string())
+            }
+        }
+    }
+
+    /// Transfers the files of the directory transfer `record` one after another,
+    /// skipping the ones done before it was paused or failed. Files which fail don't
+    /// stop it, the transfer fails once the others are done and retries them when
+    /// it's resumed.
+    async fn run_directory(
+        &self,
+        record: &TransferRecord,
+        client: Arc<EndPointClient>,
+    ) -> Result<(), String> {
+        let events = client.transfer_events();
+
+        let entries = match self.directory_entries(record, &client).await {
+            Ok(entries) => entries,
+            Err(err) => {
+                tracing::error!(id = record.id, ?err, "prepare directory transfer failed");
+                events.state(&record.id, TransferState::Failed);
+                return Err(err.to_string());
+            }
+        };
+
+        let total_size: u64 = entries.iter().map(|entry| entry.size).sum();
+        if total_size != record.total_size {
+            self.update_total_size(&record.id, total_size);
+        }
+
+        let mut completed_size: u64 = entries
+            .iter()
+            .filter(|entry| entry.state == TransferEntryState::Done)
+            .map(|entry| entry.size)
+            .sum();
+        events.start(&record.id, total_size, completed_size);
+
+        // files which can't start fail like the reads or writes the direction needs
+        let default_reason = if record.kind == TransferKind::UploadDirectory {
+            FileTransferErrorReason::ReadFailed
+        } else {
+            FileTransferErrorReason::WriteFailed
+        };
+
+        let mut failures = Vec::new();
+        for entry in entries.iter().filter(|entry| {
+            matches!(
+                entry.state,
+                TransferEntryState::Pending | TransferEntryState::Failed
+            )
+        }) {
+            let result = match directory_file_record(record, entry) {
+                Ok(file) => {
+                    if let Err(reason) = self.start_directory_file(&record.id, &file.id) {
+                        events.finish(&record.id, Some(reason));
+                        return Err(format!("file transfer failed ({:?})", reason));
+                    }
+
+                    let result =
+                        transfer_directory_file(&file, &client, &record.id, completed_size).await;
+                    self.end_directory_file(&record.id);
+                    result
+                }
+                Err(err) => Err((err.to_string(), None)),
+            };
+
+            completed_size += entry.size;
+
+            let (entry_state, error) = match result {
+                Ok(_) => (TransferEntryState::Done, None),
+                Err((
+                    _,
+                    Some(
+                        reason @ (FileTransferErrorReason::Paused
+                        | FileTransferErrorReason::Cancelled
+                        | FileTransferErrorReason::Disconnected),
+                    ),
+                )) => {
+                    // the file is transferred again when the directory transfer resumes
+                    events.finish(&record.id, Some(reason));
+                    return Err(format!("file transfer failed ({:?})", reason));
+                }
+                Err((message, reason)) => {
+                    tracing::warn!(
+                        id = record.id,
+                        path = entry.path,
+                        error = message,
+                        "transfer directory entry failed"
+                    );
+                    failures.push(reason.unwrap_or(default_reason));
+                    (TransferEntryState::Failed, Some(message))
+                }
+            };
+
+            self.finish_directory_entry(
+                &record.id,
+                entry,
+                entry_state,
+                error.as_deref(),
+                completed_size,
+            );
+            events.progress(&record.id, completed_size);
+        }
+
+        match failures.last() {
+            None => {
+                events.finish(&record.id, None);
+                Ok(())
+            }
+            Some(reason) => {
+                events.finish(&record.id, Some(*reason));
+                Err(format!("{} files failed", failures.len()))
+            }
+        }
+    }
+
+    /// Returns the entries of the directory transfer `record`, its structure is
+    /// recreated on the receiving side the first time it runs.
+    async fn directory_entries(
+        &self,
+        record: &TransferRecord,
+        client: &EndPointClient,
+    ) -> CoreResult<Vec<TransferEntry>> {
+        let entries = self.storage.transfer().list_entries(&record.id)?;
+        if !entries.is_empty() {
+            return Ok(entries);
+        }
+
+        let entries = prepare_directory_transfer(record, client).await?;
+        self.storage.transfer().save_entries(&record.id, &entries)?;
+
+        Ok(entries)
+    }
+
+    /// Records `file` as the running file of the directory transfer `id`, or returns
+    /// why it stopped when it was paused or cancelled before.
+    fn start_directory_file(&self, id: &str, file: &str) -> Result<(), FileTransferErrorReason> {
+        let Ok(mut state) = self.lock() else {
+            return Err(FileTransferErrorReason::Cancelled);
+        };
+
+        match state.find(id).map(|record| record.state) {
+            Some(TransferState::Running) => {
+                state.files.insert(id.to_string(), file.to_string());
+                Ok(())
+            }
+            Some(TransferState::Paused) => Err(FileTransferErrorReason::Paused),
+            _ => Err(FileTransferErrorReason::Cancelled),
+        }
+    }
+
+    fn end_directory_file(&self, id: &str) {
+        if let Ok(mut state) = self.lock() {
+            state.files.remove(id);
+        }
+    }
+
+    fn finish_directory_entry(
+        &self,
+        id: &str,
+        entry: &TransferEntry,
+        entry_state: TransferEntryState,
+        error: Option<&str>,
+        completed_size: u64,
+    ) {
+        let Ok(mut state) = self.lock() else {
+            return;
+        };
+
+        // a cancelled transfer is removed with its entries
+        let Some(record) = state.find_mut(id) else {
+            return;
+        };
+
+        record.transferred_size = completed_size;
+
+        let transfer = self.storage.transfer();
+        if let Err(err) = transfer
+            .update_entry(id, &entry.path, entry_state, error)
+            .and_then(|_| {
+                transfer.update_state(
+                    id,
+                    record.state,
+                    record.transferred_size,
+                    record.error.as_deref(),
+                )
+            })
+        {
+            tracing::error!(?err, "save transfer entry failed");
+        }
+    }
+
+    fn finish(&self, id: &str, result: Result<(), String>) {
+        let Ok(mut state) = self.lock() else {
+            return;
+        };
+
+        state.active.remove(id);
+        let transferred = state
+            .clients
+            .get(id)
+            .map(|client| query_transferred_bytes_count(client, id));
+
+        // paused and cancelled transfers keep the state the user gave them, unless they
+        // were paused too late and completed
+        let Some(record) = state.find_mut(id).filter(|record| {
+            record.state == TransferState::Running
+                || (record.state == TransferState::Paused && result.is_ok())
+        }) else {
+            return;
+        };
+
+        if let Some(transferred) = transferred.filter(|_| !record.kind.is_directory()) {
+            record.transferred_size = transferred;
+        }
+
+        match result {
+            Ok(_) => {
+                record.state = TransferState::Done;
+                record.transferred_size = record.total_size;
+            }
+            Err(err) => {
+                record.state = TransferState::Failed;
+                record.error = Some(err);
+            }
+        }
+
+        if let Err(err) = self.storage.transfer().update_state(
+            id,
+            record.state,
+            record.transferred_size,
+            record.error.as_deref(),
+        ) {
+            tracing::error!(?err, "save transfer state failed");
+        }
+
+        state.clients.remove(id);
+    }
+
+    /// Corrects the size of a download listed before the remote file changed.
+    fn update_total_size(&self, id: &str, size: u64) {
+        let Ok(mut state) = self.lock() else {
+            return;
+        };
+
+        if let Some(record) = state.find_mut(id) {
+            record.total_size = size;
+            if let Err(err) = self.storage.transfer().save(record) {
+                tracing::error!(?err, "save transfer failed");
+            }
+        }
+    }
+
+    fn lock(&self) -> CoreResult<std::sync::MutexGuard<ManagerState>> {
+        self.state
+            .lock()
+            .map_err(|_| core_error!("transfer manager state is poisoned"))
+    }
+}
+
+impl ManagerState {
+    fn find(&self, id: &str) -> Option<&TransferRecord> {
+        self.transfers.iter().find(|record| record.id == id)
+    }
+
+    fn find_mut(&mut self, id: &str) -> Option<&mut TransferRecord> {
+        self.transfers.iter_mut().find(|record| record.id == id)
+    }
+
+    /// Returns the id of the file transfer running for the transfer `id`, none for a
+    /// directory transfer between two files.
+    fn running_file(&self, id: &str, is_directory: bool) -> Option<String> {
+        match self.files.get(id) {
+            Some(file) => Some(file.clone()),
+            None if is_directory => None,
+            None => Some(id.to_string()),
+        }
+    }
+}
+
+/// Transfers a file of the directory transfer `parent`, its progress counts after the
+/// `completed_size` of the files before it. Returns why it failed.
+async fn transfer_directory_file(
+    file: &TransferRecord,
+    client: &Arc<EndPointClient>,
+    parent: &str,
+    completed_size: u64,
+) -> Result<(), (String, Option<FileTransferErrorReason>)> {
+    let (ended, _) = start_transfer(file, client.clone())
+        .await
+        .map_err(|err| (err.to_string(), None))?;
+
+    client
+        .transfer_events()
+        .attach(&file.id, parent, completed_size);
+
+    match ended.await {
+        Ok(None) => Ok(()),
+        Ok(Some(reason)) => Err((format!("file transfer failed ({:?})", reason), Some(reason))),
+        Err(err) => Err((err.to_string(), None)),
+    }
+}
+
+/// Requests the transfer from the remote side and starts it. Returns a receiver
+/// notified when it ends on this side and the size of the file.
+async fn start_transfer(
+    record: &TransferRecord,
+    client: Arc<EndPointClient>,
+) -> CoreResult<(oneshot::Receiver<Option<FileTransferErrorReason>>, u64)> {
+    let id = record.id.clone();
+
+    match record.kind {
+        TransferKind::Upload => {
+            let Some(filename) = record.local_path.file_name() else {
+                return Err(core_error!("local path get filename failed"));
+            };
+
+            let reply: EndPointSendFileReply = client
+                .call(EndPointCallRequest::SendFileRequest(
+                    EndPointSendFileRequest {
+                        id: id.clone(),
+                        filename: filename.to_string_lossy().into_owned(),
+                        path: record.remote_path.clone(),
+                        size: record.total_size,
+                    },
+                ))
+                .await?;
+
+            let offset =
+                confirm_resume_offset(&record.local_path, reply.offset, reply.checksums).await?;
+
+            let ended = wait_transfer_end(&client, &id);
+            send_file_to_remote(id, client, &record.local_path, offset).await?;
+
+            Ok((ended, record.total_size))
+        }
+        TransferKind::Download => {
+            // existing files are never overwritten, the partial file of this transfer
+            // resumes
+            let (offset, checksums) = prepare_receive_file(&id, &record.local_path).await?;
+
+            let reply: EndPointDownloadFileReply = client
+                .call(EndPointCallRequest::DownloadFileRequest(
+                    EndPointDownloadFileRequest {
+                        id: id.clone(),
+                        path: record.remote_path.clone(),
+                        offset,
+                        checksums,
+                    },
+                ))
+                .await?;
+
+            let ended = wait_transfer_end(&client, &id);
+            if let Err(err) = create_file_append_session(
+                id.clone(),
+                client.clone(),
+                &record.local_path,
+                reply.size,
+            )
+            .await
+            {
+                let _ = control_transfer(&client, &id, FileTransferAction::Cancel).await;
+                return Err(err);
+            }
+
+            Ok((ended, reply.size))
+        }
+        TransferKind::UploadDirectory | TransferKind::DownloadDirectory => Err(core_error!(
+            "directory transfers start their files one by one"
+        )),
+    }
+}
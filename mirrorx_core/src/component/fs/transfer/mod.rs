@@ -0,0 +1,741 @@
+pub mod manager;
+pub mod progress;
+
+use crate::{
+    api::endpoint::{
+        client::EndPointClient,
+        message::{
+            EndPointFileTransferBlock, EndPointFileTransferComplete, EndPointFileTransferControl,
+            EndPointFileTransferError, EndPointMessage, FileTransferAction,
+            FileTransferErrorReason,
+        },
+    },
+    core_error,
+    error::CoreResult,
+};
+use dashmap::DashMap;
+use moka::future::{Cache, CacheBuilder};
+use sha2::{Digest, Sha256};
+use std::{
+    io::{Read, SeekFrom},
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
+use tokio::{
+    io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt, BufReader, BufWriter},
+    sync::{
+        mpsc::{UnboundedReceiver, UnboundedSender},
+        oneshot,
+    },
+};
+
+/// Interrupted transfers resume at a multiple of this size, the blocks before it are
+/// compared by their SHA-256 digests to confirm both sides hold the same prefix.
+pub const RESUME_BLOCK_SIZE: u64 = 4 * 1024 * 1024;
+
+/// Extension of the file a transfer is received to, it's renamed to the destination
+/// once its digest is verified.
+const PARTIAL_FILE_EXTENSION: &str = "mxpart";
+
+/// How often an idle receiver checks whether its transfer was stopped.
+const SESSION_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// SHA-256 digest of a block or of a whole file.
+pub type BlockChecksum = [u8; 32];
+
+/// State of the file transfers over one endpoint, kept by the endpoint so transfers of
+/// different peers never mix. Transfer ids only identify a transfer with one remote
+/// device, two peers sending the same paths get the same id.
+#[derive(Clone)]
+pub struct TransferSessions {
+    append_files: Cache<String, UnboundedSender<EndPointFileTransferBlock>>,
+    bytes_transferred: Cache<String, u64>,
+    /// Why transfers failed, reported by either side.
+    failed: Cache<String, FileTransferErrorReason>,
+    /// Notified when transfers end on this side, with the reason when they failed.
+    waiters: Arc<DashMap<String, oneshot::Sender<Option<FileTransferErrorReason>>>>,
+    /// Senders waiting for the receivers to verify the files they sent, resolved with
+    /// the reason when it failed.
+    acks: Arc<DashMap<String, oneshot::Sender<Option<FileTransferErrorReason>>>>,
+}
+
+impl TransferSessions {
+    pub fn new() -> Self {
+        Self {
+            append_files: session_cache(),
+            bytes_transferred: session_cache(),
+            failed: session_cache(),
+            waiters: Arc::new(DashMap::new()),
+            acks: Arc::new(DashMap::new()),
+        }
+    }
+}
+
+impl Default for TransferSessions {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl std::fmt::Debug for TransferSessions {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("TransferSessions").finish_non_exhaustive()
+    }
+}
+
+fn session_cache<V: Clone + Send + Sync + 'static>() -> Cache<String, V> {
+    CacheBuilder::new(64)
+        .time_to_idle(Duration::from_secs(3 * 60))
+        .build()
+}
+
+/// Identifies the transfer of `source` to `destination` with a remote device, the same
+/// transfer started again after an interruption gets the same id and resumes.
+pub fn transfer_id(remote_device_id: &str, source: &Path, destination: &Path) -> String {
+    let mut hasher = Sha256::new();
+    for part in [
+        remote_device_id.as_bytes(),
+        source.to_string_lossy().as_bytes(),
+        destination.to_string_lossy().as_bytes(),
+    ] {
+        hasher.update(part);
+        hasher.update([0]);
+    }
+
+    hex::encode(&hasher.finalize()[..16])
+}
+
+/// Returns the hidden file next to `path` the transfer `id` is received to.
+pub fn partial_file_path(path: &Path, id: &str) -> PathBuf {
+    let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+    let id = id.get(..8).unwrap_or(id);
+
+    path.with_file_name(format!(".{file_name}.{id}.{PARTIAL_FILE_EXTENSION}"))
+}
+
+/// Prepares `path` to receive the transfer `id` and returns the offset it resumes at
+/// with the checksums of the blocks of its partial file before it. Existing files are
+/// never overwritten.
+pub async fn prepare_receive_file(id: &str, path: &Path) -> CoreResult<(u64, Vec<BlockChecksum>)> {
+    if path.exists() {
+        return Err(core_error!("file already exists"));
+    }
+
+    let path = partial_file_path(path, id);
+    if !path.exists() {
+        return Ok((0, Vec::new()));
+    }
+
+    tokio::task::spawn_blocking(move || block_checksums(&path))
+        .await
+        .map_err(|err| core_error!("compute block checksums failed ({})", err))?
+}
+
+/// Returns the offset the transfer of `path` resumes at, the length of the prefix
+/// whose blocks match `checksums` reported by the receiver for its first `offset`
+/// bytes.
+pub async fn confirm_resume_offset(
+    path: &Path,
+    offset: u64,
+    checksums: Vec<BlockChecksum>,
+) -> CoreResult<u64> {
+    if offset == 0 || checksums.is_empty() {
+        return Ok(0);
+    }
+
+    let path = path.to_path_buf();
+    let confirmed = tokio::task::spawn_blocking(move || -> CoreResult<u64> {
+        let mut file = std::fs::File::open(path)?;
+        let mut buffer = vec![0u8; RESUME_BLOCK_SIZE as usize];
+        let mut confirmed = 0;
+
+        for checksum in checksums {
+            if confirmed + RESUME_BLOCK_SIZE > offset || !read_block(&mut file, &mut buffer)? {
+                break;
+            }
+
+            if Sha256::digest(&buffer).as_slice() != checksum {
+                break;
+            }
+
+            confirmed += RESUME_BLOCK_SIZE;
+        }
+
+        Ok(confirmed)
+    })
+    .await
+    .map_err(|err| core_error!("confirm resume offset failed ({})", err))??;
+
+    if confirmed < offset {
+        tracing::info!(
+            ?offset,
+            ?confirmed,
+            "transfer resumes before the reported offset"
+        );
+    }
+
+    Ok(confirmed)
+}
+
+/// Starts receiving the transfer `id` of a file of `size` bytes to `path`.
+pub async fn create_file_append_session(
+    id: String,
+    client: Arc<EndPointClient>,
+    path: &Path,
+    size: u64,
+) -> CoreResult<()> {
+    let (tx, rx) = tokio::sync::mpsc::unbounded_channel();
+    let sessions = client.transfer_sessions();
+
+    sessions.append_files.insert(id.clone(), tx).await;
+    sessions.failed.invalidate(&id).await;
+
+    if let Err(err) = save_file_from_remote(id.clone(), client.clone(), path, size, rx).await {
+        sessions.append_files.invalidate(&id).await;
+        end_transfer(&client, &id, Some(FileTransferErrorReason::WriteFailed));
+        return Err(err);
+    }
+
+    Ok(())
+}
+
+/// Handles a transfer error reported by the remote side, the transfer stops on both.
+pub async fn handle_file_transfer_error(client: &EndPointClient, error: EndPointFileTransferError) {
+    tracing::warn!(id = error.id, reason = ?error.reason, "file transfer failed");
+    stop_transfer(client, error.id, error.reason).await;
+}
+
+/// Handles the receiver confirming the file of the transfer is in place.
+pub fn handle_file_transfer_complete(
+    client: &EndPointClient,
+    complete: EndPointFileTransferComplete,
+) {
+    tracing::info!(id = complete.id, "file transfer complete");
+
+    if let Some((_, tx)) = client.transfer_sessions().acks.remove(&complete.id) {
+        let _ = tx.send(None);
+    }
+}
+
+/// Handles a control message of the side which queued the transfer.
+pub async fn handle_file_transfer_control(
+    client: &EndPointClient,
+    control: EndPointFileTransferControl,
+) {
+    tracing::info!(id = control.id, action = ?control.action, "file transfer control");
+
+    match control.action {
+        FileTransferAction::Pause => {
+            stop_transfer(client, control.id, FileTransferErrorReason::Paused).await
+        }
+        FileTransferAction::Cancel => {
+            stop_transfer(client, control.id, FileTransferErrorReason::Cancelled).await
+        }
+        // the transfer is requested again, it resumes at the offset both sides confirm
+        FileTransferAction::Resume => {
+            client
+                .transfer_sessions()
+                .failed
+                .invalidate(&control.id)
+                .await
+        }
+    }
+}
+
+/// Applies `action` to the transfer `id` on both sides. A paused transfer keeps its
+/// partial file and resumes when it's requested again, a cancelled one removes it.
+pub async fn control_transfer(
+    client: &EndPointClient,
+    id: &str,
+    action: FileTransferAction,
+) -> CoreResult<()> {
+    let control = EndPointFileTransferControl {
+        id: id.to_string(),
+        action,
+    };
+
+    handle_file_transfer_control(client, control.clone()).await;
+    client
+        .send(&EndPointMessage::FileTransferControl(control))
+        .await
+}
+
+/// Stops the transfer `id` on this side, its sender stops before the next block and
+/// its receiver once it notices the reason. The reason is stored before the receiver's
+/// channel closes, so it's what the receiver reports.
+async fn stop_transfer(client: &EndPointClient, id: String, reason: FileTransferErrorReason) {
+    let sessions = client.transfer_sessions();
+    sessions.failed.insert(id.clone(), reason).await;
+
+    if let Some((_, tx)) = sessions.acks.remove(&id) {
+        let _ = tx.send(Some(reason));
+    }
+
+    sessions.append_files.invalidate(&id).await;
+}
+
+fn stopped_reason(sessions: &TransferSessions, id: &str) -> FileTransferErrorReason {
+    sessions
+        .failed
+        .get(id)
+        .unwrap_or(FileTransferErrorReason::SessionNotFound)
+}
+
+pub async fn append_file_block(client: Arc<EndPointClient>, block: EndPointFileTransferBlock) {
+    let id = block.id.clone();
+    let sessions = client.transfer_sessions();
+
+    // blocks sent before the sender noticed the transfer stopped
+    if sessions.failed.get(&id).is_some() {
+        return;
+    }
+
+    if let Some(tx) = sessions.append_files.get(&id) {
+        match tx.send(block) {
+            Ok(_) => return,
+            Err(_) => {
+                tracing::error!(id, "append file block channel failed");
+            }
+        }
+    } else {
+        tracing::error!(id, "file session not exists");
+    }
+
+    fail_transfer(&client, id, FileTransferErrorReason::SessionNotFound).await;
+}
+
+async fn save_file_from_remote(
+    id: String,
+    client: Arc<EndPointClient>,
+    path: &Path,
+    size: u64,
+    mut rx: UnboundedReceiver<EndPointFileTransferBlock>,
+) -> CoreResult<()> {
+    // an interrupted transfer keeps the partial file, the first block of the resumed
+    // one tells where the sender resumes
+    let path = path.to_path_buf();
+    let partial_path = partial_file_path(&path, &id);
+    let file = tokio::fs::OpenOptions::new()
+        .write(true)
+        .create(true)
+        .open(&partial_path)
+        .await?;
+    let mut writer = BufWriter::new(file);
+
+    tokio::spawn(async move {
+        let events = client.transfer_events();
+        let sessions = client.transfer_sessions();
+        let mut position = 0;
+        let mut started = false;
+        let mut digest = None;
+        let mut failure = None;
+
+        loop {
+            let block = match tokio::time::timeout(SESSION_CHECK_INTERVAL, rx.recv()).await {
+                Ok(Some(block)) => block,
+                Ok(None)
+                    if sessions.failed.get(&id).is_none()
+                        && sessions.append_files.contains_key(&id) =>
+                {
+                    // the same transfer was started again, its new session takes over
+                    tracing::info!(id, "file session superseded");
+                    return;
+                }
+                Ok(None) => {
+                    failure = Some(stopped_reason(sessions, &id));
+                    break;
+                }
+                Err(_)
+                    if sessions.failed.get(&id).is_none()
+                        && sessions.append_files.contains_key(&id) =>
+                {
+                    continue;
+                }
+                Err(_) => {
+                    // stopped by either side or the session expired
+                    failure = Some(stopped_reason(sessions, &id));
+                    break;
+                }
+            };
+
+            // the first block tells the offset the transfer resumes at
+            if !started {
+                events.start(&id, size, block.offset);
+                started = true;
+            }
+
+            if block.offset != position {
+                if let Err(err) = seek_truncate(&mut writer, block.offset).await {
+                    tracing::error!(?err, offset = block.offset, "seek file has error occurred");
+                    failure = Some(FileTransferErrorReason::WriteFailed);
+                    break;
+                }
+
+                position = block.offset;
+                set_transferred_bytes_count(sessions, &id, position).await;
+                events.progress(&id, position);
+            }
+
+            match block.data {
+                Some(buffer) => {
+                    if let Err(err) = writer.write_all(&buffer).await {
+                        tracing::error!(?err, "write file has error occurred");
+                        failure = Some(FileTransferErrorReason::WriteFailed);
+                        break;
+                    }
+
+                    position += buffer.len() as u64;
+                    update_transferred_bytes_count(sessions, &id, buffer.len() as _).await;
+                    events.progress(&id, position);
+                }
+                None => {
+                    digest = Some(block.digest);
+                    break;
+                }
+            }
+        }
+
+        sessions.append_files.invalidate(&id).await;
+
+        if let Some(digest) = digest {
+            match finish_file(writer, position, &partial_path, &path, digest).await {
+                Ok(_) => {
+                    tracing::info!(?path, "file received");
+
+                    let _ = client
+                        .send(&EndPointMessage::FileTransferComplete(
+                            EndPointFileTransferComplete { id: id.clone() },
+                        ))
+                        .await;
+                }
+                Err(reason) => failure = Some(reason),
+            }
+        } else {
+            let _ = writer.flush().await;
+        }
+
+        if failure == Some(FileTransferErrorReason::Cancelled) {
+            let _ = tokio::fs::remove_file(&partial_path).await;
+        }
+
+        if let Some(reason) = failure {
+            if sessions.failed.get(&id).is_none() {
+                fail_transfer(&client, id.clone(), reason).await;
+            }
+        }
+
+        end_transfer(&client, &id, failure);
+    });
+
+    Ok(())
+}
+
+/// Verifies the received file against the digest the sender computed and moves it
+/// to its final name. A corrupted file is removed so the next attempt starts over.
+async fn finish_file(
+    mut writer: BufWriter<tokio::fs::File>,
+    length: u64,
+    partial_path: &Path,
+    path: &Path,
+    digest: Option<BlockChecksum>,
+) -> Result<(), FileTransferErrorReason> {
+    // a resumed file may be longer than the content written over it
+    let truncated = seek_truncate(&mut writer, length).await;
+    drop(writer);
+
+    if let Err(err) = truncated {
+        tracing::error!(?err, "truncate file has error occurred");
+        return Err(FileTransferErrorReason::WriteFailed);
+    }
+
+    let actual = file_digest(partial_path).await.map_err(|err| {
+        tracing::error!(?err, "compute file digest has error occurred");
+        FileTransferErrorReason::WriteFailed
+    })?;
+
+    if digest != Some(actual) {
+        tracing::error!(
+            ?path,
+            "received file doesn't match the digest of the sender"
+        );
+        let _ = tokio::fs::remove_file(partial_path).await;
+        return Err(FileTransferErrorReason::DigestMismatch);
+    }
+
+    if path.exists() {
+        return Err(FileTransferErrorReason::AlreadyExists);
+    }
+
+    tokio::fs::rename(partial_path, path).await.map_err(|err| {
+        tracing::error!(?err, "rename received file has error occurred");
+        FileTransferErrorReason::WriteFailed
+    })
+}
+
+/// Stops the transfer `id` on this side and reports `reason` to the remote side.
+async fn fail_transfer(client: &EndPointClient, id: String, reason: FileTransferErrorReason) {
+    stop_transfer(client, id.clone(), reason).await;
+
+    let _ = client
+        .send(&EndPointMessage::FileTransferError(
+            EndPointFileTransferError { id, reason },
+        ))
+        .await;
+}
+
+/// Sends the content of `path` from `offset` on, the last block carries the digest of
+/// the whole file.
+pub async fn send_file_to_remote(
+    id: String,
+    client: Arc<EndPointClient>,
+    path: &Path,
+    offset: u64,
+) -> CoreResult<()> {
+    let opened = match tokio::fs::File::open(path).await {
+        Ok(file) => file.metadata().await.map(|meta| (file, meta.len())),
+        Err(err) => Err(err),
+    };
+
+    let (file, size) = match opened {
+        Ok(opened) => opened,
+        Err(err) => {
+            end_transfer(&client, &id, Some(FileTransferErrorReason::ReadFailed));
+            return Err(err.into());
+        }
+    };
+
+    let sessions = client.transfer_sessions();
+    set_transferred_bytes_count(sessions, &id, offset).await;
+    sessions.failed.invalidate(&id).await;
+
+    tokio::spawn(async move {
+        client.transfer_events().start(&id, size, offset);
+
+        // registered before the last block so the receiver can't answer first
+        let (ack_tx, ack_rx) = oneshot::channel();
+        client.transfer_sessions().acks.insert(id.clone(), ack_tx);
+
+        let failure = match stream_file(&id, &client, file, offset).await {
+            None => wait_transfer_ack(&id, &client, ack_rx).await,
+            failure => failure,
+        };
+
+        client.transfer_sessions().acks.remove(&id);
+        end_transfer(&client, &id, failure);
+    });
+
+    Ok(())
+}
+
+async fn stream_file(
+    id: &str,
+    client: &EndPointClient,
+    mut file: tokio::fs::File,
+    offset: u64,
+) -> Option<FileTransferErrorReason> {
+    let mut buffer = [0u8; 1024 * 64];
+    let mut position = offset;
+    let mut hasher = Sha256::new();
+    let sessions = client.transfer_sessions();
+
+    // the digest covers the prefix the receiver already holds as well
+    if let Err(err) = hash_prefix(&mut file, offset, &mut hasher).await {
+        tracing::error!(?err, "read file failed");
+        fail_transfer(client, id.to_string(), FileTransferErrorReason::ReadFailed).await;
+        return Some(FileTransferErrorReason::ReadFailed);
+    }
+
+    let mut reader = BufReader::new(file);
+
+    loop {
+        // the receiver failed, e.g. it can't write the file
+        if let Some(reason) = sessions.failed.get(id) {
+            return Some(reason);
+        }
+
+        let n = match reader.read(&mut buffer).await {
+            Ok(n) => n,
+            Err(err) => {
+                tracing::error!(?err, "read file failed");
+                fail_transfer(client, id.to_string(), FileTransferErrorReason::ReadFailed).await;
+                return Some(FileTransferErrorReason::ReadFailed);
+            }
+        };
+
+        let (data, digest) = if n > 0 {
+            hasher.update(&buffer[..n]);
+            (Some(buffer[..n].to_vec()), None)
+        } else {
+            (None, Some(hasher.finalize_reset().into()))
+        };
+
+        let message = EndPointMessage::FileTransferBlock(EndPointFileTransferBlock {
+            id: id.to_string(),
+            offset: position,
+            data,
+            digest,
+        });
+
+        if let Err(err) = client.send(&message).await {
+            tracing::error!(?err, "send file message failed");
+            sessions
+                .failed
+                .insert(id.to_string(), FileTransferErrorReason::Disconnected)
+                .await;
+            return Some(FileTransferErrorReason::Disconnected);
+        }
+
+        if n == 0 {
+            return None;
+        }
+
+        position += n as u64;
+        update_transferred_bytes_count(sessions, id, n as _).await;
+        client.transfer_events().progress(id, position);
+    }
+}
+
+/// Waits for the receiver to verify the file of the transfer `id` once its last block
+/// is sent, returns why it failed.
+async fn wait_transfer_ack(
+    id: &str,
+    client: &EndPointClient,
+    ack_rx: oneshot::Receiver<Option<FileTransferErrorReason>>,
+) -> Option<FileTransferErrorReason> {
+    tokio::select! {
+        ack = ack_rx => ack.unwrap_or(Some(FileTransferErrorReason::SessionNotFound)),
+        _ = client.wait_closed() => {
+            client
+                .transfer_sessions()
+                .failed
+                .insert(id.to_string(), FileTransferErrorReason::Disconnected)
+                .await;
+            Some(FileTransferErrorReason::Disconnected)
+        }
+    }
+}
+
+/// Returns a receiver notified when the transfer `id` over `client` ends on this side,
+/// with the reason when it failed. It must be created before the transfer starts, the
+/// sending side is notified once the receiver confirmed the file is verified and in
+/// place.
+pub fn wait_transfer_end(
+    client: &EndPointClient,
+    id: &str,
+) -> oneshot::Receiver<Option<FileTransferErrorReason>> {
+    let (tx, rx) = oneshot::channel();
+    client
+        .transfer_sessions()
+        .waiters
+        .insert(id.to_string(), tx);
+    rx
+}
+
+/// Emits how the transfer `id` ended on this side and notifies its waiter.
+fn end_transfer(client: &EndPointClient, id: &str, failure: Option<FileTransferErrorReason>) {
+    client.transfer_events().finish(id, failure);
+
+    if let Some((_, tx)) = client.transfer_sessions().waiters.remove(id) {
+        let _ = tx.send(failure);
+    }
+}
+
+pub fn query_transferred_bytes_count(client: &EndPointClient, id: &str) -> u64 {
+    client
+        .transfer_sessions()
+        .bytes_transferred
+        .get(id)
+        .unwrap_or_default()
+}
+
+/// Returns why the transfer `id` over `client` failed, `None` while it's running or
+/// succeeded.
+pub fn query_transfer_error(client: &EndPointClient, id: &str) -> Option<FileTransferErrorReason> {
+    client.transfer_sessions().failed.get(id)
+}
+
+async fn set_transferred_bytes_count(sessions: &TransferSessions, id: &str, count: u64) {
+    sessions
+        .bytes_transferred
+        .insert(id.to_string(), count)
+        .await;
+}
+
+async fn update_transferred_bytes_count(sessions: &TransferSessions, id: &str, delta: u64) {
+    let transferred = sessions.bytes_transferred.get(id).unwrap_or_default() + delta;
+    sessions
+        .bytes_transferred
+        .insert(id.to_string(), transferred)
+        .await;
+}
+
+async fn hash_prefix(
+    file: &mut tokio::fs::File,
+    length: u64,
+    hasher: &mut Sha256,
+) -> CoreResult<()> {
+    let mut prefix = file.take(length);
+    let mut buffer = vec![0u8; 1024 * 64];
+
+    loop {
+        let n = prefix.read(&mut buffer).await?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buffer[..n]);
+    }
+
+    if prefix.limit() > 0 {
+        return Err(core_error!("file is shorter than the resume offset"));
+    }
+
+    Ok(())
+}
+
+async fn file_digest(path: &Path) -> CoreResult<BlockChecksum> {
+    let path = path.to_path_buf();
+
+    tokio::task::spawn_blocking(move || -> CoreResult<BlockChecksum> {
+        let mut file = std::fs::File::open(path)?;
+        let mut hasher = Sha256::new();
+        std::io::copy(&mut file, &mut hasher)?;
+        Ok(hasher.finalize().into())
+    })
+    .await
+    .map_err(|err| core_error!("compute file digest failed ({})", err))?
+}
+
+async fn seek_truncate(writer: &mut BufWriter<tokio::fs::File>, offset: u64) -> CoreResult<()> {
+    writer.flush().await?;
+    writer.get_mut().set_len(offset).await?;
+    writer.seek(SeekFrom::Start(offset)).await?;
+    Ok(())
+}
+
+/// Returns the length of the complete blocks of `path` with their checksums.
+fn block_checksums(path: &Path) -> CoreResult<(u64, Vec<BlockChecksum>)> {
+    let mut file = std::fs::File::open(path)?;
+    let mut buffer = vec![0u8; RESUME_BLOCK_SIZE as usize];
+    let mut checksums = Vec::new();
+
+    while read_block(&mut file, &mut buffer)? {
+        checksums.push(Sha256::digest(&buffer).into());
+    }
+
+    Ok((checksums.len() as u64 * RESUME_BLOCK_SIZE, checksums))
+}
+
+/// Fills `buffer` with the next block, returns `false` when the file ends before.
+fn read_block(file: &mut std::fs::File, buffer: &mut [u8]) -> CoreResult<bool> {
+    let mut filled = 0;
+    while filled < buffer.len() {
+        let n = file.read(&mut buffer[filled..])?;
+        if n == 0 {
+            return Ok(false);
+        }
+        filled += n;
+    }
+
+    Ok(true)
+}
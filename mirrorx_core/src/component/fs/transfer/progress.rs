@@ -0,0 +1,269 @@
+use crate::api::{
+    config::entity::transfer::TransferState, endpoint::message::FileTransferErrorReason,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+use tokio::sync::broadcast;
+
+/// Progress of a transfer is emitted at most once per this interval.
+pub const PROGRESS_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Weight of the latest sample in the smoothed rate.
+const RATE_SMOOTHING: f64 = 0.3;
+
+const EVENTS_CAPACITY: usize = 256;
+
+/// Event of a transfer of an endpoint, serialized with its kind in `type`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum TransferEvent {
+    Progress {
+        id: String,
+        transferred_size: u64,
+        total_size: u64,
+        /// Bytes per second.
+        rate: u64,
+        /// Seconds left at the current rate, `None` before the rate is known.
+        eta_secs: Option<u64>,
+    },
+    State {
+        id: String,
+        state: TransferState,
+    },
+    Error {
+        id: String,
+        reason: FileTransferErrorReason,
+    },
+}
+
+impl TransferEvent {
+    pub fn id(&self) -> &str {
+        match self {
+            TransferEvent::Progress { id, .. }
+            | TransferEvent::State { id, .. }
+            | TransferEvent::Error { id, .. } => id,
+        }
+    }
+}
+
+/// Rate and remaining time of a transfer, sampled while its bytes are counted.
+#[derive(Debug, Clone)]
+pub struct TransferProgress {
+    total_size: u64,
+    transferred_size: u64,
+    /// Bytes per second.
+    rate: f64,
+    sampled_at: Instant,
+    sampled_size: u64,
+}
+
+impl TransferProgress {
+    pub fn new(total_size: u64, transferred_size: u64, now: Instant) -> Self {
+        Self {
+            total_size,
+            transferred_size,
+            rate: 0.0,
+            sampled_at: now,
+            sampled_size: transferred_size,
+        }
+    }
+
+    /// Counts the bytes transferred so far and returns the rate with the remaining
+    /// seconds once [`PROGRESS_INTERVAL`] passed since the last sample, or once the
+    /// transfer completed.
+    pub fn update(&mut self, transferred_size: u64, now: Instant) -> Option<(u64, Option<u64>)> {
+        self.transferred_size = transferred_size;
+
+        let elapsed = now.saturating_duration_since(self.sampled_at);
+        if elapsed < PROGRESS_INTERVAL && transferred_size < self.total_size {
+            return None;
+        }
+
+        // a resumed receiver may seek back before the bytes it counted
+        let sample = transferred_size.saturating_sub(self.sampled_size) as f64
+            / elapsed.as_secs_f64().max(f64::EPSILON);
+
+        self.rate = if self.rate == 0.0 {
+            sample
+        } else {
+            RATE_SMOOTHING * sample + (1.0 - RATE_SMOOTHING) * self.rate
+        };
+        self.sampled_at = now;
+        self.sampled_size = transferred_size;
+
+        Some((self.rate as u64, self.eta_secs()))
+    }
+
+    pub fn transferred_size(&self) -> u64 {
+        self.transferred_size
+    }
+
+    pub fn total_size(&self) -> u64 {
+        self.total_size
+    }
+
+    fn eta_secs(&self) -> Option<u64> {
+        let remaining = self.total_size.saturating_sub(self.transferred_size);
+        if remaining == 0 {
+            Some(0)
+        } else if self.rate >= 1.0 {
+            Some((remaining as f64 / self.rate).ceil() as u64)
+        } else {
+            None
+        }
+    }
+}
+
+/// Broadcasts the events of the transfers over one endpoint, so transfers with the
+/// same id over different endpoints never mix.
+#[derive(Debug, Clone)]
+pub struct TransferEvents {
+    tx: broadcast::Sender<TransferEvent>,
+    state: Arc<Mutex<EventsState>>,
+}
+
+#[derive(Debug, Default)]
+struct EventsState {
+    progresses: HashMap<String, TransferProgress>,
+    /// Files of directory transfers with the directory transfer id and the size of
+    /// its files completed before them.
+    parents: HashMap<String, (String, u64)>,
+}
+
+impl TransferEvents {
+    pub fn new() -> Self {
+        let (tx, _) = broadcast::channel(EVENTS_CAPACITY);
+
+        Self {
+            tx,
+            state: Arc::new(Mutex::new(EventsState::default())),
+        }
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<TransferEvent> {
+        self.tx.subscribe()
+    }
+
+    /// Starts counting the progress of the transfer `id` at `transferred_size`.
+    pub fn start(&self, id: &str, total_size: u64, transferred_size: u64) {
+        if let Ok(mut state) = self.state.lock() {
+            state.progresses.insert(
+                id.to_string(),
+                TransferProgress::new(total_size, transferred_size, Instant::now()),
+            );
+        }
+
+        self.state(id, TransferState::Running);
+        self.send(TransferEvent::Progress {
+            id: id.to_string(),
+            transferred_size,
+            total_size,
+            rate: 0,
+            eta_secs: None,
+        });
+    }
+
+    /// Counts the progress of the file transfer `id` to the directory transfer
+    /// `parent` as well, after the `completed_size` of its previous files.
+    pub fn attach(&self, id: &str, parent: &str, completed_size: u64) {
+        if let Ok(mut state) = self.state.lock() {
+            state
+                .parents
+                .insert(id.to_string(), (parent.to_string(), completed_size));
+        }
+
+        self.progress(parent, completed_size);
+    }
+
+    /// Updates the bytes transferred by `id`, the progress is emitted when it's due.
+    pub fn progress(&self, id: &str, transferred_size: u64) {
+        let mut events = Vec::with_capacity(2);
+
+        if let Ok(mut state) = self.state.lock() {
+            let mut pending = vec![(id.to_string(), transferred_size)];
+            if let Some((parent, completed_size)) = state.parents.get(id) {
+                pending.push((parent.clone(), completed_size + transferred_size));
+            }
+
+            let now = Instant::now();
+            for (id, transferred_size) in pending {
+                let Some(progress) = state.progresses.get_mut(&id) else {
+                    continue;
+                };
+
+                if let Some((rate, eta_secs)) = progress.update(transferred_size, now) {
+                    events.push(TransferEvent::Progress {
+                        id,
+                        transferred_size,
+                        total_size: progress.total_size(),
+                        rate,
+                        eta_secs,
+                    });
+                }
+            }
+        }
+
+        for event in events {
+            self.send(event);
+        }
+    }
+
+    /// Emits a state of the transfer `id` which isn't running, e.g. it's queued.
+    pub fn state(&self, id: &str, state: TransferState) {
+        self.send(TransferEvent::State {
+            id: id.to_string(),
+            state,
+        });
+    }
+
+    /// Stops counting the progress of the transfer `id` and emits how it ended. A
+    /// paused transfer is only paused, other reasons are reported as errors.
+    pub fn finish(&self, id: &str, failure: Option<FileTransferErrorReason>) {
+        let progress = self.state.lock().ok().and_then(|mut state| {
+            state.parents.remove(id);
+            state.progresses.remove(id)
+        });
+
+        match failure {
+            None => {
+                if let Some(progress) = progress {
+                    self.send(TransferEvent::Progress {
+                        id: id.to_string(),
+                        transferred_size: progress.total_size(),
+                        total_size: progress.total_size(),
+                        rate: 0,
+                        eta_secs: Some(0),
+                    });
+                }
+                self.state(id, TransferState::Done);
+            }
+            Some(FileTransferErrorReason::Paused) => self.state(id, TransferState::Paused),
+            Some(reason) => {
+                self.send(TransferEvent::Error {
+                    id: id.to_string(),
+                    reason,
+                });
+
+                // cancelled transfers leave the queue
+                if reason != FileTransferErrorReason::Cancelled {
+                    self.state(id, TransferState::Failed);
+                }
+            }
+        }
+    }
+
+    fn send(&self, event: TransferEvent) {
+        // no one listens to the transfers served for the remote side
+        let _ = self.tx.send(event);
+    }
+}
+
+impl Default for TransferEvents {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,198 @@
+use crate::{core_error, error::CoreResult};
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use serde::{Deserialize, Serialize};
+use std::path::{Component, Path, PathBuf};
+use walkdir::WalkDir;
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone, Copy, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum SymlinkPolicy {
+    /// Symbolic links are left out of the transfer.
+    #[default]
+    Skip,
+    /// Symbolic links are transferred as the files and directories they point to.
+    Follow,
+}
+
+/// Selects the entries of a directory transfer. Globs match paths relative to the
+/// transferred directory, `exclude` wins over `include` and an excluded directory
+/// is skipped with its content. An empty `include` selects every file.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone, Default)]
+#[serde(default)]
+pub struct DirectoryTransferOptions {
+    pub symlinks: SymlinkPolicy,
+    pub include: Vec<String>,
+    pub exclude: Vec<String>,
+}
+
+/// An entry of a directory tree, `path` is relative to its root and always separated
+/// by `/` so both sides agree on it whatever their platforms.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
+pub struct TreeEntry {
+    pub path: String,
+    pub is_dir: bool,
+    pub size: u64,
+}
+
+/// An entry left out of a directory transfer and why.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
+pub struct TreeEntryError {
+    pub path: String,
+    pub message: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone, Default)]
+pub struct Tree {
+    /// Directories come before their content.
+    pub entries: Vec<TreeEntry>,
+    pub errors: Vec<TreeEntryError>,
+}
+
+impl Tree {
+    pub fn total_size(&self) -> u64 {
+        self.entries.iter().map(|entry| entry.size).sum()
+    }
+
+    pub fn directories(&self) -> impl Iterator<Item = &TreeEntry> {
+        self.entries.iter().filter(|entry| entry.is_dir)
+    }
+
+    pub fn files(&self) -> impl Iterator<Item = &TreeEntry> {
+        self.entries.iter().filter(|entry| !entry.is_dir)
+    }
+}
+
+/// Walks the directory `root` and returns the entries selected by `options`.
+/// Entries which can't be read are reported in [`Tree::errors`] instead of failing
+/// the whole walk.
+pub fn walk_tree(root: &Path, options: &DirectoryTransferOptions) -> CoreResult<Tree> {
+    if !root.is_dir() {
+        return Err(core_error!("path is not a directory"));
+    }
+
+    let include = build_glob_set(&options.include)?;
+    let exclude = build_glob_set(&options.exclude)?;
+    let follow_links = options.symlinks == SymlinkPolicy::Follow;
+
+    let mut tree = Tree::default();
+
+    let walker = WalkDir::new(root)
+        .min_depth(1)
+        .follow_links(follow_links)
+        .sort_by_file_name()
+        .into_iter()
+        .filter_entry(|entry| match relative_path(root, entry.path()) {
+            Some(path) => !exclude.is_match(path),
+            None => true,
+        });
+
+    for entry in walker {
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(err) => {
+                let path = err
+                    .path()
+                    .and_then(|path| relative_path(root, path))
+                    .unwrap_or_default();
+
+                tree.errors.push(TreeEntryError {
+                    path,
+                    message: err.to_string(),
+                });
+                continue;
+            }
+        };
+
+        // links are only reported as such when they aren't followed
+        if entry.path_is_symlink() && !follow_links {
+            continue;
+        }
+
+        let Some(path) = relative_path(root, entry.path()) else {
+            continue;
+        };
+
+        if entry.file_type().is_dir() {
+            tree.entries.push(TreeEntry {
+                path,
+                is_dir: true,
+                size: 0,
+            });
+            continue;
+        }
+
+        if !entry.file_type().is_file() || (!include.is_empty() && !include.is_match(&path)) {
+            continue;
+        }
+
+        match entry.metadata() {
+            Ok(meta) => tree.entries.push(TreeEntry {
+                path,
+                is_dir: false,
+                size: meta.len(),
+            }),
+            Err(err) => tree.errors.push(TreeEntryError {
+                path,
+                message: err.to_string(),
+            }),
+        }
+    }
+
+    // only recreate the directories leading to included files
+    if !include.is_empty() {
+        let files: Vec<String> = tree.files().map(|entry| entry.path.clone()).collect();
+
+        tree.entries.retain(|entry| {
+            !entry.is_dir
+                || files
+                    .iter()
+                    .any(|file| file.starts_with(&format!("{}/", entry.path)))
+        });
+    }
+
+    Ok(tree)
+}
+
+/// Joins the relative `path` of a [`TreeEntry`] to `root`, rejecting paths which
+/// would escape it since they come from the remote side.
+pub fn join_tree_path(root: &Path, path: &str) -> CoreResult<PathBuf> {
+    let mut joined = root.to_path_buf();
+
+    for segment in path.split('/') {
+        let mut components = Path::new(segment).components();
+
+        match (components.next(), components.next()) {
+            (Some(Component::Normal(name)), None) => joined.push(name),
+            _ => return Err(core_error!("invalid entry path ({})", path)),
+        }
+    }
+
+    Ok(joined)
+}
+
+fn relative_path(root: &Path, path: &Path) -> Option<String> {
+    let relative = path.strip_prefix(root).ok()?;
+    let segments: Vec<String> = relative
+        .components()
+        .map(|component| component.as_os_str().to_string_lossy().into_owned())
+        .collect();
+
+    if segments.is_empty() {
+        None
+    } else {
+        Some(segments.join("/"))
+    }
+}
+
+fn build_glob_set(patterns: &[String]) -> CoreResult<GlobSet> {
+    let mut builder = GlobSetBuilder::new();
+    for pattern in patterns {
+        let glob =
+            Glob::new(pattern).map_err(|err| core_error!("invalid glob ({}): {}", pattern, err))?;
+        builder.add(glob);
+    }
+
+    builder
+        .build()
+        .map_err(|err| core_error!("build glob set failed ({})", err))
+}
@@ -1,60 +1,300 @@
-use crate::error::CoreResult;
+use super::{
+    identity::{fingerprint, verify, NodeIdentity},
+    mdns::{remove_signed_nodes, LocalService, MdnsService, MDNS_NODE_TTL},
+    wake::read_arp_table,
+};
+use crate::{
+    api::{
+        config::{
+            entity::{
+                history::SessionType,
+                lan_node::{LanPeer, NodeTrust},
+            },
+            settings::{current_settings, subscribe_settings},
+            LocalStorage,
+        },
+        endpoint::{client::passive_session_count, message::VideoCodec},
+    },
+    core_error,
+    error::CoreResult,
+    utility::{lan_ip::LanInterface, version::client_version},
+};
 use hostname;
 use moka::future::Cache;
 use serde::{Deserialize, Serialize};
 use std::{
+    collections::HashMap,
     ffi::OsStr,
     net::{IpAddr, Ipv4Addr},
     sync::{atomic::AtomicBool, Arc},
     time::Duration,
 };
 
+/// Signed announcements older or newer than this are dropped to limit replays.
+const ANNOUNCEMENT_MAX_SKEW_SECS: i64 = 300;
+
+/// Version of the LAN discovery and connection protocol, raised on incompatible changes.
+pub const LAN_PROTOCOL_VERSION: u32 = 1;
+
+/// Port versions announcing without a port accept LAN connections on, it was fixed
+/// before it became configurable.
+pub const LEGACY_LAN_SERVER_PORT: u16 = 48001;
+
+/// Largest UDP payload over IPv4, the broadcast receive buffer holds any datagram.
+pub(crate) const MAX_DATAGRAM_SIZE: usize = 65_507;
+
+/// Longest host name in bytes an announcement carries, the limit of DNS names.
+pub(crate) const MAX_HOST_NAME_LENGTH: usize = 255;
+
+/// Most MAC addresses an announcement carries, one per interface is plenty to wake a
+/// node.
+pub(crate) const MAX_MAC_ADDRESSES: usize = 16;
+
 #[derive(Debug, Clone, Serialize)]
 pub struct Node {
     pub host_name: String,
     pub addr: IpAddr,
+    /// Port the node accepts LAN connections on.
+    pub port: u16,
     pub os: String,
     pub os_version: String,
+    /// Stable id of the installation, `None` for nodes of versions without identities.
+    pub node_id: Option<String>,
+    /// Fingerprint of the key the node signed its announcement with.
+    pub fingerprint: Option<String>,
+    pub trust: NodeTrust,
+    pub capabilities: NodeCapabilities,
+}
+
+/// Features and state a node announces, fields missing in announcements of older
+/// versions take their defaults.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct NodeCapabilities {
+    /// [`LAN_PROTOCOL_VERSION`] of the node, `0` for versions which don't announce it.
+    pub protocol_version: u32,
+    pub client_version: String,
+    pub session_types: Vec<SessionType>,
+    pub video_codecs: Vec<VideoCodec>,
+    pub password_required: bool,
+    /// Device id in the primary domain of the node, if it's registered to one.
+    pub device_id: Option<i64>,
+    /// Sessions the node currently serves, it's busy when nonzero.
+    pub session_count: u32,
+    /// Hardware addresses of the node interfaces, used to wake it.
+    pub mac_addresses: Vec<String>,
+}
+
+impl NodeCapabilities {
+    /// Returns the capabilities of this device with the MAC addresses of `interfaces`.
+    pub fn local(storage: Option<&LocalStorage>, interfaces: &[LanInterface]) -> Self {
+        let device_id = storage
+            .and_then(|storage| storage.domain().get_primary_domain().ok())
+            .map(|domain| domain.device_id)
+            .filter(|device_id| *device_id != 0);
+
+        Self {
+            protocol_version: LAN_PROTOCOL_VERSION,
+            client_version: client_version().to_string(),
+            session_types: vec![SessionType::Desktop, SessionType::FileManager],
+            // the passive side always encodes H264 whatever the active side offers
+            video_codecs: vec![VideoCodec::H264],
+            // LAN sessions aren't authenticated by a device password
+            password_required: false,
+            device_id,
+            session_count: passive_session_count(),
+            mac_addresses: local_mac_addresses(interfaces),
+        }
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub enum BroadcastPacket {
     TargetLive(TargetLivePacket),
     TargetDead,
+    Signed(SignedAnnouncement),
 }
 
-#[derive(Debug, Default, Serialize, Deserialize)]
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
 pub struct TargetLivePacket {
     host_name: String,
     os: String,
     os_version: String,
 }
 
+impl TargetLivePacket {
+    /// Host names longer than [`MAX_HOST_NAME_LENGTH`] are cut at a char boundary.
+    pub(crate) fn new(mut host_name: String, os: String, os_version: String) -> Self {
+        if host_name.len() > MAX_HOST_NAME_LENGTH {
+            let mut end = MAX_HOST_NAME_LENGTH;
+            while !host_name.is_char_boundary(end) {
+                end -= 1;
+            }
+            host_name.truncate(end);
+        }
+
+        Self {
+            host_name,
+            os,
+            os_version,
+        }
+    }
+}
+
+/// JSON encoded [`Announcement`] signed with the node key.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SignedAnnouncement {
+    payload: Vec<u8>,
+    public_key: Vec<u8>,
+    signature: Vec<u8>,
+}
+
+/// Fields added after the first signed version have defaults so announcements of
+/// older versions still parse.
+#[derive(Debug, Serialize, Deserialize)]
+struct Announcement {
+    node_id: String,
+    timestamp: i64,
+    /// `None` when the node leaves.
+    live: Option<TargetLivePacket>,
+    #[serde(default)]
+    port: Option<u16>,
+    #[serde(default)]
+    capabilities: NodeCapabilities,
+    /// Addresses the node announces from, a copy replayed from any other address is
+    /// dropped. Announcements of versions which don't sign them are dropped too, their
+    /// unsigned packets still list them.
+    #[serde(default)]
+    addrs: Vec<IpAddr>,
+}
+
+/// What a live node announces besides [`TargetLivePacket`].
+pub(crate) struct LiveAnnouncement {
+    pub packet: TargetLivePacket,
+    pub port: u16,
+    pub capabilities: NodeCapabilities,
+}
+
+impl SignedAnnouncement {
+    fn new(
+        identity: &NodeIdentity,
+        addrs: &[IpAddr],
+        live: Option<LiveAnnouncement>,
+    ) -> CoreResult<Self> {
+        let (live, port, capabilities) = match live {
+            Some(live) => (Some(live.packet), Some(live.port), live.capabilities),
+            None => (None, None, NodeCapabilities::default()),
+        };
+
+        let payload = serde_json::to_vec(&Announcement {
+            node_id: identity.node_id().to_string(),
+            timestamp: chrono::Utc::now().timestamp(),
+            live,
+            port,
+            capabilities,
+            addrs: addrs.to_vec(),
+        })?;
+
+        Ok(Self {
+            signature: identity.sign(&payload),
+            public_key: identity.public_key().to_vec(),
+            payload,
+        })
+    }
+
+    /// Returns the announcement when the signature and timestamp are valid.
+    fn open(&self) -> Option<Announcement> {
+        if !verify(&self.public_key, &self.payload, &self.signature) {
+            return None;
+        }
+
+        let announcement: Announcement = serde_json::from_slice(&self.payload).ok()?;
+        let skew = (chrono::Utc::now().timestamp() - announcement.timestamp).abs();
+        if skew > ANNOUNCEMENT_MAX_SKEW_SECS {
+            return None;
+        }
+
+        Some(announcement)
+    }
+}
+
 pub struct Discover {
+    interfaces: Vec<LanInterface>,
     cache: Cache<IpAddr, Node>,
+    mdns_cache: Cache<String, Vec<Node>>,
+    mdns: Option<Arc<MdnsService>>,
+    storage: Option<LocalStorage>,
     discoverable: Arc<AtomicBool>,
     write_exit_tx: Option<tokio::sync::oneshot::Sender<()>>,
     read_exit_tx: Option<tokio::sync::oneshot::Sender<()>>,
 }
 
 impl Discover {
-    pub async fn new(local_lan_ip: IpAddr) -> CoreResult<Self> {
-        // why udp not polled when udp listen on specified ip on macOS?
-        let listen_ip = if cfg!(target_os = "macos") {
-            IpAddr::V4(Ipv4Addr::UNSPECIFIED)
-        } else {
-            local_lan_ip
-        };
+    /// Announces this device on `interfaces` and discovers other devices reachable from
+    /// them. Announcements are signed by `identity`, keys pinned in `storage` decide
+    /// the trust of discovered nodes.
+    pub async fn new(
+        interfaces: &[LanInterface],
+        identity: Arc<NodeIdentity>,
+        storage: Option<LocalStorage>,
+    ) -> CoreResult<Self> {
+        let settings = current_settings();
+        let port = settings.lan_discover_port;
+
+        // one socket serves every interface, broadcasts are sent to each one explicitly
+        let local_ips: Vec<IpAddr> = interfaces.iter().map(|interface| interface.ip).collect();
+        let mut broadcast_ips: Vec<Ipv4Addr> = interfaces
+            .iter()
+            .filter_map(|interface| interface.broadcast)
+            .collect();
+        broadcast_ips.sort();
+        broadcast_ips.dedup();
+        if broadcast_ips.is_empty() {
+            broadcast_ips.push(Ipv4Addr::BROADCAST);
+        }
+
+        // a selected interface is the only one discovery may listen on
+        let selected_interface = settings
+            .lan_interface
+            .as_ref()
+            .and_then(|_| interfaces.iter().find(|interface| interface.ip.is_ipv4()));
 
-        let stream = tokio::net::UdpSocket::bind((listen_ip, 48000)).await?;
+        let stream = bind_discover_socket(selected_interface, port).await?;
         stream.set_broadcast(true)?;
 
         tracing::info!("lan discover listen on {}", stream.local_addr()?);
 
         let live_packet = gen_target_live_packet()?;
-        let local_host_name = live_packet.host_name.clone();
+
+        // mDNS is preferred, the legacy broadcast keeps working when it's unavailable
+        let mdns_cache = Cache::builder().time_to_live(MDNS_NODE_TTL).build();
+        let mut capabilities = NodeCapabilities::local(storage.as_ref(), interfaces);
+        let mdns = match MdnsService::new(
+            LocalService {
+                identity: identity.clone(),
+                host_name: live_packet.host_name.clone(),
+                os: live_packet.os.clone(),
+                os_version: live_packet.os_version.clone(),
+                addrs: local_ips.clone(),
+                server_port: settings.lan_server_port,
+            },
+            &capabilities,
+            mdns_cache.clone(),
+        ) {
+            Ok(mdns) => {
+                mdns.set_advertised(settings.lan_discoverable);
+                Some(mdns)
+            }
+            Err(err) => {
+                tracing::warn!(?err, "lan discover mdns unavailable");
+                None
+            }
+        };
+
         let dead_packet = bincode::serialize(&BroadcastPacket::TargetDead)?;
-        let live_packet = bincode::serialize(&BroadcastPacket::TargetLive(live_packet))?;
+        let signed_dead_packet = signed_packet(&identity, &local_ips, None)?;
+        let unsigned_live_packet =
+            bincode::serialize(&BroadcastPacket::TargetLive(live_packet.clone()))?;
 
         let cache = Cache::builder()
             .time_to_live(Duration::from_secs(17))
@@ -65,26 +305,36 @@ impl Discover {
 
         let (write_exit_tx, mut write_exit_rx) = tokio::sync::oneshot::channel();
         let (read_exit_tx, mut read_exit_rx) = tokio::sync::oneshot::channel();
-        let discoverable = Arc::new(AtomicBool::new(true));
+        let discoverable = Arc::new(AtomicBool::new(settings.lan_discoverable));
+        let legacy_broadcast = Arc::new(AtomicBool::new(settings.lan_legacy_broadcast));
         let cache_copy = cache.clone();
+        let mdns_cache_copy = mdns_cache.clone();
+        let local_node_id = identity.node_id().to_string();
+        let source_ips = local_ips.clone();
 
         tokio::spawn(async move {
-            let mut buffer = [0u8; 256];
+            let mut buffer = vec![0u8; MAX_DATAGRAM_SIZE];
 
             loop {
-                let Err(tokio::sync::oneshot::error::TryRecvError::Empty) = read_exit_rx.try_recv() else {
-                    tracing::info!("lan discover broadcast recv loop exit");
-                    return;
-                };
-
-                let (buffer_len, target_addr) = match reader.recv_from(&mut buffer).await {
-                    Ok(v) => v,
-                    Err(err) => {
-                        tracing::error!(?err, "lan discover broadcast packet recv failed");
-                        continue;
+                // exit without waiting for the next packet so the port is released at once
+                let (buffer_len, target_addr) = tokio::select! {
+                    _ = &mut read_exit_rx => {
+                        tracing::info!("lan discover broadcast recv loop exit");
+                        return;
+                    }
+                    res = reader.recv_from(&mut buffer) => match res {
+                        Ok(v) => v,
+                        Err(err) => {
+                            tracing::error!(?err, "lan discover broadcast packet recv failed");
+                            continue;
+                        }
                     }
                 };
 
+                if local_ips.contains(&target_addr.ip()) {
+                    continue;
+                }
+
                 let packet = match bincode::deserialize::<BroadcastPacket>(&buffer[..buffer_len]) {
                     Ok(v) => v,
                     Err(err) => {
@@ -97,71 +347,156 @@ impl Discover {
                     }
                 };
 
-                match packet {
-                    BroadcastPacket::TargetLive(live_packet) => {
-                        if local_host_name == live_packet.host_name {
-                            continue;
-                        }
-
-                        tracing::info!(?target_addr, "lan discover target live");
-
-                        cache_copy
-                            .insert(
-                                target_addr.ip(),
-                                Node {
-                                    host_name: live_packet.host_name.to_string(),
-                                    addr: target_addr.ip(),
-                                    os: live_packet.os.to_string(),
-                                    os_version: live_packet.os_version.to_string(),
-                                },
-                            )
-                            .await;
-                    }
-                    BroadcastPacket::TargetDead => {
-                        tracing::info!(?target_addr, "lan discover target dead");
-                        cache_copy.invalidate(&target_addr.ip()).await;
-                    }
-                }
+                handle_broadcast_packet(
+                    &cache_copy,
+                    &mdns_cache_copy,
+                    target_addr.ip(),
+                    packet,
+                    &local_node_id,
+                )
+                .await;
             }
         });
 
         let discoverable_copy = discoverable.clone();
+        let interfaces_copy = interfaces.to_vec();
+        let cache_copy = cache.clone();
+        let mdns_cache_copy = mdns_cache.clone();
+        let mdns_copy = mdns.clone();
+        let storage_copy = storage.clone();
+        let server_port = settings.lan_server_port;
+        let mut settings_rx = subscribe_settings();
         tokio::spawn(async move {
             let mut ticker = tokio::time::interval(Duration::from_secs(11));
+            let mut learned_peers = HashMap::new();
 
             loop {
                 tokio::select! {
                     _ = ticker.tick() => (),
+                    Ok(_) = settings_rx.changed() => {
+                        let (discoverable, legacy) = {
+                            let settings = settings_rx.borrow();
+                            (settings.lan_discoverable, settings.lan_legacy_broadcast)
+                        };
+
+                        discoverable_copy.store(discoverable, std::sync::atomic::Ordering::SeqCst);
+                        legacy_broadcast.store(legacy, std::sync::atomic::Ordering::SeqCst);
+                        if let Some(ref mdns) = mdns_copy {
+                            mdns.set_advertised(discoverable);
+                        }
+                        continue;
+                    }
                     _ = &mut write_exit_rx => {
-                        let _ = writer.send(&dead_packet).await;
+                        if legacy_broadcast.load(std::sync::atomic::Ordering::SeqCst) {
+                            for broadcast_ip in &broadcast_ips {
+                                let _ = writer.send_to(&dead_packet, (*broadcast_ip, port)).await;
+                                let _ = writer.send_to(&signed_dead_packet, (*broadcast_ip, port)).await;
+                            }
+                        }
                         tracing::info!("lan discover broadcast loop exit");
                         return;
                     }
                 };
 
-                if !discoverable_copy.load(std::sync::atomic::Ordering::SeqCst) {
-                    continue;
+                if let Some(ref storage) = storage_copy {
+                    let nodes = merge_nodes(&mdns_cache_copy, &cache_copy);
+                    learn_peers(storage, &nodes, &mut learned_peers).await;
                 }
 
-                if let Err(err) = writer
-                    .send_to(&live_packet, (Ipv4Addr::BROADCAST, 48000))
-                    .await
+                let current_capabilities =
+                    NodeCapabilities::local(storage_copy.as_ref(), &interfaces_copy);
+                if current_capabilities != capabilities {
+                    capabilities = current_capabilities;
+                    if let Some(ref mdns) = mdns_copy {
+                        mdns.set_capabilities(&capabilities);
+                    }
+                }
+
+                if !discoverable_copy.load(std::sync::atomic::Ordering::SeqCst)
+                    || !legacy_broadcast.load(std::sync::atomic::Ordering::SeqCst)
                 {
-                    tracing::warn!(?err, "lan discover broadcast failed");
+                    continue;
+                }
+
+                // signed again on every tick as announcements carry a timestamp
+                let live = LiveAnnouncement {
+                    packet: live_packet.clone(),
+                    port: server_port,
+                    capabilities: capabilities.clone(),
+                };
+
+                let signed_live_packet = match signed_packet(&identity, &source_ips, Some(live)) {
+                    Ok(packet) => packet,
+                    Err(err) => {
+                        tracing::error!(?err, "sign lan discover announcement failed");
+                        continue;
+                    }
+                };
+
+                // versions without identities can't read signed packets and still
+                // rely on the unsigned one
+                for broadcast_ip in &broadcast_ips {
+                    for packet in [&signed_live_packet, &unsigned_live_packet] {
+                        if let Err(err) = writer.send_to(packet, (*broadcast_ip, port)).await {
+                            tracing::warn!(?err, ?broadcast_ip, "lan discover broadcast failed");
+                        }
+                    }
                 }
             }
         });
 
         Ok(Self {
+            interfaces: interfaces.to_vec(),
             cache,
+            mdns_cache,
+            mdns,
+            storage,
             discoverable,
             write_exit_tx: Some(write_exit_tx),
             read_exit_tx: Some(read_exit_tx),
         })
     }
 
+    /// Returns nodes found over mDNS followed by nodes only seen by the legacy broadcast,
+    /// see [`merge_nodes`].
     pub fn nodes_snapshot(&self) -> Vec<Node> {
-        self.cache.iter().map(|(_, node)| node).collect()
+        let mut nodes = merge_nodes(&self.mdns_cache, &self.cache);
+
+        for node in nodes.iter_mut() {
+            node.trust = self.trust(node);
+        }
+
+        nodes
+    }
+
+    /// Returns the node announced from `addr`, if any.
+    pub fn node(&self, addr: IpAddr) -> Option<Node> {
+        self.nodes_snapshot()
+            .into_iter()
+            .find(|node| node.addr == addr)
+    }
+
+    /// Interfaces this device announces itself on.
+    pub fn interfaces(&self) -> &[LanInterface] {
+        &self.interfaces
+    }
+
+    fn trust(&self, node: &Node) -> NodeTrust {
+        let (Some(ref node_id), Some(ref fingerprint)) = (&node.node_id, &node.fingerprint) else {
+            return NodeTrust::Unsigned;
+        };
+
+        let Some(ref storage) = self.storage else {
+            return NodeTrust::Unknown;
+        };
+
+        storage
+            .lan_node()
+            .trust(node_id, fingerprint)
+            .unwrap_or_else(|err| {
+                tracing::warn!(?err, ?node_id, "query lan node trust failed");
+                NodeTrust::Unknown
+            })
     }
 
     pub fn discoverable(&self) -> bool {
@@ -170,7 +505,11 @@ impl Discover {
 
     pub fn set_discoverable(&self, discoverable: bool) {
         self.discoverable
-            .store(discoverable, std::sync::atomic::Ordering::SeqCst)
+            .store(discoverable, std::sync::atomic::Ordering::SeqCst);
+
+        if let Some(ref mdns) = self.mdns {
+            mdns.set_advertised(discoverable);
+        }
     }
 }
 
@@ -186,6 +525,231 @@ impl Drop for Discover {
     }
 }
 
+/// Lists one node per address, mDNS nodes first. A signed node replaces an unsigned one
+/// with the same address whichever way they were found, so an unsigned announcement
+/// can't shadow a verified one.
+pub(crate) fn merge_nodes(
+    mdns_cache: &Cache<String, Vec<Node>>,
+    cache: &Cache<IpAddr, Node>,
+) -> Vec<Node> {
+    let mut nodes: Vec<Node> = Vec::new();
+
+    let candidates = mdns_cache
+        .iter()
+        .flat_map(|(_, nodes)| nodes)
+        .chain(cache.iter().map(|(_, node)| node));
+
+    for node in candidates {
+        match nodes.iter_mut().find(|v| v.addr == node.addr) {
+            None => nodes.push(node),
+            Some(existing) if existing.fingerprint.is_none() && node.fingerprint.is_some() => {
+                *existing = node;
+            }
+            Some(_) => {}
+        }
+    }
+
+    nodes
+}
+
+fn local_mac_addresses(interfaces: &[LanInterface]) -> Vec<String> {
+    let mut mac_addresses: Vec<String> = interfaces
+        .iter()
+        .filter_map(|interface| interface.mac.clone())
+        .collect();
+    mac_addresses.sort();
+    mac_addresses.dedup();
+    mac_addresses.truncate(MAX_MAC_ADDRESSES);
+    mac_addresses
+}
+
+/// Remembers the MAC addresses of discovered nodes so they can still be woken once they
+/// stop announcing. Nodes which don't advertise them are looked up in the ARP table,
+/// `learned` keeps what was saved so unchanged peers aren't written on every tick.
+async fn learn_peers(
+    storage: &LocalStorage,
+    nodes: &[Node],
+    learned: &mut HashMap<IpAddr, Vec<String>>,
+) {
+    let mut arp_table = None;
+
+    for node in nodes {
+        let mut mac_addresses = node.capabilities.mac_addresses.clone();
+
+        if mac_addresses.is_empty() {
+            if learned.contains_key(&node.addr) {
+                continue;
+            }
+
+            // read once per tick and only when a node needs it
+            if arp_table.is_none() {
+                arp_table = Some(read_arp_table().await);
+            }
+
+            if let Some(mac) = arp_table.as_ref().and_then(|table| table.get(&node.addr)) {
+                mac_addresses.push(mac.clone());
+            }
+        }
+
+        if mac_addresses.is_empty() || learned.get(&node.addr) == Some(&mac_addresses) {
+            continue;
+        }
+
+        let peer = LanPeer {
+            addr: node.addr.to_string(),
+            mac_addresses: mac_addresses.clone(),
+            host_name: node.host_name.clone(),
+            node_id: node.node_id.clone(),
+            updated_at: chrono::Utc::now().timestamp(),
+        };
+
+        match storage.lan_node().save_peer(&peer) {
+            Ok(_) => {
+                learned.insert(node.addr, mac_addresses);
+            }
+            Err(err) => tracing::warn!(?err, addr = ?node.addr, "save lan peer failed"),
+        }
+    }
+}
+
+/// Signs an announcement of `identity` sent from `addrs`, the interface addresses.
+pub(crate) fn signed_packet(
+    identity: &NodeIdentity,
+    addrs: &[IpAddr],
+    live: Option<LiveAnnouncement>,
+) -> CoreResult<Vec<u8>> {
+    let announcement = SignedAnnouncement::new(identity, addrs, live)?;
+    let packet = bincode::serialize(&BroadcastPacket::Signed(announcement))?;
+    if packet.len() > MAX_DATAGRAM_SIZE {
+        return Err(core_error!(
+            "lan discover announcement exceeds a datagram ({} bytes)",
+            packet.len()
+        ));
+    }
+
+    Ok(packet)
+}
+
+/// Updates `cache` from a broadcast packet. Entries made from signed announcements can
+/// only be replaced or removed by packets signed with the same key, so an unsigned or
+/// forged packet from a spoofed address can't take them over. Signed announcements are
+/// only accepted from the addresses they list, so one can't be replayed from another
+/// host. A signed leave also removes the node from `mdns_cache`, where goodbye packets
+/// can't.
+pub(crate) async fn handle_broadcast_packet(
+    cache: &Cache<IpAddr, Node>,
+    mdns_cache: &Cache<String, Vec<Node>>,
+    target_ip: IpAddr,
+    packet: BroadcastPacket,
+    local_node_id: &str,
+) {
+    let cached_fingerprint = cache.get(&target_ip).and_then(|node| node.fingerprint);
+
+    match packet {
+        BroadcastPacket::TargetLive(live_packet) => {
+            if cached_fingerprint.is_some() {
+                return;
+            }
+
+            tracing::info!(?target_ip, "lan discover target live");
+
+            cache
+                .insert(
+                    target_ip,
+                    Node {
+                        host_name: live_packet.host_name,
+                        addr: target_ip,
+                        // legacy packets don't carry the port
+                        port: LEGACY_LAN_SERVER_PORT,
+                        os: live_packet.os,
+                        os_version: live_packet.os_version,
+                        node_id: None,
+                        fingerprint: None,
+                        trust: NodeTrust::Unsigned,
+                        capabilities: NodeCapabilities::default(),
+                    },
+                )
+                .await;
+        }
+        BroadcastPacket::TargetDead => {
+            if cached_fingerprint.is_some() {
+                return;
+            }
+
+            tracing::info!(?target_ip, "lan discover target dead");
+            cache.invalidate(&target_ip).await;
+        }
+        BroadcastPacket::Signed(signed) => {
+            let Some(announcement) = signed.open() else {
+                tracing::warn!(
+                    ?target_ip,
+                    "lan discover announcement has invalid signature"
+                );
+                return;
+            };
+
+            if announcement.node_id == local_node_id {
+                return;
+            }
+
+            if !announcement.addrs.contains(&target_ip) {
+                tracing::warn!(
+                    ?target_ip,
+                    node_id = announcement.node_id,
+                    "lan discover announcement sent from an address it doesn't list"
+                );
+                return;
+            }
+
+            let fingerprint = fingerprint(&signed.public_key);
+            if cached_fingerprint.map_or(false, |cached| cached != fingerprint) {
+                tracing::warn!(
+                    ?target_ip,
+                    node_id = announcement.node_id,
+                    "lan discover announcement signed by another key than the cached one"
+                );
+                return;
+            }
+
+            match announcement.live {
+                Some(live_packet) => {
+                    tracing::info!(
+                        ?target_ip,
+                        node_id = announcement.node_id,
+                        "lan discover target live"
+                    );
+
+                    cache
+                        .insert(
+                            target_ip,
+                            Node {
+                                host_name: live_packet.host_name,
+                                addr: target_ip,
+                                port: announcement.port.unwrap_or(LEGACY_LAN_SERVER_PORT),
+                                os: live_packet.os,
+                                os_version: live_packet.os_version,
+                                node_id: Some(announcement.node_id),
+                                fingerprint: Some(fingerprint),
+                                trust: NodeTrust::Unknown,
+                                capabilities: announcement.capabilities,
+                            },
+                        )
+                        .await;
+                }
+                None => {
+                    tracing::info!(
+                        ?target_ip,
+                        node_id = announcement.node_id,
+                        "lan discover target dead"
+                    );
+                    cache.invalidate(&target_ip).await;
+                    remove_signed_nodes(mdns_cache, &announcement.node_id, &fingerprint).await;
+                }
+            }
+        }
+    }
+}
+
 fn gen_target_live_packet() -> CoreResult<TargetLivePacket> {
     let host_name = convert_host_name_to_string(&hostname::get()?)?;
     let os_info = os_info::get();
@@ -235,17 +799,77 @@ fn gen_target_live_packet() -> CoreResult<TargetLivePacket> {
     }
     .to_string();
 
-    Ok(TargetLivePacket {
-        host_name,
-        os,
-        os_version,
-    })
+    Ok(TargetLivePacket::new(host_name, os, os_version))
+}
+
+/// Binds the discovery socket, restricted to `interface` when one is selected. Sockets
+/// bound to a unicast address don't receive broadcasts on Linux and macOS, there the
+/// socket is bound to the wildcard address and tied to the interface device instead.
+async fn bind_discover_socket(
+    interface: Option<&LanInterface>,
+    port: u16,
+) -> CoreResult<tokio::net::UdpSocket> {
+    let Some(interface) = interface else {
+        return Ok(tokio::net::UdpSocket::bind((Ipv4Addr::UNSPECIFIED, port)).await?);
+    };
+
+    #[cfg(target_os = "windows")]
+    {
+        Ok(tokio::net::UdpSocket::bind((interface.ip, port)).await?)
+    }
+
+    #[cfg(not(target_os = "windows"))]
+    {
+        use std::os::unix::io::AsRawFd;
+
+        let socket = tokio::net::UdpSocket::bind((Ipv4Addr::UNSPECIFIED, port)).await?;
+
+        #[cfg(any(target_os = "linux", target_os = "android"))]
+        let ret = unsafe {
+            libc::setsockopt(
+                socket.as_raw_fd(),
+                libc::SOL_SOCKET,
+                libc::SO_BINDTODEVICE,
+                interface.name.as_ptr() as *const libc::c_void,
+                interface.name.len() as libc::socklen_t,
+            )
+        };
+
+        #[cfg(target_os = "macos")]
+        let ret = {
+            let name = std::ffi::CString::new(interface.name.as_str())
+                .map_err(|_| core_error!("invalid lan interface name"))?;
+            let index = unsafe { libc::if_nametoindex(name.as_ptr()) };
+            if index == 0 {
+                return Err(core_error!("lan interface '{}' not found", interface.name));
+            }
+
+            unsafe {
+                libc::setsockopt(
+                    socket.as_raw_fd(),
+                    libc::IPPROTO_IP,
+                    libc::IP_BOUND_IF,
+                    &index as *const u32 as *const libc::c_void,
+                    std::mem::size_of::<u32>() as libc::socklen_t,
+                )
+            }
+        };
+
+        if ret != 0 {
+            return Err(core_error!(
+                "bind lan discover socket to interface '{}' failed ({})",
+                interface.name,
+                std::io::Error::last_os_error()
+            ));
+        }
+
+        Ok(socket)
+    }
 }
 
 fn convert_host_name_to_string(v: &OsStr) -> CoreResult<String> {
     #[cfg(target_os = "windows")]
     {
-        use crate::core_error;
         use std::os::windows::ffi::OsStrExt;
 
         let result: Vec<u16> = v.encode_wide().collect();
@@ -0,0 +1,230 @@
+use super::{
+    discover::Node,
+    identity::{fingerprint, verify, NodeIdentity},
+};
+use crate::{
+    api::config::LocalStorage,
+    core_error,
+    error::{CoreError, CoreResult},
+    utility::proxy::connect_tcp,
+};
+use ring::rand::{SecureRandom, SystemRandom};
+use serde::{Deserialize, Serialize};
+use std::{
+    net::SocketAddr,
+    time::{Duration, Instant},
+};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpStream,
+};
+
+/// Sent before the challenge, streams of versions without node identities never start
+/// with it because it would be a frame length over 1 GiB.
+const MAGIC: &[u8; 4] = b"MXID";
+/// Sent instead of [`MAGIC`] by reachability probes, which close the stream after the
+/// handshake instead of starting a session.
+const PROBE_MAGIC: &[u8; 4] = b"MXPR";
+const CHALLENGE_LENGTH: usize = 32;
+const SIGNATURE_CONTEXT: &[u8] = b"mirrorx lan handshake";
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+/// How long the passive side waits for the first bytes before treating the peer as a
+/// version without node identities.
+const PEEK_TIMEOUT: Duration = Duration::from_secs(2);
+/// How long a probe waits for the identity of a reachable host.
+const PROBE_TIMEOUT: Duration = Duration::from_secs(3);
+
+#[derive(Debug, Serialize, Deserialize)]
+struct HandshakeResponse {
+    node_id: String,
+    public_key: Vec<u8>,
+    signature: Vec<u8>,
+}
+
+/// How the active side opened a LAN connection.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IncomingHandshake {
+    /// A version without node identities, its stream is left untouched.
+    Legacy,
+    /// The identity was proven to a peer which starts a session next.
+    Verified,
+    /// The identity was proven to a reachability probe, which closes the stream.
+    Probe,
+}
+
+/// Result of probing a LAN host which accepted the connection.
+#[derive(Debug, Clone, Serialize)]
+pub struct ProbeResult {
+    pub addr: SocketAddr,
+    /// Time the TCP connection took to be established.
+    pub latency_ms: u64,
+    /// `None` when the host runs a version without node identities.
+    pub node_id: Option<String>,
+    pub fingerprint: Option<String>,
+}
+
+/// Identity a LAN peer proved to hold during the handshake.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PeerIdentity {
+    pub node_id: String,
+    pub fingerprint: String,
+}
+
+/// Connects to a LAN node. Nodes which announced an identity have to prove they hold
+/// the announced key, which is pinned in `storage` on first use; a node presenting
+/// another key than the pinned one is refused.
+pub async fn connect_node(
+    addr: SocketAddr,
+    node: Option<&Node>,
+    storage: Option<&LocalStorage>,
+) -> CoreResult<TcpStream> {
+    let mut stream = connect_tcp(addr).await?;
+
+    let Some(node) = node else {
+        return Ok(stream);
+    };
+
+    let (Some(node_id), Some(announced_fingerprint)) = (&node.node_id, &node.fingerprint) else {
+        return Ok(stream);
+    };
+
+    let peer = request_identity(&mut stream).await?;
+    if peer.node_id != *node_id || peer.fingerprint != *announced_fingerprint {
+        return Err(core_error!(
+            "lan peer identity {} ({}) doesn't match the announced {} ({})",
+            peer.node_id,
+            peer.fingerprint,
+            node_id,
+            announced_fingerprint
+        ));
+    }
+
+    if let Some(storage) = storage {
+        storage
+            .lan_node()
+            .pin(&peer.node_id, &peer.fingerprint, &node.host_name)?;
+    }
+
+    Ok(stream)
+}
+
+/// Challenges the passive side of a freshly connected stream to sign a random nonce
+/// with its node key.
+pub async fn request_identity(stream: &mut TcpStream) -> CoreResult<PeerIdentity> {
+    challenge_identity(stream, MAGIC).await
+}
+
+/// Checks that a LAN node accepts connections on `addr` and asks for its identity,
+/// without starting a session.
+pub async fn probe_node(addr: SocketAddr) -> CoreResult<ProbeResult> {
+    let started = Instant::now();
+    let mut stream = connect_tcp(addr).await?;
+    let latency = started.elapsed();
+
+    // versions without node identities don't answer but are reachable all the same
+    let challenged =
+        tokio::time::timeout(PROBE_TIMEOUT, challenge_identity(&mut stream, PROBE_MAGIC)).await;
+
+    let peer = match challenged {
+        Ok(Ok(peer)) => Some(peer),
+        Ok(Err(err)) => {
+            tracing::info!(?err, ?addr, "lan probe got no identity");
+            None
+        }
+        Err(_) => None,
+    };
+
+    Ok(ProbeResult {
+        addr,
+        latency_ms: latency.as_millis() as u64,
+        node_id: peer.as_ref().map(|peer| peer.node_id.clone()),
+        fingerprint: peer.map(|peer| peer.fingerprint),
+    })
+}
+
+async fn challenge_identity(stream: &mut TcpStream, magic: &[u8; 4]) -> CoreResult<PeerIdentity> {
+    let mut challenge = [0u8; CHALLENGE_LENGTH];
+    SystemRandom::new().fill(&mut challenge)?;
+
+    tokio::time::timeout(HANDSHAKE_TIMEOUT, async {
+        stream.write_all(magic).await?;
+        stream.write_all(&challenge).await?;
+
+        let response_length = stream.read_u16().await?;
+        let mut response_buffer = vec![0u8; response_length as usize];
+        stream.read_exact(&mut response_buffer).await?;
+
+        let response: HandshakeResponse = bincode::deserialize(&response_buffer)?;
+
+        if !verify(
+            &response.public_key,
+            &signed_message(&challenge, &response.node_id),
+            &response.signature,
+        ) {
+            return Err(core_error!("lan peer failed to prove its identity"));
+        }
+
+        Ok(PeerIdentity {
+            fingerprint: fingerprint(&response.public_key),
+            node_id: response.node_id,
+        })
+    })
+    .await
+    .map_err(|_| CoreError::Timeout)?
+}
+
+/// Answers the identity challenge when the active side sends one.
+pub async fn serve_identity(
+    stream: &mut TcpStream,
+    identity: &NodeIdentity,
+) -> CoreResult<IncomingHandshake> {
+    let mut magic = [0u8; MAGIC.len()];
+
+    let peeked = tokio::time::timeout(PEEK_TIMEOUT, async {
+        loop {
+            let len = stream.peek(&mut magic).await?;
+            if len == 0
+                || len == magic.len()
+                || (magic[..len] != MAGIC[..len] && magic[..len] != PROBE_MAGIC[..len])
+            {
+                return CoreResult::Ok(len);
+            }
+
+            // only a part of the magic arrived, peek returns at once until more does
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    })
+    .await;
+
+    let handshake = match peeked {
+        Ok(Ok(len)) if len == MAGIC.len() && magic == *MAGIC => IncomingHandshake::Verified,
+        Ok(Ok(len)) if len == PROBE_MAGIC.len() && magic == *PROBE_MAGIC => {
+            IncomingHandshake::Probe
+        }
+        Ok(Err(err)) => return Err(err),
+        _ => return Ok(IncomingHandshake::Legacy),
+    };
+
+    tokio::time::timeout(HANDSHAKE_TIMEOUT, async {
+        let mut buffer = [0u8; MAGIC.len() + CHALLENGE_LENGTH];
+        stream.read_exact(&mut buffer).await?;
+
+        let challenge = &buffer[MAGIC.len()..];
+        let response = bincode::serialize(&HandshakeResponse {
+            node_id: identity.node_id().to_string(),
+            public_key: identity.public_key().to_vec(),
+            signature: identity.sign(&signed_message(challenge, identity.node_id())),
+        })?;
+
+        stream.write_u16(response.len() as u16).await?;
+        stream.write_all(&response).await?;
+
+        Ok(handshake)
+    })
+    .await
+    .map_err(|_| CoreError::Timeout)?
+}
+
+fn signed_message(challenge: &[u8], node_id: &str) -> Vec<u8> {
+    [SIGNATURE_CONTEXT, challenge, node_id.as_bytes()].concat()
+}
@@ -0,0 +1,67 @@
+use crate::{core_error, error::CoreResult};
+use ring::{
+    rand::SystemRandom,
+    signature::{Ed25519KeyPair, KeyPair, UnparsedPublicKey, ED25519},
+};
+use sha2::{Digest, Sha256};
+
+/// Persistent identity of this installation on the LAN, announcements and handshakes
+/// are signed with its Ed25519 key.
+pub struct NodeIdentity {
+    node_id: String,
+    key_pair: Ed25519KeyPair,
+}
+
+impl std::fmt::Debug for NodeIdentity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("NodeIdentity")
+            .field("node_id", &self.node_id)
+            .field("fingerprint", &self.fingerprint())
+            .finish()
+    }
+}
+
+impl NodeIdentity {
+    /// Generates a new identity and returns it with the PKCS#8 document of its key.
+    pub fn generate() -> CoreResult<(Self, Vec<u8>)> {
+        let pkcs8 = Ed25519KeyPair::generate_pkcs8(&SystemRandom::new())?;
+        let node_id = uuid::Uuid::new_v4().to_string();
+        let identity = Self::from_pkcs8(node_id, pkcs8.as_ref())?;
+        Ok((identity, pkcs8.as_ref().to_vec()))
+    }
+
+    pub fn from_pkcs8(node_id: String, pkcs8: &[u8]) -> CoreResult<Self> {
+        let key_pair = Ed25519KeyPair::from_pkcs8(pkcs8)
+            .map_err(|err| core_error!("parse lan identity key failed ({})", err))?;
+
+        Ok(Self { node_id, key_pair })
+    }
+
+    pub fn node_id(&self) -> &str {
+        &self.node_id
+    }
+
+    pub fn public_key(&self) -> &[u8] {
+        self.key_pair.public_key().as_ref()
+    }
+
+    pub fn fingerprint(&self) -> String {
+        fingerprint(self.public_key())
+    }
+
+    pub fn sign(&self, message: &[u8]) -> Vec<u8> {
+        self.key_pair.sign(message).as_ref().to_vec()
+    }
+}
+
+/// Returns whether `signature` of `message` was made by the owner of `public_key`.
+pub fn verify(public_key: &[u8], message: &[u8], signature: &[u8]) -> bool {
+    UnparsedPublicKey::new(&ED25519, public_key)
+        .verify(message, signature)
+        .is_ok()
+}
+
+/// Hex encoded SHA-256 digest of a public key, shown to users to compare identities.
+pub fn fingerprint(public_key: &[u8]) -> String {
+    hex::encode(Sha256::digest(public_key))
+}
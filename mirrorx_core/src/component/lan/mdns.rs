@@ -0,0 +1,400 @@
+use super::{
+    discover::{Node, NodeCapabilities},
+    identity::{fingerprint, verify, NodeIdentity},
+};
+use crate::{
+    api::config::entity::lan_node::NodeTrust, core_error, error::CoreResult,
+    utility::version::client_version,
+};
+use mdns_sd::{ServiceDaemon, ServiceEvent, ServiceInfo};
+use moka::future::Cache;
+use std::{
+    collections::HashMap,
+    net::IpAddr,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+
+/// DNS-SD service type advertised by every MirrorX client on the LAN.
+pub const SERVICE_TYPE: &str = "_mirrorx._tcp.local.";
+
+/// How long a resolved service stays listed, the TTL of mDNS service records. Goodbye
+/// packets aren't signed, so signed services are only dropped once this expires or a
+/// signed announcement says they left.
+pub const MDNS_NODE_TTL: Duration = Duration::from_secs(75 * 60);
+
+/// TXT key of the JSON encoded [`NodeCapabilities`].
+const CAPABILITIES_PROPERTY: &str = "info";
+
+/// TXT key of the comma separated addresses of the service. The A and AAAA records
+/// aren't signed, only the addresses listed here are accepted for a signed service.
+const ADDRESSES_PROPERTY: &str = "addrs";
+
+/// TXT keys of the signature and the key it's made with, every other TXT record is
+/// covered by the signature.
+const PUBLIC_KEY_PROPERTY: &str = "pk";
+const SIGNATURE_PROPERTY: &str = "sig";
+
+/// Local properties advertised as TXT records.
+pub struct LocalService {
+    /// Signs the TXT records, its node id names the service instance and ignores its own
+    /// announcements instead of comparing host names which may be shared by several hosts.
+    pub identity: Arc<NodeIdentity>,
+    pub host_name: String,
+    pub os: String,
+    pub os_version: String,
+    pub addrs: Vec<IpAddr>,
+    pub server_port: u16,
+}
+
+impl LocalService {
+    pub(crate) fn service_info(&self, capabilities: &NodeCapabilities) -> CoreResult<ServiceInfo> {
+        let mut properties: HashMap<String, String> = [
+            ("id", self.identity.node_id().to_string()),
+            ("host_name", self.host_name.clone()),
+            ("os", self.os.clone()),
+            ("os_version", self.os_version.clone()),
+            ("version", client_version().to_string()),
+            ("port", self.server_port.to_string()),
+            (CAPABILITIES_PROPERTY, serde_json::to_string(capabilities)?),
+            (
+                ADDRESSES_PROPERTY,
+                self.addrs
+                    .iter()
+                    .map(|addr| addr.to_string())
+                    .collect::<Vec<_>>()
+                    .join(","),
+            ),
+        ]
+        .into_iter()
+        .map(|(key, value)| (key.to_string(), value))
+        .collect();
+
+        let signature = self.identity.sign(&signed_message(&properties));
+        properties.insert(
+            PUBLIC_KEY_PROPERTY.to_string(),
+            base64::encode(self.identity.public_key()),
+        );
+        properties.insert(SIGNATURE_PROPERTY.to_string(), base64::encode(signature));
+
+        // mDNS host names must be valid labels under .local.
+        let host_label: String = self
+            .host_name
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() { c } else { '-' })
+            .collect();
+
+        ServiceInfo::new(
+            SERVICE_TYPE,
+            self.identity.node_id(),
+            &format!("{host_label}.local."),
+            &self.addrs[..],
+            self.server_port,
+            properties,
+        )
+        .map_err(|err| core_error!("create mdns service info failed ({})", err))
+    }
+}
+
+/// Advertises this device over mDNS and browses other devices into `nodes`, keyed by
+/// the full service name so goodbye packets can remove them.
+pub struct MdnsService {
+    daemon: ServiceDaemon,
+    local: LocalService,
+    service_info: Mutex<ServiceInfo>,
+    registered: AtomicBool,
+}
+
+impl MdnsService {
+    pub fn new(
+        local: LocalService,
+        capabilities: &NodeCapabilities,
+        nodes: Cache<String, Vec<Node>>,
+    ) -> CoreResult<Arc<Self>> {
+        let daemon = ServiceDaemon::new()
+            .map_err(|err| core_error!("create mdns daemon failed ({})", err))?;
+
+        let service_info = local.service_info(capabilities)?;
+
+        let receiver = daemon
+            .browse(SERVICE_TYPE)
+            .map_err(|err| core_error!("browse mdns service failed ({})", err))?;
+
+        let local_node_id = local.identity.node_id().to_string();
+        tokio::spawn(async move {
+            while let Ok(event) = receiver.recv_async().await {
+                if let ServiceEvent::SearchStopped(_) = event {
+                    break;
+                }
+
+                handle_service_event(&nodes, event, &local_node_id).await;
+            }
+
+            tracing::info!("mdns browse loop exit");
+        });
+
+        Ok(Arc::new(Self {
+            daemon,
+            local,
+            service_info: Mutex::new(service_info),
+            registered: AtomicBool::new(false),
+        }))
+    }
+
+    /// Replaces the advertised capabilities, a registered service is announced again so
+    /// browsers pick up the new TXT records.
+    pub fn set_capabilities(&self, capabilities: &NodeCapabilities) {
+        let service_info = match self.local.service_info(capabilities) {
+            Ok(service_info) => service_info,
+            Err(err) => {
+                tracing::warn!(?err, "update mdns service capabilities failed");
+                return;
+            }
+        };
+
+        *self.lock_service_info() = service_info.clone();
+
+        if self.registered.load(Ordering::SeqCst) {
+            if let Err(err) = self.daemon.register(service_info) {
+                tracing::warn!(?err, "register updated mdns service failed");
+            }
+        }
+    }
+
+    /// Registers or unregisters the advertised service.
+    pub fn set_advertised(&self, advertised: bool) {
+        if self.registered.swap(advertised, Ordering::SeqCst) == advertised {
+            return;
+        }
+
+        let service_info = self.lock_service_info().clone();
+        let result = if advertised {
+            self.daemon.register(service_info)
+        } else {
+            self.daemon
+                .unregister(service_info.get_fullname())
+                .map(|_| ())
+        };
+
+        if let Err(err) = result {
+            tracing::warn!(?err, advertised, "update mdns service registration failed");
+        }
+    }
+
+    fn lock_service_info(&self) -> std::sync::MutexGuard<ServiceInfo> {
+        match self.service_info.lock() {
+            Ok(service_info) => service_info,
+            Err(poisoned) => poisoned.into_inner(),
+        }
+    }
+}
+
+impl Drop for MdnsService {
+    fn drop(&mut self) {
+        self.set_advertised(false);
+
+        if let Err(err) = self.daemon.shutdown() {
+            tracing::warn!(?err, "shutdown mdns daemon failed");
+        }
+    }
+}
+
+/// Updates `nodes` from a browse event. A cached signed service can only be replaced by
+/// one signed with the same key and isn't removed by goodbye packets, which anyone can
+/// send, it expires after [`MDNS_NODE_TTL`] instead.
+pub(crate) async fn handle_service_event(
+    nodes: &Cache<String, Vec<Node>>,
+    event: ServiceEvent,
+    local_node_id: &str,
+) {
+    match event {
+        ServiceEvent::ServiceResolved(info) => {
+            if info.get_property_val_str("id") == Some(local_node_id) {
+                return;
+            }
+
+            let fullname = info.get_fullname().to_string();
+            let resolved_nodes = parse_nodes(&info);
+            let Some(resolved_node) = resolved_nodes.first() else {
+                return;
+            };
+
+            let cached_fingerprint = nodes
+                .get(&fullname)
+                .and_then(|cached| cached.first().and_then(|node| node.fingerprint.clone()));
+            if cached_fingerprint.is_some() && cached_fingerprint != resolved_node.fingerprint {
+                tracing::warn!(
+                    name = fullname,
+                    "mdns service signed by another key than the cached one"
+                );
+                return;
+            }
+
+            tracing::info!(
+                name = fullname,
+                count = resolved_nodes.len(),
+                "mdns discover target live"
+            );
+
+            nodes.insert(fullname, resolved_nodes).await;
+        }
+        ServiceEvent::ServiceRemoved(_, fullname) => {
+            let signed = nodes.get(&fullname).map_or(false, |cached| {
+                cached.iter().any(|node| node.fingerprint.is_some())
+            });
+            if signed {
+                tracing::info!(?fullname, "mdns discover signed target keeps until expired");
+                return;
+            }
+
+            tracing::info!(?fullname, "mdns discover target dead");
+            nodes.invalidate(&fullname).await;
+        }
+        _ => {}
+    }
+}
+
+/// Removes the services of `node_id` signed with the key of `fingerprint`, after the
+/// node announced it left in a signed packet.
+pub(crate) async fn remove_signed_nodes(
+    nodes: &Cache<String, Vec<Node>>,
+    node_id: &str,
+    fingerprint: &str,
+) {
+    let fullnames: Vec<Arc<String>> = nodes
+        .iter()
+        .filter(|(_, cached)| {
+            cached.iter().any(|node| {
+                node.node_id.as_deref() == Some(node_id)
+                    && node.fingerprint.as_deref() == Some(fingerprint)
+            })
+        })
+        .map(|(fullname, _)| fullname)
+        .collect();
+
+    for fullname in fullnames {
+        nodes.invalidate(fullname.as_ref()).await;
+    }
+}
+
+/// Parses nodes of a resolved service, one per address. Services signed with an
+/// invalid signature or named after another id than the signed one are dropped, as are
+/// the addresses of a signed service it doesn't list. Unsigned ones come from versions
+/// without identities.
+pub(crate) fn parse_nodes(info: &ServiceInfo) -> Vec<Node> {
+    let property = |key: &str| {
+        info.get_property_val_str(key)
+            .unwrap_or_default()
+            .to_string()
+    };
+
+    let (node_id, fingerprint, trust, signed_addrs) = match verify_properties(info) {
+        Ok(Some(public_key)) => {
+            // the instance name isn't signed, it must be the signed id
+            let node_id = property("id");
+            if info.get_fullname() != format!("{node_id}.{SERVICE_TYPE}") {
+                tracing::warn!(
+                    name = info.get_fullname(),
+                    node_id,
+                    "drop mdns service named after another id than the signed one"
+                );
+                return Vec::new();
+            }
+
+            let signed_addrs: Vec<IpAddr> = property(ADDRESSES_PROPERTY)
+                .split(',')
+                .filter_map(|addr| addr.parse().ok())
+                .collect();
+
+            (
+                Some(node_id),
+                Some(fingerprint(&public_key)),
+                NodeTrust::Unknown,
+                Some(signed_addrs),
+            )
+        }
+        Ok(None) => (None, None, NodeTrust::Unsigned, None),
+        Err(err) => {
+            tracing::warn!(?err, name = info.get_fullname(), "drop mdns service");
+            return Vec::new();
+        }
+    };
+
+    // services of older versions don't advertise capabilities
+    let capabilities: NodeCapabilities =
+        serde_json::from_str(&property(CAPABILITIES_PROPERTY)).unwrap_or_default();
+
+    let addrs: Vec<IpAddr> = info
+        .get_addresses()
+        .iter()
+        .filter(|addr| {
+            signed_addrs
+                .as_ref()
+                .map_or(true, |signed_addrs| signed_addrs.contains(*addr))
+        })
+        .copied()
+        .collect();
+
+    if addrs.is_empty() && signed_addrs.is_some() {
+        tracing::warn!(
+            name = info.get_fullname(),
+            "drop mdns service resolved to addresses it doesn't list"
+        );
+    }
+
+    addrs
+        .into_iter()
+        .map(|addr| Node {
+            host_name: property("host_name"),
+            addr,
+            port: info.get_port(),
+            os: property("os"),
+            os_version: property("os_version"),
+            node_id: node_id.clone(),
+            fingerprint: fingerprint.clone(),
+            trust,
+            capabilities: capabilities.clone(),
+        })
+        .collect()
+}
+
+/// Returns the public key the TXT records are signed with, `None` when unsigned.
+pub(crate) fn verify_properties(info: &ServiceInfo) -> CoreResult<Option<Vec<u8>>> {
+    let (Some(public_key), Some(signature)) = (
+        info.get_property_val_str(PUBLIC_KEY_PROPERTY),
+        info.get_property_val_str(SIGNATURE_PROPERTY),
+    ) else {
+        return Ok(None);
+    };
+
+    let public_key = base64::decode(public_key)?;
+    let signature = base64::decode(signature)?;
+
+    let properties: HashMap<String, String> = info
+        .get_properties()
+        .iter()
+        .filter(|property| {
+            property.key() != PUBLIC_KEY_PROPERTY && property.key() != SIGNATURE_PROPERTY
+        })
+        .map(|property| (property.key().to_string(), property.val_str().to_string()))
+        .collect();
+
+    if !verify(&public_key, &signed_message(&properties), &signature) {
+        return Err(core_error!("mdns service signature mismatch"));
+    }
+
+    Ok(Some(public_key))
+}
+
+/// Canonical form of TXT records for signing, sorted `key=value` lines.
+fn signed_message(properties: &HashMap<String, String>) -> Vec<u8> {
+    let mut entries: Vec<String> = properties
+        .iter()
+        .map(|(key, value)| format!("{key}={value}\n"))
+        .collect();
+    entries.sort();
+    entries.concat().into_bytes()
+}
@@ -1,2 +1,6 @@
 pub mod discover;
+pub mod handshake;
+pub mod identity;
+pub mod mdns;
 pub mod server;
+pub mod wake;
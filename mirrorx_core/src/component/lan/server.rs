@@ -1,64 +1,166 @@
+use super::{
+    handshake::{serve_identity, IncomingHandshake},
+    identity::NodeIdentity,
+};
 use crate::{
-    api::endpoint::{create_passive_endpoint_client, EndPointStream},
+    api::{
+        config::{
+            entity::audit::{AuditCredential, AuditSessionStart},
+            settings::current_settings,
+            LocalStorage,
+        },
+        endpoint::{create_passive_endpoint_client, EndPointStream},
+    },
+    core_error,
     error::CoreResult,
 };
-use std::net::IpAddr;
+use std::{net::IpAddr, sync::Arc};
+use tokio::net::TcpStream;
 
 pub struct Server {
-    exit_tx: Option<tokio::sync::oneshot::Sender<()>>,
+    exit_txs: Vec<tokio::sync::oneshot::Sender<()>>,
 }
 
 impl Server {
-    pub async fn new(local_lan_ip: IpAddr) -> CoreResult<Self> {
-        let listener = tokio::net::TcpListener::bind((local_lan_ip, 48001)).await?;
-        let local_addr = listener.local_addr()?;
-        let (exit_tx, mut exit_rx) = tokio::sync::oneshot::channel();
-        tracing::info!(?local_addr, "local lan server listen");
-
-        tokio::spawn(async move {
-            loop {
-                let (stream, addr) = tokio::select! {
-                    _ = &mut exit_rx => {
-                        tracing::info!("local lan server exit");
-                        return;
-                    },
-                    res = listener.accept() => match res {
-                        Ok(stream) => stream,
-                        Err(err) => {
-                            tracing::error!(?err, "local lan server accept stream failed");
-                            continue;
-                        }
-                    }
-                };
-
-                if let Err(err) = create_passive_endpoint_client(
-                    crate::api::endpoint::id::EndPointID::LANID {
-                        local_ip: local_lan_ip,
-                        remote_ip: addr.ip(),
-                    },
-                    None,
-                    EndPointStream::PassiveTCP(stream),
-                    None,
-                )
-                .await
-                {
-                    tracing::error!(?err, "create passive endpoint client from lan failed");
-                }
+    /// Accepts LAN connections on every address of `local_lan_ips`, incoming sessions are
+    /// recorded to the audit log of `storage` when present. Addresses which can't be
+    /// bound are skipped, it fails only when none of them can. Peers asking for it get
+    /// proof that this node holds the key of `identity`.
+    pub async fn new(
+        local_lan_ips: &[IpAddr],
+        identity: Arc<NodeIdentity>,
+        storage: Option<LocalStorage>,
+    ) -> CoreResult<Self> {
+        let port = current_settings().lan_server_port;
+        let mut exit_txs = Vec::new();
 
-                tracing::info!(?addr, "local lan server accept stream");
+        for local_lan_ip in local_lan_ips {
+            match tokio::net::TcpListener::bind((*local_lan_ip, port)).await {
+                Ok(listener) => {
+                    exit_txs.push(serve(
+                        *local_lan_ip,
+                        listener,
+                        identity.clone(),
+                        storage.clone(),
+                    ));
+                }
+                Err(err) => {
+                    tracing::warn!(?err, ?local_lan_ip, "local lan server bind failed");
+                }
             }
-        });
+        }
+
+        if exit_txs.is_empty() {
+            return Err(core_error!(
+                "local lan server can't listen on any address with port {}",
+                port
+            ));
+        }
 
-        Ok(Self {
-            exit_tx: Some(exit_tx),
-        })
+        Ok(Self { exit_txs })
     }
 }
 
 impl Drop for Server {
     fn drop(&mut self) {
-        if let Some(exit_ex) = self.exit_tx.take() {
-            let _ = exit_ex.send(());
+        for exit_tx in self.exit_txs.drain(..) {
+            let _ = exit_tx.send(());
+        }
+    }
+}
+
+fn serve(
+    local_lan_ip: IpAddr,
+    listener: tokio::net::TcpListener,
+    identity: Arc<NodeIdentity>,
+    storage: Option<LocalStorage>,
+) -> tokio::sync::oneshot::Sender<()> {
+    let (exit_tx, mut exit_rx) = tokio::sync::oneshot::channel();
+    tracing::info!(local_addr = ?listener.local_addr(), "local lan server listen");
+
+    tokio::spawn(async move {
+        loop {
+            let (stream, addr) = tokio::select! {
+                _ = &mut exit_rx => {
+                    tracing::info!("local lan server exit");
+                    return;
+                },
+                res = listener.accept() => match res {
+                    Ok(stream) => stream,
+                    Err(err) => {
+                        tracing::error!(?err, "local lan server accept stream failed");
+                        continue;
+                    }
+                }
+            };
+
+            tracing::info!(?addr, "local lan server accept stream");
+
+            // the identity handshake waits for the peer, don't block accepting others
+            let identity = identity.clone();
+            let storage = storage.clone();
+            tokio::spawn(async move {
+                serve_stream(local_lan_ip, stream, addr.ip(), &identity, storage.as_ref()).await
+            });
+        }
+    });
+
+    exit_tx
+}
+
+async fn serve_stream(
+    local_lan_ip: IpAddr,
+    mut stream: TcpStream,
+    remote_ip: IpAddr,
+    identity: &NodeIdentity,
+    storage: Option<&LocalStorage>,
+) {
+    match serve_identity(&mut stream, identity).await {
+        Ok(IncomingHandshake::Probe) => {
+            tracing::info!(?remote_ip, "lan probe answered");
+            return;
+        }
+        Ok(handshake) => tracing::info!(?remote_ip, ?handshake, "lan identity handshake"),
+        Err(err) => {
+            tracing::error!(?err, ?remote_ip, "lan identity handshake failed");
+            return;
+        }
+    }
+
+    let audit = storage.and_then(|storage| {
+        let session = AuditSessionStart {
+            device_id: None,
+            remote_ip: remote_ip.to_string(),
+            domain: String::default(),
+            credential: AuditCredential::Lan,
+            permissions: vec![String::from("desktop"), String::from("file_manager")],
+        };
+
+        match storage.audit().start_session(&session) {
+            Ok(audit) => Some(audit),
+            Err(err) => {
+                tracing::error!(?err, "start audit session failed");
+                None
+            }
+        }
+    });
+
+    if let Err(err) = create_passive_endpoint_client(
+        crate::api::endpoint::id::EndPointID::LANID {
+            local_ip: local_lan_ip,
+            remote_ip,
+        },
+        None,
+        EndPointStream::PassiveTCP(stream),
+        None,
+        audit.clone(),
+    )
+    .await
+    {
+        tracing::error!(?err, "create passive endpoint client from lan failed");
+
+        if let Some(ref audit) = audit {
+            audit.finish();
         }
     }
 }
@@ -0,0 +1,158 @@
+use crate::{core_error, error::CoreResult, utility::lan_ip::LanInterface};
+use std::{
+    collections::HashMap,
+    net::{IpAddr, Ipv4Addr},
+};
+
+/// UDP port magic packets are sent to, the discard port is the common choice.
+const WAKE_ON_LAN_PORT: u16 = 9;
+
+/// Parses a MAC address separated by `:` or `-`, groups may omit the leading zero as
+/// printed by `arp` on macOS.
+pub fn parse_mac(mac: &str) -> Option<[u8; 6]> {
+    let groups: Vec<&str> = mac.trim().split(|c| c == ':' || c == '-').collect();
+    if groups.len() != 6 {
+        return None;
+    }
+
+    let mut bytes = [0u8; 6];
+    for (byte, group) in bytes.iter_mut().zip(groups) {
+        if group.is_empty() || group.len() > 2 {
+            return None;
+        }
+
+        *byte = u8::from_str_radix(group, 16).ok()?;
+    }
+
+    Some(bytes)
+}
+
+/// Formats a MAC address as lowercase hex separated by colons.
+pub fn format_mac(mac: &[u8; 6]) -> String {
+    mac.iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect::<Vec<String>>()
+        .join(":")
+}
+
+/// Builds the magic packet waking the interface with `mac`: six `0xFF` followed by the
+/// address repeated sixteen times.
+pub fn magic_packet(mac: &[u8; 6]) -> Vec<u8> {
+    let mut packet = vec![0xFF; 6];
+    for _ in 0..16 {
+        packet.extend_from_slice(mac);
+    }
+    packet
+}
+
+/// Sends magic packets for every address of `macs` through the interfaces whose subnet
+/// contains `target_ip`, or through every IPv4 interface when none does.
+pub async fn send_magic_packets(
+    macs: &[String],
+    target_ip: IpAddr,
+    interfaces: &[LanInterface],
+) -> CoreResult<()> {
+    let macs: Vec<[u8; 6]> = macs.iter().filter_map(|mac| parse_mac(mac)).collect();
+    if macs.is_empty() {
+        return Err(core_error!("no valid mac address to wake {}", target_ip));
+    }
+
+    let mut targets: Vec<&LanInterface> = interfaces
+        .iter()
+        .filter(|interface| interface.contains(target_ip))
+        .collect();
+
+    if targets.is_empty() {
+        targets = interfaces
+            .iter()
+            .filter(|interface| interface.broadcast.is_some())
+            .collect();
+    }
+
+    let mut sent = false;
+    for interface in targets {
+        let broadcast = interface.broadcast.unwrap_or(Ipv4Addr::BROADCAST);
+
+        // bound to the interface address so the packet leaves through it
+        let socket = match tokio::net::UdpSocket::bind((interface.ip, 0)).await {
+            Ok(socket) => socket,
+            Err(err) => {
+                tracing::warn!(
+                    ?err,
+                    interface = interface.name,
+                    "bind wake on lan socket failed"
+                );
+                continue;
+            }
+        };
+        socket.set_broadcast(true)?;
+
+        for mac in &macs {
+            match socket
+                .send_to(&magic_packet(mac), (broadcast, WAKE_ON_LAN_PORT))
+                .await
+            {
+                Ok(_) => sent = true,
+                Err(err) => tracing::warn!(
+                    ?err,
+                    interface = interface.name,
+                    mac = format_mac(mac),
+                    "send magic packet failed"
+                ),
+            }
+        }
+    }
+
+    if !sent {
+        return Err(core_error!("send magic packet to {} failed", target_ip));
+    }
+
+    Ok(())
+}
+
+/// Reads IPv4 neighbours from the system ARP table.
+pub async fn read_arp_table() -> HashMap<IpAddr, String> {
+    let output = if cfg!(target_os = "linux") {
+        tokio::fs::read_to_string("/proc/net/arp").await.ok()
+    } else {
+        tokio::process::Command::new("arp")
+            .arg("-a")
+            .output()
+            .await
+            .ok()
+            .map(|output| String::from_utf8_lossy(&output.stdout).to_string())
+    };
+
+    output
+        .map(|output| parse_arp_table(&output))
+        .unwrap_or_default()
+}
+
+/// Parses ARP tables printed by `/proc/net/arp` on Linux and `arp -a` on macOS and
+/// Windows: every line holding an IPv4 address and a unicast MAC address is an entry.
+pub fn parse_arp_table(output: &str) -> HashMap<IpAddr, String> {
+    output
+        .lines()
+        .filter_map(|line| {
+            let tokens = line
+                .split_whitespace()
+                .map(|token| token.trim_matches(|c| c == '(' || c == ')'));
+
+            let mut ip = None;
+            let mut mac = None;
+            for token in tokens {
+                if ip.is_none() {
+                    ip = token.parse::<Ipv4Addr>().ok();
+                }
+
+                if mac.is_none() {
+                    // incomplete entries show zeros, broadcast and multicast ones
+                    // have the group bit set
+                    mac = parse_mac(token).filter(|mac| *mac != [0u8; 6] && mac[0] & 1 == 0);
+                }
+            }
+
+            Some((IpAddr::V4(ip?), format_mac(&mac?)))
+        })
+        .collect()
+}
@@ -1,8 +1,11 @@
 use super::config::EncoderConfig;
 use crate::{
-    api::endpoint::{
-        client::EndPointClient,
-        message::{EndPointMessage, EndPointVideoFrame},
+    api::{
+        config::settings::current_settings,
+        endpoint::{
+            client::EndPointClient,
+            message::{EndPointMessage, EndPointVideoFrame},
+        },
     },
     component::frame::DesktopEncodeFrame,
     core_error,
@@ -57,8 +60,8 @@ where
                 )?);
             }
 
-            let Some(ref encode_context)= self.encode_context else{
-                return Err(core_error!("encode context is empty"))
+            let Some(ref encode_context) = self.encode_context else {
+                return Err(core_error!("encode context is empty"));
             };
 
             ret = av_frame_make_writable(encode_context.frame);
@@ -154,15 +157,25 @@ impl EncodeContext {
                 return Err(core_error!("avcodec_alloc_context3 returns null pointer"));
             }
 
+            let settings = current_settings();
+            let frame_rate = settings.frame_rate as i32;
+            let bit_rate = settings.max_bitrate_kbps as i64 * 1000;
+
             (*encoder_context.codec_ctx).width = width;
             (*encoder_context.codec_ctx).height = height;
-            (*encoder_context.codec_ctx).framerate = AVRational { num: 60, den: 1 };
-            (*encoder_context.codec_ctx).time_base = AVRational { num: 1, den: 60 };
+            (*encoder_context.codec_ctx).framerate = AVRational {
+                num: frame_rate,
+                den: 1,
+            };
+            (*encoder_context.codec_ctx).time_base = AVRational {
+                num: 1,
+                den: frame_rate,
+            };
             (*encoder_context.codec_ctx).gop_size = 4000;
-            (*encoder_context.codec_ctx).bit_rate = 4000 * 1000;
-            (*encoder_context.codec_ctx).rc_max_rate = 4000 * 1000;
-            (*encoder_context.codec_ctx).rc_min_rate = 4000 * 1000;
-            (*encoder_context.codec_ctx).rc_buffer_size = 4000 * 1000 * 2;
+            (*encoder_context.codec_ctx).bit_rate = bit_rate;
+            (*encoder_context.codec_ctx).rc_max_rate = bit_rate;
+            (*encoder_context.codec_ctx).rc_min_rate = bit_rate;
+            (*encoder_context.codec_ctx).rc_buffer_size = (bit_rate * 2) as i32;
             (*encoder_context.codec_ctx).has_b_frames = 0;
             (*encoder_context.codec_ctx).max_b_frames = 0;
             (*encoder_context.codec_ctx).pix_fmt = AV_PIX_FMT_NV12;
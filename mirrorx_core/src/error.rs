@@ -94,6 +94,15 @@ pub enum CoreError {
 
     #[error("image process error ({0:?})")]
     ImageError(#[from] image::ImageError),
+
+    #[error("tls error ({0:?})")]
+    TlsError(#[from] rustls::Error),
+
+    #[error("server identity mismatch (expected = {expected}, actual = {actual})")]
+    ServerIdentityMismatch { expected: String, actual: String },
+
+    #[error("client version is too low (current = {current}, required = {required})")]
+    ClientVersionTooLow { current: String, required: String },
 }
 
 impl serde::Serialize for CoreError {
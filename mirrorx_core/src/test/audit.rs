@@ -0,0 +1,88 @@
+use crate::{
+    api::config::entity::audit::{AuditCredential, AuditFilter, AuditSessionStart, FileOperation},
+    test::TempStorage,
+};
+use std::path::Path;
+
+#[test]
+fn test_audit_log() -> anyhow::Result<()> {
+    let storage = TempStorage::new()?;
+
+    let session = storage.audit().start_session(&AuditSessionStart {
+        device_id: Some(1234567890),
+        remote_ip: String::default(),
+        domain: String::from("mirrorx.cloud"),
+        credential: AuditCredential::OneTimePassword,
+        permissions: vec![String::from("desktop"), String::from("file_manager")],
+    })?;
+
+    session.record_file_operation(
+        FileOperation::DownloadFile,
+        Path::new("/home/user/report, final.pdf"),
+        None,
+        Some(4096),
+        None,
+    );
+    session.record_file_operation(
+        FileOperation::SendFile,
+        Path::new("/home/user/exists.txt"),
+        None,
+        Some(12),
+        Some(String::from("file already exists")),
+    );
+    session.record_file_operation(
+        FileOperation::Rename,
+        Path::new("/home/user/draft.txt"),
+        Some(Path::new("/home/user/archive/draft.txt")),
+        None,
+        None,
+    );
+    session.finish();
+
+    storage.audit().start_session(&AuditSessionStart {
+        device_id: None,
+        remote_ip: String::from("192.168.1.20"),
+        domain: String::default(),
+        credential: AuditCredential::Lan,
+        permissions: vec![String::from("desktop"), String::from("file_manager")],
+    })?;
+
+    let page = storage.audit().query(&AuditFilter::default())?;
+    assert_eq!(page.total, 2);
+
+    let page = storage.audit().query(&AuditFilter {
+        keyword: Some(String::from("12-3456")),
+        ..Default::default()
+    })?;
+    assert_eq!(page.total, 1);
+    assert_eq!(
+        page.sessions[0].credential,
+        AuditCredential::OneTimePassword
+    );
+    assert!(page.sessions[0].end_time.is_some());
+
+    let events = storage.audit().query_events(session.id())?;
+    assert_eq!(events.len(), 3);
+    assert_eq!(events[0].operation, FileOperation::DownloadFile);
+    assert_eq!(events[0].size, Some(4096));
+    assert_eq!(events[0].destination, None);
+    assert_eq!(events[1].error, "file already exists");
+
+    // renames and copies are recorded with where the entry went
+    assert_eq!(events[2].path, "/home/user/draft.txt");
+    assert_eq!(
+        events[2].destination.as_deref(),
+        Some("/home/user/archive/draft.txt")
+    );
+
+    let csv = storage.audit().export_csv(&AuditFilter::default())?;
+    assert_eq!(csv.lines().count(), 6);
+    assert!(csv.contains("\"/home/user/report, final.pdf\""));
+    assert!(csv.contains(",rename,/home/user/draft.txt,/home/user/archive/draft.txt,,\n"));
+
+    // retention only removes sessions older than the given days
+    storage.audit().delete_expired(1)?;
+    assert_eq!(storage.audit().query(&AuditFilter::default())?.total, 2);
+
+    Ok(())
+}
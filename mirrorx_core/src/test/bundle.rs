@@ -0,0 +1,265 @@
+use crate::{
+    api::config::{
+        bundle::{export_bundle, import_bundle, ExportOptions, ImportConflictPolicy},
+        entity::{
+            contact::{ConnectionPreference, Contact},
+            domain::Domain,
+            history::{ConnectionType, HistoryFilter, SessionStart, SessionType},
+        },
+    },
+    test::{TempDatabase, TempStorage},
+    utility::proxy::{Proxy, ProxyConfig, ProxyKind},
+};
+use rusqlite::Connection;
+
+fn domain(name: &str, is_primary: bool) -> Domain {
+    Domain {
+        id: 0,
+        name: name.to_string(),
+        addr: format!("http://{name}:28000"),
+        signaling_port: 28000,
+        subscribe_port: 28001,
+        is_primary,
+        device_id: 1234567890,
+        password: String::from("password"),
+        finger_print: String::from("finger_print"),
+        remarks: String::default(),
+        tls_pin: String::default(),
+        expire: 0,
+    }
+}
+
+#[test]
+fn test_export_import_bundle() -> anyhow::Result<()> {
+    let source = TempStorage::new()?;
+    source.domain().add_domain(domain("mirrorx.cloud", true))?;
+    source.domain().add_domain(domain("mirrorx.local", false))?;
+    source.kv().set_language("zh")?;
+    source.history().start_session(&SessionStart {
+        device_id: 9876543210,
+        domain: String::from("mirrorx.cloud"),
+        remote_addr: String::default(),
+        session_type: SessionType::Desktop,
+        connection_type: ConnectionType::Domain,
+    })?;
+
+    let options = ExportOptions {
+        include_secrets: true,
+        include_identities: false,
+    };
+    let content = export_bundle(&source, Some("passphrase"), options)?;
+    assert!(content.contains("\"format\": \"encrypted\""));
+
+    let target = TempStorage::new()?;
+    target.domain().add_domain(domain("mirrorx.cloud", true))?;
+
+    assert!(import_bundle(&target, &content, None, ImportConflictPolicy::Skip).is_err());
+    assert!(import_bundle(&target, &content, Some("wrong"), ImportConflictPolicy::Skip).is_err());
+
+    let summary = import_bundle(
+        &target,
+        &content,
+        Some("passphrase"),
+        ImportConflictPolicy::Rename,
+    )?;
+
+    assert_eq!(summary.domains_imported, 1);
+    assert_eq!(summary.domains_renamed, 1);
+    assert_eq!(summary.history_imported, 1);
+
+    let renamed = target
+        .domain()
+        .get_domain_by_name(String::from("mirrorx.cloud (2)"))?;
+    assert_eq!(renamed.password, "password");
+    assert!(!renamed.is_primary);
+
+    // the identity stays with the exporting device, the import registers anew
+    assert_eq!(renamed.device_id, 0);
+    assert!(!renamed.finger_print.is_empty());
+    assert_ne!(renamed.finger_print, "finger_print");
+    assert!(target.domain().domain_exist("mirrorx.local")?);
+    assert_eq!(target.kv().get_language()?, Some(String::from("zh")));
+    let records = target.history().query(&HistoryFilter::default())?.records;
+    assert_eq!(records[0].domain, "mirrorx.cloud (2)");
+    assert_eq!(records[0].session_type, SessionType::Desktop);
+
+    let summary = import_bundle(
+        &target,
+        &content,
+        Some("passphrase"),
+        ImportConflictPolicy::Skip,
+    )?;
+    assert_eq!(summary.domains_skipped, 2);
+
+    Ok(())
+}
+
+#[test]
+fn test_export_plain_bundle_without_secrets() -> anyhow::Result<()> {
+    let source = TempStorage::new()?;
+    source.domain().add_domain(domain("mirrorx.cloud", true))?;
+    source.kv().set_proxy(&ProxyConfig::Manual(Proxy {
+        kind: ProxyKind::Http,
+        host: String::from("proxy.local"),
+        port: 8080,
+        username: Some(String::from("user")),
+        password: Some(String::from("proxy_password")),
+    }))?;
+
+    // secrets and identities only leave encrypted
+    for (include_secrets, include_identities) in [(true, false), (false, true)] {
+        let options = ExportOptions {
+            include_secrets,
+            include_identities,
+        };
+        assert!(export_bundle(&source, None, options).is_err());
+    }
+
+    let content = export_bundle(&source, None, ExportOptions::default())?;
+    assert!(content.contains("\"format\": \"plain\""));
+    assert!(!content.contains("password\": \"password"));
+    assert!(!content.contains("proxy_password"));
+    assert!(!content.contains("finger_print\": \"finger_print"));
+
+    let target = TempStorage::new()?;
+    import_bundle(&target, &content, None, ImportConflictPolicy::Skip)?;
+
+    // a new device password is generated in place of the missing one
+    let imported = target.domain().get_primary_domain()?;
+    assert!(!imported.password.is_empty());
+    assert_ne!(imported.password, "password");
+    let Some(ProxyConfig::Manual(proxy)) = target.kv().get_proxy()? else {
+        panic!("proxy should be manual");
+    };
+    assert_eq!(proxy.username.as_deref(), Some("user"));
+    assert_eq!(proxy.password, None);
+
+    Ok(())
+}
+
+fn manual_proxy(host: &str, password: Option<&str>) -> ProxyConfig {
+    ProxyConfig::Manual(Proxy {
+        kind: ProxyKind::Http,
+        host: host.to_string(),
+        port: 8080,
+        username: Some(String::from("user")),
+        password: password.map(String::from),
+    })
+}
+
+#[test]
+fn test_import_bundle_keeps_proxy_password() -> anyhow::Result<()> {
+    let source = TempStorage::new()?;
+    source
+        .kv()
+        .set_proxy(&manual_proxy("proxy.local", Some("proxy_password")))?;
+    let content = export_bundle(&source, None, ExportOptions::default())?;
+
+    // the same proxy keeps the local password the bundle left out
+    let target = TempStorage::new()?;
+    target
+        .kv()
+        .set_proxy(&manual_proxy("proxy.local", Some("local_password")))?;
+    import_bundle(&target, &content, None, ImportConflictPolicy::Skip)?;
+    assert_eq!(
+        target.kv().get_proxy()?,
+        Some(manual_proxy("proxy.local", Some("local_password")))
+    );
+
+    // another proxy doesn't get it
+    let target = TempStorage::new()?;
+    target
+        .kv()
+        .set_proxy(&manual_proxy("other.local", Some("local_password")))?;
+    import_bundle(&target, &content, None, ImportConflictPolicy::Skip)?;
+    assert_eq!(
+        target.kv().get_proxy()?,
+        Some(manual_proxy("proxy.local", None))
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_import_bundle_validates_settings() -> anyhow::Result<()> {
+    let source = TempStorage::new()?;
+    source
+        .kv()
+        .set_setting("frame_rate", serde_json::json!(30))?;
+    let content = export_bundle(&source, None, ExportOptions::default())?;
+
+    let with_settings = |settings: &[(&str, &str)]| -> anyhow::Result<String> {
+        let mut file: serde_json::Value = serde_json::from_str(&content)?;
+        let entries = file["bundle"]["settings"]
+            .as_array_mut()
+            .expect("bundle should have settings");
+        for (key, value) in settings {
+            entries.push(serde_json::json!([key, value]));
+        }
+
+        Ok(serde_json::to_string(&file)?)
+    };
+
+    let target = TempStorage::new()?;
+    for settings in [
+        [("settings.transfer_concurrency", "0")],
+        [("settings.frame_rate", "\"fast\"")],
+    ] {
+        let content = with_settings(&settings)?;
+        assert!(import_bundle(&target, &content, None, ImportConflictPolicy::Skip).is_err());
+        assert_eq!(target.kv().get_settings()?.frame_rate, 60);
+    }
+
+    // ports checked against each other are applied together
+    let content = with_settings(&[
+        ("settings.lan_discover_port", "48001"),
+        ("settings.lan_server_port", "48000"),
+    ])?;
+    import_bundle(&target, &content, None, ImportConflictPolicy::Skip)?;
+    let settings = target.kv().get_settings()?;
+    assert_eq!(settings.frame_rate, 30);
+    assert_eq!(settings.lan_discover_port, 48001);
+    assert_eq!(settings.lan_server_port, 48000);
+
+    Ok(())
+}
+
+#[test]
+fn test_import_bundle_atomic() -> anyhow::Result<()> {
+    let source = TempStorage::new()?;
+    source.domain().add_domain(domain("mirrorx.cloud", true))?;
+    source.kv().set_language("zh")?;
+    source.contact().create(Contact {
+        id: 0,
+        device_id: 9876543210,
+        domain: String::from("mirrorx.cloud"),
+        alias: String::from("office"),
+        group_name: String::default(),
+        tags: Vec::new(),
+        notes: String::default(),
+        connection_preference: ConnectionPreference::Desktop,
+        favorite: false,
+        created_at: 0,
+        updated_at: 0,
+    })?;
+
+    let content = export_bundle(&source, None, ExportOptions::default())?;
+
+    // contacts are imported last, failing them discards the whole import
+    let database = TempDatabase::new();
+    let target = database.open()?;
+    Connection::open(database.path())?.execute_batch(
+        r"
+        CREATE TRIGGER reject_contacts BEFORE INSERT ON contacts
+        BEGIN
+            SELECT RAISE(ABORT, 'rejected');
+        END;
+        ",
+    )?;
+
+    assert!(import_bundle(&target, &content, None, ImportConflictPolicy::Skip).is_err());
+    assert_eq!(target.domain().get_domain_count()?, 0);
+    assert_eq!(target.kv().get_language()?, None);
+
+    Ok(())
+}
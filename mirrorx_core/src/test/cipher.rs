@@ -0,0 +1,151 @@
+use crate::{
+    api::config::{
+        cipher::{KeyStore, StorageCipher},
+        entity::domain::Domain,
+    },
+    test::TempDatabase,
+    utility::proxy::{Proxy, ProxyConfig, ProxyKind},
+};
+use rusqlite::Connection;
+
+fn domain() -> Domain {
+    Domain {
+        id: 0,
+        name: String::from("mirrorx.cloud"),
+        addr: String::from("http://mirrorx.cloud:28000"),
+        signaling_port: 28000,
+        subscribe_port: 28001,
+        is_primary: true,
+        device_id: 1234567890,
+        password: String::from("password"),
+        finger_print: String::from("finger_print"),
+        remarks: String::default(),
+        tls_pin: String::default(),
+        expire: 0,
+    }
+}
+
+#[test]
+fn test_storage_cipher() -> anyhow::Result<()> {
+    let cipher = StorageCipher::new(&[7u8; 32])?;
+
+    let encrypted = cipher.encrypt("device_password")?;
+    assert!(StorageCipher::is_encrypted(&encrypted));
+    assert_ne!(encrypted, cipher.encrypt("device_password")?);
+    assert_eq!(cipher.decrypt(&encrypted)?, "device_password");
+
+    // legacy plaintext values pass through
+    assert_eq!(cipher.decrypt("device_password")?, "device_password");
+
+    let other_cipher = StorageCipher::new(&[8u8; 32])?;
+    assert!(other_cipher.decrypt(&encrypted).is_err());
+
+    Ok(())
+}
+
+#[test]
+fn test_storage_key_source_recorded() -> anyhow::Result<()> {
+    let database = TempDatabase::new();
+
+    let storage = database.open()?;
+    storage.domain().add_domain(domain())?;
+    drop(storage);
+
+    // a locked keyring fails rather than falling back to a new key file
+    database.keyring.set_unavailable(true);
+    assert!(database.open().is_err());
+    assert!(database.key_file.read()?.is_none());
+
+    database.keyring.set_unavailable(false);
+    let storage = database.open()?;
+    assert_eq!(storage.domain().get_primary_domain()?.password, "password");
+    drop(storage);
+
+    // a lost key is never replaced while encrypted values exist
+    database.keyring.clear();
+    assert!(database.open().is_err());
+    assert!(database.keyring.read()?.is_none());
+
+    Ok(())
+}
+
+#[test]
+fn test_storage_key_file_stays_source() -> anyhow::Result<()> {
+    let database = TempDatabase::new();
+
+    database.keyring.set_unavailable(true);
+    let storage = database.open()?;
+    storage.domain().add_domain(domain())?;
+    drop(storage);
+    assert!(database.key_file.read()?.is_some());
+
+    // a keyring working later doesn't get a new key
+    database.keyring.set_unavailable(false);
+    let storage = database.open()?;
+    assert_eq!(storage.domain().get_primary_domain()?.password, "password");
+    assert!(database.keyring.read()?.is_none());
+
+    Ok(())
+}
+
+#[test]
+fn test_storage_key_passphrase_required() -> anyhow::Result<()> {
+    let database = TempDatabase::new();
+
+    let storage = database.open_with_passphrase(Some("passphrase"))?;
+    storage.domain().add_domain(domain())?;
+    drop(storage);
+
+    assert!(database.open().is_err());
+    assert!(database.open_with_passphrase(Some("wrong")).is_err());
+
+    let storage = database.open_with_passphrase(Some("passphrase"))?;
+    assert_eq!(storage.domain().get_primary_domain()?.password, "password");
+    assert!(database.keyring.read()?.is_none());
+
+    Ok(())
+}
+
+#[test]
+fn test_storage_secrets_encrypted() -> anyhow::Result<()> {
+    let database = TempDatabase::new();
+    let storage = database.open()?;
+
+    let domain = storage.domain().add_domain(domain())?;
+    storage
+        .temporary_password()
+        .create(domain.id, "temporary", true, None)?;
+    storage.kv().set_proxy(&ProxyConfig::Manual(Proxy {
+        kind: ProxyKind::Socks5,
+        host: String::from("127.0.0.1"),
+        port: 1080,
+        username: Some(String::from("user")),
+        password: Some(String::from("proxy_password")),
+    }))?;
+
+    let conn = Connection::open(database.path())?;
+    let password: String =
+        conn.query_row("SELECT password FROM temporary_passwords", [], |row| {
+            row.get(0)
+        })?;
+    assert!(StorageCipher::is_encrypted(&password));
+    let proxy: String = conn.query_row("SELECT value FROM kv WHERE key = 'proxy'", [], |row| {
+        row.get(0)
+    })?;
+    assert!(StorageCipher::is_encrypted(&proxy));
+    drop(conn);
+
+    assert_eq!(
+        storage
+            .temporary_password()
+            .get_valid_passwords(domain.id)?[0]
+            .password,
+        "temporary"
+    );
+    let Some(ProxyConfig::Manual(proxy)) = storage.kv().get_proxy()? else {
+        panic!("proxy should be manual");
+    };
+    assert_eq!(proxy.password.as_deref(), Some("proxy_password"));
+
+    Ok(())
+}
@@ -0,0 +1,76 @@
+use crate::{
+    api::config::entity::{
+        contact::{ConnectionPreference, Contact, ContactFilter},
+        history::{ConnectionType, HistoryFilter, SessionStart, SessionType},
+    },
+    test::TempStorage,
+};
+
+fn contact(device_id: i64, alias: &str, group_name: &str, favorite: bool) -> Contact {
+    Contact {
+        id: 0,
+        device_id,
+        domain: String::from("mirrorx.cloud"),
+        alias: alias.to_string(),
+        group_name: group_name.to_string(),
+        tags: vec![String::from("support")],
+        notes: String::default(),
+        connection_preference: ConnectionPreference::Desktop,
+        favorite,
+        created_at: 0,
+        updated_at: 0,
+    }
+}
+
+#[test]
+fn test_contact_search() -> anyhow::Result<()> {
+    let storage = TempStorage::new()?;
+
+    storage
+        .contact()
+        .create(contact(1234567890, "Reception", "Front Desk", false))?;
+    storage
+        .contact()
+        .create(contact(9876543210, "Accounting_PC", "Office", true))?;
+
+    let contacts = storage.contact().search(&ContactFilter::default())?;
+    assert_eq!(contacts.len(), 2);
+    assert_eq!(contacts[0].alias, "Accounting_PC");
+
+    let contacts = storage.contact().search(&ContactFilter {
+        keyword: Some(String::from("12-3456")),
+        ..Default::default()
+    })?;
+    assert_eq!(contacts.len(), 1);
+    assert_eq!(contacts[0].device_id, 1234567890);
+
+    // wildcards in keywords are matched literally
+    let contacts = storage.contact().search(&ContactFilter {
+        keyword: Some(String::from("g_P")),
+        ..Default::default()
+    })?;
+    assert_eq!(contacts.len(), 1);
+
+    let contacts = storage.contact().search(&ContactFilter {
+        group_name: Some(String::from("Front Desk")),
+        ..Default::default()
+    })?;
+    assert_eq!(contacts[0].alias, "Reception");
+
+    storage.history().start_session(&SessionStart {
+        device_id: 1234567890,
+        domain: String::from("mirrorx.cloud"),
+        remote_addr: String::default(),
+        session_type: SessionType::Desktop,
+        connection_type: ConnectionType::Domain,
+    })?;
+    let records = storage.history().query(&HistoryFilter::default())?.records;
+    assert_eq!(records[0].contact_alias.as_deref(), Some("Reception"));
+
+    assert_eq!(
+        storage.contact().get_group_names()?,
+        vec![String::from("Front Desk"), String::from("Office")]
+    );
+
+    Ok(())
+}
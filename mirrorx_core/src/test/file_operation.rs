@@ -0,0 +1,231 @@
+use crate::component::fs::operation::{
+    copy, create_directory, delete, move_across_devices, rename, FileOperationError,
+    FileOperationErrorKind,
+};
+use std::path::{Path, PathBuf};
+
+fn temp_root() -> anyhow::Result<PathBuf> {
+    let root = std::env::temp_dir().join(format!("mirrorx_{}", uuid::Uuid::new_v4()));
+    std::fs::create_dir_all(&root)?;
+    Ok(root)
+}
+
+fn kinds(errors: &[FileOperationError]) -> Vec<FileOperationErrorKind> {
+    errors.iter().map(|err| err.kind).collect()
+}
+
+#[test]
+fn test_create_directory_and_rename() -> anyhow::Result<()> {
+    let root = temp_root()?;
+
+    create_directory(&root.join("a"))?;
+    assert_eq!(
+        create_directory(&root.join("a")).unwrap_err().kind,
+        FileOperationErrorKind::AlreadyExists
+    );
+    assert_eq!(
+        create_directory(&root.join("missing/a")).unwrap_err().kind,
+        FileOperationErrorKind::NotFound
+    );
+    assert_eq!(
+        create_directory(Path::new("relative")).unwrap_err().kind,
+        FileOperationErrorKind::InvalidPath
+    );
+
+    std::fs::write(root.join("a/file"), b"content")?;
+    create_directory(&root.join("b"))?;
+
+    // moved into another directory
+    rename(&root.join("a/file"), &root.join("b/moved"))?;
+    assert_eq!(std::fs::read(root.join("b/moved"))?, b"content");
+
+    // an existing destination is never overwritten
+    std::fs::write(root.join("a/other"), b"other")?;
+    assert_eq!(
+        rename(&root.join("a/other"), &root.join("b/moved"))
+            .unwrap_err()
+            .kind,
+        FileOperationErrorKind::AlreadyExists
+    );
+    assert_eq!(std::fs::read(root.join("b/moved"))?, b"content");
+
+    std::fs::remove_dir_all(root)?;
+    Ok(())
+}
+
+#[test]
+fn test_copy() -> anyhow::Result<()> {
+    let root = temp_root()?;
+
+    std::fs::create_dir_all(root.join("source/nested"))?;
+    std::fs::write(root.join("source/file"), b"file")?;
+    std::fs::write(root.join("source/nested/file"), b"nested")?;
+
+    assert!(copy(&root.join("source"), &root.join("copied")).is_empty());
+    assert_eq!(std::fs::read(root.join("copied/file"))?, b"file");
+    assert_eq!(std::fs::read(root.join("copied/nested/file"))?, b"nested");
+
+    assert!(copy(&root.join("source/file"), &root.join("file")).is_empty());
+    assert_eq!(std::fs::read(root.join("file"))?, b"file");
+
+    assert_eq!(
+        kinds(&copy(&root.join("source"), &root.join("copied"))),
+        vec![FileOperationErrorKind::AlreadyExists]
+    );
+    assert_eq!(
+        kinds(&copy(
+            &root.join("source"),
+            &root.join("source/nested/inner")
+        )),
+        vec![FileOperationErrorKind::InvalidPath]
+    );
+    // `..` can't hide that the destination is inside the source
+    assert_eq!(
+        kinds(&copy(
+            &root.join("source"),
+            &root.join("source/nested/../nested/inner")
+        )),
+        vec![FileOperationErrorKind::InvalidPath]
+    );
+    assert!(!root.join("source/nested/inner").exists());
+    assert_eq!(
+        kinds(&copy(&root.join("missing"), &root.join("other"))),
+        vec![FileOperationErrorKind::NotFound]
+    );
+
+    std::fs::remove_dir_all(root)?;
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn test_copy_keeps_symlinks() -> anyhow::Result<()> {
+    let root = temp_root()?;
+
+    std::fs::create_dir_all(root.join("target"))?;
+    std::fs::write(root.join("target/file"), b"file")?;
+    std::fs::create_dir(root.join("source"))?;
+    std::os::unix::fs::symlink(root.join("target/file"), root.join("source/file_link"))?;
+    std::os::unix::fs::symlink(root.join("target"), root.join("source/directory_link"))?;
+    std::os::unix::fs::symlink(root.join("target"), root.join("link"))?;
+
+    assert!(copy(&root.join("source"), &root.join("copied")).is_empty());
+    for name in ["file_link", "directory_link"] {
+        let path = root.join("copied").join(name);
+        assert!(path.symlink_metadata()?.file_type().is_symlink());
+        assert_eq!(
+            std::fs::read_link(path)?,
+            std::fs::read_link(root.join("source").join(name))?
+        );
+    }
+
+    // a linked root is copied as a link too instead of its target's content
+    assert!(copy(&root.join("link"), &root.join("copied_link")).is_empty());
+    assert_eq!(
+        std::fs::read_link(root.join("copied_link"))?,
+        root.join("target")
+    );
+    assert_eq!(std::fs::read_dir(root.join("target"))?.count(), 1);
+
+    // the destination is inside the target of the link, not inside the link
+    assert!(copy(&root.join("link"), &root.join("link/inner")).is_empty());
+    assert!(root.join("target/inner").symlink_metadata()?.is_symlink());
+
+    std::fs::remove_dir_all(root)?;
+    Ok(())
+}
+
+#[test]
+fn test_move_across_devices() -> anyhow::Result<()> {
+    let root = temp_root()?;
+
+    std::fs::create_dir_all(root.join("source/nested"))?;
+    std::fs::write(root.join("source/nested/file"), b"nested")?;
+    std::fs::create_dir(root.join("destination"))?;
+
+    move_across_devices(&root.join("source"), &root.join("destination/moved"))?;
+    assert_eq!(
+        std::fs::read(root.join("destination/moved/nested/file"))?,
+        b"nested"
+    );
+    assert!(!root.join("source").exists());
+
+    // a failed copy leaves the source untouched
+    assert_eq!(
+        move_across_devices(&root.join("destination/moved"), &root.join("missing/moved"))
+            .unwrap_err()
+            .kind,
+        FileOperationErrorKind::NotFound
+    );
+    assert!(root.join("destination/moved/nested/file").exists());
+
+    // an existing destination is kept
+    std::fs::write(root.join("existing"), b"existing")?;
+    assert_eq!(
+        move_across_devices(&root.join("destination/moved"), &root.join("existing"))
+            .unwrap_err()
+            .kind,
+        FileOperationErrorKind::AlreadyExists
+    );
+    assert_eq!(std::fs::read(root.join("existing"))?, b"existing");
+    assert!(root.join("destination/moved/nested/file").exists());
+
+    std::fs::remove_dir_all(root)?;
+    Ok(())
+}
+
+#[test]
+fn test_delete() -> anyhow::Result<()> {
+    let root = temp_root()?;
+
+    std::fs::create_dir_all(root.join("full/nested"))?;
+    std::fs::write(root.join("full/nested/file"), b"file")?;
+    std::fs::create_dir(root.join("empty"))?;
+    std::fs::write(root.join("file"), b"file")?;
+
+    assert!(delete(&root.join("file"), false).is_empty());
+    assert!(!root.join("file").exists());
+
+    assert!(delete(&root.join("empty"), false).is_empty());
+    assert!(!root.join("empty").exists());
+
+    // a directory with content needs the recursive deletion to be confirmed
+    assert_eq!(
+        kinds(&delete(&root.join("full"), false)),
+        vec![FileOperationErrorKind::NotEmpty]
+    );
+    assert!(root.join("full/nested/file").exists());
+
+    assert!(delete(&root.join("full"), true).is_empty());
+    assert!(!root.join("full").exists());
+
+    assert_eq!(
+        kinds(&delete(&root.join("missing"), true)),
+        vec![FileOperationErrorKind::NotFound]
+    );
+    assert_eq!(
+        kinds(&delete(Path::new("/"), true)),
+        vec![FileOperationErrorKind::InvalidPath]
+    );
+
+    std::fs::remove_dir_all(root)?;
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn test_delete_keeps_symlink_target() -> anyhow::Result<()> {
+    let root = temp_root()?;
+
+    std::fs::create_dir_all(root.join("target"))?;
+    std::fs::write(root.join("target/file"), b"file")?;
+    std::fs::create_dir(root.join("directory"))?;
+    std::os::unix::fs::symlink(root.join("target"), root.join("directory/link"))?;
+
+    assert!(delete(&root.join("directory"), true).is_empty());
+    assert!(!root.join("directory").exists());
+    assert!(root.join("target/file").exists());
+
+    std::fs::remove_dir_all(root)?;
+    Ok(())
+}
@@ -0,0 +1,79 @@
+use crate::{
+    api::config::entity::history::{
+        ConnectionType, HistoryFilter, SessionResult, SessionResultKind, SessionStart, SessionType,
+    },
+    test::TempStorage,
+};
+
+fn session(device_id: i64, session_type: SessionType) -> SessionStart {
+    SessionStart {
+        device_id,
+        domain: String::from("mirrorx.cloud"),
+        remote_addr: String::default(),
+        session_type,
+        connection_type: ConnectionType::Domain,
+    }
+}
+
+#[test]
+fn test_history_sessions() -> anyhow::Result<()> {
+    let storage = TempStorage::new()?;
+
+    let id = storage
+        .history()
+        .start_session(&session(1234567890, SessionType::Desktop))?;
+    storage
+        .history()
+        .start_session(&session(1234567890, SessionType::FileManager))?;
+    storage.history().record_failure(
+        &session(9876543210, SessionType::Desktop),
+        &SessionResult::VisitFailed(String::from("InvalidPassword")),
+    )?;
+
+    let page = storage.history().query(&HistoryFilter::default())?;
+    assert_eq!(page.total, 3);
+    // live sessions have no end time yet, failures end immediately
+    assert_eq!(
+        page.records
+            .iter()
+            .filter(|record| record.end_time.is_none())
+            .count(),
+        2
+    );
+
+    storage
+        .history()
+        .finish_session(id, 1024, 4096, Some("Windows 11"))?;
+
+    let page = storage.history().query(&HistoryFilter {
+        session_type: Some(SessionType::Desktop),
+        result: Some(SessionResultKind::Success),
+        ..Default::default()
+    })?;
+    assert_eq!(page.total, 1);
+    assert!(page.records[0].end_time.is_some());
+    assert_eq!(page.records[0].bytes_sent, 1024);
+    assert_eq!(page.records[0].bytes_received, 4096);
+    assert_eq!(page.records[0].peer_os.as_deref(), Some("Windows 11"));
+
+    let page = storage.history().query(&HistoryFilter {
+        result: Some(SessionResultKind::Failed),
+        ..Default::default()
+    })?;
+    assert_eq!(page.total, 1);
+    assert_eq!(
+        page.records[0].result,
+        SessionResult::VisitFailed(String::from("InvalidPassword"))
+    );
+
+    // pages are counted from 1, total keeps the count of all matched records
+    let page = storage.history().query(&HistoryFilter {
+        page: Some(2),
+        limit: Some(2),
+        ..Default::default()
+    })?;
+    assert_eq!(page.total, 3);
+    assert_eq!(page.records.len(), 1);
+
+    Ok(())
+}
@@ -0,0 +1,94 @@
+use crate::{
+    api::config::entity::lan_bookmark::LanBookmark,
+    component::lan::{
+        handshake::{probe_node, serve_identity, IncomingHandshake},
+        identity::NodeIdentity,
+    },
+    test::TempStorage,
+    utility::lan_ip::resolve_lan_host,
+};
+use std::{
+    net::{IpAddr, Ipv4Addr, SocketAddr},
+    sync::Arc,
+};
+
+#[test]
+fn test_lan_bookmarks() -> anyhow::Result<()> {
+    let storage = TempStorage::new()?;
+
+    let mut bookmark = storage.lan_bookmark().create(LanBookmark {
+        id: 0,
+        alias: String::from("office"),
+        host: String::from(" office.example.lan "),
+        port: 48001,
+        created_at: 0,
+        updated_at: 0,
+        last_connected_at: None,
+    })?;
+    assert_eq!(bookmark.host, "office.example.lan");
+    assert_eq!(storage.lan_bookmark().get(bookmark.id)?, bookmark);
+
+    storage.lan_bookmark().touch("OFFICE.example.lan", 48001)?;
+    assert!(storage
+        .lan_bookmark()
+        .get(bookmark.id)?
+        .last_connected_at
+        .is_some());
+
+    bookmark.alias = String::from("build server");
+    bookmark.port = 48002;
+    storage.lan_bookmark().update(&bookmark)?;
+    let list = storage.lan_bookmark().list()?;
+    assert_eq!(list.len(), 1);
+    assert_eq!(list[0].alias, "build server");
+    assert_eq!(list[0].port, 48002);
+
+    bookmark.host = String::from("  ");
+    assert!(storage.lan_bookmark().update(&bookmark).is_err());
+
+    storage.lan_bookmark().delete(bookmark.id)?;
+    assert!(storage.lan_bookmark().list()?.is_empty());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_resolve_lan_host() -> anyhow::Result<()> {
+    assert_eq!(
+        resolve_lan_host("192.168.1.20", 48001).await?,
+        SocketAddr::new(IpAddr::V4(Ipv4Addr::new(192, 168, 1, 20)), 48001)
+    );
+    assert_eq!(
+        resolve_lan_host("[::1]", 48001).await?,
+        "[::1]:48001".parse::<SocketAddr>()?
+    );
+    assert!(resolve_lan_host("localhost", 48001)
+        .await?
+        .ip()
+        .is_loopback());
+    assert!(resolve_lan_host(" ", 48001).await.is_err());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_lan_probe() -> anyhow::Result<()> {
+    let identity = Arc::new(NodeIdentity::generate()?.0);
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+    let addr = listener.local_addr()?;
+
+    let server_identity = identity.clone();
+    let server = tokio::spawn(async move {
+        let (mut stream, _) = listener.accept().await?;
+        serve_identity(&mut stream, &server_identity).await
+    });
+
+    let probe = probe_node(addr).await?;
+
+    assert_eq!(server.await??, IncomingHandshake::Probe);
+    assert_eq!(probe.addr, addr);
+    assert_eq!(probe.node_id.as_deref(), Some(identity.node_id()));
+    assert_eq!(probe.fingerprint, Some(identity.fingerprint()));
+
+    Ok(())
+}
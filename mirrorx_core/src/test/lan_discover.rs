@@ -0,0 +1,431 @@
+use crate::{
+    api::{
+        config::entity::{history::SessionType, lan_node::NodeTrust},
+        endpoint::message::VideoCodec,
+    },
+    component::lan::{
+        discover::{
+            handle_broadcast_packet, merge_nodes, signed_packet, BroadcastPacket, LiveAnnouncement,
+            Node, NodeCapabilities, TargetLivePacket, MAX_DATAGRAM_SIZE, MAX_HOST_NAME_LENGTH,
+            MAX_MAC_ADDRESSES,
+        },
+        identity::NodeIdentity,
+        mdns::{handle_service_event, parse_nodes, verify_properties, LocalService, SERVICE_TYPE},
+    },
+    utility::lan_ip::LanInterface,
+};
+use mdns_sd::{ServiceEvent, ServiceInfo};
+use moka::future::Cache;
+use std::{
+    collections::HashMap,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr},
+    sync::Arc,
+};
+
+const LOCAL_NODE_ID: &str = "local";
+const TARGET_IP: IpAddr = IpAddr::V4(Ipv4Addr::new(192, 168, 1, 20));
+const REPLAY_IP: IpAddr = IpAddr::V4(Ipv4Addr::new(192, 168, 1, 66));
+
+fn live_packet(identity: &NodeIdentity, port: u16) -> anyhow::Result<BroadcastPacket> {
+    let packet = signed_packet(
+        identity,
+        &[TARGET_IP],
+        Some(LiveAnnouncement {
+            packet: TargetLivePacket::default(),
+            port,
+            capabilities: NodeCapabilities::default(),
+        }),
+    )?;
+
+    Ok(bincode::deserialize(&packet)?)
+}
+
+fn dead_packet(identity: &NodeIdentity) -> anyhow::Result<BroadcastPacket> {
+    Ok(bincode::deserialize(&signed_packet(
+        identity,
+        &[TARGET_IP],
+        None,
+    )?)?)
+}
+
+#[tokio::test]
+async fn test_broadcast_forged_packets() -> anyhow::Result<()> {
+    let cache: Cache<IpAddr, Node> = Cache::builder().build();
+    let mdns_cache: Cache<String, Vec<Node>> = Cache::builder().build();
+    let identity = NodeIdentity::generate()?.0;
+    let forger = NodeIdentity::generate()?.0;
+
+    // a tampered payload doesn't match the signature
+    let mut packet = signed_packet(&identity, &[TARGET_IP], None)?;
+    packet[20] ^= 0x01;
+    handle_broadcast_packet(
+        &cache,
+        &mdns_cache,
+        TARGET_IP,
+        bincode::deserialize(&packet)?,
+        LOCAL_NODE_ID,
+    )
+    .await;
+    assert!(cache.get(&TARGET_IP).is_none());
+
+    handle_broadcast_packet(
+        &cache,
+        &mdns_cache,
+        TARGET_IP,
+        live_packet(&identity, 48002)?,
+        LOCAL_NODE_ID,
+    )
+    .await;
+    let node = cache.get(&TARGET_IP).expect("signed node should be cached");
+    assert_eq!(node.port, 48002);
+    assert_eq!(node.fingerprint, Some(identity.fingerprint()));
+
+    // neither unsigned packets nor packets signed by another key take it over
+    let forged_packets = [
+        BroadcastPacket::TargetLive(TargetLivePacket::default()),
+        BroadcastPacket::TargetDead,
+        live_packet(&forger, 48003)?,
+        dead_packet(&forger)?,
+    ];
+    for packet in forged_packets {
+        handle_broadcast_packet(&cache, &mdns_cache, TARGET_IP, packet, LOCAL_NODE_ID).await;
+        assert_eq!(cache.get(&TARGET_IP).map(|node| node.port), Some(48002));
+    }
+
+    handle_broadcast_packet(
+        &cache,
+        &mdns_cache,
+        TARGET_IP,
+        dead_packet(&identity)?,
+        LOCAL_NODE_ID,
+    )
+    .await;
+    assert!(cache.get(&TARGET_IP).is_none());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_broadcast_replayed_packets() -> anyhow::Result<()> {
+    let cache: Cache<IpAddr, Node> = Cache::builder().build();
+    let mdns_cache: Cache<String, Vec<Node>> = Cache::builder().build();
+    let identity = Arc::new(NodeIdentity::generate()?.0);
+
+    // a captured announcement sent again from another host
+    handle_broadcast_packet(
+        &cache,
+        &mdns_cache,
+        REPLAY_IP,
+        live_packet(&identity, 48002)?,
+        LOCAL_NODE_ID,
+    )
+    .await;
+    assert!(cache.get(&REPLAY_IP).is_none());
+
+    // a replayed leave doesn't remove the node either
+    let info = local_service(identity.clone()).service_info(&NodeCapabilities::default())?;
+    mdns_cache
+        .insert(info.get_fullname().to_string(), parse_nodes(&info))
+        .await;
+    handle_broadcast_packet(
+        &cache,
+        &mdns_cache,
+        REPLAY_IP,
+        dead_packet(&identity)?,
+        LOCAL_NODE_ID,
+    )
+    .await;
+    assert!(mdns_cache.get(info.get_fullname()).is_some());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_broadcast_packet_size() -> anyhow::Result<()> {
+    let cache: Cache<IpAddr, Node> = Cache::builder().build();
+    let mdns_cache: Cache<String, Vec<Node>> = Cache::builder().build();
+    let identity = NodeIdentity::generate()?.0;
+
+    let interfaces: Vec<LanInterface> = (0..64u16)
+        .map(|index| LanInterface {
+            name: format!("eth{}", index),
+            ip: IpAddr::V6(Ipv6Addr::new(0xfe80, 0, 0, 0, 0, 0, 0, index)),
+            broadcast: None,
+            netmask: None,
+            mac: Some(format!("02:00:00:00:00:{:02x}", index)),
+        })
+        .collect();
+
+    let capabilities = NodeCapabilities {
+        protocol_version: u32::MAX,
+        client_version: "255.255.65535-rc.65535".to_string(),
+        session_types: vec![
+            SessionType::Desktop,
+            SessionType::FileManager,
+            SessionType::Unknown,
+        ],
+        video_codecs: vec![
+            VideoCodec::H264,
+            VideoCodec::Hevc,
+            VideoCodec::VP8,
+            VideoCodec::VP9,
+        ],
+        password_required: true,
+        device_id: Some(i64::MAX),
+        session_count: u32::MAX,
+        ..NodeCapabilities::local(None, &interfaces)
+    };
+    assert_eq!(capabilities.mac_addresses.len(), MAX_MAC_ADDRESSES);
+
+    // multibyte chars so the cut has to find a char boundary
+    let live = TargetLivePacket::new(
+        "名".repeat(MAX_HOST_NAME_LENGTH),
+        "Redhat Enterprise".to_string(),
+        "Rolling Release".to_string(),
+    );
+    let mut addrs: Vec<IpAddr> = interfaces.iter().map(|interface| interface.ip).collect();
+    addrs.push(TARGET_IP);
+
+    let packet = signed_packet(
+        &identity,
+        &addrs,
+        Some(LiveAnnouncement {
+            packet: live,
+            port: u16::MAX,
+            capabilities,
+        }),
+    )?;
+    assert!(packet.len() <= MAX_DATAGRAM_SIZE);
+
+    handle_broadcast_packet(
+        &cache,
+        &mdns_cache,
+        TARGET_IP,
+        bincode::deserialize(&packet)?,
+        LOCAL_NODE_ID,
+    )
+    .await;
+    let node = cache.get(&TARGET_IP).expect("signed node should be cached");
+    assert!(node.host_name.len() <= MAX_HOST_NAME_LENGTH);
+    assert!(node.host_name.chars().all(|c| c == '名'));
+    assert_eq!(node.capabilities.mac_addresses.len(), MAX_MAC_ADDRESSES);
+
+    Ok(())
+}
+
+fn local_service(identity: Arc<NodeIdentity>) -> LocalService {
+    LocalService {
+        identity,
+        host_name: String::from("office"),
+        os: String::from("Linux"),
+        os_version: String::from("6.1"),
+        addrs: vec![TARGET_IP],
+        server_port: 48001,
+    }
+}
+
+/// Builds a service from the TXT records of `info` with `name` and `properties` changed.
+fn forge_service(
+    info: &ServiceInfo,
+    name: &str,
+    properties: &[(&str, &str)],
+) -> anyhow::Result<ServiceInfo> {
+    let mut forged_properties: HashMap<String, String> = info
+        .get_properties()
+        .iter()
+        .map(|property| (property.key().to_string(), property.val_str().to_string()))
+        .collect();
+
+    for (key, value) in properties {
+        forged_properties.insert(key.to_string(), value.to_string());
+    }
+
+    Ok(ServiceInfo::new(
+        SERVICE_TYPE,
+        name,
+        "forged.local.",
+        &[REPLAY_IP][..],
+        48001,
+        forged_properties,
+    )?)
+}
+
+#[test]
+fn test_mdns_verify_properties() -> anyhow::Result<()> {
+    let identity = Arc::new(NodeIdentity::generate()?.0);
+    let info = local_service(identity.clone()).service_info(&NodeCapabilities::default())?;
+
+    assert_eq!(
+        verify_properties(&info)?,
+        Some(identity.public_key().to_vec())
+    );
+
+    let nodes = parse_nodes(&info);
+    assert_eq!(nodes.len(), 1);
+    assert_eq!(nodes[0].node_id.as_deref(), Some(identity.node_id()));
+    assert_eq!(nodes[0].trust, NodeTrust::Unknown);
+
+    // changed TXT records no longer match the signature
+    let forged = forge_service(&info, identity.node_id(), &[("port", "1")])?;
+    assert!(verify_properties(&forged).is_err());
+    assert!(parse_nodes(&forged).is_empty());
+
+    // valid records replayed under another instance name
+    let renamed = forge_service(&info, "forged", &[])?;
+    assert!(verify_properties(&renamed)?.is_some());
+    assert!(parse_nodes(&renamed).is_empty());
+
+    // valid records replayed from an address they don't list
+    let replayed = forge_service(&info, identity.node_id(), &[])?;
+    assert!(verify_properties(&replayed)?.is_some());
+    assert!(parse_nodes(&replayed).is_empty());
+
+    // services without a signature come from versions without identities
+    let unsigned = ServiceInfo::new(
+        SERVICE_TYPE,
+        "legacy",
+        "legacy.local.",
+        &[TARGET_IP][..],
+        48001,
+        HashMap::from([(String::from("host_name"), String::from("legacy"))]),
+    )?;
+    assert_eq!(verify_properties(&unsigned)?, None);
+    assert_eq!(parse_nodes(&unsigned)[0].trust, NodeTrust::Unsigned);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_mdns_signed_service_takeover() -> anyhow::Result<()> {
+    let nodes: Cache<String, Vec<Node>> = Cache::builder().build();
+    let identity = Arc::new(NodeIdentity::generate()?.0);
+    let info = local_service(identity.clone()).service_info(&NodeCapabilities::default())?;
+    let fullname = info.get_fullname().to_string();
+
+    handle_service_event(
+        &nodes,
+        ServiceEvent::ServiceResolved(info.clone()),
+        LOCAL_NODE_ID,
+    )
+    .await;
+    let fingerprint = Some(identity.fingerprint());
+    assert_eq!(nodes.get(&fullname).unwrap()[0].fingerprint, fingerprint);
+
+    // another key signing a service with the same instance name and id
+    let mut forger = local_service(Arc::new(NodeIdentity::from_pkcs8(
+        identity.node_id().to_string(),
+        NodeIdentity::generate()?.1.as_ref(),
+    )?));
+    forger.addrs = vec![REPLAY_IP];
+    let forged = forger.service_info(&NodeCapabilities::default())?;
+    assert_eq!(forged.get_fullname(), fullname);
+    handle_service_event(&nodes, ServiceEvent::ServiceResolved(forged), LOCAL_NODE_ID).await;
+    assert_eq!(nodes.get(&fullname).unwrap()[0].addr, TARGET_IP);
+
+    // an unsigned one under the same name
+    let unsigned = ServiceInfo::new(
+        SERVICE_TYPE,
+        identity.node_id(),
+        "forged.local.",
+        &[REPLAY_IP][..],
+        48001,
+        HashMap::<String, String>::new(),
+    )?;
+    handle_service_event(
+        &nodes,
+        ServiceEvent::ServiceResolved(unsigned),
+        LOCAL_NODE_ID,
+    )
+    .await;
+    assert_eq!(nodes.get(&fullname).unwrap()[0].fingerprint, fingerprint);
+
+    // goodbye packets aren't signed and keep signed services
+    handle_service_event(
+        &nodes,
+        ServiceEvent::ServiceRemoved(SERVICE_TYPE.to_string(), fullname.clone()),
+        LOCAL_NODE_ID,
+    )
+    .await;
+    assert!(nodes.get(&fullname).is_some());
+
+    // until the node says it left in a signed packet
+    let cache: Cache<IpAddr, Node> = Cache::builder().build();
+    handle_broadcast_packet(
+        &cache,
+        &nodes,
+        TARGET_IP,
+        dead_packet(&identity)?,
+        LOCAL_NODE_ID,
+    )
+    .await;
+    assert!(nodes.get(&fullname).is_none());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_mdns_unsigned_service_removed() -> anyhow::Result<()> {
+    let nodes: Cache<String, Vec<Node>> = Cache::builder().build();
+    let unsigned = ServiceInfo::new(
+        SERVICE_TYPE,
+        "legacy",
+        "legacy.local.",
+        &[TARGET_IP][..],
+        48001,
+        HashMap::<String, String>::new(),
+    )?;
+    let fullname = unsigned.get_fullname().to_string();
+
+    handle_service_event(
+        &nodes,
+        ServiceEvent::ServiceResolved(unsigned),
+        LOCAL_NODE_ID,
+    )
+    .await;
+    assert!(nodes.get(&fullname).is_some());
+
+    handle_service_event(
+        &nodes,
+        ServiceEvent::ServiceRemoved(SERVICE_TYPE.to_string(), fullname.clone()),
+        LOCAL_NODE_ID,
+    )
+    .await;
+    assert!(nodes.get(&fullname).is_none());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_merge_nodes_prefers_signed() -> anyhow::Result<()> {
+    let mdns_cache: Cache<String, Vec<Node>> = Cache::builder().build();
+    let cache: Cache<IpAddr, Node> = Cache::builder().build();
+    let identity = NodeIdentity::generate()?.0;
+
+    let unsigned = ServiceInfo::new(
+        SERVICE_TYPE,
+        "spoofed",
+        "spoofed.local.",
+        &[TARGET_IP][..],
+        48001,
+        HashMap::<String, String>::new(),
+    )?;
+    mdns_cache
+        .insert(unsigned.get_fullname().to_string(), parse_nodes(&unsigned))
+        .await;
+
+    handle_broadcast_packet(
+        &cache,
+        &mdns_cache,
+        TARGET_IP,
+        live_packet(&identity, 48002)?,
+        LOCAL_NODE_ID,
+    )
+    .await;
+
+    let nodes = merge_nodes(&mdns_cache, &cache);
+    assert_eq!(nodes.len(), 1);
+    assert_eq!(nodes[0].fingerprint, Some(identity.fingerprint()));
+    assert_eq!(nodes[0].port, 48002);
+
+    Ok(())
+}
@@ -0,0 +1,136 @@
+use crate::{
+    api::config::entity::lan_node::{LanPeer, NodeTrust},
+    component::lan::{
+        discover::{NodeCapabilities, LAN_PROTOCOL_VERSION},
+        handshake::{request_identity, serve_identity, IncomingHandshake},
+        identity::{verify, NodeIdentity},
+    },
+    test::TempStorage,
+};
+use std::sync::Arc;
+
+#[test]
+fn test_lan_identity_persistence() -> anyhow::Result<()> {
+    let storage = TempStorage::new()?;
+
+    let identity = storage.lan_node().identity()?;
+    let loaded = storage.lan_node().identity()?;
+    assert_eq!(identity.node_id(), loaded.node_id());
+    assert_eq!(identity.fingerprint(), loaded.fingerprint());
+
+    let signature = loaded.sign(b"announcement");
+    assert!(verify(identity.public_key(), b"announcement", &signature));
+    assert!(!verify(identity.public_key(), b"forged", &signature));
+
+    Ok(())
+}
+
+#[test]
+fn test_lan_node_pinning() -> anyhow::Result<()> {
+    let storage = TempStorage::new()?;
+
+    assert_eq!(
+        storage.lan_node().trust("node", "fingerprint")?,
+        NodeTrust::Unknown
+    );
+
+    storage.lan_node().pin("node", "fingerprint", "host")?;
+    assert_eq!(
+        storage.lan_node().trust("node", "fingerprint")?,
+        NodeTrust::Trusted
+    );
+    assert_eq!(
+        storage.lan_node().trust("node", "other")?,
+        NodeTrust::KeyChanged
+    );
+
+    // pinning the same key again only refreshes the node
+    storage.lan_node().pin("node", "fingerprint", "renamed")?;
+    assert_eq!(storage.lan_node().list()?[0].host_name, "renamed");
+
+    assert!(storage.lan_node().pin("node", "other", "host").is_err());
+
+    storage.lan_node().delete("node")?;
+    storage.lan_node().pin("node", "other", "host")?;
+    assert_eq!(
+        storage.lan_node().trust("node", "other")?,
+        NodeTrust::Trusted
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_lan_identity_handshake() -> anyhow::Result<()> {
+    let identity = Arc::new(NodeIdentity::generate()?.0);
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+    let addr = listener.local_addr()?;
+
+    let server_identity = identity.clone();
+    let server = tokio::spawn(async move {
+        let (mut stream, _) = listener.accept().await?;
+        serve_identity(&mut stream, &server_identity).await
+    });
+
+    let mut stream = tokio::net::TcpStream::connect(addr).await?;
+    let peer = request_identity(&mut stream).await?;
+
+    assert_eq!(server.await??, IncomingHandshake::Verified);
+    assert_eq!(peer.node_id, identity.node_id());
+    assert_eq!(peer.fingerprint, identity.fingerprint());
+
+    Ok(())
+}
+
+#[test]
+fn test_node_capabilities_tolerant() -> anyhow::Result<()> {
+    // announced by versions before capabilities existed
+    assert_eq!(
+        serde_json::from_str::<NodeCapabilities>("{}")?,
+        NodeCapabilities::default()
+    );
+
+    // announced by a newer version with fields unknown to this one
+    let capabilities: NodeCapabilities =
+        serde_json::from_str(r#"{"protocol_version":9,"session_count":2,"remote_printing":true}"#)?;
+    assert_eq!(capabilities.protocol_version, 9);
+    assert_eq!(capabilities.session_count, 2);
+    assert!(capabilities.session_types.is_empty());
+
+    let local = NodeCapabilities::local(None, &[]);
+    assert_eq!(local.protocol_version, LAN_PROTOCOL_VERSION);
+    assert_eq!(local.device_id, None);
+    assert_eq!(
+        serde_json::from_str::<NodeCapabilities>(&serde_json::to_string(&local)?)?,
+        local
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_lan_peers() -> anyhow::Result<()> {
+    let storage = TempStorage::new()?;
+
+    let mut peer = LanPeer {
+        addr: String::from("192.168.1.20"),
+        mac_addresses: vec![String::from("00:11:22:33:44:55")],
+        host_name: String::from("host"),
+        node_id: None,
+        updated_at: 1,
+    };
+
+    storage.lan_node().save_peer(&peer)?;
+    assert_eq!(storage.lan_node().get_peer(&peer.addr)?, Some(peer.clone()));
+
+    // a peer learned again replaces the stored one
+    peer.mac_addresses.push(String::from("66:77:88:99:aa:bb"));
+    peer.node_id = Some(String::from("node"));
+    storage.lan_node().save_peer(&peer)?;
+    assert_eq!(storage.lan_node().list_peers()?, vec![peer.clone()]);
+
+    storage.lan_node().delete_peer(&peer.addr)?;
+    assert_eq!(storage.lan_node().get_peer(&peer.addr)?, None);
+
+    Ok(())
+}
@@ -0,0 +1,233 @@
+use crate::{
+    api::config::{
+        cipher::StorageCipher,
+        entity::{
+            contact::{ConnectionPreference, Contact, ContactFilter},
+            domain::Domain,
+            history::{ConnectionType, HistoryFilter, SessionResult, SessionStart, SessionType},
+        },
+        migration::{backup_path, schema_version, LATEST_SCHEMA_VERSION},
+    },
+    test::TempDatabase,
+};
+use rusqlite::Connection;
+
+#[test]
+fn test_migrate_new_database() -> anyhow::Result<()> {
+    let database = TempDatabase::new();
+    let db_path = database.path();
+
+    let storage = database.open()?;
+    assert_eq!(storage.domain().get_domain_count()?, 0);
+    drop(storage);
+
+    let conn = Connection::open(db_path)?;
+    assert_eq!(schema_version(&conn)?, LATEST_SCHEMA_VERSION);
+    assert!(!backup_path(db_path, 0).exists());
+
+    Ok(())
+}
+
+#[test]
+fn test_migrate_unversioned_database() -> anyhow::Result<()> {
+    let database = TempDatabase::new();
+    let db_path = database.path();
+
+    // schema created by versions before migrations were introduced
+    let conn = Connection::open(db_path)?;
+    conn.execute_batch(
+        r"
+        CREATE TABLE domains(
+            id INTEGER PRIMARY KEY,
+            name TEXT NOT NULL UNIQUE,
+            addr TEXT NOT NULL,
+            signaling_port INTEGER NOT NULL,
+            subscribe_port INTEGER NOT NULL,
+            is_primary BOOLEAN NOT NULL,
+            device_id INTEGER NOT NULL,
+            password TEXT NOT NULL,
+            finger_print TEXT NOT NULL,
+            remarks TEXT NOT NULL
+        );
+        CREATE TABLE kv(
+            id INTEGER PRIMARY KEY,
+            key TEXT NOT NULL UNIQUE,
+            value TEXT NOT NULL
+        );
+        CREATE TABLE history(
+            id INTEGER PRIMARY KEY,
+            device_id INTEGER NOT NULL,
+            domain TEXT NOT NULL,
+            timestamp INTEGER NOT NULL
+        );
+        INSERT INTO domains VALUES(1, 'mirrorx.cloud', 'http://mirrorx.cloud:28000', 28000, 28001, 1, 1234567890, 'password', 'finger_print', '');
+        INSERT INTO kv(key, value) VALUES('language', 'en');
+        INSERT INTO history(device_id, domain, timestamp) VALUES(9876543210, 'mirrorx.cloud', 1660000000);
+        ",
+    )?;
+    drop(conn);
+
+    let storage = database.open()?;
+
+    let domain = storage.domain().get_primary_domain()?;
+    assert_eq!(domain.name, "mirrorx.cloud");
+    assert_eq!(domain.device_id, 1234567890);
+    assert_eq!(domain.password, "password");
+    assert_eq!(domain.tls_pin, "");
+    assert_eq!(domain.expire, 0);
+    assert_eq!(storage.kv().get_language()?, Some(String::from("en")));
+    assert!(storage
+        .temporary_password()
+        .get_valid_passwords(1)?
+        .is_empty());
+
+    // one row per device becomes a session with unknown details
+    let records = storage
+        .history()
+        .query_by_device(9876543210, "mirrorx.cloud")?;
+    assert_eq!(records.len(), 1);
+    assert_eq!(records[0].timestamp, 1660000000);
+    assert_eq!(records[0].end_time, Some(1660000000));
+    assert_eq!(records[0].session_type, SessionType::Unknown);
+    assert_eq!(records[0].result, SessionResult::Success);
+    drop(storage);
+
+    let conn = Connection::open(db_path)?;
+    assert_eq!(schema_version(&conn)?, LATEST_SCHEMA_VERSION);
+
+    // secrets written by old versions are encrypted in place
+    let (password, finger_print): (String, String) =
+        conn.query_row("SELECT password, finger_print FROM domains", [], |row| {
+            Ok((row.get(0)?, row.get(1)?))
+        })?;
+    assert!(StorageCipher::is_encrypted(&password));
+    assert!(StorageCipher::is_encrypted(&finger_print));
+    drop(conn);
+
+    // the backup still holding plaintext secrets is removed after success
+    assert!(!backup_path(db_path, 0).exists());
+
+    Ok(())
+}
+
+#[test]
+fn test_migrate_failed_keeps_backup() -> anyhow::Result<()> {
+    let database = TempDatabase::new();
+    let db_path = database.path();
+
+    // a table of a later step already exists, so that step fails
+    let conn = Connection::open(db_path)?;
+    conn.execute_batch(
+        r"
+        CREATE TABLE domains(id INTEGER PRIMARY KEY, name TEXT NOT NULL UNIQUE);
+        CREATE TABLE contacts(id INTEGER PRIMARY KEY);
+        INSERT INTO domains(name) VALUES('mirrorx.cloud');
+        ",
+    )?;
+    drop(conn);
+
+    assert!(database.open().is_err());
+
+    // the backup keeps the old schema and data to restore from
+    let backup_path = backup_path(db_path, 0);
+    let backup_conn = Connection::open(&backup_path)?;
+    assert_eq!(schema_version(&backup_conn)?, 0);
+    let name: String = backup_conn.query_row("SELECT name FROM domains", [], |row| row.get(0))?;
+    assert_eq!(name, "mirrorx.cloud");
+    drop(backup_conn);
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let mode = std::fs::metadata(&backup_path)?.permissions().mode();
+        assert_eq!(mode & 0o077, 0);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_migrate_newer_database() -> anyhow::Result<()> {
+    let database = TempDatabase::new();
+    let db_path = database.path();
+
+    let conn = Connection::open(db_path)?;
+    conn.pragma_update(None, "user_version", LATEST_SCHEMA_VERSION + 1)?;
+    drop(conn);
+
+    assert!(database.open().is_err());
+
+    Ok(())
+}
+
+#[test]
+fn test_columns_added_later_keep_fields() -> anyhow::Result<()> {
+    let database = TempDatabase::new();
+
+    let storage = database.open()?;
+    storage.domain().add_domain(Domain {
+        id: 0,
+        name: String::from("mirrorx.cloud"),
+        addr: String::from("http://mirrorx.cloud:28000"),
+        signaling_port: 28000,
+        subscribe_port: 28001,
+        is_primary: true,
+        device_id: 1234567890,
+        password: String::from("password"),
+        finger_print: String::from("finger_print"),
+        remarks: String::from("remarks"),
+        tls_pin: String::from("tls_pin"),
+        expire: 42,
+    })?;
+    storage.contact().create(Contact {
+        id: 0,
+        device_id: 9876543210,
+        domain: String::from("mirrorx.cloud"),
+        alias: String::from("office"),
+        group_name: String::from("work"),
+        tags: vec![String::from("linux")],
+        notes: String::default(),
+        connection_preference: ConnectionPreference::FileManager,
+        favorite: true,
+        created_at: 0,
+        updated_at: 0,
+    })?;
+    storage.history().start_session(&SessionStart {
+        device_id: 9876543210,
+        domain: String::from("mirrorx.cloud"),
+        remote_addr: String::from("192.168.1.20"),
+        session_type: SessionType::FileManager,
+        connection_type: ConnectionType::Lan,
+    })?;
+    drop(storage);
+
+    // columns a later migration adds land behind the existing ones
+    Connection::open(database.path())?.execute_batch(
+        r"
+        ALTER TABLE domains ADD COLUMN added TEXT;
+        ALTER TABLE contacts ADD COLUMN added TEXT;
+        ALTER TABLE history ADD COLUMN added TEXT;
+        ",
+    )?;
+
+    let storage = database.open()?;
+    let domain = storage.domain().get_primary_domain()?;
+    assert_eq!(domain.password, "password");
+    assert_eq!(domain.finger_print, "finger_print");
+    assert_eq!(domain.tls_pin, "tls_pin");
+    assert_eq!(domain.expire, 42);
+
+    let contacts = storage.contact().search(&ContactFilter::default())?;
+    assert_eq!(contacts.len(), 1);
+    assert_eq!(contacts[0].tags, vec![String::from("linux")]);
+    assert!(contacts[0].favorite);
+
+    let records = storage.history().query(&HistoryFilter::default())?.records;
+    assert_eq!(records.len(), 1);
+    assert_eq!(records[0].remote_addr, "192.168.1.20");
+    assert_eq!(records[0].session_type, SessionType::FileManager);
+    assert_eq!(records[0].contact_id, Some(contacts[0].id));
+    assert_eq!(records[0].contact_alias.as_deref(), Some("office"));
+
+    Ok(())
+}
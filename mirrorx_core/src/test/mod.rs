@@ -1,6 +1,152 @@
 mod audio;
+mod audit;
+mod bundle;
+mod cipher;
+mod contact;
 mod decode;
 mod display;
 mod duplicator;
 mod encode;
+mod file_operation;
+mod history;
+mod lan_bookmark;
+mod lan_discover;
+mod lan_node;
+mod migration;
 mod mouse;
+mod proxy;
+mod settings;
+mod temporary_password;
+mod tls;
+mod transfer;
+mod transfer_progress;
+mod transfer_queue;
+mod tree;
+mod version;
+mod wake;
+
+use crate::{
+    api::config::{
+        cipher::{KeyStore, KeyStores},
+        migration::{backup_path, LATEST_SCHEMA_VERSION},
+        LocalStorage,
+    },
+    core_error,
+    error::CoreResult,
+};
+use std::{
+    ops::Deref,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+};
+
+/// Key store kept in memory, so tests never touch the OS keyring or a shared key file.
+#[derive(Default)]
+pub(crate) struct MemoryKeyStore {
+    raw_key: Mutex<Option<Vec<u8>>>,
+    unavailable: AtomicBool,
+}
+
+impl MemoryKeyStore {
+    /// Makes the store fail like a locked keychain.
+    pub fn set_unavailable(&self, unavailable: bool) {
+        self.unavailable.store(unavailable, Ordering::SeqCst);
+    }
+
+    pub fn clear(&self) {
+        *self.raw_key.lock().unwrap() = None;
+    }
+}
+
+impl KeyStore for MemoryKeyStore {
+    fn read(&self) -> CoreResult<Option<Vec<u8>>> {
+        if self.unavailable.load(Ordering::SeqCst) {
+            return Err(core_error!("key store unavailable"));
+        }
+
+        Ok(self.raw_key.lock().unwrap().clone())
+    }
+
+    fn write(&self, raw_key: &[u8]) -> CoreResult<()> {
+        if self.unavailable.load(Ordering::SeqCst) {
+            return Err(core_error!("key store unavailable"));
+        }
+
+        *self.raw_key.lock().unwrap() = Some(raw_key.to_vec());
+        Ok(())
+    }
+}
+
+/// Path of a database file of its own, the file and its migration backups are
+/// removed when dropped, also when the test failed early.
+pub(crate) struct TempDatabase {
+    path: PathBuf,
+    pub keyring: Arc<MemoryKeyStore>,
+    pub key_file: Arc<MemoryKeyStore>,
+}
+
+impl TempDatabase {
+    pub fn new() -> Self {
+        Self {
+            path: std::env::temp_dir().join(format!("mirrorx_{}.db", uuid::Uuid::new_v4())),
+            keyring: Arc::default(),
+            key_file: Arc::default(),
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Opens the storage with the in-memory key stores of this database.
+    pub fn open(&self) -> anyhow::Result<LocalStorage> {
+        self.open_with_passphrase(None)
+    }
+
+    pub fn open_with_passphrase(&self, passphrase: Option<&str>) -> anyhow::Result<LocalStorage> {
+        let key_stores = KeyStores {
+            passphrase: passphrase.map(String::from),
+            keyring: self.keyring.clone(),
+            file: self.key_file.clone(),
+        };
+
+        Ok(LocalStorage::with_key_stores(&self.path, &key_stores)?)
+    }
+}
+
+impl Drop for TempDatabase {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+        for version in 0..=LATEST_SCHEMA_VERSION {
+            let _ = std::fs::remove_file(backup_path(&self.path, version));
+        }
+    }
+}
+
+/// Storage opened on a [`TempDatabase`], closed before its file is removed.
+pub(crate) struct TempStorage {
+    storage: LocalStorage,
+    _database: TempDatabase,
+}
+
+impl TempStorage {
+    pub fn new() -> anyhow::Result<Self> {
+        let database = TempDatabase::new();
+
+        Ok(Self {
+            storage: database.open()?,
+            _database: database,
+        })
+    }
+}
+
+impl Deref for TempStorage {
+    type Target = LocalStorage;
+
+    fn deref(&self) -> &Self::Target {
+        &self.storage
+    }
+}
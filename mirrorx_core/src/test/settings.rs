@@ -0,0 +1,94 @@
+use crate::{
+    api::{
+        config::settings::{current_settings, set_current_settings, subscribe_settings, Settings},
+        endpoint::message::VideoCodec,
+    },
+    test::TempStorage,
+};
+
+#[test]
+fn test_settings_storage() -> anyhow::Result<()> {
+    let storage = TempStorage::new()?;
+
+    assert_eq!(storage.kv().get_settings()?, Settings::default());
+
+    let settings = storage
+        .kv()
+        .set_setting("frame_rate", serde_json::json!(30))?;
+    assert_eq!(settings.frame_rate, 30);
+
+    assert!(storage
+        .kv()
+        .set_setting("frame_rate", serde_json::json!(0))
+        .is_err());
+    assert!(storage
+        .kv()
+        .set_setting("frame_rate", serde_json::json!("fast"))
+        .is_err());
+    assert!(storage
+        .kv()
+        .set_setting("unknown", serde_json::json!(1))
+        .is_err());
+
+    assert!(storage
+        .kv()
+        .set_settings(&Settings {
+            lan_discover_port: 48001,
+            ..Default::default()
+        })
+        .is_err());
+
+    let settings = storage
+        .kv()
+        .set_setting("lan_interface", serde_json::json!("eth0"))?;
+    assert_eq!(settings.lan_interface.as_deref(), Some("eth0"));
+
+    let settings = storage
+        .kv()
+        .set_setting("lan_interface", serde_json::Value::Null)?;
+    assert_eq!(settings.lan_interface, None);
+
+    assert!(storage
+        .kv()
+        .set_setting("lan_interface", serde_json::json!(""))
+        .is_err());
+
+    // broken values written by other versions or imports fall back to defaults
+    storage.kv().import("settings.max_bitrate_kbps", "1")?;
+    storage.kv().import("settings.preferred_codec", "\"VP9\"")?;
+
+    let settings = storage.kv().get_settings()?;
+    assert_eq!(settings.frame_rate, 30);
+    assert_eq!(settings.max_bitrate_kbps, 4000);
+    assert_eq!(settings.preferred_codec, VideoCodec::VP9);
+
+    Ok(())
+}
+
+/// Restores the process wide settings when dropped, even when the test panics, so
+/// tests running in parallel keep reading the defaults.
+struct RestoreSettings(Settings);
+
+impl Drop for RestoreSettings {
+    fn drop(&mut self) {
+        set_current_settings(self.0.clone());
+    }
+}
+
+#[test]
+fn test_settings_notification() {
+    let _restore = RestoreSettings(current_settings());
+
+    let mut settings_rx = subscribe_settings();
+    let mut settings = current_settings();
+    settings.audio_enabled = !settings.audio_enabled;
+
+    set_current_settings(settings.clone());
+
+    assert!(settings_rx.has_changed().unwrap_or_default());
+    assert_eq!(*settings_rx.borrow_and_update(), settings);
+
+    // setting the same value again doesn't notify subscribers
+    set_current_settings(settings);
+    assert!(!settings_rx.has_changed().unwrap_or_default());
+}
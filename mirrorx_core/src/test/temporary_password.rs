@@ -0,0 +1,40 @@
+use crate::test::TempStorage;
+use std::time::Duration;
+
+#[test]
+fn test_temporary_passwords() -> anyhow::Result<()> {
+    let storage = TempStorage::new()?;
+    let passwords = storage.temporary_password();
+
+    let permanent = passwords.create(1, "permanent", false, None)?;
+    let one_time = passwords.create(1, "one_time", true, Some(Duration::from_secs(3600)))?;
+    passwords.create(1, "expired", false, Some(Duration::ZERO))?;
+    passwords.create(2, "other_domain", false, None)?;
+
+    // expired passwords are never valid, the others are ordered by creation time
+    let valid: Vec<String> = passwords
+        .get_valid_passwords(1)?
+        .into_iter()
+        .map(|password| password.password)
+        .collect();
+    assert_eq!(valid, vec!["permanent", "one_time"]);
+    assert_eq!(
+        passwords.get_valid_passwords(1)?[1].expire_at,
+        Some(one_time.created_at + 3600)
+    );
+
+    // one-time passwords are consumed once, the others never
+    assert!(passwords.consume(one_time.id)?);
+    assert!(!passwords.consume(one_time.id)?);
+    assert!(!passwords.consume(permanent.id)?);
+    assert_eq!(passwords.get_valid_passwords(1)?.len(), 1);
+
+    passwords.delete_expired()?;
+    passwords.delete(permanent.id)?;
+    assert!(passwords.get_valid_passwords(1)?.is_empty());
+
+    passwords.delete_domain_related(2)?;
+    assert!(passwords.get_valid_passwords(2)?.is_empty());
+
+    Ok(())
+}
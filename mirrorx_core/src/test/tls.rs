@@ -0,0 +1,125 @@
+use crate::api::signaling::{
+    tls::{extract_subject_public_key_info, read_der_element, TlsPin, TlsPinKind},
+    SignalingClient,
+};
+use rustls::Certificate;
+
+// self-signed P-256 certificate for CN=mirrorx.test
+const CERTIFICATE: &str = "MIIBhDCCASugAwIBAgIUQHf3ffn1q61drCcHXT7H1nNPi9YwCgYIKoZIzj0EAwIw\
+FzEVMBMGA1UEAwwMbWlycm9yeC50ZXN0MCAXDTI2MTAxOTA0NDEwOVoYDzIxMjYw\
+OTI1MDQ0MTA5WjAXMRUwEwYDVQQDDAxtaXJyb3J4LnRlc3QwWTATBgcqhkjOPQIB\
+BggqhkjOPQMBBwNCAASUDNXWI87E+YbNfbqi59vWgQBS50ehdczEFjA2kSJZbdI/\
+kajmFYNM9rt1kTpqwz9wXG5c1A9XOdTyXr3GIPJ5o1MwUTAdBgNVHQ4EFgQUTMKD\
+QoY840FpC3ff/k+EXhHqDOUwHwYDVR0jBBgwFoAUTMKDQoY840FpC3ff/k+EXhHq\
+DOUwDwYDVR0TAQH/BAUwAwEB/zAKBggqhkjOPQQDAgNHADBEAiAY4gkhKHQDLzJ1\
+bM23IYo8dNgccCj5sbhXSZdvqFj1SgIgCNlQVU/Cz0Fmc+lSz2Yo8+Jwz8939A8G\
+SfQG0svVYqs=";
+
+// sha256 of the DER certificate and of `openssl x509 -pubkey | openssl pkey -pubin -outform der`
+const CERTIFICATE_DIGEST: &str = "63b5f9ab8b06869bd04b0ed602eb0eb5c0df03bbc686ae8d7541d34927650dfb";
+const PUBLIC_KEY_DIGEST: &str = "322dce4032e7f9359d40fa217cb4d0969927b617b0443f2af04d7528bfaf260c";
+
+#[test]
+fn test_extract_subject_public_key_info() -> anyhow::Result<()> {
+    let certificate = base64::decode(CERTIFICATE)?;
+
+    let spki = extract_subject_public_key_info(&certificate).expect("parse spki failed");
+    // SEQUENCE { AlgorithmIdentifier, BIT STRING } of an uncompressed P-256 point
+    assert_eq!(spki.len(), 91);
+    assert_eq!(&spki[..2], &[0x30, 0x59]);
+
+    assert!(extract_subject_public_key_info(&certificate[..certificate.len() - 1]).is_none());
+    assert!(extract_subject_public_key_info(&certificate[..64]).is_none());
+    assert!(extract_subject_public_key_info(&[]).is_none());
+
+    Ok(())
+}
+
+#[test]
+fn test_read_der_element() {
+    assert_eq!(
+        read_der_element(&[0x02, 0x01, 0x05, 0xFF], 0x02),
+        Some((&[0x02, 0x01, 0x05][..], &[0x05][..], &[0xFF][..]))
+    );
+
+    // long form lengths
+    assert_eq!(
+        read_der_element(&[0x30, 0x81, 0x02, 0x01, 0x02], 0x30),
+        Some((
+            &[0x30, 0x81, 0x02, 0x01, 0x02][..],
+            &[0x01, 0x02][..],
+            &[][..]
+        ))
+    );
+    let mut input = vec![0x04, 0x82, 0x01, 0x00];
+    input.extend(std::iter::repeat(0xAB).take(256));
+    let (element, content, remaining) = read_der_element(&input, 0x04).unwrap();
+    assert_eq!(element.len(), 260);
+    assert_eq!(content.len(), 256);
+    assert!(remaining.is_empty());
+
+    // wrong tag, truncated content and truncated or unsupported length encodings
+    assert!(read_der_element(&[0x30, 0x01, 0x05], 0x02).is_none());
+    assert!(read_der_element(&[0x30, 0x03, 0x01, 0x02], 0x30).is_none());
+    assert!(read_der_element(&[0x30, 0x82, 0x01], 0x30).is_none());
+    assert!(read_der_element(&[0x30, 0x80, 0x00, 0x00], 0x30).is_none());
+    assert!(read_der_element(&[0x30, 0x85, 0, 0, 0, 0, 1, 0], 0x30).is_none());
+    assert!(read_der_element(&[0x30, 0x84, 0xFF, 0xFF, 0xFF, 0xFF], 0x30).is_none());
+    assert!(read_der_element(&[0x30], 0x30).is_none());
+    assert!(read_der_element(&[], 0x30).is_none());
+}
+
+#[test]
+fn test_tls_pin_compute() -> anyhow::Result<()> {
+    let certificate = Certificate(base64::decode(CERTIFICATE)?);
+
+    let certificate_pin = TlsPin::compute(TlsPinKind::Certificate, &certificate)?;
+    assert_eq!(
+        certificate_pin,
+        TlsPin::Certificate(hex::decode(CERTIFICATE_DIGEST)?)
+    );
+
+    let public_key_pin = TlsPin::compute(TlsPinKind::PublicKey, &certificate)?;
+    assert_eq!(
+        public_key_pin,
+        TlsPin::PublicKey(hex::decode(PUBLIC_KEY_DIGEST)?)
+    );
+
+    assert!(TlsPin::compute(TlsPinKind::PublicKey, &Certificate(vec![0x30, 0x00])).is_err());
+
+    Ok(())
+}
+
+#[test]
+fn test_tls_pin_parse_and_display() -> anyhow::Result<()> {
+    let text = format!("spki-sha256:{}", PUBLIC_KEY_DIGEST);
+    let pin: TlsPin = text.parse()?;
+    assert_eq!(pin.kind(), TlsPinKind::PublicKey);
+    assert_eq!(pin.to_string(), text);
+
+    let text = format!("cert-sha256:{}", CERTIFICATE_DIGEST);
+    let pin: TlsPin = text.parse()?;
+    assert_eq!(pin.kind(), TlsPinKind::Certificate);
+    assert_eq!(pin.to_string(), text);
+
+    assert!(PUBLIC_KEY_DIGEST.parse::<TlsPin>().is_err());
+    assert!("sha1:0011".parse::<TlsPin>().is_err());
+    assert!("spki-sha256:not-hex".parse::<TlsPin>().is_err());
+    assert!("spki-sha256:0011".parse::<TlsPin>().is_err());
+    assert!(format!("spki-sha256:{}00", PUBLIC_KEY_DIGEST)
+        .parse::<TlsPin>()
+        .is_err());
+
+    Ok(())
+}
+
+#[test]
+fn test_tls_pin_requires_https() -> anyhow::Result<()> {
+    let pin: TlsPin = format!("spki-sha256:{}", PUBLIC_KEY_DIGEST).parse()?;
+
+    assert!(SignalingClient::new("http://127.0.0.1:28000", Some(pin.clone())).is_err());
+    assert!(SignalingClient::new("https://127.0.0.1:28000", Some(pin)).is_ok());
+    assert!(SignalingClient::new("http://127.0.0.1:28000", None).is_ok());
+
+    Ok(())
+}
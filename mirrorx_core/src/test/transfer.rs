@@ -0,0 +1,341 @@
+use crate::{
+    api::endpoint::{
+        client::EndPointClient,
+        id::EndPointID,
+        message::{
+            EndPointCallRequest, EndPointFileTransferBlock, EndPointMessage, EndPointSendFileReply,
+            EndPointSendFileRequest, FileTransferAction, FileTransferErrorReason,
+        },
+        EndPointStream,
+    },
+    component::fs::transfer::{
+        confirm_resume_offset, control_transfer, partial_file_path, prepare_receive_file,
+        query_transfer_error, query_transferred_bytes_count, send_file_to_remote, transfer_id,
+        wait_transfer_end, BlockChecksum, RESUME_BLOCK_SIZE,
+    },
+};
+use sha2::{Digest, Sha256};
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
+use tokio::net::{TcpListener, TcpStream};
+
+const TRANSFER_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[test]
+fn test_transfer_id() {
+    let id = transfer_id("device", Path::new("/a/file"), Path::new("/b/file"));
+
+    assert_eq!(
+        id,
+        transfer_id("device", Path::new("/a/file"), Path::new("/b/file"))
+    );
+    assert_ne!(
+        id,
+        transfer_id("device", Path::new("/b/file"), Path::new("/a/file"))
+    );
+    assert_ne!(
+        id,
+        transfer_id("other", Path::new("/a/file"), Path::new("/b/file"))
+    );
+}
+
+#[tokio::test]
+async fn test_resume_transfer() -> anyhow::Result<()> {
+    let dir = std::env::temp_dir().join(format!("mirrorx_{}", uuid::Uuid::new_v4()));
+    std::fs::create_dir_all(&dir)?;
+
+    let block = RESUME_BLOCK_SIZE as usize;
+    let source: Vec<u8> = (0..block * 3 + 100).map(|i| (i % 251) as u8).collect();
+    let source_path = dir.join("source");
+    std::fs::write(&source_path, &source)?;
+
+    // a new destination starts from the beginning
+    let destination_path = dir.join("destination");
+    assert_eq!(
+        prepare_receive_file("transfer", &destination_path).await?,
+        (0, Vec::new())
+    );
+
+    // the transfer is received to a hidden partial file named after it
+    let partial_path = partial_file_path(&destination_path, "transfer");
+    assert_eq!(partial_path, dir.join(".destination.transfer.mxpart"));
+    std::fs::write(&partial_path, &source[..block * 2 + 10])?;
+
+    // another transfer to the same destination starts over
+    assert_eq!(
+        prepare_receive_file("other", &destination_path).await?,
+        (0, Vec::new())
+    );
+
+    // only complete blocks are reported
+    let (offset, checksums) = prepare_receive_file("transfer", &destination_path).await?;
+    assert_eq!(offset, RESUME_BLOCK_SIZE * 2);
+    assert_eq!(checksums.len(), 2);
+
+    assert_eq!(
+        confirm_resume_offset(&source_path, offset, checksums.clone()).await?,
+        RESUME_BLOCK_SIZE * 2
+    );
+
+    // the sender resumes before the first block which differs
+    let mut changed = source.clone();
+    changed[block + 1] ^= 0xFF;
+    std::fs::write(&source_path, &changed)?;
+    assert_eq!(
+        confirm_resume_offset(&source_path, offset, checksums).await?,
+        RESUME_BLOCK_SIZE
+    );
+
+    // existing files are never overwritten
+    std::fs::write(&destination_path, &source)?;
+    assert!(prepare_receive_file("transfer", &destination_path)
+        .await
+        .is_err());
+
+    let _ = std::fs::remove_dir_all(dir);
+
+    Ok(())
+}
+
+/// Connects an endpoint to one served by this process, so files sent over it are
+/// received here as well. Returns the connecting endpoint and the serving one.
+async fn loopback_client() -> anyhow::Result<(Arc<EndPointClient>, Arc<EndPointClient>)> {
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    let addr = listener.local_addr()?;
+    let endpoint_id = EndPointID::LANID {
+        local_ip: addr.ip(),
+        remote_ip: addr.ip(),
+    };
+
+    let (connected, accepted) = tokio::join!(TcpStream::connect(addr), listener.accept());
+
+    let passive = EndPointClient::new_passive(
+        endpoint_id,
+        None,
+        EndPointStream::PassiveTCP(accepted?.0),
+        None,
+        None,
+    )
+    .await?;
+
+    let active = EndPointClient::new_file_manager_active(
+        endpoint_id,
+        None,
+        EndPointStream::ConnectedTCP(connected?),
+        None,
+    )
+    .await?;
+
+    Ok((active, passive))
+}
+
+/// Creates a directory with the file `source` to send and the directory `received`
+/// it's sent into.
+fn transfer_dir(content: &[u8]) -> anyhow::Result<(PathBuf, PathBuf, PathBuf)> {
+    let dir = std::env::temp_dir().join(format!("mirrorx_{}", uuid::Uuid::new_v4()));
+    std::fs::create_dir_all(dir.join("received"))?;
+
+    let source_path = dir.join("source");
+    std::fs::write(&source_path, content)?;
+
+    let destination_path = dir.join("received").join("source");
+
+    Ok((dir, source_path, destination_path))
+}
+
+async fn request_send_file(
+    client: &EndPointClient,
+    id: &str,
+    destination_path: &Path,
+    size: u64,
+) -> anyhow::Result<EndPointSendFileReply> {
+    Ok(client
+        .call(EndPointCallRequest::SendFileRequest(
+            EndPointSendFileRequest {
+                id: id.to_string(),
+                filename: String::from("source"),
+                path: destination_path.parent().unwrap().to_path_buf(),
+                size,
+            },
+        ))
+        .await?)
+}
+
+#[tokio::test]
+async fn test_transfer_received_into_place() -> anyhow::Result<()> {
+    let source: Vec<u8> = (0..200_000).map(|i| (i % 251) as u8).collect();
+    let (dir, source_path, destination_path) = transfer_dir(&source)?;
+    let id = transfer_id("loopback", &source_path, &destination_path);
+    let (client, passive) = loopback_client().await?;
+
+    let reply = request_send_file(&client, &id, &destination_path, source.len() as u64).await?;
+    assert_eq!(reply.offset, 0);
+
+    let ended = wait_transfer_end(&client, &id);
+    send_file_to_remote(id.clone(), client.clone(), &source_path, reply.offset).await?;
+    assert_eq!(tokio::time::timeout(TRANSFER_TIMEOUT, ended).await??, None);
+
+    // the verified file is moved to its final name
+    assert_eq!(std::fs::read(&destination_path)?, source);
+    assert!(!partial_file_path(&destination_path, &id).exists());
+    assert_eq!(query_transfer_error(&client, &id), None);
+    assert_eq!(query_transfer_error(&passive, &id), None);
+
+    let _ = std::fs::remove_dir_all(dir);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_transfer_corrupted_block() -> anyhow::Result<()> {
+    let source: Vec<u8> = (0..200_000).map(|i| (i % 241) as u8).collect();
+    let (dir, source_path, destination_path) = transfer_dir(&source)?;
+    let id = transfer_id("loopback", &source_path, &destination_path);
+    let (client, passive) = loopback_client().await?;
+
+    request_send_file(&client, &id, &destination_path, source.len() as u64).await?;
+    let ended = wait_transfer_end(&passive, &id);
+
+    // the second block is changed on the way, the digest covers the source
+    let block_size = 64 * 1024;
+    for (index, chunk) in source.chunks(block_size).enumerate() {
+        let mut data = chunk.to_vec();
+        if index == 1 {
+            data[0] ^= 0xFF;
+        }
+
+        client
+            .send(&EndPointMessage::FileTransferBlock(
+                EndPointFileTransferBlock {
+                    id: id.clone(),
+                    offset: (index * block_size) as u64,
+                    data: Some(data),
+                    digest: None,
+                },
+            ))
+            .await?;
+    }
+
+    let digest: BlockChecksum = Sha256::digest(&source).into();
+    client
+        .send(&EndPointMessage::FileTransferBlock(
+            EndPointFileTransferBlock {
+                id: id.clone(),
+                offset: source.len() as u64,
+                data: None,
+                digest: Some(digest),
+            },
+        ))
+        .await?;
+
+    assert_eq!(
+        tokio::time::timeout(TRANSFER_TIMEOUT, ended).await??,
+        Some(FileTransferErrorReason::DigestMismatch)
+    );
+
+    // the corrupted file is removed so the next attempt starts over
+    assert!(!partial_file_path(&destination_path, &id).exists());
+    assert!(!destination_path.exists());
+    assert_eq!(
+        query_transfer_error(&passive, &id),
+        Some(FileTransferErrorReason::DigestMismatch)
+    );
+
+    let _ = std::fs::remove_dir_all(dir);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_transfer_cancelled() -> anyhow::Result<()> {
+    let source: Vec<u8> = (0..200_000).map(|i| (i % 239) as u8).collect();
+    let (dir, source_path, destination_path) = transfer_dir(&source)?;
+    let id = transfer_id("loopback", &source_path, &destination_path);
+    let (client, passive) = loopback_client().await?;
+
+    request_send_file(&client, &id, &destination_path, source.len() as u64).await?;
+    let ended = wait_transfer_end(&passive, &id);
+
+    // the receiver holds part of the file when the transfer is cancelled
+    client
+        .send(&EndPointMessage::FileTransferBlock(
+            EndPointFileTransferBlock {
+                id: id.clone(),
+                offset: 0,
+                data: Some(source[..64 * 1024].to_vec()),
+                digest: None,
+            },
+        ))
+        .await?;
+
+    tokio::time::timeout(TRANSFER_TIMEOUT, async {
+        while query_transferred_bytes_count(&passive, &id) == 0 {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    })
+    .await?;
+
+    control_transfer(&client, &id, FileTransferAction::Cancel).await?;
+
+    assert_eq!(
+        tokio::time::timeout(TRANSFER_TIMEOUT, ended).await??,
+        Some(FileTransferErrorReason::Cancelled)
+    );
+
+    // a cancelled transfer doesn't resume, its partial file is removed
+    assert!(!partial_file_path(&destination_path, &id).exists());
+    assert!(!destination_path.exists());
+    assert_eq!(
+        query_transfer_error(&passive, &id),
+        Some(FileTransferErrorReason::Cancelled)
+    );
+
+    let _ = std::fs::remove_dir_all(dir);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_transfer_ids_per_endpoint() -> anyhow::Result<()> {
+    let source: Vec<u8> = (0..100_000).map(|i| (i % 233) as u8).collect();
+    let (dir, source_path, destination_path) = transfer_dir(&source)?;
+    let other_destination_path = dir.join("other");
+    std::fs::create_dir_all(&other_destination_path)?;
+    let other_destination_path = other_destination_path.join("source");
+
+    // two peers sending the same paths get the same id
+    let id = transfer_id("loopback", &source_path, &destination_path);
+    let (client, passive) = loopback_client().await?;
+    let (other_client, other_passive) = loopback_client().await?;
+
+    request_send_file(&client, &id, &destination_path, source.len() as u64).await?;
+    request_send_file(
+        &other_client,
+        &id,
+        &other_destination_path,
+        source.len() as u64,
+    )
+    .await?;
+
+    // cancelling the transfer of one peer leaves the other one running
+    let other_ended = wait_transfer_end(&other_passive, &id);
+    control_transfer(&other_client, &id, FileTransferAction::Cancel).await?;
+    assert_eq!(
+        tokio::time::timeout(TRANSFER_TIMEOUT, other_ended).await??,
+        Some(FileTransferErrorReason::Cancelled)
+    );
+    assert_eq!(query_transfer_error(&passive, &id), None);
+
+    let ended = wait_transfer_end(&client, &id);
+    send_file_to_remote(id.clone(), client.clone(), &source_path, 0).await?;
+    assert_eq!(tokio::time::timeout(TRANSFER_TIMEOUT, ended).await??, None);
+    assert_eq!(std::fs::read(&destination_path)?, source);
+    assert!(!other_destination_path.exists());
+
+    let _ = std::fs::remove_dir_all(dir);
+
+    Ok(())
+}
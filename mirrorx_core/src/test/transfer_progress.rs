@@ -0,0 +1,156 @@
+use crate::{
+    api::{config::entity::transfer::TransferState, endpoint::message::FileTransferErrorReason},
+    component::fs::transfer::progress::{
+        TransferEvent, TransferEvents, TransferProgress, PROGRESS_INTERVAL,
+    },
+};
+use std::time::{Duration, Instant};
+
+#[test]
+fn test_transfer_progress_rate() {
+    let start = Instant::now();
+    let mut progress = TransferProgress::new(10_000, 1_000, start);
+
+    // throttled until the interval passed
+    assert_eq!(progress.update(1_500, start + PROGRESS_INTERVAL / 2), None);
+    assert_eq!(progress.transferred_size(), 1_500);
+
+    let (rate, eta_secs) = progress
+        .update(2_000, start + Duration::from_secs(1))
+        .unwrap();
+    assert_eq!(rate, 1_000);
+    assert_eq!(eta_secs, Some(8));
+
+    // the rate is smoothed rather than replaced
+    let (rate, _) = progress
+        .update(4_000, start + Duration::from_secs(2))
+        .unwrap();
+    assert!(rate > 1_000 && rate < 2_000);
+
+    // completion is never throttled
+    let (_, eta_secs) = progress
+        .update(10_000, start + Duration::from_millis(2010))
+        .unwrap();
+    assert_eq!(eta_secs, Some(0));
+}
+
+#[test]
+fn test_transfer_progress_seek_back() {
+    let start = Instant::now();
+    let mut progress = TransferProgress::new(10_000, 4_000, start);
+
+    // a resumed receiver truncates to the confirmed offset
+    let (rate, eta_secs) = progress
+        .update(2_000, start + Duration::from_secs(1))
+        .unwrap();
+    assert_eq!(rate, 0);
+    assert_eq!(eta_secs, None);
+}
+
+#[tokio::test]
+async fn test_transfer_events() {
+    let events = TransferEvents::new();
+    let mut events_rx = events.subscribe();
+
+    events.start("file", 100, 40);
+    events.progress("file", 50);
+    events.finish("file", None);
+
+    events.start("paused", 100, 0);
+    events.finish("paused", Some(FileTransferErrorReason::Paused));
+
+    events.finish("failed", Some(FileTransferErrorReason::WriteFailed));
+    events.finish("cancelled", Some(FileTransferErrorReason::Cancelled));
+
+    let mut received = Vec::new();
+    while let Ok(event) = events_rx.try_recv() {
+        received.push(event);
+    }
+
+    let state = |id: &str, state| TransferEvent::State {
+        id: id.to_string(),
+        state,
+    };
+
+    assert_eq!(
+        received,
+        vec![
+            state("file", TransferState::Running),
+            TransferEvent::Progress {
+                id: String::from("file"),
+                transferred_size: 40,
+                total_size: 100,
+                rate: 0,
+                eta_secs: None,
+            },
+            TransferEvent::Progress {
+                id: String::from("file"),
+                transferred_size: 100,
+                total_size: 100,
+                rate: 0,
+                eta_secs: Some(0),
+            },
+            state("file", TransferState::Done),
+            state("paused", TransferState::Running),
+            TransferEvent::Progress {
+                id: String::from("paused"),
+                transferred_size: 0,
+                total_size: 100,
+                rate: 0,
+                eta_secs: None,
+            },
+            state("paused", TransferState::Paused),
+            TransferEvent::Error {
+                id: String::from("failed"),
+                reason: FileTransferErrorReason::WriteFailed,
+            },
+            state("failed", TransferState::Failed),
+            TransferEvent::Error {
+                id: String::from("cancelled"),
+                reason: FileTransferErrorReason::Cancelled,
+            },
+        ]
+    );
+}
+
+#[tokio::test]
+async fn test_transfer_events_directory() {
+    let events = TransferEvents::new();
+    let mut events_rx = events.subscribe();
+
+    events.start("directory", 300, 0);
+    events.attach("file", "directory", 200);
+    events.start("file", 100, 0);
+    events.finish("file", None);
+
+    let mut received = Vec::new();
+    while let Ok(event) = events_rx.try_recv() {
+        received.push(event);
+    }
+
+    // the progress of the directory is throttled like the one of its files
+    assert!(received.contains(&TransferEvent::State {
+        id: String::from("file"),
+        state: TransferState::Done,
+    }));
+    let directory_progress = received
+        .iter()
+        .filter(|event| matches!(event, TransferEvent::Progress { id, .. } if id == "directory"))
+        .count();
+    assert_eq!(directory_progress, 1);
+
+    // the last file completes the directory
+    events.attach("next", "directory", 300);
+
+    match events_rx.try_recv() {
+        Ok(TransferEvent::Progress {
+            id,
+            transferred_size,
+            ..
+        }) => {
+            assert_eq!(id, "directory");
+            assert_eq!(transferred_size, 300);
+        }
+        event => panic!("unexpected event {:?}", event),
+    }
+}
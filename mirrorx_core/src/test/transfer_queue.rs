@@ -0,0 +1,195 @@
+use crate::{
+    api::config::entity::transfer::{
+        TransferEntry, TransferEntryState, TransferKind, TransferRecord, TransferState,
+    },
+    component::fs::{
+        directory::directory_file_record,
+        transfer::{manager::TransferManager, partial_file_path},
+        tree::DirectoryTransferOptions,
+    },
+    test::TempStorage,
+};
+use std::path::PathBuf;
+
+fn transfer_record(id: &str, state: TransferState, created_at: i64) -> TransferRecord {
+    TransferRecord {
+        id: id.to_string(),
+        remote_device_id: String::from("1234567890"),
+        kind: TransferKind::Download,
+        local_path: std::env::temp_dir().join(format!("mirrorx_{}", uuid::Uuid::new_v4())),
+        remote_path: PathBuf::from("/remote/file"),
+        total_size: 100,
+        transferred_size: 40,
+        state,
+        error: None,
+        created_at,
+        updated_at: created_at,
+        options: None,
+    }
+}
+
+#[tokio::test]
+async fn test_transfer_queue() -> anyhow::Result<()> {
+    let storage = TempStorage::new()?;
+
+    let running = transfer_record("running", TransferState::Running, 1);
+    let queued = transfer_record("queued", TransferState::Queued, 2);
+    let done = transfer_record("done", TransferState::Done, 3);
+    for record in [&running, &queued, &done] {
+        storage.transfer().save(record)?;
+    }
+
+    storage
+        .transfer()
+        .update_state("done", TransferState::Done, 100, None)?;
+    let records = storage.transfer().list()?;
+    assert_eq!(records.len(), 3);
+    assert_eq!(records[0], running);
+    assert_eq!(records[2].transferred_size, 100);
+
+    // transfers interrupted by the last exit wait for the user to resume them
+    let manager = TransferManager::new(storage.clone())?;
+    let states: Vec<(String, TransferState)> = manager
+        .list()?
+        .into_iter()
+        .map(|record| (record.id, record.state))
+        .collect();
+    assert_eq!(
+        states,
+        vec![
+            (String::from("running"), TransferState::Paused),
+            (String::from("queued"), TransferState::Paused),
+            (String::from("done"), TransferState::Done),
+        ]
+    );
+    assert_eq!(storage.transfer().list()?[1].state, TransferState::Paused);
+
+    // only running and queued transfers can be paused
+    assert!(manager.pause("queued").await.is_err());
+    assert!(manager.pause("unknown").await.is_err());
+
+    manager.clear_done()?;
+    assert_eq!(manager.list()?.len(), 2);
+    assert_eq!(storage.transfer().list()?.len(), 2);
+
+    // cancelling discards the partial file of a download
+    let partial_path = partial_file_path(&queued.local_path, &queued.id);
+    std::fs::write(&partial_path, b"partial")?;
+    manager.cancel("queued").await?;
+    assert!(!partial_path.exists());
+    assert_eq!(manager.list()?.len(), 1);
+    assert_eq!(storage.transfer().list()?.len(), 1);
+
+    Ok(())
+}
+
+fn transfer_entry(path: &str, state: TransferEntryState, error: Option<&str>) -> TransferEntry {
+    TransferEntry {
+        path: path.to_string(),
+        size: 10,
+        state,
+        error: error.map(String::from),
+    }
+}
+
+#[tokio::test]
+async fn test_directory_transfer_entries() -> anyhow::Result<()> {
+    let storage = TempStorage::new()?;
+    let local_path = std::env::temp_dir().join(format!("mirrorx_{}", uuid::Uuid::new_v4()));
+    std::fs::create_dir_all(local_path.join("photos"))?;
+
+    let record = TransferRecord {
+        kind: TransferKind::DownloadDirectory,
+        local_path: local_path.clone(),
+        remote_path: PathBuf::from("/remote/photos"),
+        total_size: 30,
+        transferred_size: 10,
+        state: TransferState::Paused,
+        options: Some(DirectoryTransferOptions {
+            exclude: vec![String::from("*.tmp")],
+            ..Default::default()
+        }),
+        ..transfer_record("directory", TransferState::Paused, 1)
+    };
+    storage.transfer().save(&record)?;
+    storage
+        .transfer()
+        .save(&transfer_record("file", TransferState::Done, 2))?;
+    assert_eq!(storage.transfer().list()?[0], record);
+
+    let entries = vec![
+        transfer_entry("a.jpg", TransferEntryState::Done, None),
+        transfer_entry("b.jpg", TransferEntryState::Pending, None),
+        transfer_entry("c.jpg", TransferEntryState::Pending, None),
+        transfer_entry("private", TransferEntryState::Skipped, Some("denied")),
+    ];
+    storage.transfer().save_entries("directory", &entries)?;
+    assert_eq!(storage.transfer().list_entries("directory")?, entries);
+
+    // entries left out keep their states
+    storage.transfer().update_entry(
+        "directory",
+        "c.jpg",
+        TransferEntryState::Failed,
+        Some("file transfer failed (DigestMismatch)"),
+    )?;
+    storage
+        .transfer()
+        .update_entry("directory", "private", TransferEntryState::Done, None)?;
+    let entries = storage.transfer().list_entries("directory")?;
+    assert_eq!(entries[2].state, TransferEntryState::Failed);
+    assert_eq!(entries[3].state, TransferEntryState::Skipped);
+
+    let manager = TransferManager::new(storage.clone())?;
+    let status = manager
+        .directory_status("directory")?
+        .expect("directory transfer should have a status");
+    assert_eq!(status.total_files, 3);
+    assert_eq!(status.transferred_files, 1);
+    assert_eq!(status.transferred_size, 10);
+    assert!(!status.finished);
+    assert_eq!(
+        status
+            .errors
+            .iter()
+            .map(|error| error.path.as_str())
+            .collect::<Vec<_>>(),
+        vec!["c.jpg", "private"]
+    );
+    assert!(manager.directory_status("file")?.is_none());
+
+    // cancelling discards the partial files of the files which aren't done
+    let partial_paths = entries[..3]
+        .iter()
+        .map(|entry| {
+            let file = directory_file_record(&record, entry)?;
+            assert_eq!(file.kind, TransferKind::Download);
+            assert_eq!(
+                file.remote_path,
+                PathBuf::from("/remote/photos").join(&entry.path)
+            );
+            Ok(partial_file_path(&file.local_path, &file.id))
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    for path in partial_paths.iter() {
+        std::fs::write(path, b"partial")?;
+    }
+
+    manager.cancel("directory").await?;
+    assert!(partial_paths[0].exists());
+    assert!(!partial_paths[1].exists());
+    assert!(!partial_paths[2].exists());
+    assert!(storage.transfer().list_entries("directory")?.is_empty());
+
+    // removing the transfers which are done removes their entries as well
+    storage.transfer().save_entries(
+        "file",
+        &[transfer_entry("x", TransferEntryState::Done, None)],
+    )?;
+    manager.clear_done()?;
+    assert!(storage.transfer().list_entries("file")?.is_empty());
+
+    let _ = std::fs::remove_dir_all(local_path);
+
+    Ok(())
+}
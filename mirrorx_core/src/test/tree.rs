@@ -0,0 +1,141 @@
+use crate::component::fs::tree::{
+    join_tree_path, walk_tree, DirectoryTransferOptions, SymlinkPolicy, TreeEntry,
+};
+use std::path::Path;
+
+#[test]
+fn test_walk_tree() -> anyhow::Result<()> {
+    let dir = std::env::temp_dir().join(format!("mirrorx_{}", uuid::Uuid::new_v4()));
+    std::fs::create_dir_all(dir.join("src/nested"))?;
+    std::fs::create_dir_all(dir.join("target"))?;
+    std::fs::create_dir_all(dir.join("empty"))?;
+    std::fs::write(dir.join("README.md"), b"readme")?;
+    std::fs::write(dir.join("src/main.rs"), b"fn main() {}")?;
+    std::fs::write(dir.join("src/nested/lib.rs"), b"")?;
+    std::fs::write(dir.join("src/nested/notes.txt"), b"notes")?;
+    std::fs::write(dir.join("target/output"), b"binary")?;
+
+    let tree = walk_tree(&dir, &DirectoryTransferOptions::default())?;
+    let paths: Vec<&str> = tree
+        .entries
+        .iter()
+        .map(|entry| entry.path.as_str())
+        .collect();
+    assert_eq!(
+        paths,
+        vec![
+            "README.md",
+            "empty",
+            "src",
+            "src/main.rs",
+            "src/nested",
+            "src/nested/lib.rs",
+            "src/nested/notes.txt",
+            "target",
+            "target/output",
+        ]
+    );
+    assert_eq!(tree.total_size(), 6 + 12 + 5 + 6);
+    assert!(tree.errors.is_empty());
+
+    // excluded directories are skipped with their content and only the directories
+    // leading to included files are kept
+    let options = DirectoryTransferOptions {
+        include: vec![String::from("*.rs")],
+        exclude: vec![String::from("target")],
+        ..Default::default()
+    };
+    let tree = walk_tree(&dir, &options)?;
+    assert_eq!(
+        tree.entries,
+        vec![
+            TreeEntry {
+                path: String::from("src"),
+                is_dir: true,
+                size: 0
+            },
+            TreeEntry {
+                path: String::from("src/main.rs"),
+                is_dir: false,
+                size: 12
+            },
+            TreeEntry {
+                path: String::from("src/nested"),
+                is_dir: true,
+                size: 0
+            },
+            TreeEntry {
+                path: String::from("src/nested/lib.rs"),
+                is_dir: false,
+                size: 0
+            },
+        ]
+    );
+
+    let options = DirectoryTransferOptions {
+        include: vec![String::from("[")],
+        ..Default::default()
+    };
+    assert!(walk_tree(&dir, &options).is_err());
+
+    let _ = std::fs::remove_dir_all(dir);
+
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn test_walk_tree_symlinks() -> anyhow::Result<()> {
+    let dir = std::env::temp_dir().join(format!("mirrorx_{}", uuid::Uuid::new_v4()));
+    let root = dir.join("root");
+    std::fs::create_dir_all(&root)?;
+    std::fs::create_dir_all(dir.join("outside"))?;
+    std::fs::write(dir.join("outside/file"), b"outside")?;
+    std::fs::write(root.join("file"), b"inside")?;
+    std::os::unix::fs::symlink(dir.join("outside"), root.join("linked"))?;
+
+    let tree = walk_tree(&root, &DirectoryTransferOptions::default())?;
+    let paths: Vec<&str> = tree
+        .entries
+        .iter()
+        .map(|entry| entry.path.as_str())
+        .collect();
+    assert_eq!(paths, vec!["file"]);
+
+    let options = DirectoryTransferOptions {
+        symlinks: SymlinkPolicy::Follow,
+        ..Default::default()
+    };
+    let tree = walk_tree(&root, &options)?;
+    let paths: Vec<&str> = tree
+        .entries
+        .iter()
+        .map(|entry| entry.path.as_str())
+        .collect();
+    assert_eq!(paths, vec!["file", "linked", "linked/file"]);
+
+    // a link to an ancestor is reported instead of walked forever
+    std::os::unix::fs::symlink(&root, root.join("loop"))?;
+    let tree = walk_tree(&root, &options)?;
+    assert_eq!(tree.errors.len(), 1);
+    assert_eq!(tree.errors[0].path, "loop");
+
+    let _ = std::fs::remove_dir_all(dir);
+
+    Ok(())
+}
+
+#[test]
+fn test_join_tree_path() {
+    let root = Path::new("/root");
+
+    assert_eq!(
+        join_tree_path(root, "a/b.txt").unwrap(),
+        Path::new("/root/a/b.txt")
+    );
+    assert!(join_tree_path(root, "../etc/passwd").is_err());
+    assert!(join_tree_path(root, "a/../../b").is_err());
+    assert!(join_tree_path(root, "/etc/passwd").is_err());
+    assert!(join_tree_path(root, "a//b").is_err());
+    assert!(join_tree_path(root, "").is_err());
+}
@@ -0,0 +1,11 @@
+use crate::utility::version::compare_version;
+use std::cmp::Ordering;
+
+#[test]
+fn test_compare_version() {
+    assert_eq!(compare_version("0.1.0", "0.1.0"), Ordering::Equal);
+    assert_eq!(compare_version("0.1", "0.1.0"), Ordering::Equal);
+    assert_eq!(compare_version("0.1.0", "0.2.0"), Ordering::Less);
+    assert_eq!(compare_version("1.10.0", "1.9.3"), Ordering::Greater);
+    assert_eq!(compare_version("v1.2.0", "1.2.0-beta"), Ordering::Equal);
+}
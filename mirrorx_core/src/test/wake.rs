@@ -0,0 +1,69 @@
+use crate::component::lan::wake::{format_mac, magic_packet, parse_arp_table, parse_mac};
+use std::net::IpAddr;
+
+#[test]
+fn test_parse_mac() {
+    let mac = [0x00, 0x1a, 0x2b, 0x3c, 0x4d, 0x5e];
+
+    assert_eq!(parse_mac("00:1A:2B:3C:4D:5E"), Some(mac));
+    assert_eq!(parse_mac("00-1a-2b-3c-4d-5e"), Some(mac));
+    // printed by arp on macOS without leading zeros
+    assert_eq!(parse_mac("0:1a:2b:3c:4d:5e"), Some(mac));
+    assert_eq!(format_mac(&mac), "00:1a:2b:3c:4d:5e");
+
+    assert_eq!(parse_mac("00:1a:2b:3c:4d"), None);
+    assert_eq!(parse_mac("00:1a:2b:3c:4d:5g"), None);
+    assert_eq!(parse_mac("000:1a:2b:3c:4d:5e"), None);
+}
+
+#[test]
+fn test_magic_packet() {
+    let mac = [0x00, 0x1a, 0x2b, 0x3c, 0x4d, 0x5e];
+    let packet = magic_packet(&mac);
+
+    assert_eq!(packet.len(), 102);
+    assert_eq!(packet[..6], [0xFF; 6]);
+    assert!(packet[6..].chunks(6).all(|chunk| chunk == mac));
+}
+
+#[test]
+fn test_parse_arp_table() -> anyhow::Result<()> {
+    let linux = "\
+IP address       HW type     Flags       HW address            Mask     Device
+192.168.1.1      0x1         0x2         00:1a:2b:3c:4d:5e     *        eth0
+192.168.1.30     0x1         0x0         00:00:00:00:00:00     *        eth0
+";
+    let table = parse_arp_table(linux);
+    assert_eq!(table.len(), 1);
+    assert_eq!(
+        table[&"192.168.1.1".parse::<IpAddr>()?],
+        "00:1a:2b:3c:4d:5e"
+    );
+
+    let macos = "\
+? (192.168.1.1) at 0:1a:2b:3c:4d:5e on en0 ifscope [ethernet]
+? (192.168.1.30) at (incomplete) on en0 ifscope [ethernet]
+? (224.0.0.251) at 1:0:5e:0:0:fb on en0 ifscope permanent [ethernet]
+";
+    let table = parse_arp_table(macos);
+    assert_eq!(table.len(), 1);
+    assert_eq!(
+        table[&"192.168.1.1".parse::<IpAddr>()?],
+        "00:1a:2b:3c:4d:5e"
+    );
+
+    let windows = "\
+Interface: 192.168.1.10 --- 0x4
+  Internet Address      Physical Address      Type
+  192.168.1.1           00-1a-2b-3c-4d-5e     dynamic
+  192.168.1.255         ff-ff-ff-ff-ff-ff     static
+";
+    let table = parse_arp_table(windows);
+    assert_eq!(table.len(), 1);
+    assert_eq!(
+        table[&"192.168.1.1".parse::<IpAddr>()?],
+        "00:1a:2b:3c:4d:5e"
+    );
+
+    Ok(())
+}
@@ -1,9 +1,120 @@
-use crate::error::CoreResult;
-use std::net::IpAddr;
+use crate::{core_error, error::CoreResult};
+use serde::Serialize;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
 
-pub async fn get_lan_ip() -> CoreResult<IpAddr> {
-    let socket = tokio::net::UdpSocket::bind("0.0.0.0:0").await?;
-    socket.connect("255.255.255.255:80").await?;
+/// Address of a network interface usable for LAN discovery and connections.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct LanInterface {
+    pub name: String,
+    pub ip: IpAddr,
+    /// Directed broadcast address of IPv4 interfaces.
+    pub broadcast: Option<Ipv4Addr>,
+    pub netmask: Option<Ipv4Addr>,
+    /// Hardware address, lowercase hex separated by colons.
+    pub mac: Option<String>,
+}
+
+impl LanInterface {
+    /// Returns whether `ip` is in the IPv4 subnet of this interface.
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        match (self.ip, self.netmask, ip) {
+            (IpAddr::V4(local), Some(netmask), IpAddr::V4(ip)) => {
+                u32::from(local) & u32::from(netmask) == u32::from(ip) & u32::from(netmask)
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Returns the local address the system routes `remote_ip` through.
+pub async fn get_route_ip(remote_ip: IpAddr) -> CoreResult<IpAddr> {
+    let bind_ip = if remote_ip.is_ipv4() {
+        IpAddr::V4(Ipv4Addr::UNSPECIFIED)
+    } else {
+        IpAddr::V6(std::net::Ipv6Addr::UNSPECIFIED)
+    };
+
+    let socket = tokio::net::UdpSocket::bind((bind_ip, 0)).await?;
+    socket.connect((remote_ip, 80)).await?;
     let addr = socket.local_addr()?;
     Ok(addr.ip())
 }
+
+/// Resolves a LAN host given as an IP address or a hostname, IPv4 addresses are
+/// preferred when the name resolves to both families.
+pub async fn resolve_lan_host(host: &str, port: u16) -> CoreResult<SocketAddr> {
+    let host = host.trim().trim_start_matches('[').trim_end_matches(']');
+    if host.is_empty() {
+        return Err(core_error!("lan host is empty"));
+    }
+
+    if let Ok(ip) = host.parse::<IpAddr>() {
+        return Ok(SocketAddr::new(ip, port));
+    }
+
+    let addrs: Vec<SocketAddr> = tokio::net::lookup_host((host, port)).await?.collect();
+
+    addrs
+        .iter()
+        .find(|addr| addr.is_ipv4())
+        .or_else(|| addrs.first())
+        .copied()
+        .ok_or_else(|| core_error!("lan host '{}' doesn't resolve to any address", host))
+}
+
+/// Lists addresses of all interfaces except loopback and IPv6 link-local ones, which
+/// can't be bound without a scope id.
+pub fn list_lan_interfaces() -> CoreResult<Vec<LanInterface>> {
+    let interfaces = if_addrs::get_if_addrs()?
+        .into_iter()
+        .filter(|interface| !interface.is_loopback())
+        .filter_map(|interface| {
+            let mac = mac_address::mac_address_by_name(&interface.name)
+                .ok()
+                .flatten()
+                .map(|mac| mac.to_string().to_lowercase());
+
+            match interface.addr {
+                if_addrs::IfAddr::V4(addr) => Some(LanInterface {
+                    name: interface.name,
+                    ip: IpAddr::V4(addr.ip),
+                    broadcast: addr.broadcast,
+                    netmask: Some(addr.netmask),
+                    mac,
+                }),
+                if_addrs::IfAddr::V6(addr) => {
+                    if (addr.ip.segments()[0] & 0xffc0) == 0xfe80 {
+                        None
+                    } else {
+                        Some(LanInterface {
+                            name: interface.name,
+                            ip: IpAddr::V6(addr.ip),
+                            broadcast: None,
+                            netmask: None,
+                            mac,
+                        })
+                    }
+                }
+            }
+        })
+        .collect();
+
+    Ok(interfaces)
+}
+
+/// Returns the interfaces LAN components bind to, all of them when `name` is `None`.
+pub fn select_lan_interfaces(name: Option<&str>) -> CoreResult<Vec<LanInterface>> {
+    let interfaces: Vec<LanInterface> = list_lan_interfaces()?
+        .into_iter()
+        .filter(|interface| name.map_or(true, |name| interface.name == name))
+        .collect();
+
+    if interfaces.is_empty() {
+        return match name {
+            Some(name) => Err(core_error!("lan interface '{}' not found", name)),
+            None => Err(core_error!("no lan interface available")),
+        };
+    }
+
+    Ok(interfaces)
+}
@@ -3,4 +3,6 @@ pub mod lan_ip;
 pub mod macros;
 pub mod nonce_value;
 pub mod os;
+pub mod proxy;
 pub mod rand;
+pub mod version;
@@ -0,0 +1,312 @@
+use crate::{
+    core_error,
+    error::{CoreError, CoreResult},
+};
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use std::{
+    net::{IpAddr, SocketAddr},
+    sync::RwLock,
+    time::Duration,
+};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpStream,
+};
+use url::Url;
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Max length of the response header returned by a http proxy for a CONNECT request.
+const MAX_CONNECT_RESPONSE_LENGTH: usize = 8 * 1024;
+
+static PROXY_CONFIG: Lazy<RwLock<ProxyConfig>> = Lazy::new(|| RwLock::new(ProxyConfig::System));
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ProxyKind {
+    Http,
+    Socks5,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Proxy {
+    pub kind: ProxyKind,
+    pub host: String,
+    pub port: u16,
+    pub username: Option<String>,
+    pub password: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "mode", rename_all = "snake_case")]
+pub enum ProxyConfig {
+    /// Use the proxy from `ALL_PROXY` or `HTTPS_PROXY` environment variables, see
+    /// [`system_proxy`].
+    #[default]
+    System,
+    None,
+    Manual(Proxy),
+}
+
+impl ProxyConfig {
+    /// Returns the proxy connections should go through, `None` means connect directly.
+    pub fn resolve(&self) -> Option<Proxy> {
+        match self {
+            ProxyConfig::System => system_proxy(),
+            ProxyConfig::None => None,
+            ProxyConfig::Manual(proxy) => Some(proxy.clone()),
+        }
+    }
+}
+
+impl Proxy {
+    pub(crate) fn parse(value: &str) -> Option<Self> {
+        let url = Url::parse(value).ok()?;
+
+        let (kind, default_port) = match url.scheme() {
+            "http" => (ProxyKind::Http, 80),
+            "socks5" | "socks5h" => (ProxyKind::Socks5, 1080),
+            _ => return None,
+        };
+
+        let username = if url.username().is_empty() {
+            None
+        } else {
+            Some(url.username().to_string())
+        };
+
+        Some(Proxy {
+            kind,
+            host: url.host_str()?.to_string(),
+            port: url.port().unwrap_or(default_port),
+            username,
+            password: url.password().map(|password| password.to_string()),
+        })
+    }
+
+    /// Converts the proxy to a reqwest proxy applied to all requests.
+    pub fn to_reqwest_proxy(&self) -> CoreResult<reqwest::Proxy> {
+        let mut url = match self.kind {
+            ProxyKind::Http => Url::parse(&format!("http://{}:{}", self.host, self.port))?,
+            ProxyKind::Socks5 => Url::parse(&format!("socks5h://{}:{}", self.host, self.port))?,
+        };
+
+        if let Some(ref username) = self.username {
+            url.set_username(username)
+                .map_err(|_| core_error!("invalid proxy username"))?;
+            url.set_password(self.password.as_deref())
+                .map_err(|_| core_error!("invalid proxy password"))?;
+        }
+
+        Ok(reqwest::Proxy::all(url)?)
+    }
+}
+
+/// Reads the proxy from the `ALL_PROXY` and `HTTPS_PROXY` environment variables (either
+/// case) only. The OS proxy settings (Windows internet options, macOS network preferences,
+/// PAC files) and `NO_PROXY` are not consulted, local addresses are bypassed regardless.
+fn system_proxy() -> Option<Proxy> {
+    ["ALL_PROXY", "all_proxy", "HTTPS_PROXY", "https_proxy"]
+        .iter()
+        .filter_map(|key| std::env::var(key).ok())
+        .find_map(|value| Proxy::parse(&value))
+}
+
+pub fn set_proxy_config(config: ProxyConfig) {
+    if let Ok(mut current) = PROXY_CONFIG.write() {
+        *current = config;
+    }
+}
+
+pub fn proxy_config() -> ProxyConfig {
+    PROXY_CONFIG
+        .read()
+        .map(|config| config.clone())
+        .unwrap_or_default()
+}
+
+/// Returns the proxy requests to `url` should go through, local hosts are never proxied.
+pub fn resolve_proxy_for_url(url: &Url) -> Option<Proxy> {
+    let is_local = match url.host() {
+        Some(url::Host::Ipv4(ip)) => is_local_addr(&IpAddr::V4(ip)),
+        Some(url::Host::Ipv6(ip)) => is_local_addr(&IpAddr::V6(ip)),
+        Some(url::Host::Domain(domain)) => domain.eq_ignore_ascii_case("localhost"),
+        None => true,
+    };
+
+    if is_local {
+        None
+    } else {
+        proxy_config().resolve()
+    }
+}
+
+/// Connects to `addr` through the current proxy. Loopback and private addresses are
+/// always connected directly so LAN connections keep working behind a proxy.
+pub async fn connect_tcp(addr: SocketAddr) -> CoreResult<TcpStream> {
+    let proxy = if is_local_addr(&addr.ip()) {
+        None
+    } else {
+        proxy_config().resolve()
+    };
+
+    tokio::time::timeout(CONNECT_TIMEOUT, async move {
+        match proxy {
+            Some(proxy) => connect_tcp_with_proxy(&proxy, addr).await,
+            None => Ok(TcpStream::connect(addr).await?),
+        }
+    })
+    .await
+    .map_err(|_| CoreError::Timeout)?
+}
+
+pub(crate) async fn connect_tcp_with_proxy(
+    proxy: &Proxy,
+    addr: SocketAddr,
+) -> CoreResult<TcpStream> {
+    let mut stream = TcpStream::connect((proxy.host.as_str(), proxy.port)).await?;
+
+    match proxy.kind {
+        ProxyKind::Http => http_connect(&mut stream, proxy, addr).await?,
+        ProxyKind::Socks5 => socks5_connect(&mut stream, proxy, addr).await?,
+    }
+
+    tracing::info!(?addr, proxy = ?proxy.kind, "connected through proxy");
+
+    Ok(stream)
+}
+
+async fn http_connect(stream: &mut TcpStream, proxy: &Proxy, addr: SocketAddr) -> CoreResult<()> {
+    let mut request = format!("CONNECT {addr} HTTP/1.1\r\nHost: {addr}\r\n");
+
+    if let Some(ref username) = proxy.username {
+        let credentials = format!(
+            "{}:{}",
+            username,
+            proxy.password.as_deref().unwrap_or_default()
+        );
+
+        request.push_str(&format!(
+            "Proxy-Authorization: Basic {}\r\n",
+            base64::encode(credentials)
+        ));
+    }
+
+    request.push_str("\r\n");
+    stream.write_all(request.as_bytes()).await?;
+
+    let mut response = Vec::new();
+    while !response.ends_with(b"\r\n\r\n") {
+        if response.len() >= MAX_CONNECT_RESPONSE_LENGTH {
+            return Err(core_error!("http proxy response too large"));
+        }
+
+        response.push(stream.read_u8().await?);
+    }
+
+    let response = String::from_utf8_lossy(&response);
+    let status = response
+        .lines()
+        .next()
+        .and_then(|status_line| status_line.split_whitespace().nth(1))
+        .unwrap_or_default();
+
+    if status != "200" {
+        return Err(core_error!("http proxy CONNECT failed ({})", status));
+    }
+
+    Ok(())
+}
+
+async fn socks5_connect(stream: &mut TcpStream, proxy: &Proxy, addr: SocketAddr) -> CoreResult<()> {
+    const VERSION: u8 = 0x05;
+    const METHOD_NO_AUTH: u8 = 0x00;
+    const METHOD_USERNAME_PASSWORD: u8 = 0x02;
+
+    if proxy.username.is_some() {
+        stream
+            .write_all(&[VERSION, 2, METHOD_NO_AUTH, METHOD_USERNAME_PASSWORD])
+            .await?;
+    } else {
+        stream.write_all(&[VERSION, 1, METHOD_NO_AUTH]).await?;
+    }
+
+    let mut reply = [0u8; 2];
+    stream.read_exact(&mut reply).await?;
+
+    if reply[0] != VERSION {
+        return Err(core_error!("socks5 proxy replied unknown version"));
+    }
+
+    match reply[1] {
+        METHOD_NO_AUTH => {}
+        METHOD_USERNAME_PASSWORD => {
+            let username = proxy.username.as_deref().unwrap_or_default();
+            let password = proxy.password.as_deref().unwrap_or_default();
+
+            if username.len() > 255 || password.len() > 255 {
+                return Err(core_error!("socks5 proxy username or password too long"));
+            }
+
+            // RFC 1929
+            let mut request = vec![0x01, username.len() as u8];
+            request.extend_from_slice(username.as_bytes());
+            request.push(password.len() as u8);
+            request.extend_from_slice(password.as_bytes());
+            stream.write_all(&request).await?;
+
+            let mut reply = [0u8; 2];
+            stream.read_exact(&mut reply).await?;
+
+            if reply[1] != 0x00 {
+                return Err(core_error!("socks5 proxy authentication failed"));
+            }
+        }
+        _ => return Err(core_error!("socks5 proxy has no acceptable auth method")),
+    }
+
+    let mut request = vec![VERSION, 0x01, 0x00];
+    match addr.ip() {
+        IpAddr::V4(ip) => {
+            request.push(0x01);
+            request.extend_from_slice(&ip.octets());
+        }
+        IpAddr::V6(ip) => {
+            request.push(0x04);
+            request.extend_from_slice(&ip.octets());
+        }
+    }
+    request.extend_from_slice(&addr.port().to_be_bytes());
+    stream.write_all(&request).await?;
+
+    let mut reply = [0u8; 4];
+    stream.read_exact(&mut reply).await?;
+
+    if reply[1] != 0x00 {
+        return Err(core_error!("socks5 proxy CONNECT failed ({})", reply[1]));
+    }
+
+    // skip the bound address
+    let bound_addr_length = match reply[3] {
+        0x01 => 4,
+        0x04 => 16,
+        0x03 => stream.read_u8().await? as usize,
+        _ => return Err(core_error!("socks5 proxy replied unknown address type")),
+    };
+
+    let mut bound_addr = vec![0u8; bound_addr_length + 2];
+    stream.read_exact(&mut bound_addr).await?;
+
+    Ok(())
+}
+
+pub(crate) fn is_local_addr(ip: &IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => ip.is_loopback() || ip.is_private() || ip.is_link_local(),
+        IpAddr::V6(ip) => {
+            let segment = ip.segments()[0];
+            ip.is_loopback() || (segment & 0xfe00) == 0xfc00 || (segment & 0xffc0) == 0xfe80
+        }
+    }
+}
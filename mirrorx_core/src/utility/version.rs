@@ -0,0 +1,50 @@
+use once_cell::sync::OnceCell;
+use std::cmp::Ordering;
+
+static CLIENT_VERSION: OnceCell<String> = OnceCell::new();
+
+/// Sets the version of the application embedding this crate, it's reported to the
+/// signaling server and in LAN announcements. Only the first call takes effect.
+pub fn set_client_version(version: &str) {
+    let _ = CLIENT_VERSION.set(version.to_string());
+}
+
+/// Version of this client which is compared against the signaling server requirement,
+/// falls back to the version of this crate when the application didn't set one.
+pub fn client_version() -> &'static str {
+    CLIENT_VERSION
+        .get()
+        .map(String::as_str)
+        .unwrap_or(env!("CARGO_PKG_VERSION"))
+}
+
+/// Compares dotted numeric versions such as `1.2.10`, missing components are treated as
+/// zero and pre-release or build suffixes (`-beta`, `+build`) are ignored.
+pub fn compare_version(left: &str, right: &str) -> Ordering {
+    let left = parse_version(left);
+    let right = parse_version(right);
+
+    for i in 0..left.len().max(right.len()) {
+        let l = left.get(i).copied().unwrap_or_default();
+        let r = right.get(i).copied().unwrap_or_default();
+
+        match l.cmp(&r) {
+            Ordering::Equal => continue,
+            ordering => return ordering,
+        }
+    }
+
+    Ordering::Equal
+}
+
+fn parse_version(version: &str) -> Vec<u64> {
+    version
+        .trim()
+        .trim_start_matches('v')
+        .split(|c| c == '-' || c == '+')
+        .next()
+        .unwrap_or_default()
+        .split('.')
+        .map(|component| component.parse().unwrap_or_default())
+        .collect()
+}
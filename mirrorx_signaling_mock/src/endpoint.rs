@@ -0,0 +1,145 @@
+use crate::{
+    message::{
+        bincode_deserialize, bincode_serialize, EndPointHandshakeRequest, EndPointHandshakeResponse,
+    },
+    state::{RelayTicket, ServerState},
+};
+use bytes::Bytes;
+use dashmap::DashMap;
+use futures::{SinkExt, StreamExt};
+use std::{net::SocketAddr, sync::Arc, time::Duration};
+use tokio::net::{TcpListener, TcpStream};
+use tokio_util::codec::{Framed, LengthDelimitedCodec};
+
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How long the first side of a visit waits for its peer to connect.
+const PAIR_TIMEOUT: Duration = Duration::from_secs(30);
+
+type EndPointFramed = Framed<TcpStream, LengthDelimitedCodec>;
+
+/// Relays endpoint traffic between the active and passive device of a visit. Frames are
+/// sealed end to end by the devices, the relay only forwards them.
+pub async fn serve_endpoint(state: Arc<ServerState>) -> anyhow::Result<()> {
+    let listener = TcpListener::bind(state.config.endpoint_addr).await?;
+    let waiting: Arc<DashMap<u64, (RelayTicket, EndPointFramed)>> = Arc::new(DashMap::new());
+
+    tracing::info!(addr = ?listener.local_addr()?, "endpoint relay listen");
+
+    loop {
+        let (stream, addr) = match listener.accept().await {
+            Ok(v) => v,
+            Err(err) => {
+                tracing::error!(?err, "endpoint relay accept stream failed");
+                continue;
+            }
+        };
+
+        let state = state.clone();
+        let waiting = waiting.clone();
+        tokio::spawn(async move {
+            if let Err(err) = serve_endpoint_stream(state, waiting, stream, addr).await {
+                tracing::warn!(?addr, ?err, "endpoint relay stream failed");
+            }
+        });
+    }
+}
+
+async fn serve_endpoint_stream(
+    state: Arc<ServerState>,
+    waiting: Arc<DashMap<u64, (RelayTicket, EndPointFramed)>>,
+    stream: TcpStream,
+    addr: SocketAddr,
+) -> anyhow::Result<()> {
+    let mut framed = Framed::new(
+        stream,
+        LengthDelimitedCodec::builder()
+            .little_endian()
+            .max_frame_length(32 * 1024 * 1024)
+            .new_codec(),
+    );
+
+    let buffer = tokio::time::timeout(HANDSHAKE_TIMEOUT, framed.next())
+        .await?
+        .ok_or_else(|| anyhow::anyhow!("stream closed before handshake"))??;
+
+    let req: EndPointHandshakeRequest = bincode_deserialize(&buffer)?;
+
+    let Some((_, ticket)) = state.relay_tickets.remove(&req.visit_credentials) else {
+        anyhow::bail!("unknown visit credentials");
+    };
+
+    if ticket.device_id != req.device_id {
+        anyhow::bail!("visit credentials belong to another device");
+    }
+
+    tracing::info!(?addr, device_id = ticket.device_id, "endpoint handshake");
+
+    let session_id = ticket.session_id;
+    let Some((_, (peer_ticket, peer_framed))) = waiting.remove(&session_id) else {
+        waiting.insert(session_id, (ticket, framed));
+
+        // drop the waiting stream if the peer never shows up
+        tokio::spawn(async move {
+            tokio::time::sleep(PAIR_TIMEOUT).await;
+            if waiting.remove(&session_id).is_some() {
+                tracing::warn!(session_id, "endpoint relay peer not connected");
+            }
+        });
+
+        return Ok(());
+    };
+
+    relay(ticket, framed, peer_ticket, peer_framed).await
+}
+
+async fn relay(
+    ticket: RelayTicket,
+    mut framed: EndPointFramed,
+    peer_ticket: RelayTicket,
+    mut peer_framed: EndPointFramed,
+) -> anyhow::Result<()> {
+    let response = bincode_serialize(&EndPointHandshakeResponse {
+        remote_device_id: ticket.remote_device_id,
+    })?;
+    framed.send(Bytes::from(response)).await?;
+
+    let response = bincode_serialize(&EndPointHandshakeResponse {
+        remote_device_id: peer_ticket.remote_device_id,
+    })?;
+    peer_framed.send(Bytes::from(response)).await?;
+
+    tracing::info!(
+        session_id = ticket.session_id,
+        device_id = ticket.device_id,
+        remote_device_id = ticket.remote_device_id,
+        "endpoint relay start"
+    );
+
+    let (mut sink, mut stream) = framed.split();
+    let (mut peer_sink, mut peer_stream) = peer_framed.split();
+
+    let forward = async {
+        while let Some(buffer) = stream.next().await {
+            peer_sink.send(buffer?.freeze()).await?;
+        }
+        anyhow::Ok(())
+    };
+
+    let backward = async {
+        while let Some(buffer) = peer_stream.next().await {
+            sink.send(buffer?.freeze()).await?;
+        }
+        anyhow::Ok(())
+    };
+
+    // either side closing ends the whole session
+    tokio::select! {
+        result = forward => result?,
+        result = backward => result?,
+    };
+
+    tracing::info!(session_id = ticket.session_id, "endpoint relay exit");
+
+    Ok(())
+}
@@ -0,0 +1,147 @@
+use crate::{
+    message::{
+        HttpError, IdentityResponse, PresenceRequest, PresenceResponse, RegisterRequest,
+        RegisterResponse, Response, ServerMessage, VisitFailureReason, VisitRequest, VisitResponse,
+    },
+    state::ServerState,
+};
+use axum::{
+    extract::State,
+    routing::{get, post},
+    Json, Router,
+};
+use std::{sync::Arc, time::Duration};
+
+const VISIT_TIMEOUT: Duration = Duration::from_secs(60);
+
+const PRESENCE_MAX_DEVICES: usize = 128;
+
+pub async fn serve_http(state: Arc<ServerState>) -> anyhow::Result<()> {
+    let addr = state.config.http_addr;
+
+    let app = Router::new()
+        .route("/api/identity", get(identity))
+        .route("/api/domain/register", post(domain_register))
+        .route("/api/device/presence", post(presence))
+        .route("/api/visit", post(visit))
+        .with_state(state);
+
+    tracing::info!(?addr, "http server listen");
+
+    axum::Server::bind(&addr)
+        .serve(app.into_make_service())
+        .await?;
+
+    Ok(())
+}
+
+async fn identity(State(state): State<Arc<ServerState>>) -> Json<Response<IdentityResponse>> {
+    Json(Response::Message(IdentityResponse {
+        domain: state.config.domain.clone(),
+        min_client_version: state.config.min_client_version.clone(),
+        signaling_port: state.config.http_addr.port(),
+        subscribe_port: state.config.subscribe_addr.port(),
+    }))
+}
+
+async fn domain_register(
+    State(state): State<Arc<ServerState>>,
+    Json(req): Json<RegisterRequest>,
+) -> Json<Response<RegisterResponse>> {
+    if req.device_finger_print.is_empty() {
+        return Json(Response::Error(HttpError::InvalidArgs));
+    }
+
+    let (device_id, expire) = state.register(req.device_id, &req.device_finger_print);
+
+    tracing::info!(device_id, expire, "device registered");
+
+    Json(Response::Message(RegisterResponse { device_id, expire }))
+}
+
+async fn presence(
+    State(state): State<Arc<ServerState>>,
+    Json(req): Json<PresenceRequest>,
+) -> Json<Response<PresenceResponse>> {
+    if req.device_ids.len() > PRESENCE_MAX_DEVICES {
+        return Json(Response::Error(HttpError::InvalidArgs));
+    }
+
+    let online_device_ids = req
+        .device_ids
+        .into_iter()
+        .filter(|device_id| state.subscribers.contains_key(device_id))
+        .collect();
+
+    Json(Response::Message(PresenceResponse { online_device_ids }))
+}
+
+async fn visit(
+    State(state): State<Arc<ServerState>>,
+    Json(req): Json<VisitRequest>,
+) -> Json<Response<VisitResponse>> {
+    let (Ok(password_salt), Ok(secret), Ok(secret_nonce)) = (
+        base64::decode(&req.password_salt),
+        base64::decode(&req.secret),
+        base64::decode(&req.secret_nonce),
+    ) else {
+        return Json(Response::Error(HttpError::InvalidArgs));
+    };
+
+    let Some(subscriber) = state
+        .subscribers
+        .get(&req.passive_device_id)
+        .map(|subscriber| subscriber.clone())
+    else {
+        return Json(Response::Error(HttpError::RemoteOffline));
+    };
+
+    let (active_credentials, passive_credentials) =
+        state.create_relay_tickets(req.active_device_id, req.passive_device_id);
+
+    let visit_key = (req.active_device_id, req.passive_device_id);
+    let (tx, rx) = tokio::sync::oneshot::channel();
+    state.pending_visits.insert(visit_key, tx);
+
+    let endpoint_addr = state.config.public_endpoint_addr.to_string();
+
+    let visit_request = ServerMessage::VisitRequest {
+        active_device_id: req.active_device_id,
+        passive_device_id: req.passive_device_id,
+        visit_desktop: req.visit_desktop,
+        endpoint_addr: endpoint_addr.clone(),
+        password_salt,
+        secret,
+        secret_nonce,
+        passive_visit_credentials: passive_credentials.clone(),
+    };
+
+    if subscriber.send(visit_request).await.is_err() {
+        state.pending_visits.remove(&visit_key);
+        state.relay_tickets.remove(&active_credentials);
+        state.relay_tickets.remove(&passive_credentials);
+        return Json(Response::Error(HttpError::RemoteOffline));
+    }
+
+    let result = match tokio::time::timeout(VISIT_TIMEOUT, rx).await {
+        Ok(Ok(result)) => result,
+        Ok(Err(_)) => Err(VisitFailureReason::InternalError),
+        Err(_) => {
+            state.pending_visits.remove(&visit_key);
+            state.relay_tickets.remove(&active_credentials);
+            state.relay_tickets.remove(&passive_credentials);
+            return Json(Response::Error(HttpError::Timeout));
+        }
+    };
+
+    if result.is_err() {
+        state.relay_tickets.remove(&active_credentials);
+        state.relay_tickets.remove(&passive_credentials);
+    }
+
+    Json(Response::Message(VisitResponse {
+        endpoint_addr,
+        visit_credentials: base64::encode(active_credentials),
+        result: result.map(base64::encode),
+    }))
+}
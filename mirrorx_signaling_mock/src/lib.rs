@@ -0,0 +1,33 @@
+//! A small in-memory signaling server speaking the MirrorX signaling protocol. It is meant
+//! for development, integration tests and small self-hosted installations, devices are
+//! forgotten when the process exits.
+
+#[cfg(test)]
+mod test;
+
+pub mod endpoint;
+pub mod http;
+pub mod message;
+pub mod state;
+pub mod subscribe;
+
+use self::{
+    endpoint::serve_endpoint,
+    http::serve_http,
+    state::{ServerConfig, ServerState},
+    subscribe::serve_subscribe,
+};
+use std::sync::Arc;
+
+/// Runs the http api, the subscribe server and the endpoint relay until one of them fails.
+pub async fn run(config: ServerConfig) -> anyhow::Result<()> {
+    let state = Arc::new(ServerState::new(config));
+
+    tokio::try_join!(
+        serve_http(state.clone()),
+        serve_subscribe(state.clone()),
+        serve_endpoint(state),
+    )?;
+
+    Ok(())
+}
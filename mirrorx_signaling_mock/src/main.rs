@@ -0,0 +1,67 @@
+use mirrorx_signaling_mock::state::ServerConfig;
+use std::{net::SocketAddr, time::Duration};
+use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt, EnvFilter};
+
+const USAGE: &str = r"Usage: mirrorx_signaling_mock [OPTIONS]
+
+Options:
+  --domain <NAME>               domain name reported by /api/identity
+  --min-client-version <VER>    minimum client version reported by /api/identity
+  --http <ADDR>                 http api listen address
+  --subscribe <ADDR>            subscribe server listen address
+  --endpoint <ADDR>             endpoint relay listen address
+  --public-endpoint <ADDR>      endpoint relay address told to clients
+  --registration-ttl <SECS>     device registration lifetime";
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    tracing_subscriber::Registry::default()
+        .with(EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::from("info")))
+        .with(tracing_subscriber::fmt::layer())
+        .init();
+
+    let config = match parse_args(std::env::args().skip(1)) {
+        Ok(config) => config,
+        Err(err) => {
+            eprintln!("{err}\n\n{USAGE}");
+            std::process::exit(2);
+        }
+    };
+
+    tracing::info!(?config, "mock signaling server start");
+
+    mirrorx_signaling_mock::run(config).await
+}
+
+fn parse_args(mut args: impl Iterator<Item = String>) -> anyhow::Result<ServerConfig> {
+    let mut config = ServerConfig::default();
+    let mut public_endpoint_addr = None;
+
+    while let Some(arg) = args.next() {
+        let mut value = || {
+            args.next()
+                .ok_or_else(|| anyhow::anyhow!("missing value for '{arg}'"))
+        };
+
+        match arg.as_str() {
+            "--domain" => config.domain = value()?,
+            "--min-client-version" => config.min_client_version = value()?,
+            "--http" => config.http_addr = value()?.parse()?,
+            "--subscribe" => config.subscribe_addr = value()?.parse()?,
+            "--endpoint" => config.endpoint_addr = value()?.parse()?,
+            "--public-endpoint" => public_endpoint_addr = Some(value()?.parse::<SocketAddr>()?),
+            "--registration-ttl" => {
+                config.registration_ttl = Duration::from_secs(value()?.parse()?)
+            }
+            "-h" | "--help" => {
+                println!("{USAGE}");
+                std::process::exit(0);
+            }
+            _ => anyhow::bail!("unknown argument '{arg}'"),
+        }
+    }
+
+    config.public_endpoint_addr = public_endpoint_addr.unwrap_or(config.endpoint_addr);
+
+    Ok(config)
+}
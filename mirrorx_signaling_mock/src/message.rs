@@ -0,0 +1,151 @@
+//! Wire types of the signaling protocol, they mirror `mirrorx_core::api::signaling` and
+//! `mirrorx_core::api::endpoint::message` and must be kept compatible with them.
+
+use bincode::{
+    config::{LittleEndian, VarintEncoding, WithOtherEndian, WithOtherIntEncoding},
+    DefaultOptions, Options,
+};
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+
+static SERIALIZER: Lazy<
+    WithOtherIntEncoding<WithOtherEndian<DefaultOptions, LittleEndian>, VarintEncoding>,
+> = Lazy::new(|| {
+    bincode::DefaultOptions::new()
+        .with_little_endian()
+        .with_varint_encoding()
+});
+
+pub fn bincode_serialize<S>(t: &S) -> bincode::Result<Vec<u8>>
+where
+    S: ?Sized + serde::Serialize,
+{
+    SERIALIZER.serialize(t)
+}
+
+pub fn bincode_deserialize<'a, T>(bytes: &'a [u8]) -> bincode::Result<T>
+where
+    T: serde::Deserialize<'a>,
+{
+    SERIALIZER.deserialize(bytes)
+}
+
+#[derive(Debug, Serialize)]
+pub enum HttpError {
+    Internal,
+    Timeout,
+    InvalidArgs,
+    ResourceExhausted,
+    RemoteOffline,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(untagged)]
+pub enum Response<T> {
+    Message(T),
+    Error(HttpError),
+}
+
+#[derive(Debug, Serialize)]
+pub struct IdentityResponse {
+    pub domain: String,
+    pub min_client_version: String,
+    pub signaling_port: u16,
+    pub subscribe_port: u16,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RegisterRequest {
+    pub device_id: i64,
+    pub device_finger_print: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RegisterResponse {
+    pub device_id: i64,
+    pub expire: i64,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct VisitRequest {
+    pub active_device_id: i64,
+    pub passive_device_id: i64,
+    pub visit_desktop: bool,
+    pub password_salt: String,
+    pub secret: String,
+    pub secret_nonce: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct VisitResponse {
+    pub endpoint_addr: String,
+    pub visit_credentials: String,
+    pub result: Result<String, VisitFailureReason>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PresenceRequest {
+    pub device_ids: Vec<i64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PresenceResponse {
+    pub online_device_ids: Vec<i64>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Subscription {
+    pub device_id: i64,
+    pub device_finger_print: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum VisitFailureReason {
+    RemoteReject,
+    InvalidPassword,
+    InternalError,
+    InvalidArgs,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub enum ServerMessage {
+    Pong(i32),
+    VisitRequest {
+        active_device_id: i64,
+        passive_device_id: i64,
+        visit_desktop: bool,
+        endpoint_addr: String,
+        #[serde(with = "serde_bytes")]
+        password_salt: Vec<u8>,
+        #[serde(with = "serde_bytes")]
+        secret: Vec<u8>,
+        #[serde(with = "serde_bytes")]
+        secret_nonce: Vec<u8>,
+        #[serde(with = "serde_bytes")]
+        passive_visit_credentials: Vec<u8>,
+    },
+}
+
+#[serde_with::serde_as]
+#[derive(Debug, Serialize, Deserialize)]
+pub enum ClientMessage {
+    Ping(i32),
+    VisitResponse {
+        active_device_id: i64,
+        passive_device_id: i64,
+        #[serde_as(as = "Result<serde_with::Bytes, _>")]
+        result: Result<Vec<u8>, VisitFailureReason>,
+    },
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct EndPointHandshakeRequest {
+    #[serde(with = "serde_bytes")]
+    pub visit_credentials: Vec<u8>,
+    pub device_id: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct EndPointHandshakeResponse {
+    pub remote_device_id: i64,
+}
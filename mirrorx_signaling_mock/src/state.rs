@@ -0,0 +1,131 @@
+use crate::message::{ServerMessage, VisitFailureReason};
+use dashmap::DashMap;
+use rand::Rng;
+use std::{net::SocketAddr, time::Duration};
+use tokio::sync::{mpsc::Sender, oneshot};
+
+#[derive(Debug, Clone)]
+pub struct ServerConfig {
+    pub domain: String,
+    pub min_client_version: String,
+    pub http_addr: SocketAddr,
+    pub subscribe_addr: SocketAddr,
+    pub endpoint_addr: SocketAddr,
+    /// Endpoint address told to clients, it differs from `endpoint_addr` when the server
+    /// listens on an unspecified address.
+    pub public_endpoint_addr: SocketAddr,
+    pub registration_ttl: Duration,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            domain: String::from("mirrorx.local"),
+            min_client_version: String::from("0.0.0"),
+            http_addr: ([127, 0, 0, 1], 28000).into(),
+            subscribe_addr: ([127, 0, 0, 1], 28001).into(),
+            endpoint_addr: ([127, 0, 0, 1], 28002).into(),
+            public_endpoint_addr: ([127, 0, 0, 1], 28002).into(),
+            registration_ttl: Duration::from_secs(90 * 24 * 60 * 60),
+        }
+    }
+}
+
+pub struct Device {
+    pub finger_print: String,
+    pub expire: i64,
+}
+
+/// One side of a pending endpoint relay, created for each visit.
+pub struct RelayTicket {
+    pub session_id: u64,
+    pub device_id: i64,
+    pub remote_device_id: i64,
+}
+
+pub struct ServerState {
+    pub config: ServerConfig,
+    pub devices: DashMap<i64, Device>,
+    pub subscribers: DashMap<i64, Sender<ServerMessage>>,
+    pub pending_visits: DashMap<(i64, i64), oneshot::Sender<Result<Vec<u8>, VisitFailureReason>>>,
+    pub relay_tickets: DashMap<Vec<u8>, RelayTicket>,
+}
+
+impl ServerState {
+    pub fn new(config: ServerConfig) -> Self {
+        Self {
+            config,
+            devices: DashMap::new(),
+            subscribers: DashMap::new(),
+            pending_visits: DashMap::new(),
+            relay_tickets: DashMap::new(),
+        }
+    }
+
+    /// Registers a new device or renews an existing one with the same finger print.
+    pub fn register(&self, device_id: i64, device_finger_print: &str) -> (i64, i64) {
+        let expire = chrono::Utc::now().timestamp() + self.config.registration_ttl.as_secs() as i64;
+
+        if let Some(mut device) = self.devices.get_mut(&device_id) {
+            if device.finger_print == device_finger_print {
+                device.expire = expire;
+                return (device_id, expire);
+            }
+        }
+
+        let device_id = loop {
+            let device_id = rand::thread_rng().gen_range(1_000_000_000..10_000_000_000);
+            if !self.devices.contains_key(&device_id) {
+                break device_id;
+            }
+        };
+
+        self.devices.insert(
+            device_id,
+            Device {
+                finger_print: device_finger_print.to_string(),
+                expire,
+            },
+        );
+
+        (device_id, expire)
+    }
+
+    pub fn verify_device(&self, device_id: i64, device_finger_print: &str) -> bool {
+        self.devices.get(&device_id).map_or(false, |device| {
+            device.finger_print == device_finger_print
+                && device.expire > chrono::Utc::now().timestamp()
+        })
+    }
+
+    /// Creates relay credentials for both sides of a visit, returns (active, passive).
+    pub fn create_relay_tickets(
+        &self,
+        active_device_id: i64,
+        passive_device_id: i64,
+    ) -> (Vec<u8>, Vec<u8>) {
+        let session_id = rand::thread_rng().gen();
+        let active_credentials = rand::thread_rng().gen::<[u8; 16]>().to_vec();
+        let passive_credentials = rand::thread_rng().gen::<[u8; 16]>().to_vec();
+
+        self.relay_tickets.insert(
+            active_credentials.clone(),
+            RelayTicket {
+                session_id,
+                device_id: active_device_id,
+                remote_device_id: passive_device_id,
+            },
+        );
+
+        self.relay_tickets.insert(
+            passive_credentials.clone(),
+            RelayTicket {
+                session_id,
+                device_id: passive_device_id,
+                remote_device_id: active_device_id,
+            },
+        );
+
+        (active_credentials, passive_credentials)
+    }
+}
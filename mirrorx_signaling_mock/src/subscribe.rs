@@ -0,0 +1,114 @@
+use crate::{
+    message::{bincode_deserialize, bincode_serialize, ClientMessage, ServerMessage, Subscription},
+    state::ServerState,
+};
+use bytes::Bytes;
+use futures::{SinkExt, StreamExt};
+use std::{net::SocketAddr, sync::Arc, time::Duration};
+use tokio::net::{TcpListener, TcpStream};
+use tokio_util::codec::{Framed, LengthDelimitedCodec};
+
+const SUBSCRIPTION_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Clients ping every 60 seconds, a connection idle for longer than two periods is dead.
+const IDLE_TIMEOUT: Duration = Duration::from_secs(150);
+
+pub async fn serve_subscribe(state: Arc<ServerState>) -> anyhow::Result<()> {
+    let listener = TcpListener::bind(state.config.subscribe_addr).await?;
+
+    tracing::info!(addr = ?listener.local_addr()?, "subscribe server listen");
+
+    loop {
+        let (stream, addr) = match listener.accept().await {
+            Ok(v) => v,
+            Err(err) => {
+                tracing::error!(?err, "subscribe server accept stream failed");
+                continue;
+            }
+        };
+
+        let state = state.clone();
+        tokio::spawn(async move {
+            if let Err(err) = serve_subscriber(state, stream, addr).await {
+                tracing::warn!(?addr, ?err, "subscriber disconnected");
+            }
+        });
+    }
+}
+
+async fn serve_subscriber(
+    state: Arc<ServerState>,
+    stream: TcpStream,
+    addr: SocketAddr,
+) -> anyhow::Result<()> {
+    let mut framed = Framed::new(
+        stream,
+        LengthDelimitedCodec::builder()
+            .length_field_length(2)
+            .little_endian()
+            .new_codec(),
+    );
+
+    let buffer = tokio::time::timeout(SUBSCRIPTION_TIMEOUT, framed.next())
+        .await?
+        .ok_or_else(|| anyhow::anyhow!("stream closed before subscription"))??;
+
+    let subscription: Subscription = bincode_deserialize(&buffer)?;
+
+    if !state.verify_device(subscription.device_id, &subscription.device_finger_print) {
+        anyhow::bail!("device {} is not registered", subscription.device_id);
+    }
+
+    let device_id = subscription.device_id;
+    let (tx, mut rx) = tokio::sync::mpsc::channel(8);
+    state.subscribers.insert(device_id, tx.clone());
+
+    tracing::info!(?addr, device_id, "device subscribed");
+
+    let result: anyhow::Result<()> = async {
+        loop {
+            tokio::select! {
+                message = rx.recv() => {
+                    let Some(message) = message else {
+                        return Ok(());
+                    };
+
+                    framed.send(Bytes::from(bincode_serialize(&message)?)).await?;
+                }
+                buffer = tokio::time::timeout(IDLE_TIMEOUT, framed.next()) => {
+                    let Some(buffer) = buffer? else {
+                        return Ok(());
+                    };
+
+                    match bincode_deserialize::<ClientMessage>(&buffer?)? {
+                        ClientMessage::Ping(value) => {
+                            let pong = bincode_serialize(&ServerMessage::Pong(value))?;
+                            framed.send(Bytes::from(pong)).await?;
+                        }
+                        ClientMessage::VisitResponse {
+                            active_device_id,
+                            passive_device_id,
+                            result,
+                        } => {
+                            if let Some((_, tx)) = state
+                                .pending_visits
+                                .remove(&(active_device_id, passive_device_id))
+                            {
+                                let _ = tx.send(result);
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+    .await;
+
+    state
+        .subscribers
+        .remove_if(&device_id, |_, subscriber| subscriber.same_channel(&tx));
+
+    tracing::info!(?addr, device_id, "device unsubscribed");
+
+    result
+}
@@ -0,0 +1,248 @@
+use crate::state::ServerConfig;
+use mirrorx_core::api::{
+    config::{
+        cipher::{FileKeyStore, KeyStores},
+        entity::domain::Domain,
+        LocalStorage,
+    },
+    signaling::{http_message::Response, subscribe_message::VisitFailureReason, SignalingClient},
+};
+use std::{net::SocketAddr, path::Path, sync::Arc, time::Duration};
+
+fn free_addr() -> SocketAddr {
+    std::net::TcpListener::bind("127.0.0.1:0")
+        .and_then(|listener| listener.local_addr())
+        .expect("bind free port failed")
+}
+
+/// Opens a storage keyed by a file of its own, so tests never touch the OS keyring.
+fn open_storage(db_path: &Path) -> anyhow::Result<LocalStorage> {
+    let key_path = db_path.with_extension("key");
+    let key_stores = KeyStores {
+        passphrase: None,
+        keyring: Arc::new(FileKeyStore::new(key_path.clone())),
+        file: Arc::new(FileKeyStore::new(key_path)),
+    };
+
+    Ok(LocalStorage::with_key_stores(db_path, &key_stores)?)
+}
+
+async fn start_server() -> anyhow::Result<ServerConfig> {
+    let endpoint_addr = free_addr();
+    let config = ServerConfig {
+        http_addr: free_addr(),
+        subscribe_addr: free_addr(),
+        endpoint_addr,
+        public_endpoint_addr: endpoint_addr,
+        ..Default::default()
+    };
+
+    tokio::spawn(crate::run(config.clone()));
+
+    let client = SignalingClient::new(format!("http://{}", config.http_addr), None)?;
+    for _ in 0..50 {
+        if client.identity().await.is_ok() {
+            return Ok(config);
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+
+    anyhow::bail!("mock signaling server not ready")
+}
+
+async fn register(client: &SignalingClient, finger_print: &str) -> anyhow::Result<i64> {
+    match client.domain_register(0, finger_print).await? {
+        Response::Message(resp) => Ok(resp.device_id),
+        Response::Error(err) => anyhow::bail!("register failed ({:?})", err),
+    }
+}
+
+#[tokio::test]
+async fn test_visit() -> anyhow::Result<()> {
+    let config = start_server().await?;
+    let url = format!("http://{}", config.http_addr);
+
+    // passive device registers, stores its password and subscribes
+
+    let passive_finger_print = mirrorx_core::utility::rand::generate_device_finger_print();
+    let mut passive_client = SignalingClient::new(url.clone(), None)?;
+    let passive_device_id = register(&passive_client, &passive_finger_print).await?;
+
+    let db_path = std::env::temp_dir().join(format!("{passive_finger_print}.db"));
+    let storage = open_storage(&db_path)?;
+    storage.domain().add_domain(Domain {
+        id: 0,
+        name: config.domain.clone(),
+        addr: url.clone(),
+        signaling_port: config.http_addr.port(),
+        subscribe_port: config.subscribe_addr.port(),
+        is_primary: true,
+        device_id: passive_device_id,
+        password: String::from("passive_password"),
+        finger_print: passive_finger_print.clone(),
+        remarks: String::default(),
+        tls_pin: String::default(),
+        expire: 0,
+    })?;
+
+    passive_client
+        .subscribe(
+            vec![config.subscribe_addr],
+            passive_device_id,
+            &passive_finger_print,
+            storage,
+        )
+        .await?;
+
+    // active device visits with a wrong and then the right password
+
+    let active_finger_print = mirrorx_core::utility::rand::generate_device_finger_print();
+    let active_client = SignalingClient::new(url, None)?;
+    let active_device_id = register(&active_client, &active_finger_print).await?;
+
+    let resp = active_client
+        .visit(
+            active_device_id,
+            passive_device_id,
+            String::from("wrong_password"),
+            false,
+        )
+        .await?;
+
+    assert!(matches!(
+        resp,
+        Response::Message(Err(VisitFailureReason::InvalidPassword))
+    ));
+
+    let resp = active_client
+        .visit(
+            active_device_id,
+            passive_device_id,
+            String::from("passive_password"),
+            false,
+        )
+        .await?;
+
+    let Response::Message(Ok((endpoint_addr, visit_credentials, _, _))) = resp else {
+        anyhow::bail!("visit failed");
+    };
+
+    assert_eq!(endpoint_addr, config.public_endpoint_addr.to_string());
+    assert_eq!(visit_credentials.len(), 16);
+
+    let presence = active_client
+        .presence(&[passive_device_id, active_device_id])
+        .await?;
+
+    assert_eq!(presence.get(&passive_device_id), Some(&true));
+    assert_eq!(presence.get(&active_device_id), Some(&false));
+
+    let _ = std::fs::remove_file(db_path.with_extension("key"));
+    let _ = std::fs::remove_file(db_path);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_visit_temporary_password() -> anyhow::Result<()> {
+    let config = start_server().await?;
+    let url = format!("http://{}", config.http_addr);
+
+    let passive_finger_print = mirrorx_core::utility::rand::generate_device_finger_print();
+    let mut passive_client = SignalingClient::new(url.clone(), None)?;
+    let passive_device_id = register(&passive_client, &passive_finger_print).await?;
+
+    let db_path = std::env::temp_dir().join(format!("{passive_finger_print}.db"));
+    let storage = open_storage(&db_path)?;
+    let domain = storage.domain().add_domain(Domain {
+        id: 0,
+        name: config.domain.clone(),
+        addr: url.clone(),
+        signaling_port: config.http_addr.port(),
+        subscribe_port: config.subscribe_addr.port(),
+        is_primary: true,
+        device_id: passive_device_id,
+        password: String::from("passive_password"),
+        finger_print: passive_finger_print.clone(),
+        remarks: String::default(),
+        tls_pin: String::default(),
+        expire: 0,
+    })?;
+
+    storage
+        .temporary_password()
+        .create(domain.id, "one_time_password", true, None)?;
+    storage.temporary_password().create(
+        domain.id,
+        "expired_password",
+        false,
+        Some(Duration::ZERO),
+    )?;
+
+    passive_client
+        .subscribe(
+            vec![config.subscribe_addr],
+            passive_device_id,
+            &passive_finger_print,
+            storage.clone(),
+        )
+        .await?;
+
+    let active_finger_print = mirrorx_core::utility::rand::generate_device_finger_print();
+    let active_client = SignalingClient::new(url, None)?;
+    let active_device_id = register(&active_client, &active_finger_print).await?;
+
+    let visit = |password: &str| {
+        active_client.visit(
+            active_device_id,
+            passive_device_id,
+            password.to_string(),
+            false,
+        )
+    };
+
+    assert!(matches!(
+        visit("expired_password").await?,
+        Response::Message(Err(VisitFailureReason::InvalidPassword))
+    ));
+
+    // a one-time password is consumed by the first visit
+    assert!(matches!(
+        visit("one_time_password").await?,
+        Response::Message(Ok(_))
+    ));
+    assert!(storage
+        .temporary_password()
+        .get_valid_passwords(domain.id)?
+        .is_empty());
+    assert!(matches!(
+        visit("one_time_password").await?,
+        Response::Message(Err(VisitFailureReason::InvalidPassword))
+    ));
+
+    // the permanent password keeps working
+    assert!(matches!(
+        visit("passive_password").await?,
+        Response::Message(Ok(_))
+    ));
+
+    let _ = std::fs::remove_file(db_path.with_extension("key"));
+    let _ = std::fs::remove_file(db_path);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_visit_offline_device() -> anyhow::Result<()> {
+    let config = start_server().await?;
+    let client = SignalingClient::new(format!("http://{}", config.http_addr), None)?;
+    let device_id = register(&client, "finger_print").await?;
+
+    let resp = client
+        .visit(device_id, 1, String::from("password"), true)
+        .await?;
+
+    assert!(matches!(resp, Response::Error(_)));
+
+    Ok(())
+}